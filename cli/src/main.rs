@@ -0,0 +1,68 @@
+use anyhow::{bail, Context, Result};
+use gf::{syndrome, Gen};
+use raid::{
+    bench::{self, BenchConfig},
+    RaidMode, RaidSim,
+};
+
+const USAGE: &str = "Usage: raid-fun <command>
+
+Commands:
+  gf <element>                       Shows an element of GF(2^8) in every form, with its inverse
+  syndrome <byte>...                 Works out P and Q of a row of data bytes
+  bench [raid5|raid6] [drives] [size]  Measures the throughput of a simulated array";
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let Some((command, args)) = args.split_first() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+    match command.as_str() {
+        "gf" => {
+            let [element] = args else {
+                bail!("gf takes one element, like 0x1d, g^8 or x^4+x^3+x^2+1");
+            };
+            let x = element.parse::<Gen>()?;
+            println!("{} = {:#04x} = {}", x, x, x.value());
+            if x != Gen::zero() {
+                let inverse = x.inverse();
+                println!("1 / x = {} = {:#04x}", inverse, inverse);
+            }
+        }
+        "syndrome" => {
+            let data = args
+                .iter()
+                .map(|byte| Ok(byte.parse::<Gen>()?.value()))
+                .collect::<Result<Vec<u8>>>()?;
+            println!("P = {:#04x}", syndrome(&data, 0));
+            println!("Q = {:#04x}", syndrome(&data, 1));
+        }
+        "bench" => {
+            let mode = match args.first().map(String::as_str) {
+                None | Some("raid6") => RaidMode::Raid6,
+                Some("raid5") => RaidMode::Raid5,
+                Some(other) => bail!("Unknown mode {:?}, expected raid5 or raid6", other),
+            };
+            let number = |i: usize, default: usize| match args.get(i) {
+                Some(arg) => arg
+                    .parse::<usize>()
+                    .with_context(|| format!("{:?} isn't a number", arg)),
+                None => Ok(default),
+            };
+            let builder = RaidSim::builder()
+                .mode(mode)
+                .drives(number(1, 8)?, number(2, 1 << 20)?);
+            println!("{}", bench::run(&builder, &BenchConfig::default())?);
+        }
+        other => bail!("Unknown command {:?}\n\n{}", other, USAGE),
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use alloc::vec::Vec;
+
+use super::{bail, poly_mul, table, Gen, Result, RAID6_POLYNOMIAL};
+
+/// A way of doing arithmetic in GF(2^8) with the raid6 generator g = {02}, on plain byte values
+pub trait Backend {
+    /// Returns a * b
+    fn mul(a: u8, b: u8) -> u8;
+
+    /// Returns g^n
+    fn exp(n: usize) -> u8 {
+        pow::<Self>(2, n % 255)
+    }
+
+    /// Returns 1 / a, which is zero for zero
+    fn inv(a: u8) -> u8 {
+        // Every nonzero a has a^255 = 1, so a^254 = 1 / a
+        pow::<Self>(a, 254)
+    }
+}
+
+/// Raises `x` to the `n` by squaring and multiplying
+fn pow<B: Backend + ?Sized>(mut x: u8, mut n: usize) -> u8 {
+    let mut result = 1;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = B::mul(result, x);
+        }
+        x = B::mul(x, x);
+        n >>= 1;
+    }
+    result
+}
+
+/// Looks everything up in the shared log and exponent tables, what `Gen` uses
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tables;
+
+impl Backend for Tables {
+    fn mul(a: u8, b: u8) -> u8 {
+        (Gen::from(a) * b).value()
+    }
+
+    fn exp(n: usize) -> u8 {
+        table().n_to_gn[n % 255]
+    }
+
+    fn inv(a: u8) -> u8 {
+        match a {
+            0 => 0,
+            a => Gen::from(a).inverse().value(),
+        }
+    }
+}
+
+/// Multiplies bit by bit with shifts and XORs, needing no tables at all.
+///
+/// Much slower than `Tables`, but small enough for memory constrained targets,
+/// and it shares no code with the tables so it can be used to check them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShiftXor;
+
+impl Backend for ShiftXor {
+    fn mul(a: u8, b: u8) -> u8 {
+        poly_mul(a, b, RAID6_POLYNOMIAL)
+    }
+}
+
+/// Arithmetic done the way it's written down, kept deliberately slow and simple to serve as the reference
+/// everything else is checked against.
+///
+/// Multiplying takes the full carry-less product of the two polynomials, all 15 bits of it, and only then reduces it
+/// by long division, highest bit first. Inverses are found by trying every byte. Neither step shares anything with
+/// `ShiftXor`, which reduces as it goes, or with the tables.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reference;
+
+impl Backend for Reference {
+    fn mul(a: u8, b: u8) -> u8 {
+        let mut product = 0u16;
+        for bit in 0..8 {
+            if b >> bit & 1 == 1 {
+                product ^= (a as u16) << bit;
+            }
+        }
+        for bit in (8..15).rev() {
+            if product >> bit & 1 == 1 {
+                product ^= RAID6_POLYNOMIAL << (bit - 8);
+            }
+        }
+        product as u8
+    }
+
+    fn inv(a: u8) -> u8 {
+        (1..=255).find(|b| Self::mul(a, *b) == 1).unwrap_or(0)
+    }
+}
+
+/// Checks backend `B` against `Reference` for every product and quotient of two bytes, every inverse,
+/// and two full cycles of powers of g, returning the first disagreement.
+/// A new backend should pass this before it's trusted with any data.
+pub fn verify<B: Backend>() -> Result<()> {
+    let inverses = (0..=255).map(Reference::inv).collect::<Vec<u8>>();
+    for a in 0..=255u8 {
+        for b in 0..=255u8 {
+            let (expected, actual) = (Reference::mul(a, b), B::mul(a, b));
+            if expected != actual {
+                bail!(
+                    "{:#04x} * {:#04x} is {:#04x}, not {:#04x}",
+                    a,
+                    b,
+                    expected,
+                    actual
+                );
+            }
+            if b != 0 {
+                let (expected, actual) =
+                    (Reference::mul(a, Reference::inv(b)), B::mul(a, B::inv(b)));
+                if expected != actual {
+                    bail!(
+                        "{:#04x} / {:#04x} is {:#04x}, not {:#04x}",
+                        a,
+                        b,
+                        expected,
+                        actual
+                    );
+                }
+            }
+        }
+        let (expected, actual) = (inverses[a as usize], B::inv(a));
+        if expected != actual {
+            bail!("1 / {:#04x} is {:#04x}, not {:#04x}", a, expected, actual);
+        }
+    }
+    for n in 0..510 {
+        let (expected, actual) = (Reference::exp(n), B::exp(n));
+        if expected != actual {
+            bail!("g^{} is {:#04x}, not {:#04x}", n, expected, actual);
+        }
+    }
+    Ok(())
+}
+
+/// Checks every `Gen` multiplication, division, and inverse over all pairs of bytes against `Reference`
+pub fn verify_gen() -> Result<()> {
+    let inverses = (0..=255).map(Reference::inv).collect::<Vec<u8>>();
+    for a in 0..=255u8 {
+        for b in 0..=255u8 {
+            let expected = Reference::mul(a, b);
+            if (Gen::from(a) * Gen::from(b)).value() != expected
+                || (Gen::from(a) * b).value() != expected
+            {
+                bail!("Gen {:#04x} * {:#04x} isn't {:#04x}", a, b, expected);
+            }
+            if b != 0 {
+                let expected = Reference::mul(a, inverses[b as usize]);
+                if (Gen::from(a) / Gen::from(b)).value() != expected
+                    || (a / Gen::from(b)).value() != expected
+                {
+                    bail!("Gen {:#04x} / {:#04x} isn't {:#04x}", a, b, expected);
+                }
+            }
+        }
+        if a != 0 && Gen::from(a).inverse().value() != inverses[a as usize] {
+            bail!("Gen 1 / {:#04x} isn't {:#04x}", a, inverses[a as usize]);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_xor_matches_tables() {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                assert_eq!(ShiftXor::mul(a, b), Tables::mul(a, b));
+            }
+            assert_eq!(ShiftXor::inv(a), Tables::inv(a));
+        }
+        for n in 0..600 {
+            assert_eq!(ShiftXor::exp(n), Tables::exp(n));
+        }
+    }
+
+    /// A backend that gets one product wrong, which the check has to find
+    struct OffByOne;
+
+    impl Backend for OffByOne {
+        fn mul(a: u8, b: u8) -> u8 {
+            match (a, b) {
+                (0x53, 0xca) => 0,
+                _ => Tables::mul(a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn every_backend_matches_the_reference() {
+        assert_eq!(
+            Reference::mul(0x53, 0xca),
+            poly_mul(0x53, 0xca, RAID6_POLYNOMIAL)
+        );
+        verify::<ShiftXor>().unwrap();
+        verify::<Tables>().unwrap();
+        verify_gen().unwrap();
+        // Caught first by a quotient that goes through the broken product
+        let err = verify::<OffByOne>().unwrap_err().to_string();
+        assert!(err.starts_with("0x53 / "), "{}", err);
+    }
+}
@@ -0,0 +1,119 @@
+/// Field operations done so far, see `op_counts()`. Bulk operations count one for every byte.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct OpCounts {
+    pub muls: u64,
+    /// Divisions and inverses
+    pub divs: u64,
+    /// Additions, which are XORs
+    pub xors: u64,
+}
+
+impl OpCounts {
+    /// Returns the operations done between `earlier` being taken and this being taken
+    pub fn since(self, earlier: OpCounts) -> OpCounts {
+        OpCounts {
+            muls: self.muls - earlier.muls,
+            divs: self.divs - earlier.divs,
+            xors: self.xors - earlier.xors,
+        }
+    }
+
+    /// Adds the operations in `other` to these
+    pub fn add(&mut self, other: OpCounts) {
+        self.muls += other.muls;
+        self.divs += other.divs;
+        self.xors += other.xors;
+    }
+
+    /// Whether no operations were done at all
+    pub fn is_empty(&self) -> bool {
+        *self == OpCounts::default()
+    }
+}
+
+#[cfg(feature = "op-counts")]
+std::thread_local! {
+    static COUNTS: core::cell::Cell<OpCounts> = core::cell::Cell::new(OpCounts::default());
+}
+
+/// Returns the field operations done on this thread so far, for comparing what different codes and layouts cost.
+///
+/// They're only counted with the `op-counts` feature, without it these are always zero and the arithmetic
+/// pays nothing for them. Work a `ParallelEngine` hands to other threads, or `GpuEngine` to the GPU, isn't counted.
+pub fn op_counts() -> OpCounts {
+    #[cfg(feature = "op-counts")]
+    {
+        COUNTS.with(|counts| counts.get())
+    }
+    #[cfg(not(feature = "op-counts"))]
+    {
+        OpCounts::default()
+    }
+}
+
+/// Adds operations to this thread's counts, doing nothing without the `op-counts` feature
+#[inline(always)]
+fn count(f: impl FnOnce(&mut OpCounts)) {
+    #[cfg(feature = "op-counts")]
+    COUNTS.with(|counts| {
+        let mut current = counts.get();
+        f(&mut current);
+        counts.set(current);
+    });
+    #[cfg(not(feature = "op-counts"))]
+    let _ = f;
+}
+
+#[inline(always)]
+pub(crate) fn count_muls(n: usize) {
+    count(|c| c.muls += n as u64);
+}
+
+#[inline(always)]
+pub(crate) fn count_divs(n: usize) {
+    count(|c| c.divs += n as u64);
+}
+
+#[inline(always)]
+pub(crate) fn count_xors(n: usize) {
+    count(|c| c.xors += n as u64);
+}
+
+#[cfg(all(test, feature = "op-counts"))]
+mod tests {
+    use super::*;
+    use crate::{syndrome, table, xor_into, FromPower, Gen};
+
+    #[test]
+    fn operations_are_counted() {
+        let before = op_counts();
+        let x = Gen::from_power(3u8) * Gen::from(7);
+        let _ = x / Gen::from(2) + Gen::from(9);
+        let _ = x.inverse();
+        assert_eq!(
+            op_counts().since(before),
+            OpCounts {
+                muls: 1,
+                divs: 2,
+                xors: 1
+            }
+        );
+
+        let before = op_counts();
+        let mut acc = [0u8; 100];
+        xor_into(&mut acc, &[1; 100]);
+        table().mul_add_slice(&mut acc, &[2; 100], 5);
+        assert_eq!(
+            op_counts().since(before),
+            OpCounts {
+                muls: 100,
+                divs: 0,
+                xors: 200
+            }
+        );
+
+        let before = op_counts();
+        syndrome(&[1, 2, 3, 4], 0);
+        assert_eq!(op_counts().since(before).xors, 4);
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::{vec, vec::Vec};
+
+use super::{bail, syndromes, FromPower, Gen, Poly, Result};
+
+/// Divides two field elements given as plain bytes
+fn div(a: u8, b: u8) -> u8 {
+    (a / Gen::from(b)).value()
+}
+
+/// Finds the shortest linear recurrence the syndromes follow with Berlekamp-Massey.
+/// Its connection polynomial is Λ(x) = (1 - X_1 x)(1 - X_2 x)..., with one X = g^i for every error at position i,
+/// returned along with the length of the recurrence, the number of errors.
+fn berlekamp_massey(syndromes: &[u8]) -> (Poly, usize) {
+    let (mut c, mut b) = (Poly::new(vec![1]), Poly::new(vec![1]));
+    let (mut len, mut shift, mut last) = (0, 1, 1);
+    for n in 0..syndromes.len() {
+        // How far the next syndrome is from what the recurrence so far predicts
+        let discrepancy = syndromes[n]
+            ^ (1..=len)
+                .map(|i| Gen::from(*c.coefficients().get(i).unwrap_or(&0)) * syndromes[n - i])
+                .sum::<u8>();
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let mut correction = vec![0; shift];
+        correction.extend_from_slice(b.scale(div(discrepancy, last)).coefficients());
+        let next = &c + &Poly::new(correction);
+        if 2 * len <= n {
+            len = n + 1 - len;
+            b = c;
+            last = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+        c = next;
+    }
+    (c, len)
+}
+
+/// Finds errors at unknown positions in a row of `len` data bytes.
+///
+/// `differences` are the first k syndromes of the row as read, XORed with the parities stored for it,
+/// so each one is the sum of e * g^(j*i) over every error e at position i. Up to k / 2 errors can be found,
+/// which are returned as (position, error) pairs to XOR back into the row. The parities are taken to be right,
+/// anything that doesn't add up to few enough errors in the data is an error.
+pub fn locate_errors(differences: &[u8], len: usize) -> Result<Vec<(usize, u8)>> {
+    if len > 255 {
+        bail!(
+            "Rows of {} bytes are too long to locate errors in, at most 255",
+            len
+        );
+    }
+    if differences.iter().all(|s| *s == 0) {
+        return Ok(vec![]);
+    }
+    let (locator, errors) = berlekamp_massey(differences);
+    if errors > differences.len() / 2 {
+        bail!(
+            "Found {} errors, more than {} syndromes can locate",
+            errors,
+            differences.len()
+        );
+    }
+
+    // Λ(X^-1) is zero for the locator X of every error, so try every position
+    let positions = (0..len)
+        .filter(|i| locator.eval(Gen::from_power(-(*i as i32)).value()) == 0)
+        .collect::<Vec<usize>>();
+    // A locator of lower degree than the recurrence has fewer roots than it needs
+    if positions.len() != errors || locator.degree() != Some(errors) {
+        bail!(
+            "Syndromes don't point at {} positions in the row, unable to correct",
+            errors
+        );
+    }
+
+    // Forney's formula: e = X Ω(X^-1) / Λ'(X^-1) where Ω(x) = S(x)Λ(x) mod x^k
+    let omega = &Poly::new(differences.to_vec()) * &locator;
+    let omega = Poly::new(
+        omega
+            .coefficients()
+            .iter()
+            .take(differences.len())
+            .copied()
+            .collect(),
+    );
+    // The formal derivative only keeps odd powers in characteristic 2
+    let derivative = Poly::new(
+        locator
+            .coefficients()
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, c)| if i % 2 == 1 { *c } else { 0 })
+            .collect(),
+    );
+    Ok(positions
+        .into_iter()
+        .map(|i| {
+            let inverse = Gen::from_power(-(i as i32)).value();
+            let x = Gen::from_power(i);
+            let error = (x * div(omega.eval(inverse), derivative.eval(inverse))).value();
+            (i, error)
+        })
+        .collect())
+}
+
+/// Corrects errors at unknown positions in a row of data bytes given its stored parities, the first k syndromes.
+/// Returns the positions that were corrected.
+pub fn correct_errors(data: &mut [u8], parities: &[u8]) -> Result<Vec<usize>> {
+    let differences = syndromes(data, parities.len())
+        .into_iter()
+        .zip(parities)
+        .map(|(s, p)| s ^ p)
+        .collect::<Vec<u8>>();
+    let errors = locate_errors(&differences, data.len())?;
+    for (i, error) in &errors {
+        data[*i] ^= error;
+    }
+    Ok(errors.into_iter().map(|(i, _)| i).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: [u8; 12] = [7, 0, 0xff, 0x42, 1, 2, 3, 0x80, 0x99, 0, 0x10, 0xab];
+
+    #[test]
+    fn corrects_up_to_half_the_parities() {
+        let parities = syndromes(&DATA, 6);
+        for errors in [
+            vec![(3, 0x01)],
+            vec![(0, 0xff), (11, 0x5a)],
+            vec![(1, 0x33), (5, 0x01), (9, 0xc0)],
+        ] {
+            let mut data = DATA;
+            for (i, e) in &errors {
+                data[*i] ^= e;
+            }
+            let mut corrected = correct_errors(&mut data, &parities).unwrap();
+            corrected.sort_unstable();
+            let mut expected = errors.iter().map(|(i, _)| *i).collect::<Vec<usize>>();
+            expected.sort_unstable();
+            assert_eq!(corrected, expected);
+            assert_eq!(data, DATA);
+        }
+        assert!(correct_errors(&mut DATA.clone(), &parities)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn too_many_errors_are_refused() {
+        // P and Q can locate one error but not two
+        let parities = syndromes(&DATA, 2);
+        let mut data = DATA;
+        data[4] ^= 0x21;
+        assert_eq!(correct_errors(&mut data, &parities).unwrap(), vec![4]);
+        assert_eq!(data, DATA);
+        data[2] ^= 0x01;
+        data[6] ^= 0x01;
+        assert!(correct_errors(&mut data, &parities).is_err());
+
+        // A corrupted parity doesn't look like any data error
+        let mut parities = parities;
+        parities[0] ^= 1;
+        assert!(correct_errors(&mut DATA.clone(), &parities).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use super::{bail, rng::SplitMix, FromPower, Gen, GfSlice, GfVector, Result};
+
+/// Computes parity and recovers lost chunks a whole stripe at a time, the operations accelerated libraries like
+/// ISA-L offer. `GfEngine` does it with the crate's own arithmetic. Any other engine can be checked against it
+/// with `check_engine()` and put in an array with `RaidSimBuilder::parity_engine()`.
+pub trait ParityEngine: fmt::Debug {
+    /// Writes P and Q of the `data` chunks into `p` and `q`, every buffer the same length
+    fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]);
+
+    /// Works out up to two lost chunks of a stripe in place.
+    /// `chunks` holds the data chunks followed by P and Q, and `lost` the positions of those to recover,
+    /// whatever they hold now is ignored.
+    fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()>;
+}
+
+/// The built-in engine, working on `GfVector`s with the shared tables
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GfEngine;
+
+impl GfEngine {
+    /// Returns syndrome `j` of the data chunks, leaving out the chunks at `skip`
+    fn syndrome(chunks: &[&mut [u8]], data: usize, j: usize, skip: &[usize]) -> GfVector {
+        let mut sum = GfVector::zeroed(chunks[0].len());
+        for (i, chunk) in chunks[..data].iter().enumerate() {
+            if !skip.contains(&i) {
+                sum.mul_add(Gen::from_power(i * j), GfSlice(chunk));
+            }
+        }
+        sum
+    }
+}
+
+impl ParityEngine for GfEngine {
+    fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+        for (j, parity) in [p, q].iter_mut().enumerate() {
+            let mut sum = GfVector::zeroed(parity.len());
+            for (i, chunk) in data.iter().enumerate() {
+                sum.mul_add(Gen::from_power(i * j), GfSlice(chunk));
+            }
+            parity.copy_from_slice(&sum);
+        }
+    }
+
+    fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()> {
+        let Some(data) = chunks.len().checked_sub(2) else {
+            bail!("A stripe needs P and Q, got {} chunks", chunks.len());
+        };
+        let (p, q) = (data, data + 1);
+        let mut lost = lost.to_vec();
+        lost.sort_unstable();
+        lost.dedup();
+        if lost.len() > 2 || lost.iter().any(|i| *i >= chunks.len()) {
+            bail!("Unable to recover chunks {:?} of {}", lost, chunks.len());
+        }
+        let lost_data = lost
+            .iter()
+            .copied()
+            .filter(|i| *i < data)
+            .collect::<Vec<usize>>();
+        match *lost_data.as_slice() {
+            [] => {}
+            // P is there to recover it from, or only Q is missing along with it
+            [x] if !lost.contains(&p) => {
+                let mut d = Self::syndrome(chunks, data, 0, &[x]);
+                d ^= GfSlice(chunks[p]);
+                chunks[x].copy_from_slice(&d);
+            }
+            // Q_x = Q ^ (Q of the others) = g^x * D_x
+            [x] => {
+                let mut qx = Self::syndrome(chunks, data, 1, &[x]);
+                qx ^= GfSlice(chunks[q]);
+                chunks[x].copy_from_slice(&(qx * Gen::from_power(x).inverse()));
+            }
+            // D_x ^ D_y = P_xy and g^x * D_x ^ g^y * D_y = Q_xy, so D_x = (Q_xy ^ g^y * P_xy) / (g^x ^ g^y)
+            [x, y] => {
+                let mut pxy = Self::syndrome(chunks, data, 0, &[x, y]);
+                pxy ^= GfSlice(chunks[p]);
+                let mut qxy = Self::syndrome(chunks, data, 1, &[x, y]);
+                qxy ^= GfSlice(chunks[q]);
+                let (gx, gy) = (Gen::from_power(x), Gen::from_power(y));
+                qxy.mul_add(gy, pxy.as_slice());
+                let dx = qxy * Gen::from(gx ^ gy).inverse();
+                chunks[x].copy_from_slice(&dx);
+                chunks[y].copy_from_slice(&(pxy ^ dx.as_slice()));
+            }
+            _ => unreachable!(),
+        }
+        // With the data whole again, lost parity is just computed over
+        for (j, member) in [p, q].iter().copied().enumerate() {
+            if lost.contains(&member) {
+                let sum = Self::syndrome(chunks, data, j, &[]);
+                chunks[member].copy_from_slice(&sum);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks `engine` against `GfEngine` on random stripes of `data` chunks of `len` bytes each,
+/// generating parity and then recovering every single chunk and pair of chunks that could be lost
+pub fn check_engine(engine: &dyn ParityEngine, data: usize, len: usize) -> Result<()> {
+    let mut rng = SplitMix::new();
+    let chunks = (0..data).map(|_| rng.bytes(len)).collect::<Vec<Vec<u8>>>();
+    let refs = chunks.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>();
+    let (mut p, mut q) = (vec![0; len], vec![0; len]);
+    GfEngine.gen_syndrome(&refs, &mut p, &mut q);
+    let (mut engine_p, mut engine_q) = (vec![0; len], vec![0; len]);
+    engine.gen_syndrome(&refs, &mut engine_p, &mut engine_q);
+    for (j, (expected, actual)) in [(&p, &engine_p), (&q, &engine_q)].iter().enumerate() {
+        if let Some(row) = expected.iter().zip(actual.iter()).position(|(e, a)| e != a) {
+            bail!("Syndrome {} differs at row {}", j, row);
+        }
+    }
+
+    let stripe = chunks
+        .into_iter()
+        .chain(vec![p, q])
+        .collect::<Vec<Vec<u8>>>();
+    let members = stripe.len();
+    for x in 0..members {
+        for y in x..members {
+            let lost = if x == y { vec![x] } else { vec![x, y] };
+            let mut damaged = stripe.clone();
+            for i in &lost {
+                damaged[*i].fill(0);
+            }
+            let mut refs = damaged
+                .iter_mut()
+                .map(Vec::as_mut_slice)
+                .collect::<Vec<&mut [u8]>>();
+            engine.recover(&mut refs, &lost)?;
+            if damaged != stripe {
+                bail!("Recovering chunks {:?} gave the wrong contents", lost);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syndrome;
+
+    /// An engine that gets Q wrong, as a broken library might
+    #[derive(Debug)]
+    struct NoQ;
+
+    impl ParityEngine for NoQ {
+        fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+            GfEngine.gen_syndrome(data, p, q);
+            q.fill(0);
+        }
+
+        fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()> {
+            GfEngine.recover(chunks, lost)
+        }
+    }
+
+    #[test]
+    fn built_in_engine_checks_out() {
+        let data = [[0x12, 0x34], [0x00, 0xff], [0x7a, 0x01]];
+        let refs = data.iter().map(|c| c.as_slice()).collect::<Vec<&[u8]>>();
+        let (mut p, mut q) = ([0; 2], [0; 2]);
+        GfEngine.gen_syndrome(&refs, &mut p, &mut q);
+        for row in 0..2 {
+            let bytes = data.map(|c| c[row]);
+            assert_eq!((p[row], q[row]), (syndrome(&bytes, 0), syndrome(&bytes, 1)));
+        }
+
+        check_engine(&GfEngine, 6, 64).unwrap();
+        assert!(check_engine(&NoQ, 6, 64)
+            .unwrap_err()
+            .to_string()
+            .contains("Syndrome 1"));
+        let mut chunks = [[0u8; 2]; 5];
+        let mut refs = chunks
+            .iter_mut()
+            .map(|c| c.as_mut_slice())
+            .collect::<Vec<_>>();
+        assert!(GfEngine.recover(&mut refs, &[0, 1, 2]).is_err());
+    }
+}
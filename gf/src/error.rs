@@ -0,0 +1,81 @@
+use alloc::{boxed::Box, string::String, string::ToString};
+use core::fmt;
+
+/// What went wrong in the field math, a message and whatever caused it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Error {
+    message: String,
+    source: Option<Box<Error>>,
+}
+
+/// The result of anything in the crate that can fail
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+impl Error {
+    /// Returns an error saying `message`
+    pub fn msg(message: impl fmt::Display) -> Self {
+        Self {
+            message: message.to_string(),
+            source: None,
+        }
+    }
+}
+
+/// Writes the message, followed by the chain of causes with `{:#}`
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if f.alternate() {
+            if let Some(source) = &self.source {
+                write!(f, ": {:#}", source)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|e| e as &(dyn core::error::Error + 'static))
+    }
+}
+
+/// Explains an error or a missing value with a message of its own, keeping the error as the cause
+pub trait Context<T> {
+    fn context(self, message: impl fmt::Display) -> Result<T>;
+
+    fn with_context<M: fmt::Display>(self, message: impl FnOnce() -> M) -> Result<T>;
+}
+
+impl<T, E: fmt::Display> Context<T> for core::result::Result<T, E> {
+    fn context(self, message: impl fmt::Display) -> Result<T> {
+        self.with_context(|| message)
+    }
+
+    fn with_context<M: fmt::Display>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|e| Error {
+            message: message().to_string(),
+            source: Some(Box::new(Error::msg(e))),
+        })
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, message: impl fmt::Display) -> Result<T> {
+        self.ok_or_else(|| Error::msg(message))
+    }
+
+    fn with_context<M: fmt::Display>(self, message: impl FnOnce() -> M) -> Result<T> {
+        self.ok_or_else(|| Error::msg(message()))
+    }
+}
+
+/// Returns early with an error formatted like `format!`
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::Error::msg(::alloc::format!($($arg)*)))
+    };
+}
+pub(crate) use bail;
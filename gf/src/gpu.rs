@@ -0,0 +1,259 @@
+use std::{convert::TryInto, fmt, sync::mpsc};
+
+use wgpu::util::DeviceExt;
+
+use super::{Context, Error, GfEngine, ParityEngine, Result};
+
+/// Works out P and Q for 4 rows at once, one packed word of every data chunk per invocation.
+/// Q is built by Horner's rule from the last chunk down, `Q = g * Q ^ D_i`, and multiplying a packed
+/// word by g = {02} shifts each byte left and reduces the ones that carried out with 0x1d.
+const SHADER: &str = r#"
+struct Params {
+    words: u32,
+    chunks: u32,
+    stride: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> data: array<u32>;
+@group(0) @binding(2) var<storage, read_write> parity: array<u32>;
+
+fn times_g(x: u32) -> u32 {
+    return ((x & 0x7f7f7f7fu) << 1u) ^ (((x >> 7u) & 0x01010101u) * 0x1du);
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let w = id.x;
+    if (w >= params.words) {
+        return;
+    }
+    var p = 0u;
+    var q = 0u;
+    for (var i = params.chunks; i > 0u; i = i - 1u) {
+        let d = data[(i - 1u) * params.stride + w];
+        p = p ^ d;
+        q = times_g(q) ^ d;
+    }
+    parity[w] = p;
+    parity[params.words + w] = q;
+}
+"#;
+
+/// Invocations in a workgroup, as the shader declares
+const WORKGROUP_SIZE: usize = 64;
+
+/// An experimental `ParityEngine` generating syndromes with a compute shader through wgpu.
+///
+/// The data chunks are uploaded packed four rows to a word, P and Q are computed on the GPU, and read back.
+/// Stripes too big for one storage buffer or dispatch go through in batches of rows.
+/// Moving every byte across the bus makes this slower than the CPU for anything but very large stripes,
+/// it's here to see where that line is. `check_engine()` holds it to `GfEngine`, which also does its recovery.
+pub struct GpuEngine {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    adapter: String,
+}
+
+impl GpuEngine {
+    /// Returns an engine on the default adapter, failing if there isn't one
+    pub fn new() -> Result<Self> {
+        pollster::block_on(Self::connect())
+    }
+
+    async fn connect() -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .context("No GPU adapter to compute parity with")?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("raid parity"),
+                required_limits: adapter.limits(),
+                ..Default::default()
+            })
+            .await
+            .context("Unable to open the GPU")?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("raid parity"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("raid parity"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            adapter: adapter.get_info().name,
+        })
+    }
+
+    /// Returns how many packed words of each chunk fit in one batch of `chunks` chunks
+    fn batch_words(&self, chunks: usize) -> usize {
+        let limits = self.device.limits();
+        let binding = limits
+            .max_storage_buffer_binding_size
+            .min(limits.max_buffer_size.try_into().unwrap_or(u32::MAX))
+            as usize;
+        let by_size = binding / 4 / chunks.max(2);
+        let by_dispatch = limits.max_compute_workgroups_per_dimension as usize * WORKGROUP_SIZE;
+        by_size.min(by_dispatch).max(1)
+    }
+
+    /// Computes P and Q of `words` packed words of every data chunk, starting at row `start`
+    fn run_batch(&self, data: &[&[u8]], start: usize, words: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+        let rows = words * 4;
+        let mut packed = vec![0u8; data.len() * rows];
+        for (chunk, dst) in data.iter().zip(packed.chunks_exact_mut(rows)) {
+            let end = (start + rows).min(chunk.len());
+            dst[..end - start].copy_from_slice(&chunk[start..end]);
+        }
+        let params = [words as u32, data.len() as u32, words as u32]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .chain([0; 4])
+            .collect::<Vec<u8>>();
+        let parity_size = (2 * rows) as u64;
+
+        let params = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: &params,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let input = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("data"),
+                contents: &packed,
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("parity"),
+            size: parity_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: parity_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(words.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, parity_size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .context("Lost the GPU waiting for parity")?;
+        receiver
+            .recv()
+            .map_err(|_| Error::msg("Parity readback was dropped"))?
+            .context("Unable to read parity back from the GPU")?;
+        let bytes = slice.get_mapped_range().to_vec();
+        readback.unmap();
+        let (p, q) = bytes.split_at(rows);
+        Ok((p.to_vec(), q.to_vec()))
+    }
+
+    /// Writes P and Q of the `data` chunks into `p` and `q`, a batch of rows at a time
+    pub fn try_gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) -> Result<()> {
+        if data.is_empty() {
+            p.fill(0);
+            q.fill(0);
+            return Ok(());
+        }
+        let rows = self.batch_words(data.len()) * 4;
+        for start in (0..p.len()).step_by(rows) {
+            let end = (start + rows).min(p.len());
+            let (batch_p, batch_q) = self.run_batch(data, start, (end - start).div_ceil(4))?;
+            p[start..end].copy_from_slice(&batch_p[..end - start]);
+            q[start..end].copy_from_slice(&batch_q[..end - start]);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for GpuEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GpuEngine")
+            .field("adapter", &self.adapter)
+            .finish()
+    }
+}
+
+/// Parity the GPU can't compute, because the device was lost or the readback failed, is computed on the CPU instead
+impl ParityEngine for GpuEngine {
+    fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+        if self.try_gen_syndrome(data, p, q).is_err() {
+            GfEngine.gen_syndrome(data, p, q);
+        }
+    }
+
+    fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()> {
+        GfEngine.recover(chunks, lost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check_engine;
+
+    #[test]
+    fn gpu_engine_agrees_with_the_cpu() {
+        // Not every machine has an adapter, even a software one
+        let Ok(engine) = GpuEngine::new() else {
+            return;
+        };
+        // Lengths that don't fill a word, up to as many chunks as an array can have
+        for (data, len) in [(1, 1), (3, 6), (62, 4099), (255, 64)] {
+            check_engine(&engine, data, len).unwrap();
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use core::fmt;
+
+use static_init::dynamic;
+
+use super::{
+    counts::{count_muls, count_xors},
+    xor::xor_words,
+};
+
+#[dynamic]
+static BEST: Kernel = Kernel::detect();
+
+/// Returns the kernel bulk arithmetic runs on, the best this CPU supports, picked the first time it's asked for
+pub fn kernel() -> Kernel {
+    *BEST
+}
+
+/// An implementation of the bulk primitives everything else is built from: multiplying a buffer by a constant
+/// with the split nibble tables of `MTable`, with or without adding it into another, and plain XOR.
+///
+/// The SIMD kernels do a whole register of nibble lookups with one byte shuffle, and every kernel gives the same
+/// bytes as `Scalar`. Which ones a CPU has is only known at runtime, so one binary carries them all and
+/// `kernel()` picks. A kernel that isn't `available()` must not be used, its methods panic.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Kernel {
+    /// Byte at a time table lookups and word at a time XOR, for any CPU
+    Scalar,
+    /// 16 bytes at a time with x86 `pshufb`
+    Ssse3,
+    /// 32 bytes at a time with x86 `vpshufb`
+    Avx2,
+    /// 16 bytes at a time with aarch64 `tbl`
+    Neon,
+}
+
+impl Kernel {
+    /// Every kernel, slowest first
+    pub const ALL: [Kernel; 4] = [Kernel::Scalar, Kernel::Ssse3, Kernel::Avx2, Kernel::Neon];
+
+    /// Returns the fastest kernel this CPU can run
+    pub fn detect() -> Self {
+        Self::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|k| k.available())
+            .unwrap_or(Kernel::Scalar)
+    }
+
+    /// Whether this CPU can run this kernel
+    pub fn available(self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+            Kernel::Ssse3 => std::is_x86_feature_detected!("ssse3"),
+            #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+            Kernel::Avx2 => std::is_x86_feature_detected!("avx2"),
+            #[cfg(all(feature = "std", target_arch = "aarch64"))]
+            Kernel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            // Without std there's no asking the CPU, only what the build targets can be relied on
+            #[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+            Kernel::Ssse3 => cfg!(target_feature = "ssse3"),
+            #[cfg(all(not(feature = "std"), any(target_arch = "x86", target_arch = "x86_64")))]
+            Kernel::Avx2 => cfg!(target_feature = "avx2"),
+            #[cfg(all(not(feature = "std"), target_arch = "aarch64"))]
+            Kernel::Neon => cfg!(target_feature = "neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Adds `x` multiplied through `tables` into `acc`, panicking if the two differ in length
+    pub fn mul_add(self, acc: &mut [u8], x: &[u8], tables: &[[u8; 16]; 2]) {
+        assert_eq!(acc.len(), x.len(), "Buffers differ in length");
+        count_muls(acc.len());
+        count_xors(acc.len());
+        // Safety: both buffers are `acc.len()` long, and the kernel is checked to be available
+        unsafe { self.mul_raw::<true>(acc.as_mut_ptr(), x.as_ptr(), acc.len(), tables) }
+    }
+
+    /// Multiplies every byte of `buf` through `tables` in place
+    pub fn mul(self, buf: &mut [u8], tables: &[[u8; 16]; 2]) {
+        count_muls(buf.len());
+        let dst = buf.as_mut_ptr();
+        // Safety: as for `mul_add`, each block is read in full before it's written back over
+        unsafe { self.mul_raw::<false>(dst, dst, buf.len(), tables) }
+    }
+
+    /// XORs `x` into `acc`, panicking if the two differ in length
+    pub fn xor(self, acc: &mut [u8], x: &[u8]) {
+        assert_eq!(acc.len(), x.len(), "Buffers differ in length");
+        count_xors(acc.len());
+        match self {
+            Kernel::Scalar => xor_words(acc, x),
+            // Safety: both buffers are `acc.len()` long, and the kernel is checked to be available
+            _ => unsafe { self.xor_raw(acc.as_mut_ptr(), x.as_ptr(), acc.len()) },
+        }
+    }
+
+    /// Writes `src * table` to `dst`, or XORs it in when `ADD`, for `len` bytes starting at each.
+    /// `dst` may be `src`, but the two can't otherwise overlap.
+    unsafe fn mul_raw<const ADD: bool>(
+        self,
+        dst: *mut u8,
+        src: *const u8,
+        len: usize,
+        tables: &[[u8; 16]; 2],
+    ) {
+        assert!(self.available(), "Kernel {} isn't available", self);
+        let done = match self {
+            Kernel::Scalar => 0,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Ssse3 => x86::mul_ssse3::<ADD>(dst, src, len, tables),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Avx2 => x86::mul_avx2::<ADD>(dst, src, len, tables),
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => arm::mul_neon::<ADD>(dst, src, len, tables),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        };
+        // Whatever doesn't fill a register is left for the scalar loop
+        let [low, high] = tables;
+        for i in done..len {
+            let x = *src.add(i);
+            let product = low[(x & 0x0f) as usize] ^ high[(x >> 4) as usize];
+            *dst.add(i) = if ADD { *dst.add(i) ^ product } else { product };
+        }
+    }
+
+    /// XORs `len` bytes from `src` into `dst`, which don't overlap
+    unsafe fn xor_raw(self, dst: *mut u8, src: *const u8, len: usize) {
+        assert!(self.available(), "Kernel {} isn't available", self);
+        let done = match self {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Ssse3 => x86::xor_sse2(dst, src, len),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Kernel::Avx2 => x86::xor_avx2(dst, src, len),
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => arm::xor_neon(dst, src, len),
+            _ => 0,
+        };
+        xor_words(
+            core::slice::from_raw_parts_mut(dst.add(done), len - done),
+            core::slice::from_raw_parts(src.add(done), len - done),
+        );
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Kernel::Scalar => "scalar",
+            Kernel::Ssse3 => "ssse3",
+            Kernel::Avx2 => "avx2",
+            Kernel::Neon => "neon",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Each kernel works through whole registers and returns how many bytes it did, the rest are left to the caller
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn mul_ssse3<const ADD: bool>(
+        dst: *mut u8,
+        src: *const u8,
+        len: usize,
+        tables: &[[u8; 16]; 2],
+    ) -> usize {
+        let low = _mm_loadu_si128(tables[0].as_ptr().cast());
+        let high = _mm_loadu_si128(tables[1].as_ptr().cast());
+        let mask = _mm_set1_epi8(0x0f);
+        let done = len / 16 * 16;
+        for i in (0..done).step_by(16) {
+            let x = _mm_loadu_si128(src.add(i).cast());
+            let mut product = _mm_xor_si128(
+                _mm_shuffle_epi8(low, _mm_and_si128(x, mask)),
+                _mm_shuffle_epi8(high, _mm_and_si128(_mm_srli_epi64(x, 4), mask)),
+            );
+            if ADD {
+                product = _mm_xor_si128(product, _mm_loadu_si128(dst.add(i).cast()));
+            }
+            _mm_storeu_si128(dst.add(i).cast(), product);
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn mul_avx2<const ADD: bool>(
+        dst: *mut u8,
+        src: *const u8,
+        len: usize,
+        tables: &[[u8; 16]; 2],
+    ) -> usize {
+        // vpshufb looks up within each 128 bit lane, so both lanes get the whole table
+        let low = _mm256_broadcastsi128_si256(_mm_loadu_si128(tables[0].as_ptr().cast()));
+        let high = _mm256_broadcastsi128_si256(_mm_loadu_si128(tables[1].as_ptr().cast()));
+        let mask = _mm256_set1_epi8(0x0f);
+        let done = len / 32 * 32;
+        for i in (0..done).step_by(32) {
+            let x = _mm256_loadu_si256(src.add(i).cast());
+            let mut product = _mm256_xor_si256(
+                _mm256_shuffle_epi8(low, _mm256_and_si256(x, mask)),
+                _mm256_shuffle_epi8(high, _mm256_and_si256(_mm256_srli_epi64(x, 4), mask)),
+            );
+            if ADD {
+                product = _mm256_xor_si256(product, _mm256_loadu_si256(dst.add(i).cast()));
+            }
+            _mm256_storeu_si256(dst.add(i).cast(), product);
+        }
+        done
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn xor_sse2(dst: *mut u8, src: *const u8, len: usize) -> usize {
+        let done = len / 16 * 16;
+        for i in (0..done).step_by(16) {
+            let sum = _mm_xor_si128(
+                _mm_loadu_si128(dst.add(i).cast()),
+                _mm_loadu_si128(src.add(i).cast()),
+            );
+            _mm_storeu_si128(dst.add(i).cast(), sum);
+        }
+        done
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn xor_avx2(dst: *mut u8, src: *const u8, len: usize) -> usize {
+        let done = len / 32 * 32;
+        for i in (0..done).step_by(32) {
+            let sum = _mm256_xor_si256(
+                _mm256_loadu_si256(dst.add(i).cast()),
+                _mm256_loadu_si256(src.add(i).cast()),
+            );
+            _mm256_storeu_si256(dst.add(i).cast(), sum);
+        }
+        done
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use core::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn mul_neon<const ADD: bool>(
+        dst: *mut u8,
+        src: *const u8,
+        len: usize,
+        tables: &[[u8; 16]; 2],
+    ) -> usize {
+        let low = vld1q_u8(tables[0].as_ptr());
+        let high = vld1q_u8(tables[1].as_ptr());
+        let mask = vdupq_n_u8(0x0f);
+        let done = len / 16 * 16;
+        for i in (0..done).step_by(16) {
+            let x = vld1q_u8(src.add(i));
+            let mut product = veorq_u8(
+                vqtbl1q_u8(low, vandq_u8(x, mask)),
+                vqtbl1q_u8(high, vshrq_n_u8::<4>(x)),
+            );
+            if ADD {
+                product = veorq_u8(product, vld1q_u8(dst.add(i)));
+            }
+            vst1q_u8(dst.add(i), product);
+        }
+        done
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn xor_neon(dst: *mut u8, src: *const u8, len: usize) -> usize {
+        let done = len / 16 * 16;
+        for i in (0..done).step_by(16) {
+            vst1q_u8(
+                dst.add(i),
+                veorq_u8(vld1q_u8(dst.add(i)), vld1q_u8(src.add(i))),
+            );
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table;
+
+    #[test]
+    fn every_available_kernel_matches_scalar() {
+        assert!(kernel().available());
+        let mut rng = rand::rng();
+        for len in [0, 1, 15, 16, 17, 31, 32, 33, 100, 4096] {
+            let x = (0..len)
+                .map(|_| rand::Rng::random(&mut rng))
+                .collect::<Vec<u8>>();
+            let acc = (0..len)
+                .map(|_| rand::Rng::random(&mut rng))
+                .collect::<Vec<u8>>();
+            for n in [0, 1, 2, 77, 254] {
+                let tables = &table().nibbles[n];
+                let mut expected_sum = acc.clone();
+                Kernel::Scalar.mul_add(&mut expected_sum, &x, tables);
+                let mut expected_product = x.clone();
+                Kernel::Scalar.mul(&mut expected_product, tables);
+                for (i, byte) in x.iter().enumerate() {
+                    assert_eq!(expected_product[i], table().apply_pow(*byte, n));
+                }
+                for k in Kernel::ALL.iter().filter(|k| k.available()) {
+                    let mut sum = acc.clone();
+                    k.mul_add(&mut sum, &x, tables);
+                    assert_eq!(sum, expected_sum, "{} multiply-add by g^{}", k, n);
+                    let mut product = x.clone();
+                    k.mul(&mut product, tables);
+                    assert_eq!(product, expected_product, "{} multiply by g^{}", k, n);
+                }
+            }
+            for k in Kernel::ALL.iter().filter(|k| k.available()) {
+                let mut sum = acc.clone();
+                k.xor(&mut sum, &x);
+                let expected = acc.iter().zip(&x).map(|(a, b)| a ^ b).collect::<Vec<u8>>();
+                assert_eq!(sum, expected, "{} XOR of {} bytes", k, len);
+            }
+        }
+    }
+}
@@ -0,0 +1,575 @@
+//! Arithmetic in GF(2^8) with the raid6 generator {02}, and the syndromes and recovery built on it.
+//!
+//! Everything the simulator needs to compute and check parity, kept free of its dependencies
+//! for anyone who only wants the erasure coding. Without the default `std` feature it needs only `alloc`,
+//! leaving out the threaded engine and runtime CPU feature detection.
+
+// Tests always have std, so they can keep using its prelude
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod backend;
+mod counts;
+mod decode;
+mod engine;
+mod error;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod kernel;
+#[cfg(feature = "std")]
+mod parallel;
+mod poly;
+mod primitive;
+#[cfg(feature = "reed-solomon")]
+mod reed_solomon;
+mod rng;
+mod syndrome;
+mod table;
+mod vector;
+mod xor;
+
+pub use backend::{verify, verify_gen, Backend, Reference, ShiftXor, Tables};
+pub use counts::{op_counts, OpCounts};
+pub use decode::{correct_errors, locate_errors};
+pub use engine::{check_engine, GfEngine, ParityEngine};
+pub(crate) use error::bail;
+pub use error::{Context, Error, Result};
+#[cfg(feature = "gpu")]
+pub use gpu::GpuEngine;
+pub use kernel::{kernel, Kernel};
+#[cfg(feature = "std")]
+pub use parallel::ParallelEngine;
+pub use poly::Poly;
+pub use primitive::{discrete_log, is_generator, poly_mul, primitive_elements, RAID6_POLYNOMIAL};
+#[cfg(feature = "reed-solomon")]
+pub use reed_solomon::{cross_check, ReedSolomonEngine};
+pub use syndrome::{syndrome, syndrome_with, syndromes, weighted_sum};
+pub use table::MTable;
+pub use vector::{GfSlice, GfVector};
+pub use xor::{xor_fold, xor_into};
+
+use alloc::{format, string::ToString, vec::Vec};
+use core::{
+    fmt,
+    iter::{Product, Sum},
+    ops::{Add, BitXor, BitXorAssign, Div, Mul},
+    str::FromStr,
+};
+
+use static_init::dynamic;
+
+use counts::{count_divs, count_muls, count_xors};
+
+#[dynamic]
+static TABLE: MTable = MTable::new();
+
+/// Returns the tables for GF(2^8) with the raid6 generator {02}, built once and shared by everything that uses `Gen`
+pub fn table() -> &'static MTable {
+    &TABLE
+}
+
+/// Magic value representing 0 in the field.
+/// Because there exists no n where g^n = 0 and since g^255 is redundant of g^0, we have a special case representing 0.
+/// This exhausts all possible values a u8 can hold.
+const ZERO: u8 = 255;
+
+pub trait FromPower<T> {
+    fn from_power(n: T) -> Self;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Gen {
+    n: u8,
+}
+
+impl From<u8> for Gen {
+    fn from(value: u8) -> Self {
+        if value == 0 {
+            Self::zero()
+        } else {
+            Self {
+                n: TABLE.gn_to_n[value as usize],
+            }
+        }
+    }
+}
+
+impl Mul for Gen {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        count_muls(1);
+        if cfg!(feature = "branchless") {
+            self.mul_branchless(rhs)
+        } else {
+            self.mul_branching(rhs)
+        }
+    }
+}
+
+impl Mul<u8> for Gen {
+    type Output = Self;
+
+    fn mul(self, rhs: u8) -> Self::Output {
+        self * Gen::from(rhs)
+    }
+}
+impl Mul<Gen> for u8 {
+    type Output = Gen;
+
+    fn mul(self, rhs: Gen) -> Self::Output {
+        Gen::from(self) * rhs
+    }
+}
+
+impl Div<Gen> for Gen {
+    type Output = Gen;
+
+    fn div(self, rhs: Gen) -> Self::Output {
+        debug_assert!(rhs.n != ZERO, "Division by zero not allowed.");
+        count_divs(1);
+        Self::Output {
+            n: TABLE.div[self.n as usize][rhs.n as usize],
+        }
+    }
+}
+impl Div<Gen> for u8 {
+    type Output = Gen;
+
+    fn div(self, rhs: Gen) -> Self::Output {
+        Gen::from(self) / rhs
+    }
+}
+
+impl Add<u8> for Gen {
+    type Output = Self;
+
+    // Addition in GF(2^8) is XOR
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: u8) -> Self::Output {
+        count_xors(1);
+        Self::Output {
+            n: TABLE.gn_to_n[(self.value() ^ rhs) as usize],
+        }
+    }
+}
+impl Add<Gen> for u8 {
+    type Output = Gen;
+
+    fn add(self, rhs: Gen) -> Self::Output {
+        rhs + self
+    }
+}
+impl Add<Gen> for Gen {
+    type Output = Gen;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: Gen) -> Self::Output {
+        count_xors(1);
+        Self::Output {
+            n: TABLE.gn_to_n[(self.value() ^ rhs.value()) as usize],
+        }
+    }
+}
+
+/// Adds up field elements, XORing their values, so syndromes can be written as `.map(..).sum()`
+impl Sum for Gen {
+    fn sum<I: Iterator<Item = Gen>>(iter: I) -> Self {
+        Gen::from(iter.sum::<u8>())
+    }
+}
+impl<'a> Sum<&'a Gen> for Gen {
+    fn sum<I: Iterator<Item = &'a Gen>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// Adds up field elements straight into the value they sum to, without going back to a power
+impl Sum<Gen> for u8 {
+    fn sum<I: Iterator<Item = Gen>>(iter: I) -> Self {
+        iter.fold(0, |acc, x| acc ^ x)
+    }
+}
+impl<'a> Sum<&'a Gen> for u8 {
+    fn sum<I: Iterator<Item = &'a Gen>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// Multiplies field elements together, the product of none is g^0 = 1
+impl Product for Gen {
+    fn product<I: Iterator<Item = Gen>>(iter: I) -> Self {
+        iter.fold(Gen::from_power(0u8), |acc, x| acc * x)
+    }
+}
+impl<'a> Product<&'a Gen> for Gen {
+    fn product<I: Iterator<Item = &'a Gen>>(iter: I) -> Self {
+        iter.copied().product()
+    }
+}
+
+impl FromPower<u8> for Gen {
+    fn from_power(n: u8) -> Self {
+        Self { n }
+    }
+}
+
+impl FromPower<i16> for Gen {
+    fn from_power(n: i16) -> Self {
+        Self {
+            n: n.rem_euclid(255) as u8,
+        }
+    }
+}
+impl FromPower<i32> for Gen {
+    fn from_power(n: i32) -> Self {
+        Self {
+            n: n.rem_euclid(255) as u8,
+        }
+    }
+}
+impl FromPower<usize> for Gen {
+    fn from_power(n: usize) -> Self {
+        Self {
+            n: n.rem_euclid(255) as u8,
+        }
+    }
+}
+
+impl BitXor<u8> for Gen {
+    type Output = u8;
+
+    fn bitxor(self, rhs: u8) -> Self::Output {
+        count_xors(1);
+        self.value() ^ rhs
+    }
+}
+impl BitXor<Gen> for u8 {
+    type Output = u8;
+
+    fn bitxor(self, rhs: Gen) -> Self::Output {
+        count_xors(1);
+        self ^ rhs.value()
+    }
+}
+impl BitXor<Gen> for Gen {
+    type Output = u8;
+
+    fn bitxor(self, rhs: Gen) -> Self::Output {
+        count_xors(1);
+        self.value() ^ rhs.value()
+    }
+}
+
+impl BitXorAssign<Gen> for u8 {
+    fn bitxor_assign(&mut self, rhs: Gen) {
+        count_xors(1);
+        if rhs.n == ZERO {
+            return;
+        }
+        *self ^= rhs.value();
+    }
+}
+
+/// Superscript digits, for writing powers of x the way they're written by hand
+const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// Writes `value` as the polynomial over GF(2) it stands for, highest power first, like x⁴+x³+x²+1
+fn write_polynomial(f: &mut fmt::Formatter, value: u8) -> fmt::Result {
+    if value == 0 {
+        return write!(f, "0");
+    }
+    let terms = (0..8).rev().filter(|bit| value & (1 << bit) != 0);
+    for (i, bit) in terms.enumerate() {
+        if i > 0 {
+            write!(f, "+")?;
+        }
+        match bit {
+            0 => write!(f, "1")?,
+            1 => write!(f, "x")?,
+            _ => write!(f, "x{}", SUPERSCRIPTS[bit])?,
+        }
+    }
+    Ok(())
+}
+
+/// Shows the element in power form and as a polynomial, like `g^8 (x⁴+x³+x²+1)`, or `0` for zero
+impl fmt::Display for Gen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.n == ZERO {
+            return write!(f, "0");
+        }
+        write!(f, "g^{} (", self.n)?;
+        write_polynomial(f, self.value())?;
+        write!(f, ")")
+    }
+}
+
+/// Shows the element's value in hex, with the formatter's width and flags, so `{:#04x}` of g^8 is `0x1d`
+impl fmt::LowerHex for Gen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.value(), f)
+    }
+}
+
+impl fmt::UpperHex for Gen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.value(), f)
+    }
+}
+
+/// Parses one term of a polynomial, like `1`, `x`, `x^4` or `x⁴`, returning the bit it sets
+fn parse_term(term: &str) -> Result<u8> {
+    let power = match term {
+        "1" => 0,
+        "x" => 1,
+        _ => {
+            let Some(power) = term.strip_prefix('x') else {
+                bail!("{:?} isn't a term of a polynomial", term);
+            };
+            let power = match power.strip_prefix('^') {
+                Some(power) => power.to_string(),
+                None => power
+                    .chars()
+                    .map(|c| SUPERSCRIPTS.iter().position(|s| *s == c))
+                    .collect::<Option<Vec<usize>>>()
+                    .with_context(|| format!("{:?} isn't a term of a polynomial", term))?
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect(),
+            };
+            power
+                .parse::<u32>()
+                .with_context(|| format!("{:?} isn't a term of a polynomial", term))?
+        }
+    };
+    if power > 7 {
+        bail!(
+            "x^{} doesn't fit in a byte, the field only goes up to x^7",
+            power
+        );
+    }
+    Ok(1 << power)
+}
+
+/// Parses an element written as hex (`0x1d`), decimal (`29`), a power of the generator (`g^8`, `g^-1`)
+/// or a polynomial (`x^4+x^3+x^2+1`, `x⁴+x³+x²+1`). What `Display` writes parses back as well.
+impl FromStr for Gen {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        // The power form followed by the polynomial in brackets, both have to agree
+        if let Some((power, polynomial)) = s.strip_suffix(')').and_then(|s| s.split_once(" (")) {
+            let (a, b) = (power.parse::<Gen>()?, polynomial.parse::<Gen>()?);
+            if a != b {
+                bail!("{} and {} aren't the same element", power, polynomial);
+            }
+            return Ok(a);
+        }
+
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            let value = u8::from_str_radix(hex, 16)
+                .with_context(|| format!("{:?} isn't a byte in hex", s))?;
+            Ok(Gen::from(value))
+        } else if let Some(power) = s.strip_prefix("g^") {
+            let power = power
+                .parse::<i32>()
+                .with_context(|| format!("{:?} isn't a power of g", s))?;
+            Ok(Gen::from_power(power))
+        } else if s == "g" {
+            Ok(Gen::from_power(1u8))
+        } else if s.contains('x') {
+            let value = s
+                .split('+')
+                .map(|term| parse_term(term.trim()))
+                .collect::<Result<Vec<u8>>>()?
+                .into_iter()
+                // Adding a term twice cancels it out, the coefficients are in GF(2)
+                .fold(0, |acc, bit| acc ^ bit);
+            Ok(Gen::from(value))
+        } else {
+            let value = s
+                .parse::<u8>()
+                .with_context(|| format!("{:?} isn't a field element", s))?;
+            Ok(Gen::from(value))
+        }
+    }
+}
+
+impl Gen {
+    /// Gets g^-n from g^n
+    pub fn inverse(self) -> Self {
+        if self.n == ZERO {
+            panic!("Division by zero not allowed.")
+        }
+        count_divs(1);
+        Self {
+            n: TABLE.inverse[self.n as usize],
+        }
+    }
+    /// Returns g^n
+    pub fn value(self) -> u8 {
+        if self.n == ZERO {
+            0
+        } else {
+            TABLE.n_to_gn[self.n as usize]
+        }
+    }
+    /// Returns the power of the generator
+    pub fn power(self) -> u8 {
+        self.n
+    }
+    /// Returns a value representing zero
+    pub fn zero() -> Self {
+        Self { n: ZERO }
+    }
+
+    /// Multiplies by adding powers, with zero special cased
+    fn mul_branching(self, rhs: Self) -> Self {
+        if rhs.n == ZERO || self.n == ZERO {
+            Self::zero()
+        } else {
+            Self {
+                n: (((self.n as u16) + (rhs.n as u16)) % 255) as u8,
+            }
+        }
+    }
+
+    /// Multiplies by adding powers, then masks the result to zero if either side was zero instead of branching on it
+    fn mul_branchless(self, rhs: Self) -> Self {
+        let sum = (((self.n as u16) + (rhs.n as u16)) % 255) as u8;
+        let either_zero = ((self.n == ZERO) | (rhs.n == ZERO)) as u8;
+        // All ones is ZERO, all zeros leaves the sum alone
+        Self {
+            n: sum | 0u8.wrapping_sub(either_zero),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_zero() {
+        // 0 * g^i == 0
+        for i in 0..255 {
+            assert_eq!(Gen::zero() * Gen::from_power(i), Gen::zero());
+        }
+    }
+
+    #[test]
+    pub fn test_one() {
+        // 1 == g^0
+        assert_eq!(Gen::from(1).power(), 0);
+        // g^1 == g == 2
+        assert_eq!(Gen::from(2).power(), 1);
+        // g^255 == g^0
+        assert_eq!(Gen::from_power(255), Gen::from_power(0));
+    }
+
+    #[test]
+    pub fn test_inverses() {
+        // Not including 255 because g^255 = g^0 = 1
+        for i in 0..255 {
+            assert_eq!(Gen::from_power(i) * Gen::from_power(-i), Gen::from_power(0));
+        }
+    }
+
+    #[test]
+    pub fn test_division() {
+        for a in 0..=255u8 {
+            for b in 1..=255u8 {
+                let q = a / Gen::from(b);
+                assert_eq!((q * b).value(), a);
+                assert_eq!(q, Gen::from(a) * Gen::from(b).inverse());
+            }
+        }
+        assert_eq!(Gen::from(1).inverse(), Gen::from(1));
+    }
+
+    #[test]
+    pub fn test_apply_pow() {
+        let table = table();
+        for x in 0..=255u8 {
+            for n in [0, 1, 7, 254, 255, 1000] {
+                assert_eq!(table.apply_pow(x, n), (Gen::from_power(n) * x).value());
+                assert_eq!(table.apply_inverse_pow(table.apply_pow(x, n), n), x);
+            }
+        }
+        let mut buf = [0, 1, 2, 0x80, 0xff];
+        table.apply_pow_slice(&mut buf, 8);
+        assert_eq!(buf[1], 0x1d);
+        table.apply_inverse_pow_slice(&mut buf, 8);
+        assert_eq!(buf, [0, 1, 2, 0x80, 0xff]);
+    }
+
+    #[test]
+    pub fn test_branchless_matches() {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                let (a, b) = (Gen::from(a), Gen::from(b));
+                assert_eq!(a.mul_branchless(b), a.mul_branching(b));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_sum_and_product() {
+        let xs = [0x12, 0x00, 0xff, 0x7a].map(Gen::from);
+        assert_eq!(xs.iter().sum::<Gen>(), Gen::from(0x12 ^ 0xff ^ 0x7a));
+        assert_eq!(xs.iter().sum::<u8>(), 0x12 ^ 0xff ^ 0x7a);
+        assert_eq!(core::iter::empty::<Gen>().sum::<Gen>(), Gen::zero());
+        // g^3 * g^10 * g^250 = g^263 = g^8
+        let powers = [3u8, 10, 250].map(Gen::from_power);
+        assert_eq!(powers.iter().product::<Gen>(), Gen::from_power(8u8));
+        assert_eq!(xs.iter().product::<Gen>(), Gen::zero());
+        assert_eq!(core::iter::empty::<Gen>().product::<Gen>(), Gen::from(1));
+    }
+
+    #[test]
+    pub fn test_formatting() {
+        assert_eq!(Gen::from_power(8u8).to_string(), "g^8 (x⁴+x³+x²+1)");
+        assert_eq!(Gen::from(1).to_string(), "g^0 (1)");
+        assert_eq!(Gen::from(0x8e).to_string(), "g^254 (x⁷+x³+x²+x)");
+        assert_eq!(Gen::zero().to_string(), "0");
+        assert_eq!(format!("{:#04x}", Gen::from_power(8u8)), "0x1d");
+        assert_eq!(format!("{:02X}", Gen::from(0xab)), "AB");
+    }
+
+    #[test]
+    pub fn test_parsing() {
+        let g8 = Gen::from_power(8u8);
+        for s in [
+            "0x1d",
+            "0X1D",
+            "29",
+            "g^8",
+            "g^-247",
+            "x^4+x^3+x^2+1",
+            "x⁴ + x³ + x² + 1",
+            "x^4+x^3+x^2+x+x+1",
+        ] {
+            assert_eq!(s.parse::<Gen>().unwrap(), g8, "{}", s);
+        }
+        assert_eq!("g".parse::<Gen>().unwrap(), Gen::from(2));
+        assert_eq!("0".parse::<Gen>().unwrap(), Gen::zero());
+        for value in 0..=255u8 {
+            let gen = Gen::from(value);
+            assert_eq!(gen.to_string().parse::<Gen>().unwrap(), gen);
+        }
+
+        for s in ["", "256", "0x100", "g^x", "x^8", "y+1", "g^8 (x)"] {
+            assert!(s.parse::<Gen>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    pub fn test_1d() {
+        // Source: Section 1, https://www.kernel.org/pub/linux/kernel/people/hpa/raid6.pdf
+        // "Note, however: {02}^8 = {1d}"
+        assert_eq!(Gen::from_power(8).value(), 0x1d);
+    }
+}
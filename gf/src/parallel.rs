@@ -0,0 +1,84 @@
+use alloc::vec::Vec;
+
+use super::{GfEngine, ParityEngine, Result};
+
+/// Generates syndromes with `GfEngine` a block of rows at a time, spreading the blocks over threads.
+///
+/// Blocks of `chunk_size` rows keep each thread's share of every data chunk small enough to stay in cache
+/// while it's multiplied in. A stripe no bigger than one block is done on the calling thread.
+/// Recovery is left to `GfEngine` as it is.
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelEngine {
+    chunk_size: usize,
+    threads: usize,
+}
+
+impl ParallelEngine {
+    /// Rows in a block when none is given, 16 KiB of each chunk
+    pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+    /// Returns an engine working in blocks of `chunk_size` rows on as many threads as the machine has cores
+    pub fn new(chunk_size: usize) -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_threads(chunk_size, threads)
+    }
+
+    /// Returns an engine working in blocks of `chunk_size` rows on at most `threads` threads
+    pub fn with_threads(chunk_size: usize, threads: usize) -> Self {
+        assert!(chunk_size > 0, "Chunk size must be non-zero");
+        Self {
+            chunk_size,
+            threads: threads.max(1),
+        }
+    }
+}
+
+impl Default for ParallelEngine {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl ParityEngine for ParallelEngine {
+    fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+        let blocks = p.len().div_ceil(self.chunk_size);
+        if blocks <= 1 || self.threads == 1 {
+            return GfEngine.gen_syndrome(data, p, q);
+        }
+        // Each thread takes a run of whole blocks, the rows it covers in every buffer
+        let rows = blocks.div_ceil(self.threads) * self.chunk_size;
+        std::thread::scope(|scope| {
+            for (n, (p, q)) in p.chunks_mut(rows).zip(q.chunks_mut(rows)).enumerate() {
+                let start = n * rows;
+                scope.spawn(move || {
+                    for block in (start..start + p.len()).step_by(self.chunk_size) {
+                        let end = (block + self.chunk_size).min(start + p.len());
+                        let data = data.iter().map(|d| &d[block..end]).collect::<Vec<&[u8]>>();
+                        GfEngine.gen_syndrome(
+                            &data,
+                            &mut p[block - start..end - start],
+                            &mut q[block - start..end - start],
+                        );
+                    }
+                });
+            }
+        });
+    }
+
+    fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()> {
+        GfEngine.recover(chunks, lost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check_engine;
+
+    #[test]
+    fn parallel_engine_checks_out() {
+        for (chunk_size, threads) in [(7, 3), (16, 4), (64, 2), (1000, 8)] {
+            check_engine(&ParallelEngine::with_threads(chunk_size, threads), 6, 100).unwrap();
+        }
+    }
+}
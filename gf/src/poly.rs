@@ -0,0 +1,162 @@
+use alloc::{vec, vec::Vec};
+use core::ops::{Add, Mul};
+
+use super::{bail, Gen, Result};
+
+/// Multiplies two field elements given as plain bytes
+fn mul(a: u8, b: u8) -> u8 {
+    (Gen::from(a) * b).value()
+}
+
+/// A polynomial with coefficients in GF(2^8), lowest degree first.
+///
+/// A Reed-Solomon codeword is a polynomial evaluated at different points, and losing some of the points
+/// still leaves enough of them to interpolate the polynomial back.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Poly {
+    /// Never ends in a zero, so the zero polynomial has no coefficients at all
+    coefficients: Vec<u8>,
+}
+
+impl Poly {
+    /// Creates a polynomial from its coefficients, lowest degree first
+    pub fn new(mut coefficients: Vec<u8>) -> Self {
+        while coefficients.last() == Some(&0) {
+            coefficients.pop();
+        }
+        Self { coefficients }
+    }
+
+    /// Returns the polynomial's coefficients, lowest degree first
+    pub fn coefficients(&self) -> &[u8] {
+        &self.coefficients
+    }
+
+    /// Returns the degree of the polynomial, `None` for the zero polynomial
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    /// Evaluates the polynomial at `x` with Horner's method
+    pub fn eval(&self, x: u8) -> u8 {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0, |acc, c| mul(acc, x) ^ c)
+    }
+
+    /// Multiplies every coefficient by `c`
+    pub fn scale(&self, c: u8) -> Poly {
+        Poly::new(self.coefficients.iter().map(|x| mul(*x, c)).collect())
+    }
+
+    /// Finds the lowest degree polynomial passing through every (x, y) point with Lagrange interpolation
+    pub fn interpolate(points: &[(u8, u8)]) -> Result<Poly> {
+        for (i, (x, _)) in points.iter().enumerate() {
+            if points[..i].iter().any(|(other, _)| other == x) {
+                bail!(
+                    "Point x = {} given more than once, unable to interpolate",
+                    x
+                );
+            }
+        }
+        let mut result = Poly::default();
+        for (xi, yi) in points {
+            // The basis polynomial is one at xi and zero at every other point, subtraction is XOR just like addition
+            let (mut basis, mut denominator) = (Poly::new(vec![1]), 1);
+            for (xj, _) in points.iter().filter(|(xj, _)| xj != xi) {
+                basis = &basis * &Poly::new(vec![*xj, 1]);
+                denominator = mul(denominator, xi ^ xj);
+            }
+            result = &result + &basis.scale((*yi / Gen::from(denominator)).value());
+        }
+        Ok(result)
+    }
+}
+
+impl Add for &Poly {
+    type Output = Poly;
+
+    // Addition in GF(2^8) is XOR
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: &Poly) -> Poly {
+        let len = self.coefficients.len().max(rhs.coefficients.len());
+        Poly::new(
+            (0..len)
+                .map(|i| {
+                    self.coefficients.get(i).unwrap_or(&0) ^ rhs.coefficients.get(i).unwrap_or(&0)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Add for Poly {
+    type Output = Poly;
+
+    fn add(self, rhs: Poly) -> Poly {
+        &self + &rhs
+    }
+}
+
+impl Mul for &Poly {
+    type Output = Poly;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn mul(self, rhs: &Poly) -> Poly {
+        if self.coefficients.is_empty() || rhs.coefficients.is_empty() {
+            return Poly::default();
+        }
+        let mut product = vec![0; self.coefficients.len() + rhs.coefficients.len() - 1];
+        for (i, a) in self.coefficients.iter().enumerate() {
+            for (j, b) in rhs.coefficients.iter().enumerate() {
+                product[i + j] ^= mul(*a, *b);
+            }
+        }
+        Poly::new(product)
+    }
+}
+
+impl Mul for Poly {
+    type Output = Poly;
+
+    fn mul(self, rhs: Poly) -> Poly {
+        &self * &rhs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Poly::new(vec![1, 2, 3, 0]);
+        let b = Poly::new(vec![5, 0, 3]);
+        assert_eq!(a.degree(), Some(2));
+        assert_eq!((&a + &a).degree(), None);
+        assert_eq!(&a + &b, Poly::new(vec![4, 2]));
+        for x in 0..=255u8 {
+            assert_eq!((&a * &b).eval(x), mul(a.eval(x), b.eval(x)));
+            assert_eq!((&a + &b).eval(x), a.eval(x) ^ b.eval(x));
+        }
+        // (x + 1)^2 = x^2 + 1 in characteristic 2
+        let x1 = Poly::new(vec![1, 1]);
+        assert_eq!(&x1 * &x1, Poly::new(vec![1, 0, 1]));
+    }
+
+    #[test]
+    fn interpolation_recovers_the_polynomial() {
+        let poly = Poly::new(vec![0x3a, 0x00, 0x91, 0x07]);
+        // Any 4 points determine a polynomial of degree 3
+        let points = [1u8, 2, 4, 8, 16, 32]
+            .iter()
+            .map(|x| (*x, poly.eval(*x)))
+            .collect::<Vec<(u8, u8)>>();
+        assert_eq!(Poly::interpolate(&points[..4]).unwrap(), poly);
+        assert_eq!(Poly::interpolate(&points[2..]).unwrap(), poly);
+        assert_eq!(Poly::interpolate(&points).unwrap(), poly);
+        assert!(Poly::interpolate(&[(1, 2), (1, 3)]).is_err());
+        assert_eq!(Poly::interpolate(&[]).unwrap(), Poly::default());
+    }
+}
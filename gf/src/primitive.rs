@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+
+use super::{Gen, ZERO};
+
+/// The polynomial raid6 builds GF(2^8) on, x^8 + x^4 + x^3 + x^2 + 1, with its x^8 term included
+pub const RAID6_POLYNOMIAL: u16 = 0x11d;
+
+/// Multiplies two elements of GF(2^8) built on `polynomial`, given with its x^8 term like `RAID6_POLYNOMIAL`.
+///
+/// This works bit by bit without any tables, so it can be used on polynomials no table has been built for.
+pub fn poly_mul(a: u8, b: u8, polynomial: u16) -> u8 {
+    let (mut a, mut b, mut product) = (a as u16, b, 0u16);
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        a <<= 1;
+        if a & 0x100 != 0 {
+            a ^= polynomial;
+        }
+        b >>= 1;
+    }
+    product as u8
+}
+
+/// Returns whether `candidate` is a generator of GF(2^8) built on `polynomial`,
+/// meaning its powers g^0 through g^254 reach every nonzero element exactly once
+pub fn is_generator(candidate: u8, polynomial: u16) -> bool {
+    if candidate == 0 {
+        return false;
+    }
+    // The powers cycle back to 1, a generator is the only kind of element that takes all 255 steps to get there
+    let mut x = candidate;
+    for _ in 1..255 {
+        if x == 1 {
+            return false;
+        }
+        x = poly_mul(x, candidate, polynomial);
+    }
+    x == 1
+}
+
+/// Lists every generator of GF(2^8) built on `polynomial`, empty if the polynomial isn't primitive
+pub fn primitive_elements(polynomial: u16) -> Vec<u8> {
+    (1..=255).filter(|c| is_generator(*c, polynomial)).collect()
+}
+
+/// Returns the n where g^n = `x` for the raid6 generator g = {02}, `None` for zero since no power of g is zero
+pub fn discrete_log(x: u8) -> Option<u8> {
+    match Gen::from(x).power() {
+        ZERO => None,
+        n => Some(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FromPower;
+
+    #[test]
+    fn raid6_generator_is_primitive() {
+        assert!(is_generator(2, RAID6_POLYNOMIAL));
+        assert!(!is_generator(1, RAID6_POLYNOMIAL));
+        assert!(!is_generator(0, RAID6_POLYNOMIAL));
+        // One generator for every power coprime with 255
+        assert_eq!(primitive_elements(RAID6_POLYNOMIAL).len(), 128);
+    }
+
+    #[test]
+    fn aes_polynomial_needs_a_different_generator() {
+        // x^8 + x^4 + x^3 + x + 1 is irreducible but {02} only has order 51 under it
+        let aes = 0x11b;
+        assert!(!is_generator(2, aes));
+        assert!(is_generator(3, aes));
+        // x^8 + 1 = (x + 1)^8 isn't irreducible at all
+        assert!(primitive_elements(0x101).is_empty());
+    }
+
+    #[test]
+    fn discrete_log_matches_table() {
+        assert_eq!(discrete_log(0), None);
+        assert_eq!(discrete_log(1), Some(0));
+        for n in 0..255u8 {
+            let x = Gen::from_power(n).value();
+            assert_eq!(discrete_log(x), Some(n));
+            assert_eq!(
+                poly_mul(x, 2, RAID6_POLYNOMIAL),
+                Gen::from_power(n as usize + 1).value()
+            );
+        }
+    }
+}
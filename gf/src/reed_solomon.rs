@@ -0,0 +1,110 @@
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use super::{bail, rng::SplitMix, Context, GfEngine, ParityEngine, Result};
+
+/// A `ParityEngine` on top of the reed-solomon-erasure crate's RS(k, 2) code.
+///
+/// Its two parity chunks come from that crate's encoding matrix rather than being P and Q, so they can't go in
+/// an array, but anything lost from a stripe it encoded has to come back just the same. `cross_check()` holds
+/// this crate's reconstruction to that.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReedSolomonEngine;
+
+impl ReedSolomonEngine {
+    fn codec(data: usize) -> Result<ReedSolomon> {
+        ReedSolomon::new(data, 2).context("reed-solomon-erasure refused the stripe")
+    }
+}
+
+impl ParityEngine for ReedSolomonEngine {
+    fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+        let codec = Self::codec(data.len()).expect("Too many data chunks for reed-solomon-erasure");
+        codec
+            .encode_sep(data, &mut [p, q])
+            .expect("Chunks differ in length");
+    }
+
+    fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> Result<()> {
+        let Some(data) = chunks.len().checked_sub(2) else {
+            bail!(
+                "A stripe needs two parity chunks, got {} chunks",
+                chunks.len()
+            );
+        };
+        let mut shards = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| (!lost.contains(&i)).then(|| chunk.to_vec()))
+            .collect::<Vec<Option<Vec<u8>>>>();
+        Self::codec(data)?
+            .reconstruct(&mut shards)
+            .context("reed-solomon-erasure couldn't reconstruct the stripe")?;
+        for (chunk, shard) in chunks.iter_mut().zip(shards) {
+            chunk.copy_from_slice(&shard.expect("Every shard is reconstructed"));
+        }
+        Ok(())
+    }
+}
+
+/// Loses one or two random chunks from `rounds` random stripes of `data` chunks of `len` bytes,
+/// and checks `GfEngine` gets back the same data from P and Q as reed-solomon-erasure does from its own parity
+pub fn cross_check(data: usize, len: usize, rounds: usize) -> Result<()> {
+    let mut rng = SplitMix::new();
+    for round in 0..rounds {
+        let chunks = (0..data).map(|_| rng.bytes(len)).collect::<Vec<Vec<u8>>>();
+        let mut lost = vec![rng.below(data + 2)];
+        // Sometimes a second, different chunk
+        if rng.below(2) == 1 {
+            let other = (lost[0] + 1 + rng.below(data + 1)) % (data + 2);
+            lost.push(other);
+        }
+        let mut recovered = vec![];
+        for engine in [&GfEngine as &dyn ParityEngine, &ReedSolomonEngine] {
+            let refs = chunks.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>();
+            let (mut p, mut q) = (vec![0; len], vec![0; len]);
+            engine.gen_syndrome(&refs, &mut p, &mut q);
+            let mut stripe = chunks
+                .iter()
+                .cloned()
+                .chain(vec![p, q])
+                .collect::<Vec<Vec<u8>>>();
+            for i in &lost {
+                stripe[*i].fill(0);
+            }
+            let mut refs = stripe
+                .iter_mut()
+                .map(Vec::as_mut_slice)
+                .collect::<Vec<&mut [u8]>>();
+            engine.recover(&mut refs, &lost)?;
+            stripe.truncate(data);
+            recovered.push(stripe);
+        }
+        if recovered[0] != recovered[1] {
+            bail!(
+                "Round {}: recovering chunks {:?} disagrees with reed-solomon-erasure",
+                round,
+                lost
+            );
+        }
+        if recovered[0] != chunks {
+            bail!(
+                "Round {}: both recovered chunks {:?} wrong the same way",
+                round,
+                lost
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconstruction_agrees_with_reed_solomon_erasure() {
+        for data in [2, 5, 62] {
+            cross_check(data, 64, 50).unwrap();
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use alloc::vec::Vec;
+
+/// SplitMix64, enough randomness for the stripes the checks run on without depending on rand
+pub(crate) struct SplitMix(u64);
+
+impl SplitMix {
+    /// Returns a generator seeded differently every time, from the standard library's per-process hash keys
+    #[cfg(feature = "std")]
+    pub(crate) fn new() -> Self {
+        use std::{
+            collections::hash_map::RandomState,
+            hash::{BuildHasher, Hasher},
+        };
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        Self(hasher.finish())
+    }
+
+    /// Returns a generator with a fixed seed, there's nowhere to get a random one from without std
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn new() -> Self {
+        Self(0x5eed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut x = self.0;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+
+    /// Returns a number in `0..n`, close enough to uniform for small `n`
+    #[cfg(feature = "reed-solomon")]
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns `len` random bytes
+    pub(crate) fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
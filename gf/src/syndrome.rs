@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+
+use super::{xor_fold, Backend, FromPower, Gen};
+
+/// Computes the sum of c_i * d_i over every data byte d_i and its coefficient c_i
+pub fn weighted_sum(data: &[u8], coefficients: impl IntoIterator<Item = Gen>) -> u8 {
+    data.iter().zip(coefficients).map(|(d, c)| c * *d).sum()
+}
+
+/// Computes syndrome `j` of a row of data bytes, the sum of g^(j*i) * d_i over every data byte d_i.
+///
+/// Syndrome 0 is the P parity, 1 is the Q parity, and each one after is another independent parity
+/// that a row can be recovered from, like the R of a triple parity array.
+pub fn syndrome(data: &[u8], j: usize) -> u8 {
+    // Every coefficient of syndrome 0 is g^0 = 1, so it's plain XOR
+    if j == 0 {
+        return xor_fold(data);
+    }
+    weighted_sum(data, (0..data.len()).map(|i| Gen::from_power(i * j)))
+}
+
+/// Computes syndrome `j` like `syndrome()`, but with the arithmetic done by backend `B`
+pub fn syndrome_with<B: Backend>(data: &[u8], j: usize) -> u8 {
+    data.iter()
+        .enumerate()
+        .fold(0, |acc, (i, d)| acc ^ B::mul(B::exp(i * j), *d))
+}
+
+/// Computes the first `k` syndromes of a row of data bytes
+pub fn syndromes(data: &[u8], k: usize) -> Vec<u8> {
+    (0..k).map(|j| syndrome(data, j)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ShiftXor, Tables};
+
+    const DATA: [u8; 6] = [0x12, 0x00, 0xff, 0x7a, 0x01, 0xc3];
+
+    #[test]
+    fn syndromes_match_p_and_q() {
+        let p = DATA.iter().fold(0, |acc, d| acc ^ d);
+        let q = DATA
+            .iter()
+            .enumerate()
+            .map(|(i, d)| Gen::from_power(i) * *d)
+            .sum();
+        assert_eq!(syndromes(&DATA, 2), vec![p, q]);
+        assert_eq!(syndromes(&[], 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn any_syndrome_recovers_a_lost_byte() {
+        for j in 0..4 {
+            let s = syndrome(&DATA, j);
+            for lost in 0..DATA.len() {
+                let mut rest = DATA;
+                rest[lost] = 0;
+                let recovered = (s ^ syndrome(&rest, j)) / Gen::from_power(lost * j);
+                assert_eq!(
+                    recovered.value(),
+                    DATA[lost],
+                    "syndrome {} lost {}",
+                    j,
+                    lost
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn syndromes_agree_across_backends() {
+        for j in 0..4 {
+            assert_eq!(syndrome_with::<ShiftXor>(&DATA, j), syndrome(&DATA, j));
+            assert_eq!(syndrome_with::<Tables>(&DATA, j), syndrome(&DATA, j));
+        }
+    }
+
+    #[test]
+    fn weighted_sum_is_linear() {
+        let coefficients = [3u8, 9, 27, 81, 243, 1].map(Gen::from);
+        let other = [0x55; 6];
+        let mixed = DATA
+            .iter()
+            .zip(other)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            weighted_sum(&mixed, coefficients),
+            weighted_sum(&DATA, coefficients) ^ weighted_sum(&other, coefficients)
+        );
+    }
+}
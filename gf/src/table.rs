@@ -0,0 +1,180 @@
+use alloc::boxed::Box;
+use core::fmt::Display;
+
+use super::{counts::count_muls, kernel, ZERO};
+
+/// Gets the nth bit from a u8
+pub fn nth_bit(num: u8, idx: u8) -> u8 {
+    (num >> idx) & 1
+}
+
+/// Applies the {02} generator from GF(2^8), what is effectively the element x, modded by x^8 + x^4 + x^3 + x^2 + 1
+fn raid6_generator(num: u8) -> u8 {
+    if cfg!(feature = "branchless") {
+        raid6_generator_branchless(num)
+    } else {
+        raid6_generator_branching(num)
+    }
+}
+
+fn raid6_generator_branching(num: u8) -> u8 {
+    let m = if nth_bit(num, 7) == 1 {
+        0x1d // Effectively x^4 + x^3 + x^2 + 1
+    } else {
+        0
+    };
+    (num << 1) ^ m
+}
+
+/// Same as `raid6_generator_branching`, but turns the top bit into a mask of all ones or all zeros to pick the reduction
+fn raid6_generator_branchless(num: u8) -> u8 {
+    (num << 1) ^ (0x1d & 0u8.wrapping_sub(nth_bit(num, 7)))
+}
+
+/// Represents a table of repeated multiplication by the generator {02} in GF(2^8) modded by x^8 + x^4 + x^3 + x^2 + 1
+///
+/// Since {02} is a generator of GF(2^8), it is cyclic.
+/// This means we can find all g^n where 1 <= n <= 255 and create tables which map g^n -> n and n -> g^n.
+/// When given a number x, we use the first table to find an n where x = g^n.
+/// With a little algebra we know that x*g^k = g^(n+k) which tells us what x is after applying the generator k times.
+/// By using the second table, we can find g^(n+k) by indexing at (n + k) mod 255.
+///
+/// Division gets tables of its own, indexed by power just like `Gen` stores it, with index 255 standing in for zero.
+/// `inverse` maps n -> -n mod 255 and `div` maps (a, b) -> (a - b) mod 255, so dividing never has to branch.
+/// Zero divided by anything stays zero. Dividing by zero is left to the caller to avoid, its entries are zero too.
+///
+/// Bulk multiplication gets split tables, the layout SIMD shuffle kernels use. Multiplying is linear over XOR,
+/// so g^n * x = g^n * (x & 0x0f) ^ g^n * (x & 0xf0), and `nibbles[n]` holds both halves for every nibble value:
+/// two 16 byte lookups per byte, with no logarithm to take and no zero to check for.
+pub struct MTable {
+    pub n_to_gn: [u8; 255],
+    pub gn_to_n: [u8; 256],
+    pub inverse: [u8; 256],
+    pub div: Box<[[u8; 256]; 256]>,
+    pub nibbles: Box<[[[u8; 16]; 2]; 255]>,
+}
+
+impl MTable {
+    /// Generates the table for the Galois Field used by raid6, GF(2^8)
+    ///
+    /// Documentation: [The mathematics of RAID-6](https://www.kernel.org/pub/linux/kernel/people/hpa/raid6.pdf)
+    pub fn new() -> Self {
+        let mut n_to_gn = [0u8; 255];
+        let mut gn_to_n = [0u8; 256];
+        n_to_gn[0] = 1; // g^0 = e = 1
+        for n in 1..255 {
+            n_to_gn[n] = raid6_generator(n_to_gn[n - 1]); // g^n = g * g^(n-1)
+        }
+        for (i, e) in n_to_gn.iter().enumerate() {
+            gn_to_n[*e as usize] = i as u8
+        }
+        // g^-n = g^(255 - n), and g^0 is its own inverse
+        let mut inverse = [ZERO; 256];
+        for (n, inv) in inverse.iter_mut().enumerate().take(255) {
+            *inv = ((255 - n) % 255) as u8;
+        }
+        // g^a / g^b = g^(a - b)
+        let mut div = Box::new([[ZERO; 256]; 256]);
+        for (a, row) in div.iter_mut().enumerate().take(255) {
+            for (b, quotient) in row.iter_mut().enumerate().take(255) {
+                *quotient = ((a + 255 - b) % 255) as u8;
+            }
+        }
+        // Low nibbles x and high nibbles x << 4, each times g^n
+        let mut nibbles = Box::new([[[0u8; 16]; 2]; 255]);
+        for (n, [low, high]) in nibbles.iter_mut().enumerate() {
+            for x in 1..16 {
+                low[x] = n_to_gn[(gn_to_n[x] as usize + n) % 255];
+                high[x] = n_to_gn[(gn_to_n[x << 4] as usize + n) % 255];
+            }
+        }
+        MTable {
+            n_to_gn,
+            gn_to_n,
+            inverse,
+            div,
+            nibbles,
+        }
+    }
+}
+
+impl MTable {
+    /// Returns x * g^n, applying the generator to `x` n times
+    pub fn apply_pow(&self, x: u8, n: usize) -> u8 {
+        count_muls(1);
+        if x == 0 {
+            return 0;
+        }
+        let power = (self.gn_to_n[x as usize] as usize + n % 255) % 255;
+        self.n_to_gn[power]
+    }
+
+    /// Returns x * g^-n, undoing `apply_pow(x, n)`
+    pub fn apply_inverse_pow(&self, x: u8, n: usize) -> u8 {
+        self.apply_pow(x, 255 - n % 255)
+    }
+
+    /// Returns x * g^n like `apply_pow`, looked up a nibble at a time in the split tables
+    pub fn apply_pow_nibbles(&self, x: u8, n: usize) -> u8 {
+        count_muls(1);
+        let [low, high] = &self.nibbles[n % 255];
+        low[(x & 0x0f) as usize] ^ high[(x >> 4) as usize]
+    }
+
+    /// Applies the generator n times to every byte of `buf` in place
+    pub fn apply_pow_slice(&self, buf: &mut [u8], n: usize) {
+        kernel().mul(buf, &self.nibbles[n % 255]);
+    }
+
+    /// Adds `x * g^n` into `acc` with the best available kernel, the multiply-accumulate every bulk syndrome is built from
+    pub fn mul_add_slice(&self, acc: &mut [u8], x: &[u8], n: usize) {
+        kernel().mul_add(acc, x, &self.nibbles[n % 255]);
+    }
+
+    /// Undoes `apply_pow_slice(buf, n)` in place
+    pub fn apply_inverse_pow_slice(&self, buf: &mut [u8], n: usize) {
+        for x in buf {
+            *x = self.apply_inverse_pow(*x, n);
+        }
+    }
+}
+
+impl Default for MTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for MTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for num in self.n_to_gn {
+            write!(f, "{num:02X} ")?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nibble_tables_match_apply_pow() {
+        let table = MTable::new();
+        for n in 0..300 {
+            for x in 0..=255u8 {
+                assert_eq!(table.apply_pow_nibbles(x, n), table.apply_pow(x, n));
+            }
+        }
+    }
+
+    #[test]
+    fn branchless_generator_matches() {
+        for num in 0..=255u8 {
+            assert_eq!(
+                raid6_generator_branchless(num),
+                raid6_generator_branching(num)
+            );
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use alloc::{vec, vec::Vec};
+use core::ops::{BitXor, BitXorAssign, Deref, DerefMut, Mul, MulAssign};
+
+use super::{kernel, table, Gen};
+
+/// A buffer of bytes taken as a vector over GF(2^8), so whole chunks can be added and scaled at once.
+///
+/// Adding two vectors XORs them byte by byte and scaling multiplies every byte by the same `Gen`,
+/// which lets parity be written as the sum it is, `Q = D_0 ^ g * D_1 ^ g^2 * D_2 ...`.
+/// Vectors of different lengths can't be added, doing so panics.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct GfVector(pub Vec<u8>);
+
+/// A borrowed `GfVector`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct GfSlice<'a>(pub &'a [u8]);
+
+impl GfVector {
+    /// Returns the zero vector of `len` bytes
+    pub fn zeroed(len: usize) -> Self {
+        Self(vec![0; len])
+    }
+
+    pub fn as_slice(&self) -> GfSlice<'_> {
+        GfSlice(&self.0)
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Adds `c * x` into the vector in place, the fused multiply-add every syndrome is built from
+    pub fn mul_add(&mut self, c: Gen, x: GfSlice) {
+        assert_eq!(self.len(), x.len(), "Vectors differ in length");
+        if c == Gen::zero() {
+            return;
+        }
+        // Multiplying by g^0 is a no-op, leaving plain addition
+        if c.power() == 0 {
+            return kernel().xor(&mut self.0, x.0);
+        }
+        table().mul_add_slice(&mut self.0, x.0, c.power() as usize);
+    }
+}
+
+impl GfSlice<'_> {
+    pub fn to_vector(self) -> GfVector {
+        GfVector(self.0.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for GfVector {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl<'a> From<&'a [u8]> for GfSlice<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        Self(value)
+    }
+}
+
+impl Deref for GfVector {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl DerefMut for GfVector {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Deref for GfSlice<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl BitXorAssign<GfSlice<'_>> for GfVector {
+    fn bitxor_assign(&mut self, rhs: GfSlice) {
+        assert_eq!(self.len(), rhs.len(), "Vectors differ in length");
+        kernel().xor(&mut self.0, rhs.0);
+    }
+}
+impl BitXor<GfSlice<'_>> for GfVector {
+    type Output = GfVector;
+
+    fn bitxor(mut self, rhs: GfSlice) -> Self::Output {
+        self ^= rhs;
+        self
+    }
+}
+impl BitXor for GfSlice<'_> {
+    type Output = GfVector;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        self.to_vector() ^ rhs
+    }
+}
+
+impl MulAssign<Gen> for GfVector {
+    fn mul_assign(&mut self, rhs: Gen) {
+        if rhs == Gen::zero() {
+            self.0.fill(0);
+        } else {
+            table().apply_pow_slice(&mut self.0, rhs.power() as usize);
+        }
+    }
+}
+impl Mul<Gen> for GfVector {
+    type Output = GfVector;
+
+    fn mul(mut self, rhs: Gen) -> Self::Output {
+        self *= rhs;
+        self
+    }
+}
+impl Mul<Gen> for GfSlice<'_> {
+    type Output = GfVector;
+
+    fn mul(self, rhs: Gen) -> Self::Output {
+        self.to_vector() * rhs
+    }
+}
+impl Mul<GfSlice<'_>> for Gen {
+    type Output = GfVector;
+
+    fn mul(self, rhs: GfSlice) -> Self::Output {
+        rhs * self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{syndrome, FromPower};
+
+    const A: [u8; 4] = [0x12, 0x00, 0xff, 0x7a];
+    const B: [u8; 4] = [0x01, 0xc3, 0xff, 0x80];
+
+    #[test]
+    fn vectors_follow_the_field() {
+        let (a, b) = (GfSlice(&A), GfSlice(&B));
+        assert_eq!(*(a ^ b), [0x13, 0xc3, 0x00, 0xfa]);
+        assert_eq!(a ^ a, GfVector::zeroed(4));
+
+        let g = Gen::from_power(1u8);
+        let scaled = g * a;
+        for (x, y) in A.iter().zip(scaled.iter()) {
+            assert_eq!((g * *x).value(), *y);
+        }
+        assert_eq!(a * Gen::zero(), GfVector::zeroed(4));
+        assert_eq!(a * Gen::from(1), a.to_vector());
+        // Scaling distributes over addition
+        assert_eq!((a ^ b) * g, (a * g) ^ (b * g).as_slice());
+
+        let mut fused = a.to_vector();
+        fused.mul_add(g, b);
+        assert_eq!(fused, a ^ (g * b).as_slice());
+    }
+
+    #[test]
+    fn chunk_syndromes_match_rows() {
+        let chunks = [A, B, [0x55; 4]];
+        for j in 0..3 {
+            let mut parity = GfVector::zeroed(4);
+            for (i, chunk) in chunks.iter().enumerate() {
+                parity.mul_add(Gen::from_power(i * j), GfSlice(chunk));
+            }
+            for (row, byte) in parity.iter().enumerate() {
+                let data = chunks.map(|chunk| chunk[row]);
+                assert_eq!(*byte, syndrome(&data, j));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "differ in length")]
+    fn lengths_must_match() {
+        let _ = GfSlice(&A) ^ GfSlice(&A[..2]);
+    }
+}
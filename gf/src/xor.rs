@@ -0,0 +1,60 @@
+use core::convert::TryInto;
+
+use super::counts::count_xors;
+
+/// Bytes in a word, how many are XORed at once
+const WORD: usize = core::mem::size_of::<u64>();
+
+/// XORs `x` into `acc` a word at a time, the addition behind P parity and undoing it.
+/// Panics if the two differ in length.
+pub fn xor_into(acc: &mut [u8], x: &[u8]) {
+    assert_eq!(acc.len(), x.len(), "Buffers differ in length");
+    count_xors(acc.len());
+    xor_words(acc, x);
+}
+
+/// XORs `x` into `acc` like `xor_into()` without counting it, for kernels that already have
+pub(crate) fn xor_words(acc: &mut [u8], x: &[u8]) {
+    let mut acc_words = acc.chunks_exact_mut(WORD);
+    let mut x_words = x.chunks_exact(WORD);
+    for (a, b) in (&mut acc_words).zip(&mut x_words) {
+        let sum = u64::from_ne_bytes((*a).try_into().unwrap())
+            ^ u64::from_ne_bytes(b.try_into().unwrap());
+        a.copy_from_slice(&sum.to_ne_bytes());
+    }
+    for (a, b) in acc_words
+        .into_remainder()
+        .iter_mut()
+        .zip(x_words.remainder())
+    {
+        *a ^= b;
+    }
+}
+
+/// Returns the XOR of every byte of `data`, folding it a word at a time and then the bytes of that word
+pub fn xor_fold(data: &[u8]) -> u8 {
+    count_xors(data.len());
+    let words = data.chunks_exact(WORD);
+    let tail = words.remainder().iter().fold(0, |acc, x| acc ^ x);
+    let word = words.fold(0, |acc, w| acc ^ u64::from_ne_bytes(w.try_into().unwrap()));
+    word.to_ne_bytes().iter().fold(tail, |acc, x| acc ^ x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_xor_matches_bytewise() {
+        for len in [0, 1, 7, 8, 9, 62, 64, 513] {
+            let a = (0..len).map(|i| (i * 37 + 5) as u8).collect::<Vec<u8>>();
+            let b = (0..len).map(|i| (i * 101 + 3) as u8).collect::<Vec<u8>>();
+            let mut sum = a.clone();
+            xor_into(&mut sum, &b);
+            for i in 0..len {
+                assert_eq!(sum[i], a[i] ^ b[i], "length {} byte {}", len, i);
+            }
+            assert_eq!(xor_fold(&a), a.iter().fold(0, |acc, x| acc ^ x));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use divan::Bencher;
+use raid::{
+    generator::{FromPower, GfSlice, GfVector, Kernel, ParallelEngine, ParityEngine},
+    Gen, RaidSim, StorageLayout,
+};
+use rand::Rng;
+
+fn main() {
+    divan::main();
+}
+
+fn rand_vec(size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    rand::rng().fill(data.as_mut_slice());
+    data
+}
+
+#[divan::bench(args = [16, 32, 64, 128, 257])]
+fn raid6_single_write_num_drives_scale(bencher: Bencher, num_drives: usize) {
+    let mut sim = RaidSim::builder()
+        .drives(num_drives, 1024 * 16)
+        .build()
+        .unwrap();
+    sim.init().unwrap();
+    let payload = rand_vec(sim.size() as usize);
+    bencher.bench_local(move || {
+        for (i, b) in payload.iter().enumerate() {
+            sim.write(i as u64, *b).unwrap();
+        }
+    });
+}
+
+#[divan::bench(args = [16, 32, 64, 128, 257])]
+fn raid6_slice_write_num_drives_scale(bencher: Bencher, num_drives: usize) {
+    let mut sim = RaidSim::builder()
+        .drives(num_drives, 1024 * 16)
+        .build()
+        .unwrap();
+    sim.init().unwrap();
+    let payload = rand_vec(sim.size() as usize);
+    bencher.bench_local(move || {
+        sim.write_slice(0, payload.as_slice()).unwrap();
+    });
+}
+
+#[divan::bench(args = [1024, 1024*16, 1024*16*16, 1024*16*16*16])]
+fn raid6_single_write_drive_size_scale(bencher: Bencher, drive_size: usize) {
+    let mut sim = RaidSim::builder().drives(16, drive_size).build().unwrap();
+    sim.init().unwrap();
+    let payload = rand_vec(sim.size() as usize);
+    bencher.bench_local(move || {
+        for (i, b) in payload.iter().enumerate() {
+            sim.write(i as u64, *b).unwrap();
+        }
+    });
+}
+
+#[divan::bench(args = [1024, 1024*16, 1024*16*16, 1024*16*16*16])]
+fn raid6_slice_write_drive_size_scale(bencher: Bencher, drive_size: usize) {
+    let mut sim = RaidSim::builder().drives(16, drive_size).build().unwrap();
+    sim.init().unwrap();
+    let payload = rand_vec(sim.size() as usize);
+    bencher.bench_local(move || {
+        sim.write_slice(0, payload.as_slice()).unwrap();
+    });
+}
+
+fn layout_sim(layout: StorageLayout) -> RaidSim {
+    let mut sim = RaidSim::builder()
+        .drives(16, 1024 * 16 * 16)
+        .layout(layout)
+        .build()
+        .unwrap();
+    sim.init().unwrap();
+    sim.write_slice(0, &rand_vec(sim.size() as usize)).unwrap();
+    sim
+}
+
+#[divan::bench(args = [StorageLayout::DriveMajor, StorageLayout::StripeMajor])]
+fn raid6_scrub_layout(bencher: Bencher, layout: StorageLayout) {
+    let mut sim = layout_sim(layout);
+    let drive_size = sim.drive(0).size();
+    bencher.bench_local(move || {
+        sim.scrub_step(drive_size).unwrap();
+    });
+}
+
+#[divan::bench(args = [StorageLayout::DriveMajor, StorageLayout::StripeMajor])]
+fn raid6_rebuild_layout(bencher: Bencher, layout: StorageLayout) {
+    // A clone would move every member to an allocation of its own, so each run builds its array afresh
+    bencher
+        .with_inputs(|| {
+            let mut sim = layout_sim(layout);
+            sim.fail_drive(3);
+            sim.fail_drive(9);
+            sim.replace_failed_drives();
+            sim
+        })
+        .bench_local_values(|mut sim| sim.rebuild().unwrap());
+}
+
+#[divan::bench]
+fn gf_multiply(bencher: Bencher) {
+    let pairs = rand_vec(1024 * 16)
+        .chunks(2)
+        .map(|c| (Gen::from(c[0]), Gen::from(c[1])))
+        .collect::<Vec<_>>();
+    bencher.bench_local(move || {
+        pairs
+            .iter()
+            .fold(Gen::zero(), |acc, (a, b)| acc + (*a * *b))
+    });
+}
+
+#[divan::bench(args = [16, 64, 255])]
+fn q_syndrome_bulk(bencher: Bencher, num_chunks: usize) {
+    let chunks = (0..num_chunks)
+        .map(|_| rand_vec(4096))
+        .collect::<Vec<Vec<u8>>>();
+    let mut q = GfVector::zeroed(4096);
+    bencher.bench_local(move || {
+        for (i, chunk) in chunks.iter().enumerate() {
+            q.mul_add(Gen::from_power(i), GfSlice(chunk));
+        }
+    });
+}
+
+#[divan::bench(args = [1, 2, 4, 8])]
+fn q_syndrome_parallel(bencher: Bencher, threads: usize) {
+    let chunks = (0..62).map(|_| rand_vec(1 << 20)).collect::<Vec<Vec<u8>>>();
+    let engine = ParallelEngine::with_threads(ParallelEngine::DEFAULT_CHUNK_SIZE, threads);
+    let (mut p, mut q) = (vec![0; 1 << 20], vec![0; 1 << 20]);
+    bencher.bench_local(move || {
+        let data = chunks.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>();
+        engine.gen_syndrome(&data, &mut p, &mut q);
+    });
+}
+
+#[divan::bench(args = [16, 64, 255])]
+fn p_parity_bulk(bencher: Bencher, num_chunks: usize) {
+    let chunks = (0..num_chunks)
+        .map(|_| rand_vec(4096))
+        .collect::<Vec<Vec<u8>>>();
+    let mut p = GfVector::zeroed(4096);
+    bencher.bench_local(move || {
+        for chunk in &chunks {
+            p ^= GfSlice(chunk);
+        }
+    });
+}
+
+#[divan::bench(args = Kernel::ALL.iter().copied().filter(|k| k.available()))]
+fn q_syndrome_kernel(bencher: Bencher, kernel: Kernel) {
+    let chunks = (0..64).map(|_| rand_vec(4096)).collect::<Vec<Vec<u8>>>();
+    let mut q = vec![0; 4096];
+    bencher.bench_local(move || {
+        for (i, chunk) in chunks.iter().enumerate() {
+            kernel.mul_add(&mut q, chunk, &raid::generator::table().nibbles[i]);
+        }
+    });
+}
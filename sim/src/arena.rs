@@ -0,0 +1,323 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::{Index, IndexMut, Range},
+    ptr::NonNull,
+    sync::Arc,
+};
+
+/// One allocation split into equal, disjoint regions, one for each member of an array.
+///
+/// Regions are laid out a row of `height` bytes at a time: the first row of every member side by side,
+/// then the second row of every member, and so on. With rows as tall as a member, each member is in one piece
+/// a member's length after the last; with shorter rows, the same row of every member is next to each other.
+/// Each region is handed out exactly once, as a `Storage`, which is what makes giving out mutable slices of it sound.
+pub(crate) struct Arena {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// Safety: the arena only frees its allocation once the last region is dropped, and regions never overlap,
+// so a region can go to another thread, or be read from several, the same as a `Vec<u8>` can
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    /// Allocates `regions` zeroed regions of `len` bytes in rows of `height`, returning the storage of each
+    fn split(regions: usize, len: usize, height: usize) -> Vec<Storage> {
+        let height = height.clamp(1, len.max(1));
+        let stride = regions
+            .checked_mul(height)
+            .expect("Arena size overflows usize");
+        let size = len
+            .div_ceil(height)
+            .checked_mul(stride)
+            .expect("Arena size overflows usize");
+        let bytes = Box::into_raw(vec![0u8; size].into_boxed_slice());
+        // Safety: a pointer from `Box::into_raw` is never null
+        let ptr = unsafe { NonNull::new_unchecked(bytes as *mut u8) };
+        let arena = Arc::new(Arena { ptr, len: size });
+        (0..regions)
+            .map(|i| Storage::Arena {
+                arena: arena.clone(),
+                start: i * height,
+                len,
+                height,
+                stride,
+            })
+            .collect()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        // Safety: the pointer and length came from the boxed slice allocated in `split`, and every region
+        // borrowing from it holds the `Arc` this is dropped from, so none are left
+        unsafe {
+            drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                self.ptr.as_ptr(),
+                self.len,
+            )));
+        }
+    }
+}
+
+/// The bytes a drive stores, either its own vector or its region of an array's arena.
+/// Cloning always copies the bytes into a vector of their own, a region is never shared.
+///
+/// A region in rows shorter than itself isn't contiguous, so bytes are handed out a row at a time where they can be
+/// borrowed, and copied together where they span rows.
+pub(crate) enum Storage {
+    Owned(Vec<u8>),
+    Arena {
+        arena: Arc<Arena>,
+        /// Where the region's first row starts in the arena
+        start: usize,
+        len: usize,
+        /// Bytes of the region in each row
+        height: usize,
+        /// Distance from a row of the region to its next
+        stride: usize,
+    },
+}
+
+impl Storage {
+    /// Returns storage for `regions` drives of `len` bytes each, all in one allocation with every drive in one piece
+    pub(crate) fn arena(regions: usize, len: usize) -> Vec<Storage> {
+        Arena::split(regions, len, len)
+    }
+
+    /// Returns storage for `regions` drives of `len` bytes each, all in one allocation in rows of `height` bytes,
+    /// so the same row of every drive is in one run of memory
+    pub(crate) fn striped_arena(regions: usize, len: usize, height: usize) -> Vec<Storage> {
+        Arena::split(regions, len, height)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Storage::Owned(data) => data.len(),
+            Storage::Arena { len, .. } => *len,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the bytes of `range` if they're in one piece, which they always are within a row
+    pub(crate) fn contiguous(&self, range: Range<usize>) -> Option<&[u8]> {
+        match self {
+            Storage::Owned(data) => Some(&data[range]),
+            Storage::Arena {
+                arena,
+                start,
+                len,
+                height,
+                stride,
+            } => {
+                assert!(
+                    range.start <= range.end && range.end <= *len,
+                    "Range {:?} out of bounds of storage of {} bytes",
+                    range,
+                    len
+                );
+                if range.is_empty() {
+                    return Some(&[]);
+                }
+                let row = range.start / height;
+                if (range.end - 1) / height != row {
+                    return None;
+                }
+                let at = start + row * stride + range.start % height;
+                // Safety: the range lies inside one row of the region, which is inside the arena,
+                // and no other storage has this region
+                Some(unsafe { std::slice::from_raw_parts(arena.ptr.as_ptr().add(at), range.len()) })
+            }
+        }
+    }
+
+    /// Returns the bytes of `range` a row at a time
+    pub(crate) fn rows(&self, range: Range<usize>) -> impl Iterator<Item = &[u8]> + '_ {
+        let height = match self {
+            Storage::Owned(_) => range.end.max(1),
+            Storage::Arena { height, .. } => *height,
+        };
+        let end = range.end;
+        let mut offset = range.start;
+        std::iter::from_fn(move || {
+            if offset >= end {
+                return None;
+            }
+            let row_end = ((offset / height + 1) * height).min(end);
+            let row = self.contiguous(offset..row_end);
+            offset = row_end;
+            row
+        })
+    }
+
+    /// Returns the bytes of `range`, borrowed if they're in one piece and copied together if not
+    pub(crate) fn slice(&self, range: Range<usize>) -> Cow<'_, [u8]> {
+        match self.contiguous(range.clone()) {
+            Some(bytes) => Cow::Borrowed(bytes),
+            None => Cow::Owned(self.rows(range).flatten().copied().collect()),
+        }
+    }
+
+    /// Returns every byte, see `slice()`
+    pub(crate) fn contents(&self) -> Cow<'_, [u8]> {
+        self.slice(0..self.len())
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        self.contents().into_owned()
+    }
+
+    /// Copies `data` in at `offset`, a row at a time
+    pub(crate) fn write(&mut self, offset: usize, data: &[u8]) {
+        let mut written = 0;
+        while written < data.len() {
+            let row = self.contiguous_mut(offset + written, data.len() - written);
+            let n = row.len();
+            row.copy_from_slice(&data[written..written + n]);
+            written += n;
+        }
+    }
+
+    pub(crate) fn fill(&mut self, value: u8) {
+        let mut offset = 0;
+        while offset < self.len() {
+            let row = self.contiguous_mut(offset, self.len() - offset);
+            row.fill(value);
+            offset += row.len();
+        }
+    }
+
+    /// Returns as much of the `len` bytes from `offset` as are in one piece, at least a byte
+    fn contiguous_mut(&mut self, offset: usize, len: usize) -> &mut [u8] {
+        match self {
+            Storage::Owned(data) => &mut data[offset..offset + len],
+            Storage::Arena {
+                arena,
+                start,
+                len: size,
+                height,
+                stride,
+            } => {
+                assert!(
+                    offset < *size && len <= *size - offset,
+                    "{} bytes at {} out of bounds of storage of {} bytes",
+                    len,
+                    offset,
+                    size
+                );
+                let run = len.min(*height - offset % *height);
+                let at = *start + offset / *height * *stride + offset % *height;
+                // Safety: the run lies inside one row of the region, which is inside the arena,
+                // and `&mut self` makes this the only borrow of the region
+                unsafe { std::slice::from_raw_parts_mut(arena.ptr.as_ptr().add(at), run) }
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Storage {
+    fn from(value: Vec<u8>) -> Self {
+        Storage::Owned(value)
+    }
+}
+
+impl Index<usize> for Storage {
+    type Output = u8;
+
+    fn index(&self, offset: usize) -> &u8 {
+        &self
+            .contiguous(offset..offset + 1)
+            .expect("A byte is always in one piece")[0]
+    }
+}
+
+impl IndexMut<usize> for Storage {
+    fn index_mut(&mut self, offset: usize) -> &mut u8 {
+        &mut self.contiguous_mut(offset, 1)[0]
+    }
+}
+
+impl Clone for Storage {
+    fn clone(&self) -> Self {
+        Storage::Owned(self.to_vec())
+    }
+}
+
+impl PartialEq for Storage {
+    fn eq(&self, other: &Self) -> bool {
+        self.contents() == other.contents()
+    }
+}
+impl Eq for Storage {}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.contents(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_are_disjoint_and_outlive_each_other() {
+        let mut regions = Storage::arena(3, 4);
+        regions[1].write(0, &[1, 2, 3, 4]);
+        regions[2][0] = 9;
+        assert_eq!(*regions[0].contents(), [0; 4]);
+        assert_eq!(*regions[1].contents(), [1, 2, 3, 4]);
+
+        // One allocation, a stride apart
+        let at = |storage: &Storage| storage.contiguous(0..1).unwrap().as_ptr() as usize;
+        assert_eq!(at(&regions[2]) - at(&regions[1]), 4);
+        let copy = regions[1].clone();
+        assert!(matches!(copy, Storage::Owned(_)));
+        assert_eq!(copy, regions[1]);
+        let last = regions.pop().unwrap();
+        drop(regions);
+        assert_eq!(*last.contents(), [9, 0, 0, 0]);
+
+        // A region can go to another thread, and outlive the rest there
+        let moved = std::thread::spawn(move || last[0]).join().unwrap();
+        assert_eq!(moved, 9);
+    }
+
+    #[test]
+    fn striped_regions_interleave_by_row() {
+        let mut regions = Storage::striped_arena(3, 10, 4);
+        regions[1].write(2, &(1..=7).collect::<Vec<u8>>());
+        regions[0].fill(0xff);
+        assert_eq!(*regions[1].contents(), [0, 0, 1, 2, 3, 4, 5, 6, 7, 0]);
+        assert_eq!(*regions[0].contents(), [0xff; 10]);
+        assert_eq!(*regions[2].contents(), [0; 10]);
+
+        // The same row of every region is side by side, and a region's next row is a row of all of them on
+        let at = |storage: &Storage, offset: usize| {
+            storage.contiguous(offset..offset + 1).unwrap().as_ptr() as usize
+        };
+        assert_eq!(at(&regions[1], 0) - at(&regions[0], 0), 4);
+        assert_eq!(at(&regions[1], 4) - at(&regions[1], 0), 12);
+        // Within a row bytes are borrowed, across rows they have to be copied together
+        assert_eq!(regions[1].contiguous(4..8), Some(&[3, 4, 5, 6][..]));
+        assert!(regions[1].contiguous(2..6).is_none());
+        assert!(matches!(regions[1].slice(2..6), Cow::Owned(_)));
+        assert_eq!(*regions[1].slice(2..6), [1, 2, 3, 4]);
+        assert_eq!(
+            regions[1].rows(1..10).collect::<Vec<&[u8]>>(),
+            [&[0, 1, 2][..], &[3, 4, 5, 6], &[7, 0]]
+        );
+    }
+
+    #[test]
+    fn arena_drives_can_be_sent() {
+        let mut drives = crate::Drive::arena(2, 8);
+        let drive = drives.pop().unwrap();
+        assert_eq!(std::thread::spawn(move || drive.size()).join().unwrap(), 8);
+    }
+}
@@ -0,0 +1,737 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    generator::{syndrome, FromPower, Gen},
+    sim::{Io, RaidSim, RaidSimBuilder, Sequential, UniformRandom, Workload},
+};
+
+/// How much work each measurement of a benchmark does
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BenchConfig {
+    /// I/Os run for each of the throughput measurements
+    pub ops: usize,
+    /// Bytes read or written by each I/O
+    pub io_size: usize,
+    /// Seeds the random offsets and the data written
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            ops: 256,
+            io_size: 4096,
+            seed: 0,
+        }
+    }
+}
+
+/// Bytes moved over some real time
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Throughput {
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Returns the rate in bytes per real second, if any time passed at all
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        (!self.elapsed.is_zero()).then(|| self.bytes as f64 / self.elapsed.as_secs_f64())
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bytes_per_sec() {
+            Some(rate) => write!(f, "{:10.2} MB/s", rate / 1_000_000.0),
+            None => write!(f, "{:>10} MB/s", "-"),
+        }
+    }
+}
+
+/// Throughput of one array configuration, comparable with the reports of others
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BenchReport {
+    pub sequential_write: Throughput,
+    pub sequential_read: Throughput,
+    pub random_write: Throughput,
+    pub random_read: Throughput,
+    /// Random reads with a data member missing, so some of them are reconstructed from parity
+    pub degraded_read: Throughput,
+    /// Bytes written onto the replacement while it was rebuilt
+    pub rebuild: Throughput,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Sequential Write : {}", self.sequential_write)?;
+        writeln!(f, " Sequential Read : {}", self.sequential_read)?;
+        writeln!(f, "    Random Write : {}", self.random_write)?;
+        writeln!(f, "     Random Read : {}", self.random_read)?;
+        writeln!(f, "   Degraded Read : {}", self.degraded_read)?;
+        writeln!(f, "         Rebuild : {}", self.rebuild)
+    }
+}
+
+/// Runs `workload` for `ops` I/Os and times it
+fn measure(
+    sim: &mut RaidSim,
+    workload: &mut dyn Workload,
+    ops: usize,
+    seed: u64,
+) -> Result<Throughput> {
+    let started = Instant::now();
+    let report = sim.run_workload(workload, ops, seed)?;
+    Ok(Throughput {
+        bytes: report.bytes_read + report.bytes_written,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Builds an array with `builder` and measures its throughput, one access pattern after another on the same array
+pub fn run(builder: &RaidSimBuilder, config: &BenchConfig) -> Result<BenchReport> {
+    if config.io_size == 0 {
+        bail!("I/Os must move at least a byte");
+    }
+    let mut sim = builder.clone().build()?;
+    sim.init()?;
+    let (ops, io_size, seed) = (config.ops, config.io_size, config.seed);
+    let random = |read_percent| UniformRandom {
+        io_size,
+        read_percent,
+    };
+
+    let mut report = BenchReport {
+        sequential_write: measure(&mut sim, &mut Sequential::new(io_size, 0), ops, seed)?,
+        sequential_read: measure(&mut sim, &mut Sequential::new(io_size, 100), ops, seed)?,
+        random_write: measure(&mut sim, &mut random(0), ops, seed)?,
+        random_read: measure(&mut sim, &mut random(100), ops, seed)?,
+        ..BenchReport::default()
+    };
+
+    sim.fail_random_data();
+    report.degraded_read = measure(&mut sim, &mut random(100), ops, seed)?;
+
+    sim.replace_failed_drives();
+    let started = Instant::now();
+    let rebuilt = sim.rebuild()?.rebuilt.len();
+    report.rebuild = Throughput {
+        bytes: (rebuilt * sim.detail().drive_size) as u64,
+        elapsed: started.elapsed(),
+    };
+    Ok(report)
+}
+
+/// The failure scenario every scheme in a comparison is put through
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Scenario {
+    /// I/Os of the workload run healthy, and again degraded
+    pub ops: usize,
+    /// Seeds the workload and the data written
+    pub seed: u64,
+    /// Data members failed before the degraded run and rebuilt afterwards.
+    /// RAID10 loses one member of every pair before it loses a second.
+    pub failures: usize,
+}
+
+/// How one scheme fared in a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemeResult {
+    pub name: String,
+    /// Fraction of the raw capacity of the members that can hold data
+    pub capacity_efficiency: f64,
+    /// Bytes that reached the drives for every byte the healthy workload wrote
+    pub write_amplification: Option<f64>,
+    pub healthy: Throughput,
+    pub degraded: Throughput,
+    /// Bytes the rebuild read from the surviving members and wrote onto the replacements
+    pub rebuild_bytes: u64,
+    pub rebuild_time: Duration,
+}
+
+/// Schemes side by side, in the order they were given
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub schemes: Vec<SchemeResult>,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>10} {:>15} {:>15} {:>14} {:>12}",
+            "Scheme", "Capacity", "Write Amp", "Healthy", "Degraded", "Rebuild Bytes", "Rebuild"
+        )?;
+        for s in &self.schemes {
+            writeln!(
+                f,
+                "{:<16} {:>9.1}% {:>10} {} {} {:>14} {:>10.2}ms",
+                s.name,
+                s.capacity_efficiency * 100.0,
+                s.write_amplification
+                    .map_or("-".to_string(), |w| format!("{:.2}", w)),
+                s.healthy,
+                s.degraded,
+                s.rebuild_bytes,
+                s.rebuild_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A way of laying data out with redundancy, to be put through a comparison
+#[derive(Debug, Clone)]
+pub enum Scheme {
+    /// A RAID5 or RAID6 array as the sim builds it
+    Array(RaidSimBuilder),
+    /// RS(k,m), `data` data members and `parity` members of Reed-Solomon parity, any `parity` of which can be lost.
+    /// Parity member `j` holds syndrome `j` of each row, so the first two are P and Q.
+    ReedSolomon {
+        data: usize,
+        parity: usize,
+        drive_size: usize,
+    },
+    /// RAID10, `pairs` mirrored pairs of members with data striped across the pairs `chunk_size` bytes at a time
+    Raid10 {
+        pairs: usize,
+        drive_size: usize,
+        chunk_size: usize,
+    },
+}
+
+impl From<RaidSimBuilder> for Scheme {
+    fn from(builder: RaidSimBuilder) -> Self {
+        Scheme::Array(builder)
+    }
+}
+
+/// Puts one scheme through `scenario`
+fn compare_one<W: Workload>(
+    scheme: &Scheme,
+    workload: &impl Fn() -> W,
+    scenario: &Scenario,
+) -> Result<SchemeResult> {
+    let mut model = match *scheme {
+        Scheme::Array(ref builder) => return compare_array(builder, workload, scenario),
+        Scheme::ReedSolomon {
+            data,
+            parity,
+            drive_size,
+        } => Model::new(ModelLayout::ReedSolomon { data, parity }, drive_size)?,
+        Scheme::Raid10 {
+            pairs,
+            drive_size,
+            chunk_size,
+        } => Model::new(ModelLayout::Raid10 { pairs, chunk_size }, drive_size)?,
+    };
+    let raw = (model.members.len() * model.drive_size) as f64;
+
+    let healthy = model.measure(&mut workload(), scenario.ops, scenario.seed)?;
+    let write_amplification = (model.logical_written > 0)
+        .then(|| model.physical_written as f64 / model.logical_written as f64);
+    model.fail(scenario.failures);
+    let degraded = model.measure(&mut workload(), scenario.ops, scenario.seed)?;
+
+    let started = Instant::now();
+    let rebuild_bytes = model.rebuild()?;
+    Ok(SchemeResult {
+        name: String::new(),
+        capacity_efficiency: model.size() as f64 / raw,
+        write_amplification,
+        healthy,
+        degraded,
+        rebuild_bytes,
+        rebuild_time: started.elapsed(),
+    })
+}
+
+/// Puts an array the sim builds through `scenario`
+fn compare_array<W: Workload>(
+    builder: &RaidSimBuilder,
+    workload: &impl Fn() -> W,
+    scenario: &Scenario,
+) -> Result<SchemeResult> {
+    let mut sim = builder.clone().build()?;
+    sim.init()?;
+    let detail = sim.detail();
+    let raw = (detail.num_drives * detail.drive_size) as f64;
+
+    let healthy = measure(&mut sim, &mut workload(), scenario.ops, scenario.seed)?;
+    let write_amplification = sim.stats().write_amplification();
+    for _ in 0..scenario.failures {
+        sim.fail_random_data();
+    }
+    let degraded = measure(&mut sim, &mut workload(), scenario.ops, scenario.seed)?;
+
+    sim.replace_failed_drives();
+    let plan = sim.repair_plan()?;
+    let started = Instant::now();
+    sim.rebuild()?;
+    Ok(SchemeResult {
+        name: String::new(),
+        capacity_efficiency: detail.size as f64 / raw,
+        write_amplification,
+        healthy,
+        degraded,
+        rebuild_bytes: plan.reads.values().chain(plan.writes.values()).sum(),
+        rebuild_time: started.elapsed(),
+    })
+}
+
+/// Runs the same workload and failure scenario against every scheme, each on an array of its own.
+/// `workload` makes a fresh workload for every run so they all see the same I/Os.
+///
+/// RAID5 and RAID6 run on the sim itself. RS(k,m) and RAID10 are arrays it can't build, so they run on a model
+/// that only keeps the members' contents, see [`Scheme`].
+pub fn compare<W: Workload>(
+    schemes: &[(&str, Scheme)],
+    workload: impl Fn() -> W,
+    scenario: &Scenario,
+) -> Result<Comparison> {
+    let schemes = schemes
+        .iter()
+        .map(|(name, scheme)| {
+            let result = compare_one(scheme, &workload, scenario)
+                .with_context(|| format!("Scheme {} didn't get through the scenario", name))?;
+            Ok(SchemeResult {
+                name: name.to_string(),
+                ..result
+            })
+        })
+        .collect::<Result<Vec<SchemeResult>>>()?;
+    Ok(Comparison { schemes })
+}
+
+/// How a `Model` spreads data and redundancy over its members
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ModelLayout {
+    /// Data members end to end like the sim lays them, then the parity members
+    ReedSolomon { data: usize, parity: usize },
+    /// Members `2 * i` and `2 * i + 1` mirror each other
+    Raid10 { pairs: usize, chunk_size: usize },
+}
+
+/// Just enough of an array to put a scheme the sim can't build through a scenario: the members' contents,
+/// which of them have failed, and how many bytes were written to the array and to its members.
+/// Every I/O goes to the members a byte at a time, as `RaidSim::run_workload()` does.
+struct Model {
+    layout: ModelLayout,
+    drive_size: usize,
+    members: Vec<Vec<u8>>,
+    failed: Vec<bool>,
+    logical_written: u64,
+    physical_written: u64,
+}
+
+impl Model {
+    fn new(layout: ModelLayout, drive_size: usize) -> Result<Self> {
+        let num_drives = match layout {
+            ModelLayout::ReedSolomon { data, parity } => {
+                if data == 0 || data + parity > 255 {
+                    bail!(
+                        "RS({},{}) needs a data member and at most 255 members in all",
+                        data,
+                        parity
+                    );
+                }
+                data + parity
+            }
+            ModelLayout::Raid10 { pairs, chunk_size } => {
+                if pairs == 0 || chunk_size == 0 || !drive_size.is_multiple_of(chunk_size) {
+                    bail!(
+                        "RAID10 needs a pair of members holding a whole number of {} byte chunks",
+                        chunk_size
+                    );
+                }
+                2 * pairs
+            }
+        };
+        if drive_size == 0 {
+            bail!("Drives must hold at least one byte");
+        }
+        Ok(Self {
+            layout,
+            drive_size,
+            members: vec![vec![0; drive_size]; num_drives],
+            failed: vec![false; num_drives],
+            logical_written: 0,
+            physical_written: 0,
+        })
+    }
+
+    /// Returns the number of bytes storable
+    fn size(&self) -> u64 {
+        let data_drives = match self.layout {
+            ModelLayout::ReedSolomon { data, .. } => data,
+            ModelLayout::Raid10 { pairs, .. } => pairs,
+        };
+        (data_drives * self.drive_size) as u64
+    }
+
+    /// Returns the member, or the mirrored pair for RAID10, and the offset on it a logical byte is stored at
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        match self.layout {
+            ModelLayout::ReedSolomon { .. } => (offset / self.drive_size, offset % self.drive_size),
+            ModelLayout::Raid10 { pairs, chunk_size } => {
+                let chunk = offset / chunk_size;
+                (
+                    chunk % pairs,
+                    chunk / pairs * chunk_size + offset % chunk_size,
+                )
+            }
+        }
+    }
+
+    /// Returns the data row at `pos`, solving for any bytes on failed data members with the surviving parity
+    fn data_row(&self, pos: usize) -> Result<Vec<u8>> {
+        let ModelLayout::ReedSolomon { data, parity } = self.layout else {
+            unreachable!("Only Reed-Solomon has rows of parity");
+        };
+        let mut row = (0..data).map(|i| self.members[i][pos]).collect::<Vec<u8>>();
+        let lost = (0..data)
+            .filter(|i| self.failed[*i])
+            .collect::<Vec<usize>>();
+        if lost.is_empty() {
+            return Ok(row);
+        }
+        let syndromes = (0..parity)
+            .filter(|j| !self.failed[data + j])
+            .take(lost.len())
+            .collect::<Vec<usize>>();
+        if syndromes.len() < lost.len() {
+            bail!(
+                "{} data members lost with {} parity members left",
+                lost.len(),
+                syndromes.len()
+            );
+        }
+        for i in &lost {
+            row[*i] = 0;
+        }
+        // Each syndrome less what the surviving data adds to it is a sum over the lost bytes alone,
+        // so they're what solves a system of one equation per syndrome
+        let mut equations = syndromes
+            .iter()
+            .map(|j| {
+                let mut equation = lost
+                    .iter()
+                    .map(|i| Gen::from_power(i * j))
+                    .collect::<Vec<Gen>>();
+                equation.push(Gen::from(self.members[data + j][pos] ^ syndrome(&row, *j)));
+                equation
+            })
+            .collect::<Vec<Vec<Gen>>>();
+        let n = lost.len();
+        for col in 0..n {
+            let Some(pivot) = (col..n).find(|r| equations[*r][col] != Gen::zero()) else {
+                bail!(
+                    "Syndromes {:?} can't tell members {:?} apart",
+                    syndromes,
+                    lost
+                );
+            };
+            equations.swap(col, pivot);
+            let inverse = equations[col][col].inverse();
+            for term in &mut equations[col][col..] {
+                *term = *term * inverse;
+            }
+            let pivot = equations[col].clone();
+            for r in (0..n).filter(|r| *r != col) {
+                let factor = equations[r][col];
+                for (term, p) in equations[r][col..].iter_mut().zip(&pivot[col..]) {
+                    // XORing the values, `Gen`'s `+` turns x + x into g^0 rather than zero
+                    *term = Gen::from(*term ^ (factor * *p));
+                }
+            }
+        }
+        for (equation, i) in equations.iter().zip(&lost) {
+            row[*i] = equation[n].value();
+        }
+        Ok(row)
+    }
+
+    fn read(&self, offset: usize) -> Result<u8> {
+        let (index, pos) = self.locate(offset);
+        match self.layout {
+            ModelLayout::ReedSolomon { .. } if self.failed[index] => Ok(self.data_row(pos)?[index]),
+            ModelLayout::ReedSolomon { .. } => Ok(self.members[index][pos]),
+            ModelLayout::Raid10 { .. } => {
+                match (2 * index..2 * index + 2).find(|m| !self.failed[*m]) {
+                    Some(member) => Ok(self.members[member][pos]),
+                    None => bail!("Both members of pair {} have failed", index),
+                }
+            }
+        }
+    }
+
+    /// Writes a byte, updating parity from the difference to the old one or writing every copy
+    fn write(&mut self, offset: usize, value: u8) -> Result<()> {
+        let (index, pos) = self.locate(offset);
+        self.logical_written += 1;
+        let written = match self.layout {
+            ModelLayout::ReedSolomon { data, parity } => {
+                let delta = self.read(offset)? ^ value;
+                let mut written = vec![index];
+                written.extend((0..parity).map(|j| data + j));
+                for j in 0..parity {
+                    self.members[data + j][pos] ^= Gen::from_power(index * j) * delta;
+                }
+                self.members[index][pos] = value;
+                written
+            }
+            ModelLayout::Raid10 { .. } => {
+                if (2 * index..2 * index + 2).all(|m| self.failed[m]) {
+                    bail!("Both members of pair {} have failed", index);
+                }
+                for m in 2 * index..2 * index + 2 {
+                    self.members[m][pos] = value;
+                }
+                vec![2 * index, 2 * index + 1]
+            }
+        };
+        // Nothing reaches a failed member, it's rebuilt from the rest
+        self.physical_written += written.iter().filter(|m| !self.failed[**m]).count() as u64;
+        Ok(())
+    }
+
+    /// Fails `failures` members: data members first for Reed-Solomon, one member of every pair first for RAID10
+    fn fail(&mut self, failures: usize) {
+        let order = match self.layout {
+            ModelLayout::ReedSolomon { .. } => (0..self.members.len()).collect::<Vec<usize>>(),
+            ModelLayout::Raid10 { pairs, .. } => (0..pairs)
+                .map(|p| 2 * p)
+                .chain((0..pairs).map(|p| 2 * p + 1))
+                .collect(),
+        };
+        for member in order.into_iter().take(failures) {
+            self.failed[member] = true;
+        }
+    }
+
+    /// Runs a workload the same way `RaidSim::run_workload()` does and times it
+    fn measure(
+        &mut self,
+        workload: &mut dyn Workload,
+        ops: usize,
+        seed: u64,
+    ) -> Result<Throughput> {
+        let started = Instant::now();
+        let size = self.size();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut bytes = 0;
+        for _ in 0..ops {
+            match workload.next_io(size, &mut rng) {
+                Io::Read { offset, len } => {
+                    for i in offset as usize..offset as usize + len {
+                        self.read(i)?;
+                    }
+                    bytes += len as u64;
+                }
+                Io::Write { offset, len } => {
+                    let data = (0..len).map(|_| rng.random()).collect::<Vec<u8>>();
+                    for (i, byte) in data.into_iter().enumerate() {
+                        self.write(offset as usize + i, byte)?;
+                    }
+                    bytes += len as u64;
+                }
+            }
+        }
+        Ok(Throughput {
+            bytes,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Rebuilds every failed member onto a blank one, returning the bytes read from the survivors and written
+    fn rebuild(&mut self) -> Result<u64> {
+        let lost = (0..self.members.len())
+            .filter(|m| self.failed[*m])
+            .collect::<Vec<usize>>();
+        if lost.is_empty() {
+            return Ok(0);
+        }
+        let mut rebuilt = vec![vec![0; self.drive_size]; lost.len()];
+        let read = match self.layout {
+            ModelLayout::ReedSolomon { data, .. } => {
+                for pos in 0..self.drive_size {
+                    let row = self.data_row(pos)?;
+                    for (member, contents) in lost.iter().zip(&mut rebuilt) {
+                        contents[pos] = match member.checked_sub(data) {
+                            Some(j) => syndrome(&row, j),
+                            None => row[*member],
+                        };
+                    }
+                }
+                // Every row takes as many surviving members as there are data members
+                data
+            }
+            ModelLayout::Raid10 { .. } => {
+                for (member, contents) in lost.iter().zip(&mut rebuilt) {
+                    let mirror = member ^ 1;
+                    if self.failed[mirror] {
+                        bail!("Both members of pair {} have failed", member / 2);
+                    }
+                    contents.copy_from_slice(&self.members[mirror]);
+                }
+                lost.len()
+            }
+        };
+        for (member, contents) in lost.iter().zip(rebuilt) {
+            self.members[*member] = contents;
+            self.failed[*member] = false;
+        }
+        Ok(((read + lost.len()) * self.drive_size) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::RaidMode;
+
+    #[test]
+    fn raid5_bench_measures_every_pattern() {
+        let builder = RaidSim::builder()
+            .mode(RaidMode::Raid5)
+            .drives(8, 4096)
+            .seed(1);
+        let config = BenchConfig {
+            ops: 16,
+            io_size: 512,
+            seed: 2,
+        };
+        let report = run(&builder, &config).unwrap();
+        for throughput in [
+            report.sequential_write,
+            report.sequential_read,
+            report.random_write,
+            report.random_read,
+            report.degraded_read,
+        ] {
+            assert_eq!(throughput.bytes, 16 * 512);
+        }
+        assert_eq!(report.rebuild.bytes, 4096);
+        assert!(report.to_string().contains("Degraded Read"));
+
+        let config = BenchConfig {
+            io_size: 0,
+            ..config
+        };
+        assert!(run(&builder, &config).is_err());
+    }
+
+    #[test]
+    fn raid6_costs_more_than_raid5() {
+        let schemes = [
+            (
+                "raid5",
+                RaidSim::builder()
+                    .mode(RaidMode::Raid5)
+                    .drives(8, 4096)
+                    .seed(1)
+                    .into(),
+            ),
+            ("raid6", RaidSim::builder().drives(8, 4096).seed(1).into()),
+        ];
+        let workload = || UniformRandom {
+            io_size: 256,
+            read_percent: 50,
+        };
+        let scenario = Scenario {
+            ops: 32,
+            seed: 3,
+            failures: 1,
+        };
+        let comparison = compare(&schemes, workload, &scenario).unwrap();
+        let [raid5, raid6] = &comparison.schemes[..] else {
+            panic!("Expected a result per scheme");
+        };
+        assert_eq!(raid5.name, "raid5");
+        assert_eq!(raid5.capacity_efficiency, 7.0 / 8.0);
+        assert_eq!(raid6.capacity_efficiency, 6.0 / 8.0);
+        assert!(raid6.write_amplification > raid5.write_amplification);
+        assert!(raid5.rebuild_bytes > 0);
+        assert!(comparison.to_string().lines().count() == 3);
+
+        // Two failures are more than RAID5 can take
+        let scenario = Scenario {
+            failures: 2,
+            ..scenario
+        };
+        let error = compare(&schemes, workload, &scenario).unwrap_err();
+        assert!(error.to_string().contains("raid5"));
+    }
+
+    #[test]
+    fn reed_solomon_and_raid10_go_through_the_scenario() {
+        let schemes = [
+            (
+                "rs(5,3)",
+                Scheme::ReedSolomon {
+                    data: 5,
+                    parity: 3,
+                    drive_size: 4096,
+                },
+            ),
+            (
+                "raid10",
+                Scheme::Raid10 {
+                    pairs: 4,
+                    drive_size: 4096,
+                    chunk_size: 512,
+                },
+            ),
+        ];
+        let workload = || UniformRandom {
+            io_size: 256,
+            read_percent: 50,
+        };
+        let scenario = Scenario {
+            ops: 32,
+            seed: 3,
+            failures: 3,
+        };
+        let comparison = compare(&schemes, workload, &scenario).unwrap();
+        let [rs, raid10] = &comparison.schemes[..] else {
+            panic!("Expected a result per scheme");
+        };
+        assert_eq!(rs.capacity_efficiency, 5.0 / 8.0);
+        assert_eq!(rs.write_amplification, Some(4.0));
+        assert_eq!(rs.degraded.bytes, 32 * 256);
+        // Three members rebuilt from five survivors
+        assert_eq!(rs.rebuild_bytes, 8 * 4096);
+        assert_eq!(raid10.capacity_efficiency, 0.5);
+        assert_eq!(raid10.write_amplification, Some(2.0));
+        assert_eq!(raid10.rebuild_bytes, 6 * 4096);
+
+        // What a model reconstructs is what was written
+        let mut model = Model::new(ModelLayout::ReedSolomon { data: 5, parity: 3 }, 4096).unwrap();
+        model.measure(&mut workload(), 64, 1).unwrap();
+        let before = model.members.clone();
+        model.fail(3);
+        model.failed[1] = false;
+        model.failed[6] = true;
+        for offset in (0..model.size() as usize).step_by(7) {
+            let (index, pos) = model.locate(offset);
+            assert_eq!(model.read(offset).unwrap(), before[index][pos]);
+        }
+        model.rebuild().unwrap();
+        assert_eq!(model.members, before);
+
+        // A fourth loss is one more than RS(5,3) can take, and RAID10 loses a whole pair
+        let scenario = Scenario {
+            failures: 5,
+            ..scenario
+        };
+        let error = compare(&schemes[..1], workload, &scenario).unwrap_err();
+        assert!(error.to_string().contains("rs(5,3)"));
+        assert!(compare(&schemes[1..], workload, &scenario).is_err());
+    }
+}
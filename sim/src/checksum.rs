@@ -0,0 +1,126 @@
+use static_init::dynamic;
+
+/// Reflected CRC-32C (Castagnoli) polynomial, as used by iSCSI and ext4 metadata
+const CRC32C_POLYNOMIAL: u32 = 0x82f6_3b78;
+
+/// Reflected CRC-64/NVME polynomial, as used by NVMe end-to-end protection
+const CRC64_NVME_POLYNOMIAL: u64 = 0x9a6c_9329_ac4b_c9b5;
+
+/// CRC-16/T10-DIF polynomial, as used by the guard tag of SCSI protection information
+const CRC16_T10DIF_POLYNOMIAL: u16 = 0x8bb7;
+
+/// Builds the byte at a time lookup table for a reflected CRC
+fn crc_table(polynomial: u64) -> [u64; 256] {
+    let mut table = [0u64; 256];
+    for (byte, entry) in table.iter_mut().enumerate() {
+        let mut crc = byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+        }
+        *entry = crc;
+    }
+    table
+}
+
+#[dynamic]
+static CRC32C_TABLE: [u64; 256] = crc_table(CRC32C_POLYNOMIAL as u64);
+
+#[dynamic]
+static CRC64_NVME_TABLE: [u64; 256] = crc_table(CRC64_NVME_POLYNOMIAL);
+
+/// Lookup table for CRC-16/T10-DIF, which unlike the others shifts most significant bit first
+#[dynamic]
+static CRC16_T10DIF_TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    for (byte, entry) in table.iter_mut().enumerate() {
+        let mut crc = (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ CRC16_T10DIF_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+        *entry = crc;
+    }
+    table
+};
+
+/// Runs a reflected CRC `width` bits wide over `data`, starting from and finishing with every bit inverted
+fn crc(table: &[u64; 256], width: u32, data: &[u8]) -> u64 {
+    let mask = u64::MAX >> (64 - width);
+    mask ^ data.iter().fold(mask, |crc, byte| {
+        table[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Computes the CRC-32C of `data`
+pub fn crc32c(data: &[u8]) -> u32 {
+    crc(&CRC32C_TABLE, 32, data) as u32
+}
+
+/// Computes the CRC-64/NVME of `data`
+pub fn crc64_nvme(data: &[u8]) -> u64 {
+    crc(&CRC64_NVME_TABLE, 64, data)
+}
+
+/// Computes the CRC-16/T10-DIF of `data`
+pub fn crc16_t10dif(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        CRC16_T10DIF_TABLE[((crc >> 8) ^ *byte as u16) as usize] ^ (crc << 8)
+    })
+}
+
+/// The checksums drives can keep over their sectors
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ChecksumAlgorithm {
+    /// 32 bit CRC with the Castagnoli polynomial
+    #[default]
+    Crc32c,
+    /// 64 bit CRC with the NVMe polynomial, for when 32 bits of collision resistance isn't enough
+    Crc64Nvme,
+}
+
+impl ChecksumAlgorithm {
+    /// Computes the checksum of `data`, widened to 64 bits
+    pub fn checksum(self, data: &[u8]) -> u64 {
+        match self {
+            ChecksumAlgorithm::Crc32c => crc32c(data) as u64,
+            ChecksumAlgorithm::Crc64Nvme => crc64_nvme(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        // The standard check input for every CRC catalogue entry
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc64_nvme(b"123456789"), 0xae8b_1486_0a79_9888);
+        assert_eq!(crc16_t10dif(b"123456789"), 0xd0db);
+        assert_eq!(crc32c(&[]), 0);
+    }
+
+    #[test]
+    fn compensating_flips_are_caught() {
+        // Flipping the same bit in two bytes leaves an XOR checksum unchanged
+        let data = [0x5au8; 512];
+        let mut flipped = data;
+        flipped[10] ^= 0x01;
+        flipped[300] ^= 0x01;
+        assert_eq!(
+            data.iter().fold(0, |acc, x| acc ^ x),
+            flipped.iter().fold(0, |acc, x| acc ^ x)
+        );
+        for algorithm in [ChecksumAlgorithm::Crc32c, ChecksumAlgorithm::Crc64Nvme] {
+            assert_ne!(algorithm.checksum(&data), algorithm.checksum(&flipped));
+        }
+    }
+}
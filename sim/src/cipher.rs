@@ -0,0 +1,64 @@
+use crate::drive::SECTOR_SIZE;
+
+/// Mixes the bits of `x` with the SplitMix64 finalizer
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// A keyed transform standing in for XTS, the mode self-encrypting drives use.
+///
+/// Every sector is tweaked by its number, so the same data written to two sectors never looks the same on the media.
+/// It's a keystream XOR and nowhere near secure, but encrypting and decrypting are the same operation
+/// and without the key the media is noise, which is all the simulation needs.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SectorCipher {
+    key: u64,
+}
+
+impl SectorCipher {
+    pub fn new(key: u64) -> Self {
+        Self { key }
+    }
+
+    /// Returns the keystream byte for drive offset `offset`
+    fn keystream(&self, offset: usize) -> u8 {
+        let sector = (offset / SECTOR_SIZE) as u64;
+        let word = (offset % SECTOR_SIZE / 8) as u64;
+        let block = mix(self.key ^ mix(sector) ^ word.rotate_left(32));
+        block.to_le_bytes()[offset % 8]
+    }
+
+    /// Encrypts or decrypts `data` found at drive offset `offset` in place
+    pub fn apply(&self, offset: usize, data: &mut [u8]) {
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte ^= self.keystream(offset + i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sectors_are_tweaked() {
+        let cipher = SectorCipher::new(0x1234);
+        let mut media = vec![0u8; 2 * SECTOR_SIZE];
+        cipher.apply(0, &mut media);
+        assert_ne!(media[..SECTOR_SIZE], media[SECTOR_SIZE..]);
+
+        // Decrypting part of the way in lines up with the keystream of the whole
+        cipher.apply(
+            SECTOR_SIZE - 3,
+            &mut media[SECTOR_SIZE - 3..SECTOR_SIZE + 5],
+        );
+        assert!(media[SECTOR_SIZE - 3..SECTOR_SIZE + 5]
+            .iter()
+            .all(|b| *b == 0));
+        SectorCipher::new(0x1235).apply(0, &mut media[..SECTOR_SIZE]);
+        assert!(media[..16].iter().any(|b| *b != 0));
+    }
+}
@@ -0,0 +1,138 @@
+use std::convert::TryFrom;
+
+use anyhow::{bail, Result};
+
+/// Starts a serialized format with its magic and the version it was written in
+pub(crate) fn write_header(out: &mut Encoder, magic: &[u8; 8], version: u16) {
+    out.0.extend_from_slice(magic);
+    out.0.extend_from_slice(&version.to_le_bytes());
+}
+
+/// Checks that `bytes` start with `magic` and a version no newer than `current`,
+/// returning the version they were written in and what follows it. `what` names the format in errors.
+pub(crate) fn read_header<'a>(
+    bytes: &'a [u8],
+    magic: &[u8; 8],
+    current: u16,
+    what: &str,
+) -> Result<(u16, &'a [u8])> {
+    let Some(rest) = bytes.strip_prefix(magic) else {
+        bail!("Not {}", what);
+    };
+    let Some((version, rest)) = rest.split_first_chunk::<2>() else {
+        bail!("Input ends partway through");
+    };
+    let version = u16::from_le_bytes(*version);
+    if version > current {
+        bail!(
+            "Input is version {} of {}, only up to version {} can be read",
+            version,
+            what,
+            current
+        );
+    }
+    Ok((version, rest))
+}
+
+/// Writes the fields of a serialized format, integers as LEB128 so small ones take a byte
+#[derive(Debug, Default)]
+pub(crate) struct Encoder(pub(crate) Vec<u8>);
+
+impl Encoder {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn u64(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn option(&mut self, value: Option<u64>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.u64(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+/// Reads back the fields an `Encoder` wrote
+#[derive(Debug)]
+pub(crate) struct Decoder<'a>(pub(crate) &'a [u8]);
+
+impl Decoder<'_> {
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        let Some((&value, rest)) = self.0.split_first() else {
+            bail!("Input ends partway through");
+        };
+        self.0 = rest;
+        Ok(value)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Integer is more than 64 bits long");
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        let value = self.u64()?;
+        u16::try_from(value).or_else(|_| bail!("{} doesn't fit in 16 bits", value))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        let value = self.u64()?;
+        u32::try_from(value).or_else(|_| bail!("{} doesn't fit in 32 bits", value))
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => bail!("Expected a bool, got {}", other),
+        }
+    }
+
+    pub(crate) fn option(&mut self) -> Result<Option<u64>> {
+        Ok(if self.bool()? {
+            Some(self.u64()?)
+        } else {
+            None
+        })
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&[u8]> {
+        let len = self.usize()?;
+        if len > self.0.len() {
+            bail!("Input ends partway through");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+}
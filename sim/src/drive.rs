@@ -0,0 +1,1536 @@
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    fmt, fs,
+    io::{Seek, SeekFrom, Write},
+    ops::{Index, IndexMut, Range},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    arena::Storage,
+    checksum::ChecksumAlgorithm,
+    cipher::SectorCipher,
+    codec::{read_header, write_header, Decoder, Encoder},
+    merkle::MerkleTree,
+    protection::{ProtectionInfo, TagField},
+    superblock::{MdSuperblock, Superblock, MD_DATA_OFFSET, MD_SUPER_OFFSET},
+};
+
+/// Size of a sector in bytes, the smallest unit a drive can fail to read
+pub const SECTOR_SIZE: usize = 512;
+
+/// Marks the start of a drive image
+const DRIVE_IMAGE_MAGIC: &[u8; 8] = b"RAIDIMG\0";
+
+/// Version of the drive image format written by `Drive::to_image()`, older versions are migrated when read.
+///
+/// Version 0 is a bare dump of a drive's contents with no header at all, how drives were saved before images had one.
+/// With nothing to tell it apart from any other bytes it's only loaded when asked for, by `Drive::from_v0_image()`.
+/// Version 1 added the header and everything about the drive besides its contents.
+/// Version 2 ends the image with a checksum of everything before it, so a damaged image file is refused.
+/// Version 3 leaves out blocks of zeros, storing the contents as the extents that hold anything.
+/// Version 4 adds the array's chunk size to the superblock.
+pub const DRIVE_IMAGE_VERSION: u16 = 4;
+
+/// Largest drive an image may declare, so a damaged or made up size can't ask for more memory than any drive needs
+const MAX_IMAGE_DRIVE_SIZE: usize = 1 << 32;
+
+/// Granularity zeros are skipped at when exporting, a block has to be all zeros to be left out
+const SPARSE_BLOCK: usize = 4096;
+
+/// Returns the ranges of `data` holding anything other than zeros, in whole `SPARSE_BLOCK`s merged where they touch
+fn extents(data: &[u8]) -> Vec<Range<usize>> {
+    let mut extents: Vec<Range<usize>> = vec![];
+    for (i, block) in data.chunks(SPARSE_BLOCK).enumerate() {
+        if block.iter().all(|b| *b == 0) {
+            continue;
+        }
+        let start = i * SPARSE_BLOCK;
+        let end = start + block.len();
+        match extents.last_mut() {
+            Some(extent) if extent.end == start => extent.end = end,
+            _ => extents.push(start..end),
+        }
+    }
+    extents
+}
+
+/// Describes a drive that intermittently stops responding
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Hang {
+    /// On average, one in every `one_in` accesses hangs
+    pub one_in: u32,
+    /// How long the drive hangs for, in simulated milliseconds
+    pub duration: u64,
+}
+
+/// Counters describing how a drive has behaved over its lifetime
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DriveStats {
+    /// Number of accesses that hung before responding
+    pub hangs: u64,
+    /// Total simulated milliseconds spent hung
+    pub hang_time: u64,
+    /// Number of accesses that hit the timeout and got the drive kicked
+    pub timeouts: u64,
+    /// Number of reads that failed without the drive failing
+    pub read_errors: u64,
+    /// Number of times a failed read was retried
+    pub retries: u64,
+    /// Total simulated milliseconds spent backing off between retries
+    pub backoff_time: u64,
+    /// Number of reads that gave up on the drive and fell back to parity
+    pub fallbacks: u64,
+    /// Number of sectors added to the bad block list
+    pub marked_bad: u64,
+    /// Number of bytes written to the drive, the wear it has taken
+    pub bytes_written: u64,
+}
+
+/// Why an access to a drive failed, carried inside the `anyhow::Error` it fails with.
+/// Handlers can find it with `downcast_ref::<DriveError>()` instead of matching on the message.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DriveError {
+    /// The drive has failed and can't be accessed at all
+    Failed,
+    /// The drive is refusing writes
+    WriteProtected,
+    /// The access runs past the end of the drive
+    OutOfBounds {
+        offset: usize,
+        len: usize,
+        size: usize,
+    },
+    /// The drive hung for at least its timeout and was kicked
+    TimedOut { after: u64 },
+    /// The media couldn't read the sector
+    Unreadable { sector: usize },
+    /// The sector was written to while the drive was write-protected, what it holds is out of date
+    MissedWrites { sector: usize },
+    /// The sector doesn't match the checksum the drive keeps of it
+    ChecksumMismatch { sector: usize },
+    /// The sector is on the drive's bad block list, so it isn't read at all
+    MarkedBad { sector: usize },
+}
+
+impl fmt::Display for DriveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriveError::Failed => write!(f, "Failed to access drive, failed"),
+            DriveError::WriteProtected => write!(f, "Drive is write-protected"),
+            DriveError::OutOfBounds { offset, len, size } => write!(
+                f,
+                "Out of bounds access, at offset {} and length {} in drive of size {}",
+                offset, len, size
+            ),
+            DriveError::TimedOut { after } => write!(f, "Drive timed out after {}ms", after),
+            DriveError::Unreadable { sector } => {
+                write!(f, "Unrecoverable read error in sector {}", sector)
+            }
+            DriveError::MissedWrites { sector } => {
+                write!(f, "Sector {} missed writes while write-protected", sector)
+            }
+            DriveError::ChecksumMismatch { sector } => {
+                write!(f, "Checksum mismatch in sector {}", sector)
+            }
+            DriveError::MarkedBad { sector } => write!(f, "Sector {} is marked bad", sector),
+        }
+    }
+}
+
+impl std::error::Error for DriveError {}
+
+/// Where the contents of two drives differ, see `Drive::compare()`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DiffReport {
+    /// Runs of differing offsets, in order
+    pub ranges: Vec<Range<usize>>,
+    /// Number of bytes that differ, bytes past the end of the smaller drive included
+    pub differing: usize,
+}
+
+impl DiffReport {
+    /// Whether the drives hold exactly the same bytes
+    pub fn is_identical(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the sectors holding at least one differing byte
+    pub fn sectors(&self) -> BTreeSet<usize> {
+        self.ranges
+            .iter()
+            .flat_map(|r| r.start / SECTOR_SIZE..=(r.end - 1) / SECTOR_SIZE)
+            .collect()
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Ranges listed before the rest are summed up
+        const SHOWN: usize = 8;
+        if self.is_identical() {
+            return write!(f, "identical");
+        }
+        write!(
+            f,
+            "{} bytes differ in {} ranges:",
+            self.differing,
+            self.ranges.len()
+        )?;
+        for range in self.ranges.iter().take(SHOWN) {
+            write!(f, " {:?}", range)?;
+        }
+        if self.ranges.len() > SHOWN {
+            write!(f, " and {} more", self.ranges.len() - SHOWN)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checksums of every sector of a drive, kept up to date as it's written
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct SectorChecksums {
+    algorithm: ChecksumAlgorithm,
+    sums: Vec<u64>,
+}
+
+/// What a drive draws its injected faults from, its own so they don't depend on what else the array does
+#[derive(Debug, Clone)]
+struct FaultRng(RefCell<StdRng>);
+
+impl FaultRng {
+    fn random_ratio(&self, numerator: u32, denominator: u32) -> bool {
+        self.0.borrow_mut().random_ratio(numerator, denominator)
+    }
+}
+
+/// Represents a hard drive with variable bytes
+#[derive(Debug, Clone)]
+pub struct Drive {
+    data: Storage,
+    failed: Cell<bool>,
+    formatted: bool,
+    /// Set by a secure erase until the drive is formatted or given a superblock again
+    erased: bool,
+    hang: Option<Hang>,
+    timeout: Option<u64>,
+    flaky: Option<u32>,
+    latent: BTreeSet<usize>,
+    read_only: bool,
+    /// Sectors that were written to while the drive was read-only, their contents are out of date
+    missed: BTreeSet<usize>,
+    bad_sectors: RefCell<BTreeSet<usize>>,
+    superblock: Option<Superblock>,
+    checksums: Option<SectorChecksums>,
+    merkle: Option<MerkleTree>,
+    protection: Option<Vec<ProtectionInfo>>,
+    /// Key the drive encrypts everything it stores with, the data above is what it decrypts to
+    key: Option<u64>,
+    faults: FaultRng,
+    stats: Cell<DriveStats>,
+}
+
+impl Drive {
+    /// Creates a drive filled with no data
+    pub fn empty(size: usize) -> Self {
+        Drive::from_data(vec![0u8; size])
+    }
+
+    /// Creates a drive from a vec of data
+    pub fn from_data(data: Vec<u8>) -> Self {
+        Self::from_storage(data.into())
+    }
+
+    /// Creates `count` empty drives of `size` bytes in one allocation, laid out one after another
+    /// so the same offset of every drive is `size` bytes from the next. Each drive only ever sees its own bytes,
+    /// and a clone or a resize moves them to an allocation of their own.
+    pub fn arena(count: usize, size: usize) -> Vec<Drive> {
+        Storage::arena(count, size)
+            .into_iter()
+            .map(Drive::from_storage)
+            .collect()
+    }
+
+    /// Creates `count` empty drives of `size` bytes in one allocation, laid out `height` bytes at a time,
+    /// so the same `height` bytes of every drive are next to each other. Reads spanning more than one run of `height`
+    /// bytes are copied together, as are whole drives for their checksums, Merkle trees and images.
+    pub fn striped_arena(count: usize, size: usize, height: usize) -> Vec<Drive> {
+        Storage::striped_arena(count, size, height)
+            .into_iter()
+            .map(Drive::from_storage)
+            .collect()
+    }
+
+    fn from_storage(data: Storage) -> Self {
+        Self {
+            data,
+            failed: Cell::new(false),
+            formatted: false,
+            erased: false,
+            hang: None,
+            timeout: None,
+            flaky: None,
+            latent: BTreeSet::new(),
+            read_only: false,
+            missed: BTreeSet::new(),
+            bad_sectors: RefCell::new(BTreeSet::new()),
+            superblock: None,
+            checksums: None,
+            merkle: None,
+            protection: None,
+            key: None,
+            faults: FaultRng(RefCell::new(StdRng::from_rng(&mut rand::rng()))),
+            stats: Cell::new(DriveStats::default()),
+        }
+    }
+
+    /// Returns the size of the drive in bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns true if the drive has not failed and is formatted
+    pub fn writeable(&self) -> bool {
+        !self.failed.get()
+    }
+
+    fn writeable_result(&self) -> Result<()> {
+        if self.writeable() {
+            Ok(())
+        } else {
+            Err(DriveError::Failed.into())
+        }
+    }
+
+    /// Refuses a write to a read-only drive, remembering the sectors in `offset..offset + len` as out of date
+    fn check_read_only(&mut self, offset: usize, len: usize) -> Result<()> {
+        if self.read_only {
+            self.missed
+                .extend(offset / SECTOR_SIZE..=(offset + len.max(1) - 1) / SECTOR_SIZE);
+            return Err(DriveError::WriteProtected.into());
+        }
+        Ok(())
+    }
+
+    /// Fails accesses to `offset..offset + len` that don't fit on the drive
+    fn check_bounds(&self, offset: usize, len: usize) -> Result<()> {
+        if offset
+            .checked_add(len)
+            .is_none_or(|end| end > self.data.len())
+        {
+            return Err(DriveError::OutOfBounds {
+                offset,
+                len,
+                size: self.data.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks the drive can be accessed and simulates it hanging.
+    /// A hang at least as long as the timeout gets the drive kicked, just as a controller would give up on it.
+    fn access(&self) -> Result<()> {
+        self.writeable_result()?;
+        let hang = match self.hang {
+            Some(hang) if self.faults.random_ratio(1, hang.one_in.max(1)) => hang,
+            _ => return Ok(()),
+        };
+        match self.timeout {
+            Some(timeout) if hang.duration >= timeout => {
+                self.record(|s| {
+                    s.hangs += 1;
+                    s.hang_time += timeout;
+                    s.timeouts += 1;
+                });
+                self.failed.set(true);
+                Err(DriveError::TimedOut { after: timeout }.into())
+            }
+            _ => {
+                self.record(|s| {
+                    s.hangs += 1;
+                    s.hang_time += hang.duration;
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Simulates the media failing to read any of the sectors in `offset..offset + len`,
+    /// and fails reads of sectors that no longer match their checksum
+    fn check_readable(&self, offset: usize, len: usize) -> Result<()> {
+        let flaky = matches!(self.flaky, Some(n) if self.faults.random_ratio(1, n.max(1)));
+        let sectors = offset / SECTOR_SIZE..=(offset + len.max(1) - 1) / SECTOR_SIZE;
+        let latent = sectors.clone().find(|sector| self.latent.contains(sector));
+        if flaky || latent.is_some() {
+            self.record(|s| s.read_errors += 1);
+            let sector = latent.unwrap_or(offset / SECTOR_SIZE);
+            return Err(DriveError::Unreadable { sector }.into());
+        }
+        if let Some(sector) = sectors.clone().find(|sector| self.missed.contains(sector)) {
+            return Err(DriveError::MissedWrites { sector }.into());
+        }
+        if let Some(sector) = sectors.clone().find(|sector| !self.verify_sector(*sector)) {
+            self.record(|s| s.read_errors += 1);
+            return Err(DriveError::ChecksumMismatch { sector }.into());
+        }
+        if let Some(protection) = &self.protection {
+            for sector in sectors {
+                let result = protection[sector].verify(&self.sector(sector), sector, "drive");
+                if result.is_err() {
+                    self.record(|s| s.read_errors += 1);
+                }
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewriting a sector lets the drive remap it, clearing any read errors in `offset..offset + len`
+    fn rewrite_sectors(&mut self, offset: usize, len: usize) {
+        for sector in offset / SECTOR_SIZE..=(offset + len.max(1) - 1) / SECTOR_SIZE {
+            self.latent.remove(&sector);
+            self.missed.remove(&sector);
+            self.bad_sectors.get_mut().remove(&sector);
+        }
+    }
+
+    /// Returns the bytes of `sector`
+    fn sector(&self, sector: usize) -> Cow<'_, [u8]> {
+        let start = sector * SECTOR_SIZE;
+        self.data
+            .slice(start..(start + SECTOR_SIZE).min(self.data.len()))
+    }
+
+    /// Brings the checksums of the sectors in `offset..offset + len` up to date after they were written
+    fn update_checksums(&mut self, offset: usize, len: usize) {
+        let checksums = match self.checksums.take() {
+            Some(checksums) if !self.data.is_empty() => checksums,
+            checksums => {
+                self.checksums = checksums;
+                return;
+            }
+        };
+        let mut sums = checksums.sums;
+        let (first, last) = (
+            offset / SECTOR_SIZE,
+            (offset + len.max(1) - 1) / SECTOR_SIZE,
+        );
+        for (sector, sum) in sums.iter_mut().enumerate().take(last + 1).skip(first) {
+            *sum = checksums.algorithm.checksum(&self.sector(sector));
+        }
+        self.checksums = Some(SectorChecksums { sums, ..checksums });
+    }
+
+    /// Starts keeping a checksum of every sector with `algorithm`, or stops with `None`.
+    /// The checksums are computed over whatever the drive holds right now.
+    pub fn set_checksum(&mut self, algorithm: Option<ChecksumAlgorithm>) {
+        let sectors = self.data.len().div_ceil(SECTOR_SIZE);
+        self.checksums = algorithm.map(|algorithm| SectorChecksums {
+            algorithm,
+            sums: vec![0; sectors],
+        });
+        self.update_checksums(0, self.data.len());
+    }
+
+    /// Returns the algorithm the drive's sector checksums are kept with, if it keeps any
+    pub fn checksum_algorithm(&self) -> Option<ChecksumAlgorithm> {
+        self.checksums.as_ref().map(|c| c.algorithm)
+    }
+
+    /// Returns whether `sector` still matches its checksum, always true for a drive that doesn't keep any
+    pub fn verify_sector(&self, sector: usize) -> bool {
+        match &self.checksums {
+            Some(checksums) => {
+                checksums.sums[sector] == checksums.algorithm.checksum(&self.sector(sector))
+            }
+            None => true,
+        }
+    }
+
+    /// Returns a checksum of the whole drive, if it keeps checksums
+    pub fn checksum(&self) -> Option<u64> {
+        self.checksum_algorithm()
+            .map(|algorithm| algorithm.checksum(&self.data.contents()))
+    }
+
+    /// Brings the sector checksums, Merkle tree and protection information up to date
+    /// after `offset..offset + len` was written
+    fn written(&mut self, offset: usize, len: usize) {
+        self.update_checksums(offset, len);
+        if let Some(tree) = &mut self.merkle {
+            tree.update(&self.data.contents(), offset, len);
+        }
+        if let Some(mut protection) = self.protection.take() {
+            let (first, last) = (
+                offset / SECTOR_SIZE,
+                (offset + len.max(1) - 1) / SECTOR_SIZE,
+            );
+            for (sector, info) in protection.iter_mut().enumerate().take(last + 1).skip(first) {
+                *info = ProtectionInfo::generate(&self.sector(sector), sector, info.app_tag);
+            }
+            self.protection = Some(protection);
+        }
+    }
+
+    /// Starts keeping protection information with every sector, checked each time the sector is read,
+    /// or stops. The information is generated for whatever the drive holds right now.
+    pub fn set_protection(&mut self, enabled: bool) {
+        self.protection =
+            enabled.then(|| vec![ProtectionInfo::default(); self.data.len().div_ceil(SECTOR_SIZE)]);
+        self.written(0, self.data.len());
+    }
+
+    /// Returns the protection information stored with `sector`, if the drive keeps any
+    pub fn protection_info(&self, sector: usize) -> Option<ProtectionInfo> {
+        self.protection
+            .as_ref()
+            .map(|protection| protection[sector])
+    }
+
+    /// Damages `field` of the protection information stored with `sector`, leaving the data alone
+    pub fn inject_tag_error(&mut self, sector: usize, field: TagField) {
+        if let Some(protection) = &mut self.protection {
+            protection[sector].inject(field);
+        }
+    }
+
+    /// Starts keeping a Merkle tree over blocks of `block_size` bytes, hashed with CRC-64/NVME, or stops with `None`.
+    /// The tree is built over whatever the drive holds right now.
+    pub fn set_merkle(&mut self, block_size: Option<usize>) {
+        self.merkle = block_size.map(|block_size| {
+            MerkleTree::build(
+                &self.data.contents(),
+                block_size,
+                ChecksumAlgorithm::Crc64Nvme,
+            )
+        });
+    }
+
+    /// Returns the drive's Merkle tree, if it keeps one
+    pub fn merkle(&self) -> Option<&MerkleTree> {
+        self.merkle.as_ref()
+    }
+
+    /// Returns whether the blocks under node `index` of `level` of the Merkle tree still match it,
+    /// always true for a drive that doesn't keep a tree
+    pub fn verify_subtree(&self, level: usize, index: usize) -> bool {
+        self.merkle
+            .as_ref()
+            .is_none_or(|tree| tree.verify_subtree(&self.data.contents(), level, index))
+    }
+
+    /// Returns the blocks that no longer match the drive's Merkle tree, empty for a drive that doesn't keep one
+    pub fn find_corrupt_blocks(&self) -> Vec<usize> {
+        self.merkle.as_ref().map_or(vec![], |tree| {
+            tree.find_corrupt_blocks(&self.data.contents())
+        })
+    }
+
+    /// Flips the bits of `mask` in the byte at `offset` without going through a write, like bit rot would.
+    /// Sector checksums and the Merkle tree aren't updated, so a drive that keeps them can notice.
+    pub fn corrupt(&mut self, offset: usize, mask: u8) {
+        self.data[offset] ^= mask;
+    }
+
+    /// Sets the drive's data
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        self.writeable_result()?;
+        self.check_read_only(0, self.data.len())?;
+        assert_eq!(data.len(), self.data.len());
+        self.data.write(0, &data);
+        self.written(0, self.data.len());
+        Ok(())
+    }
+
+    /// Grows the drive to `new_size` bytes, zero-filled, or shrinks it.
+    /// Shrinking refuses to cut off anything but zeros, or to leave the drive smaller than the array its superblock
+    /// says it belongs to. Whatever the drive checks reads with is brought up to date for the new size.
+    pub fn resize(&mut self, new_size: usize) -> Result<()> {
+        self.writeable_result()?;
+        if self.read_only {
+            return Err(DriveError::WriteProtected.into());
+        }
+        let old_size = self.data.len();
+        if new_size < old_size {
+            if let Some(offset) = self
+                .data
+                .slice(new_size..old_size)
+                .iter()
+                .position(|b| *b != 0)
+            {
+                bail!(
+                    "Shrinking to {} bytes would truncate data at offset {}",
+                    new_size,
+                    new_size + offset
+                );
+            }
+            if let Some(superblock) = self.superblock.as_ref() {
+                if new_size < superblock.drive_size {
+                    bail!(
+                        "Shrinking to {} bytes would cut into a member of size {}",
+                        new_size,
+                        superblock.drive_size
+                    );
+                }
+            }
+        }
+        let mut data = self.data.to_vec();
+        data.resize(new_size, 0);
+        self.data = data.into();
+        let sectors = new_size.div_ceil(SECTOR_SIZE);
+        for list in [
+            &mut self.latent,
+            &mut self.missed,
+            self.bad_sectors.get_mut(),
+        ] {
+            list.retain(|sector| *sector < sectors);
+        }
+        if let Some(checksums) = &mut self.checksums {
+            checksums.sums.resize(sectors, 0);
+        }
+        if let Some(protection) = &mut self.protection {
+            protection.resize(sectors, ProtectionInfo::default());
+        }
+        if let Some(tree) = &self.merkle {
+            self.merkle = Some(MerkleTree::build(
+                &self.data.contents(),
+                tree.block_size(),
+                ChecksumAlgorithm::Crc64Nvme,
+            ));
+        }
+        // The last sector of the smaller size is the only old one whose contents changed
+        let boundary = old_size.min(new_size) / SECTOR_SIZE * SECTOR_SIZE;
+        if boundary < new_size {
+            self.written(boundary, new_size - boundary);
+        }
+        Ok(())
+    }
+
+    /// Marks a drive as failed
+    pub fn fail(&mut self) {
+        self.failed.set(true);
+    }
+
+    /// Returns whether the drive has failed
+    pub fn has_failed(&self) -> bool {
+        self.failed.get()
+    }
+
+    /// Brings a failed drive back with whatever data it had, like reseating a loose cable
+    pub fn recover(&mut self) {
+        self.failed.set(false);
+    }
+
+    /// Marks a drive as formatted
+    pub fn format(&mut self) {
+        self.formatted = true;
+        self.erased = false;
+    }
+
+    /// Marks a drive as not formatted, whatever it holds has to be rebuilt before it can be used
+    pub fn unformat(&mut self) {
+        self.formatted = false;
+    }
+
+    /// Returns whether the drive is formatted
+    pub fn is_formatted(&self) -> bool {
+        self.formatted
+    }
+
+    pub fn usable(&self) -> bool {
+        !self.failed.get() && self.formatted
+    }
+
+    /// Returns the array metadata stored on the drive
+    pub fn superblock(&self) -> Option<&Superblock> {
+        self.superblock.as_ref()
+    }
+
+    /// Overwrites the array metadata stored on the drive
+    pub fn set_superblock(&mut self, superblock: Option<Superblock>) {
+        self.erased &= superblock.is_none();
+        self.superblock = superblock;
+    }
+
+    /// Wipes the drive's data and metadata for decommissioning, leaving it unformatted and with no superblock.
+    /// Read errors and bad blocks go with the data, and the drive reports it was erased until it's used again.
+    pub fn secure_erase(&mut self) -> Result<()> {
+        self.writeable_result()?;
+        if self.read_only {
+            bail!("Drive is write-protected, unable to erase");
+        }
+        self.data.fill(0);
+        self.superblock = None;
+        self.formatted = false;
+        self.latent.clear();
+        self.missed.clear();
+        self.bad_sectors.get_mut().clear();
+        self.written(0, self.data.len());
+        self.erased = true;
+        Ok(())
+    }
+
+    /// Returns whether the drive has been securely erased since it was last used
+    pub fn is_erased(&self) -> bool {
+        self.erased
+    }
+
+    /// Reseeds what the drive's hangs and flaky reads are drawn from.
+    /// The same seed makes the same faults fire on the same accesses, whatever happens to other drives in between.
+    pub fn seed_faults(&mut self, seed: u64) {
+        self.faults = FaultRng(RefCell::new(StdRng::seed_from_u64(seed)));
+    }
+
+    /// Makes the drive intermittently hang, or stop hanging if `None`
+    pub fn set_hang(&mut self, hang: Option<Hang>) {
+        self.hang = hang;
+    }
+
+    /// Returns how the drive hangs, if it does
+    pub fn hang(&self) -> Option<Hang> {
+        self.hang
+    }
+
+    /// Sets how long an access may hang, in simulated milliseconds, before the drive is kicked.
+    /// With no timeout the drive is waited on forever.
+    pub fn set_timeout(&mut self, timeout: Option<u64>) {
+        self.timeout = timeout;
+    }
+
+    /// Makes one in every `one_in` reads fail transiently, or stops it if `None`
+    pub fn set_flaky(&mut self, one_in: Option<u32>) {
+        self.flaky = one_in;
+    }
+
+    /// Makes `sector` unreadable until it is next written to
+    pub fn add_latent_error(&mut self, sector: usize) {
+        self.latent.insert(sector);
+    }
+
+    /// Makes the drive refuse writes while still serving reads, like one that went write-protected.
+    /// Sectors it refuses writes to can't be read until they're written again once it's writeable.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns whether the drive is refusing writes
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns the sectors that were written to while the drive was read-only
+    pub fn missed_writes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.missed.iter().copied()
+    }
+
+    /// Returns whether the sector holding `offset` missed a write while the drive was read-only
+    pub fn missed_write(&self, offset: usize) -> bool {
+        self.missed.contains(&(offset / SECTOR_SIZE))
+    }
+
+    /// Returns the sectors that can't currently be read
+    pub fn latent_errors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.latent.iter().copied()
+    }
+
+    /// Records `sector` in the drive's bad block list
+    pub fn mark_bad(&self, sector: usize) {
+        if self.bad_sectors.borrow_mut().insert(sector) {
+            self.record(|s| s.marked_bad += 1);
+        }
+    }
+
+    /// Returns whether the sector containing `offset` is in the drive's bad block list
+    pub fn is_bad(&self, offset: usize) -> bool {
+        self.bad_sectors.borrow().contains(&(offset / SECTOR_SIZE))
+    }
+
+    /// Starts encrypting everything the drive stores with `key`, or stops with `None`.
+    /// Whatever the drive holds is re-encrypted, what it reads back doesn't change.
+    pub fn set_encryption_key(&mut self, key: Option<u64>) {
+        self.key = key;
+    }
+
+    /// Returns the key the drive encrypts with, if it does
+    pub fn encryption_key(&self) -> Option<u64> {
+        self.key
+    }
+
+    /// Returns what is physically on the media, ciphertext if the drive is encrypted.
+    /// This is all anyone gets out of a drive pulled from the array without its key.
+    pub fn media(&self) -> Vec<u8> {
+        let mut media = self.data.to_vec();
+        if let Some(key) = self.key {
+            SectorCipher::new(key).apply(0, &mut media);
+        }
+        media
+    }
+
+    /// Creates a drive from a raw image, every byte of the file in order, like one taken with dd from a real disk
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Drive> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Ok(Drive::from_data(data))
+    }
+
+    /// Writes what the drive holds out as a raw image, for hexdump, losetup, and the like.
+    /// An encrypted drive is written out decrypted, `media()` has what's physically on it.
+    ///
+    /// Blocks of zeros are skipped rather than written, so on filesystems with sparse files
+    /// a mostly empty drive takes up little more space than what it holds.
+    pub fn write_image(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.write_image_at(path.as_ref(), &[], 0)
+    }
+
+    /// Writes what the drive holds out as a raw image like `write_image()`, but behind the Linux md version 1.2
+    /// superblock its own superblock stands for, so a set of member images can be attached as loop devices and
+    /// assembled read-only with `mdadm --assemble --readonly` on a real host.
+    /// Fails if the drive has no superblock or one md can't describe, see `Superblock::to_md()`.
+    pub fn write_md_image(&self, path: impl AsRef<Path>) -> Result<()> {
+        let Some(superblock) = self.superblock.as_ref() else {
+            bail!("Drive has no superblock to describe to md");
+        };
+        self.write_image_at(path.as_ref(), &superblock.to_md()?, MD_DATA_OFFSET)
+    }
+
+    /// Creates a member from an image of a Linux md array member with a version 1.2 superblock,
+    /// taking the data md uses and the superblock it stands for. See `MdSuperblock::to_superblock()`.
+    ///
+    /// Only members of arrays laid out like `write_md_image()` writes them, parity first, can be imported one at a time.
+    /// Arrays that rotate parity, like every one `mdadm --create` makes, spread each member's chunks over
+    /// all of the sim's, so they have to be imported whole with `RaidSim::import_md()`.
+    pub fn from_md_image(image: &[u8]) -> Result<Drive> {
+        let (drive, md) = Drive::md_member(image)?;
+        if md.rotates() {
+            bail!(
+                "md layout {} rotates parity across the members, import the whole array",
+                md.layout
+            );
+        }
+        Ok(drive)
+    }
+
+    /// Reads a file written by `write_md_image()` with `from_md_image()`
+    pub fn from_md_file(path: impl AsRef<Path>) -> Result<Drive> {
+        let path = path.as_ref();
+        let image = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Drive::from_md_image(&image).with_context(|| format!("Unable to import {}", path.display()))
+    }
+
+    /// Reads an md member image like `from_md_file()`, whatever its layout, along with its md superblock.
+    /// The member's chunks are left where md put them.
+    pub(crate) fn md_member_file(path: &Path) -> Result<(Drive, MdSuperblock)> {
+        let image = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Drive::md_member(&image).with_context(|| format!("Unable to import {}", path.display()))
+    }
+
+    fn md_member(image: &[u8]) -> Result<(Drive, MdSuperblock)> {
+        let Some(bytes) = image.get(MD_SUPER_OFFSET..) else {
+            bail!("Image is too short to hold an md superblock");
+        };
+        let md = MdSuperblock::parse(bytes)?;
+        let superblock = md.to_superblock()?;
+        let start = md.data_offset as usize * SECTOR_SIZE;
+        let Some(data) = image.get(start..start + superblock.drive_size) else {
+            bail!(
+                "Image of {} bytes ends before the {} bytes of data md expects at {}",
+                image.len(),
+                superblock.drive_size,
+                start
+            );
+        };
+        let mut drive = Drive::from_data(data.to_vec());
+        drive.format();
+        drive.set_superblock(Some(superblock));
+        Ok((drive, md))
+    }
+
+    /// Writes `header` at `MD_SUPER_OFFSET` and the drive's data sparsely from `data_offset` on
+    fn write_image_at(&self, path: &Path, header: &[u8], data_offset: usize) -> Result<()> {
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(path)?;
+            if !header.is_empty() {
+                file.seek(SeekFrom::Start(MD_SUPER_OFFSET as u64))?;
+                file.write_all(header)?;
+            }
+            let data = self.data.contents();
+            for extent in extents(&data) {
+                file.seek(SeekFrom::Start((data_offset + extent.start) as u64))?;
+                file.write_all(&data[extent])?;
+            }
+            file.set_len((data_offset + self.data.len()) as u64)
+        };
+        write().with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// Compares what the drive holds with `other` byte by byte, without simulating any access to either.
+    /// Useful where asserting two drives equal would say nothing about where they differ.
+    pub fn compare(&self, other: &Drive) -> DiffReport {
+        let mut report = DiffReport::default();
+        let (len, longest) = (
+            self.data.len().min(other.data.len()),
+            self.data.len().max(other.data.len()),
+        );
+        let differing = (0..len)
+            .filter(|i| self.data[*i] != other.data[*i])
+            .chain(len..longest);
+        for offset in differing {
+            report.differing += 1;
+            match report.ranges.last_mut() {
+                Some(range) if range.end == offset => range.end += 1,
+                _ => report.ranges.push(offset..offset + 1),
+            }
+        }
+        report
+    }
+
+    /// Copies everything that can still be read onto a new drive of the same size, a sector at a time like ddrescue.
+    /// Sectors already in `bad_map` aren't tried again, and every sector that can't be read is added to it,
+    /// so a clone can be resumed or retried with the same map. What couldn't be read is left zeroed on the copy.
+    /// The copy is bare data, unformatted and with no superblock, ready to be put in the array.
+    pub fn clone_rescue(&self, bad_map: &mut BTreeSet<usize>) -> Drive {
+        let mut data = vec![0u8; self.data.len()];
+        for (sector, chunk) in data.chunks_mut(SECTOR_SIZE).enumerate() {
+            let offset = sector * SECTOR_SIZE;
+            if bad_map.contains(&sector) || self.is_bad(offset) {
+                bad_map.insert(sector);
+                continue;
+            }
+            match self.read_range(offset..offset + chunk.len()) {
+                Ok(bytes) => chunk.copy_from_slice(&bytes),
+                Err(_) => {
+                    bad_map.insert(sector);
+                }
+            }
+        }
+        Drive::from_data(data)
+    }
+
+    /// Loses the drive's key, leaving the media impossible to decrypt.
+    /// The drive fails like any other that can't be read, and takes a new key so it can be rebuilt onto once recovered.
+    /// The new key is drawn from `rng`. A drive that doesn't encrypt has nothing to lose.
+    pub fn lose_key(&mut self, rng: &mut impl Rng) {
+        let Some(key) = self.key else {
+            return;
+        };
+        let mut media = self.media();
+        let new_key = loop {
+            let new_key = rng.random();
+            if new_key != key {
+                break new_key;
+            }
+        };
+        SectorCipher::new(new_key).apply(0, &mut media);
+        self.data.write(0, &media);
+        self.key = Some(new_key);
+        self.superblock = None;
+        self.formatted = false;
+        self.failed.set(true);
+        self.written(0, self.data.len());
+    }
+
+    /// Returns the drive's bad block list
+    pub fn bad_sectors(&self) -> Vec<usize> {
+        self.bad_sectors.borrow().iter().copied().collect()
+    }
+
+    /// Returns the drive's lifetime counters
+    pub fn stats(&self) -> DriveStats {
+        self.stats.get()
+    }
+
+    /// Updates the drive's lifetime counters
+    pub(crate) fn record(&self, f: impl FnOnce(&mut DriveStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Returns the bytes at `offset..offset + len` without simulating an access, so nothing can hang, fail, or be counted.
+    /// Meant for checks that mustn't disturb the drive they look at.
+    pub(crate) fn peek(&self, offset: usize, len: usize) -> Cow<'_, [u8]> {
+        self.data.slice(offset..offset + len)
+    }
+
+    /// Reads the byte at the specified offset
+    pub fn read(&self, offset: usize) -> Result<u8> {
+        self.check_bounds(offset, 1)?;
+        self.access()?;
+        self.check_readable(offset, 1)?;
+        Ok(self.data[offset])
+    }
+
+    /// Reads a slice of a specified length at a specified offset, failing if it runs off the end of the drive
+    /// The bytes are borrowed straight from the drive unless it's stored in rows and they span more than one,
+    /// see `StorageLayout`.
+    pub fn read_slice(&self, offset: usize, len: usize) -> Result<Cow<'_, [u8]>> {
+        self.check_bounds(offset, len)?;
+        self.access()?;
+        self.check_readable(offset, len)?;
+        Ok(self.data.slice(offset..offset + len))
+    }
+
+    /// Reads the bytes at `range` in one access, the slice form of `read_slice`
+    pub fn read_range(&self, range: Range<usize>) -> Result<Cow<'_, [u8]>> {
+        self.read_slice(range.start, range.len())
+    }
+
+    /// Reads the whole drive in blocks of `size` bytes, the last one short if `size` doesn't divide the drive.
+    /// Each block is its own access, so one that can't be read doesn't stop the rest.
+    pub fn chunks(&self, size: usize) -> impl Iterator<Item = Result<Cow<'_, [u8]>>> + '_ {
+        assert!(size > 0, "Chunk size must be non-zero");
+        (0..self.data.len())
+            .step_by(size)
+            .map(move |start| self.read_range(start..(start + size).min(self.data.len())))
+    }
+
+    /// Writes the byte at the specified offset
+    pub fn write(&mut self, offset: usize, data: u8) -> Result<()> {
+        self.check_bounds(offset, 1)?;
+        self.access()?;
+        self.check_read_only(offset, 1)?;
+        self.rewrite_sectors(offset, 1);
+        self.record(|s| s.bytes_written += 1);
+        self.data[offset] = data;
+        self.written(offset, 1);
+        Ok(())
+    }
+
+    /// Writes the slice at the specified offset, failing if it runs off the end of the drive
+    pub fn write_slice(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        self.check_bounds(offset, data.len())?;
+        self.access()?;
+        self.check_read_only(offset, data.len())?;
+        self.rewrite_sectors(offset, data.len());
+        self.record(|s| s.bytes_written += data.len() as u64);
+        self.data.write(offset, data);
+        self.written(offset, data.len());
+        Ok(())
+    }
+}
+
+/// Drives are equal if they hold the same bytes, have failed or not together, and carry the same superblock.
+/// Counters, injected faults, and what's kept to check the data against don't count.
+impl PartialEq for Drive {
+    fn eq(&self, other: &Self) -> bool {
+        self.has_failed() == other.has_failed()
+            && self.superblock == other.superblock
+            && self.data == other.data
+    }
+}
+impl Eq for Drive {}
+
+/// The byte at an offset, straight from the media like `peek`, so nothing can hang, fail, or be counted.
+/// Panics if the offset is past the end of the drive, `read()` is the checked way in.
+impl Index<usize> for Drive {
+    type Output = u8;
+
+    fn index(&self, offset: usize) -> &u8 {
+        &self.data[offset]
+    }
+}
+
+/// Changes the byte at an offset in place like `corrupt()`, without going through a write.
+/// Sector checksums, the Merkle tree, and missed writes aren't updated, `write()` is for when they should be.
+impl IndexMut<usize> for Drive {
+    fn index_mut(&mut self, offset: usize) -> &mut u8 {
+        &mut self.data[offset]
+    }
+}
+
+/// The bytes in a range, unchecked like indexing by offset, `read_range()` is the checked way in.
+/// Also panics if the drive is stored in rows and the range spans more than one, as the bytes aren't in one piece.
+impl Index<Range<usize>> for Drive {
+    type Output = [u8];
+
+    fn index(&self, range: Range<usize>) -> &[u8] {
+        self.data
+            .contiguous(range.clone())
+            .unwrap_or_else(|| panic!("Drive stores {:?} in more than one row", range))
+    }
+}
+
+impl Drive {
+    /// Serializes the drive into a versioned image that `from_image()` can load back, on this or a later release.
+    ///
+    /// The image starts with a magic and its version, then the size of the drive and its contents, and ends with a
+    /// CRC-64 of everything before it. In between is everything else the drive stores: its state, superblock if it has one,
+    /// bad blocks, and what it checks reads with.
+    /// Injected hangs, timeouts, and flakiness are how a drive is being tested rather than what it holds, and are left out,
+    /// as are its lifetime counters.
+    pub fn to_image(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        write_header(&mut out, DRIVE_IMAGE_MAGIC, DRIVE_IMAGE_VERSION);
+        out.u64(self.data.len() as u64);
+        let data = self.data.contents();
+        let extents = extents(&data);
+        out.u64(extents.len() as u64);
+        for extent in extents {
+            out.u64(extent.start as u64);
+            out.bytes(&data[extent]);
+        }
+        for flag in [
+            self.failed.get(),
+            self.formatted,
+            self.erased,
+            self.read_only,
+        ] {
+            out.bool(flag);
+        }
+        for sectors in [&self.latent, &self.missed, &*self.bad_sectors.borrow()] {
+            out.u64(sectors.len() as u64);
+            for sector in sectors {
+                out.u64(*sector as u64);
+            }
+        }
+        out.bool(self.superblock.is_some());
+        if let Some(superblock) = &self.superblock {
+            superblock.encode(&mut out);
+        }
+        out.u8(match self.checksum_algorithm() {
+            None => 0,
+            Some(ChecksumAlgorithm::Crc32c) => 1,
+            Some(ChecksumAlgorithm::Crc64Nvme) => 2,
+        });
+        out.option(self.merkle.as_ref().map(|tree| tree.block_size() as u64));
+        out.bool(self.protection.is_some());
+        out.option(self.key);
+        let checksum = ChecksumAlgorithm::Crc64Nvme.checksum(&out.0);
+        out.0.extend_from_slice(&checksum.to_le_bytes());
+        out.0
+    }
+
+    /// Loads an image written by `to_image()` of any version from 1 up to `DRIVE_IMAGE_VERSION`, migrating older ones.
+    /// Checksums, the Merkle tree, and protection information are recomputed from the data.
+    /// A bare dump of the drive's contents from before images had a header is refused, see `from_v0_image()`.
+    pub fn from_image(bytes: &[u8]) -> Result<Drive> {
+        let (version, mut rest) = read_header(
+            bytes,
+            DRIVE_IMAGE_MAGIC,
+            DRIVE_IMAGE_VERSION,
+            "a drive image",
+        )?;
+        // Version 1 is version 2 without the checksum
+        if version >= 2 {
+            let Some((body, checksum)) = bytes.split_last_chunk::<8>() else {
+                bail!("Drive image ends partway through");
+            };
+            if ChecksumAlgorithm::Crc64Nvme.checksum(body) != u64::from_le_bytes(*checksum) {
+                bail!("Drive image doesn't match its checksum, the file is damaged");
+            }
+            rest = &rest[..rest.len().saturating_sub(8)];
+        }
+        let mut input = Decoder(rest);
+        let data = if version >= 3 {
+            let size = input.usize()?;
+            if size > MAX_IMAGE_DRIVE_SIZE {
+                bail!(
+                    "Drive image is of a drive of {} bytes, at most {} can be loaded",
+                    size,
+                    MAX_IMAGE_DRIVE_SIZE
+                );
+            }
+            let mut data = vec![0; size];
+            for _ in 0..input.u64()? {
+                let start = input.usize()?;
+                let extent = input.bytes()?;
+                let range = start
+                    .checked_add(extent.len())
+                    .and_then(|end| data.get_mut(start..end));
+                match range {
+                    Some(range) => range.copy_from_slice(extent),
+                    None => bail!("Extent at {} runs past the end of the drive image", start),
+                }
+            }
+            data
+        } else {
+            input.bytes()?.to_vec()
+        };
+        let mut drive = Drive::from_data(data);
+        drive.failed.set(input.bool()?);
+        drive.formatted = input.bool()?;
+        drive.erased = input.bool()?;
+        drive.read_only = input.bool()?;
+        let mut sets = [BTreeSet::new(), BTreeSet::new(), BTreeSet::new()];
+        for sectors in &mut sets {
+            for _ in 0..input.u64()? {
+                sectors.insert(input.usize()?);
+            }
+        }
+        let [latent, missed, bad_sectors] = sets;
+        drive.latent = latent;
+        drive.missed = missed;
+        drive.bad_sectors = RefCell::new(bad_sectors);
+        if input.bool()? {
+            drive.superblock = Some(Superblock::decode(&mut input, version)?);
+        }
+        drive.set_checksum(match input.u8()? {
+            0 => None,
+            1 => Some(ChecksumAlgorithm::Crc32c),
+            2 => Some(ChecksumAlgorithm::Crc64Nvme),
+            other => bail!("Unknown checksum {} in drive image", other),
+        });
+        drive.set_merkle(input.option()?.map(|block_size| block_size as usize));
+        if input.bool()? {
+            drive.set_protection(true);
+        }
+        drive.key = input.option()?;
+        if !input.0.is_empty() {
+            bail!("{} bytes left over after the drive image", input.0.len());
+        }
+        Ok(drive)
+    }
+
+    /// Saves the drive to a file as an image, see `to_image()`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_image())
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// Loads a drive from an image file, see `from_image()`
+    pub fn load(path: impl AsRef<Path>) -> Result<Drive> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Drive::from_image(&bytes).with_context(|| format!("Unable to load {}", path.display()))
+    }
+
+    /// Migrates a version 0 image, a bare dump of the drive's contents.
+    /// Nothing else was saved, so it loads as a healthy drive that has yet to be formatted into an array.
+    /// There's no header or checksum to check, so any bytes at all load as a drive of that many.
+    pub fn from_v0_image(bytes: &[u8]) -> Drive {
+        Drive::from_data(bytes.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::RaidMode;
+
+    #[test]
+    fn accesses_past_the_end_fail() {
+        let mut drive = Drive::empty(1024);
+        assert!(drive.read(1024).is_err());
+        assert!(drive.read_slice(1000, 25).is_err());
+        assert!(drive.read_slice(usize::MAX, 2).is_err());
+        assert!(drive.write(1024, 1).is_err());
+        assert!(drive.write_slice(1020, &[1; 5]).is_err());
+        assert_eq!(drive.stats().bytes_written, 0);
+
+        drive.write_slice(1020, &[1; 4]).unwrap();
+        assert_eq!(*drive.read_slice(1020, 4).unwrap(), [1; 4]);
+        assert!(drive.read_slice(1024, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn resize_keeps_data_and_checks() {
+        let mut drive = Drive::from_data(vec![7; 700]);
+        drive.set_checksum(Some(ChecksumAlgorithm::Crc32c));
+        drive.set_protection(true);
+        drive.resize(1500).unwrap();
+        assert_eq!(drive.size(), 1500);
+        assert_eq!(*drive.read_slice(0, 700).unwrap(), [7; 700]);
+        assert_eq!(*drive.read_slice(700, 800).unwrap(), [0; 800]);
+        assert!((0..3).all(|sector| drive.verify_sector(sector)));
+
+        assert!(drive.resize(600).is_err());
+        drive.add_latent_error(2);
+        drive.resize(700).unwrap();
+        assert_eq!(drive.latent_errors().count(), 0);
+        assert!(drive.read_slice(0, 700).is_ok());
+
+        drive.set_superblock(Some(Superblock {
+            array_id: 1,
+            mode: RaidMode::Raid5,
+            slot: 0,
+            num_drives: 3,
+            drive_size: 700,
+            chunk_size: 700,
+            events: 0,
+            clean: true,
+        }));
+        drive.write_slice(0, &[0; 700]).unwrap();
+        assert!(drive.resize(512).is_err());
+    }
+
+    #[test]
+    fn seeded_faults_repeat() {
+        let flaky_reads = |seed: u64| {
+            let mut drive = Drive::empty(1024);
+            drive.set_flaky(Some(3));
+            drive.seed_faults(seed);
+            (0..64)
+                .map(|i| drive.read(i).is_err())
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(flaky_reads(7), flaky_reads(7));
+        assert_ne!(flaky_reads(7), flaky_reads(8));
+    }
+
+    #[test]
+    fn chunks_cover_the_drive() {
+        let data = (0..1300).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut drive = Drive::from_data(data.clone());
+        assert_eq!(drive.read_range(10..20).unwrap(), &data[10..20]);
+        let chunks = drive
+            .chunks(SECTOR_SIZE)
+            .collect::<Result<Vec<Cow<[u8]>>>>()
+            .unwrap();
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<usize>>(),
+            vec![512, 512, 276]
+        );
+        assert_eq!(chunks.concat(), data);
+
+        // An unreadable sector only spoils its own chunk
+        drive.add_latent_error(1);
+        let results = drive
+            .chunks(SECTOR_SIZE)
+            .map(|c| c.is_ok())
+            .collect::<Vec<bool>>();
+        assert_eq!(results, vec![true, false, true]);
+    }
+
+    #[test]
+    fn indexing_reaches_the_media_directly() {
+        let data = (0..1024).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut drive = Drive::from_data(data.clone());
+        drive.set_checksum(Some(ChecksumAlgorithm::Crc32c));
+        drive.add_latent_error(0);
+        assert_eq!(drive[5], 5);
+        assert!(drive.read(5).is_err());
+        assert_eq!(&drive[510..520], &data[510..520]);
+
+        drive[600] ^= 0xff;
+        assert_eq!(drive[600], !data[600]);
+        assert!(!drive.verify_sector(1), "Indexing doesn't update checksums");
+        // Only the checked read was counted
+        assert_eq!(drive.stats().read_errors, 1);
+        assert_eq!(drive.stats().bytes_written, 0);
+    }
+
+    #[test]
+    fn compare_reports_differing_ranges() {
+        let drive = Drive::from_data(vec![0; 2048]);
+        assert!(drive.compare(&drive.clone()).is_identical());
+        assert_eq!(drive.compare(&drive).to_string(), "identical");
+
+        let mut other = drive.clone();
+        other.corrupt(3, 1);
+        other.corrupt(4, 1);
+        other.corrupt(1500, 0x80);
+        let diff = drive.compare(&other);
+        assert_eq!(diff.ranges, vec![3..5, 1500..1501]);
+        assert_eq!(diff.differing, 3);
+        assert_eq!(diff.sectors(), BTreeSet::from([0, 2]));
+        assert_eq!(
+            diff.to_string(),
+            "3 bytes differ in 2 ranges: 3..5 1500..1501"
+        );
+
+        let shorter = Drive::from_data(vec![0; 2000]);
+        assert_eq!(drive.compare(&shorter).ranges, vec![2000..2048]);
+    }
+
+    #[test]
+    fn images_leave_out_zeros() {
+        let mut drive = Drive::empty(1 << 20);
+        drive.write_slice(5000, &[7; 10]).unwrap();
+        drive.write(drive.size() - 1, 1).unwrap();
+        assert_eq!(
+            extents(&drive.data.contents()),
+            vec![4096..8192, drive.size() - SPARSE_BLOCK..drive.size()]
+        );
+        let image = drive.to_image();
+        assert!(image.len() < 3 * SPARSE_BLOCK);
+        assert_eq!(Drive::from_image(&image).unwrap(), drive);
+
+        // A size or extent that doesn't add up is refused before anything is allocated or copied
+        let crafted = |size: u64, start: u64| {
+            let mut out = Encoder::default();
+            write_header(&mut out, DRIVE_IMAGE_MAGIC, DRIVE_IMAGE_VERSION);
+            out.u64(size);
+            out.u64(1);
+            out.u64(start);
+            out.bytes(&[1; 4]);
+            let checksum = ChecksumAlgorithm::Crc64Nvme.checksum(&out.0);
+            out.0.extend_from_slice(&checksum.to_le_bytes());
+            Drive::from_image(&out.0).unwrap_err().to_string()
+        };
+        assert!(crafted(u64::MAX, 0).contains("at most"));
+        assert!(crafted(4096, u64::MAX - 1).contains("past the end"));
+        assert!(crafted(4096, 4093).contains("past the end"));
+
+        let path = std::env::temp_dir().join(format!("raid-fun-{}-sparse.img", std::process::id()));
+        drive.write_image(&path).unwrap();
+        let read = Drive::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(read.unwrap().compare(&drive).is_identical());
+    }
+
+    #[test]
+    fn raw_images_round_trip_through_files() {
+        let path = std::env::temp_dir().join(format!("raid-fun-{}.img", std::process::id()));
+        let drive = Drive::from_data((0..=255).cycle().take(1024).collect());
+        drive.write_image(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), *drive.data.contents());
+        let read = Drive::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(read.compare(&drive).is_identical());
+        assert!(Drive::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn md_images_carry_a_valid_superblock() {
+        use crate::{
+            sim::RaidSim,
+            superblock::{md_checksum, MD_MAGIC},
+        };
+        use std::convert::TryInto;
+
+        let mut sim = RaidSim::builder().drives(6, 4096).seed(3).build().unwrap();
+        sim.init().unwrap();
+        sim.write_slice(100, &[0x5a; 9000]).unwrap();
+        let images = (0..6)
+            .map(|slot| {
+                let path = std::env::temp_dir().join(format!(
+                    "raid-fun-{}-md{}.img",
+                    std::process::id(),
+                    slot
+                ));
+                sim.drive(slot).write_md_image(&path).unwrap();
+                let image = fs::read(&path);
+                fs::remove_file(&path).unwrap();
+                image.unwrap()
+            })
+            .collect::<Vec<Vec<u8>>>();
+        for (slot, image) in images.iter().enumerate() {
+            assert_eq!(image.len(), MD_DATA_OFFSET + 4096);
+            assert_eq!(image[..MD_SUPER_OFFSET], [0; MD_SUPER_OFFSET]);
+            let sb = &image[MD_SUPER_OFFSET..MD_SUPER_OFFSET + 256 + 2 * 6];
+            let word = |at: usize| u32::from_le_bytes(sb[at..at + 4].try_into().unwrap());
+            assert_eq!(word(0), MD_MAGIC);
+            assert_eq!((word(72), word(76), word(92)), (6, 4, 6));
+            assert_eq!(word(160), slot as u32);
+            assert_eq!(word(216), md_checksum(sb));
+            assert_eq!(image[MD_DATA_OFFSET..], *sim.drive(slot).data.contents());
+        }
+        // What md reads off the data members is the array
+        let contents = images[2..]
+            .iter()
+            .flat_map(|image| image[MD_DATA_OFFSET..].iter().copied())
+            .collect::<Vec<u8>>();
+        assert_eq!(contents[100..9100], [0x5a; 9000]);
+
+        assert!(Drive::empty(4096).write_md_image("unused").is_err());
+        let mut sb = sim.drive(0).superblock().unwrap().clone();
+        let mut md = MdSuperblock::parse(&sb.to_md().unwrap()).unwrap();
+        assert_eq!(md.to_superblock().unwrap().slot, sb.slot);
+        // A left-symmetric array rotates parity through the members, which md only has the first five layouts of
+        md.layout = 2;
+        assert_eq!(md.to_superblock().unwrap().slot, sb.slot);
+        md.layout = 5;
+        assert!(md.to_superblock().is_err());
+        sb.chunk_size = 6000;
+        assert!(sb.to_md().is_err());
+    }
+
+    #[test]
+    fn images_round_trip_and_migrate() {
+        let mut drive = Drive::from_data((0..=255).cycle().take(2048).collect());
+        drive.format();
+        drive.set_checksum(Some(ChecksumAlgorithm::Crc32c));
+        drive.set_merkle(Some(256));
+        drive.set_protection(true);
+        drive.set_encryption_key(Some(42));
+        drive.add_latent_error(1);
+        drive.mark_bad(3);
+        drive.set_superblock(Some(Superblock {
+            array_id: 7,
+            mode: RaidMode::Raid6,
+            slot: 2,
+            num_drives: 8,
+            drive_size: 2048,
+            chunk_size: 512,
+            events: 5,
+            clean: true,
+        }));
+        drive.set_read_only(true);
+        let _ = drive.write(600, 0);
+        drive.fail();
+
+        let image = drive.to_image();
+        assert!(image.starts_with(DRIVE_IMAGE_MAGIC));
+        let loaded = Drive::from_image(&image).unwrap();
+        assert_eq!(loaded, drive);
+        // Equality only looks at the contents, failure, and superblock, the rest has to come back as well
+        assert!(loaded.is_formatted() && loaded.is_read_only());
+        assert_eq!(loaded.latent_errors().collect::<Vec<usize>>(), [1]);
+        assert_eq!(loaded.bad_sectors(), drive.bad_sectors());
+        assert_eq!(loaded.checksum(), drive.checksum());
+        assert_eq!(loaded.merkle(), drive.merkle());
+        assert_eq!(loaded.protection_info(2), drive.protection_info(2));
+        assert_eq!(loaded.encryption_key(), Some(42));
+
+        // A bare dump from before images had a header loads when asked for, and only then
+        let old = Drive::from_v0_image(&[1, 2, 3, 4]);
+        assert_eq!(old, Drive::from_data(vec![1, 2, 3, 4]));
+        assert!(!old.is_formatted());
+        assert!(Drive::from_image(&[1, 2, 3, 4]).is_err());
+        let mut unmarked = image.clone();
+        unmarked[0] ^= 1;
+        assert!(Drive::from_image(&unmarked)
+            .unwrap_err()
+            .to_string()
+            .contains("Not a drive image"));
+
+        assert!(Drive::from_image(&image[..image.len() - 1]).is_err());
+        let mut damaged = image.clone();
+        damaged[100] ^= 1;
+        assert!(Drive::from_image(&damaged)
+            .unwrap_err()
+            .to_string()
+            .contains("checksum"));
+        // Version 2 stored the contents whole, and version 1 had no checksum either.
+        // Neither had a chunk size in the superblock, so the drive is taken out of its array to compare.
+        let mut bare = drive.clone();
+        bare.superblock = None;
+        let bare_image = bare.to_image();
+        let mut v2 = Encoder::default();
+        write_header(&mut v2, DRIVE_IMAGE_MAGIC, 2);
+        v2.bytes(&drive.data.contents());
+        let rest = &bare_image[10..bare_image.len() - 8];
+        let mut decoder = Decoder(rest);
+        decoder.u64().unwrap();
+        for _ in 0..decoder.u64().unwrap() {
+            decoder.u64().unwrap();
+            decoder.bytes().unwrap();
+        }
+        v2.0.extend_from_slice(decoder.0);
+        let mut v1 = v2.0.clone();
+        v1[8] = 1;
+        assert_eq!(Drive::from_image(&v1).unwrap(), bare);
+        let checksum = ChecksumAlgorithm::Crc64Nvme.checksum(&v2.0);
+        v2.0.extend_from_slice(&checksum.to_le_bytes());
+        assert_eq!(Drive::from_image(&v2.0).unwrap(), bare);
+
+        let path = std::env::temp_dir().join(format!("raid-fun-{}.rimg", std::process::id()));
+        drive.save(&path).unwrap();
+        let loaded = Drive::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), drive);
+        let mut newer = image.clone();
+        newer[8] = DRIVE_IMAGE_VERSION as u8 + 1;
+        assert!(Drive::from_image(&newer)
+            .unwrap_err()
+            .to_string()
+            .contains("version"));
+    }
+}
@@ -0,0 +1,57 @@
+//! A RAID 5/6 simulator, and the GF(2^8) arithmetic it's built on re-exported from gf as `generator`.
+//!
+//! Everything but the field math is behind the default `sim` feature. Without it the crate is only
+//! `generator`, and without `std` as well it's `no_std`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "sim")]
+mod arena;
+#[cfg(feature = "sim")]
+pub mod bench;
+#[cfg(feature = "sim")]
+pub mod checksum;
+#[cfg(feature = "sim")]
+pub mod cipher;
+#[cfg(feature = "sim")]
+mod codec;
+#[cfg(feature = "sim")]
+pub mod drive;
+pub use gf as generator;
+#[cfg(feature = "sim")]
+pub mod merkle;
+#[cfg(feature = "sim")]
+pub mod protection;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "sim")]
+pub mod superblock;
+
+#[cfg(feature = "sim")]
+pub use checksum::ChecksumAlgorithm;
+#[cfg(feature = "sim")]
+pub use cipher::SectorCipher;
+#[cfg(feature = "sim")]
+pub use drive::{
+    DiffReport, Drive, DriveError, DriveStats, Hang, DRIVE_IMAGE_VERSION, SECTOR_SIZE,
+};
+pub use generator::Gen;
+#[cfg(feature = "sim")]
+pub use merkle::MerkleTree;
+#[cfg(feature = "sim")]
+pub use protection::{ProtectionInfo, TagField};
+#[cfg(feature = "sim")]
+pub use sim::{
+    Access, ArcLite, ArrayEvent, ArrayStats, AssemblyReport, BufferStats, CachePolicy, CacheStats,
+    Capacity, ChaosConfig, ChaosFailure, ChaosOp, ChaosRun, CompressedVolume, CompressionStats,
+    ConcurrentReport, ConfigError, DedupStats, DedupVolume, Detail, DriveHandle, DriveHealth,
+    DriveRole, Exclusion, ExclusionReason, Explanation, FrozenView, HotAdd, Io, Mapping,
+    MismatchCause, MismatchCount, Observer, OnExhausted, Op, OpCategory, OpLog, ParityUpdate,
+    PatrolReport, PromotionOrder, RaidError, RaidMode, RaidSim, RaidSimBuilder, RaidState, ReAdd,
+    ReadCacheConfig, RebuildReport, RecoveryFormula, ReliabilityEstimate, ReliabilityModel,
+    RepairPlan, RetryPolicy, RunReport, ScrubReport, Sequential, SparePool, Step, StorageLayout,
+    StripeView, StripeViewMut, StripeWriter, Transition, Trigger, UniformRandom, Workload,
+    WorkloadReport, WriteBackConfig, WriteBackStats, Zipfian,
+};
+#[cfg(feature = "sim")]
+pub use superblock::{MdSuperblock, Superblock};
@@ -0,0 +1,203 @@
+use crate::checksum::ChecksumAlgorithm;
+
+/// A hash tree over fixed-size blocks of a drive.
+///
+/// Each leaf is the hash of a block and each node above is the hash of its two children, up to a single root.
+/// A block can be checked against the root by hashing it and the siblings along its path, and two trees
+/// can be compared top down to find which blocks differ without looking at any subtree that matches.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MerkleTree {
+    algorithm: ChecksumAlgorithm,
+    block_size: usize,
+    /// Hashes of every level, leaves first and the root last
+    levels: Vec<Vec<u64>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree over `data` split into blocks of `block_size` bytes, the last one possibly shorter
+    pub fn build(data: &[u8], block_size: usize, algorithm: ChecksumAlgorithm) -> Self {
+        assert!(
+            block_size > 0,
+            "Merkle tree blocks must hold at least a byte"
+        );
+        let leaves = data
+            .chunks(block_size)
+            .map(|block| algorithm.checksum(block))
+            .collect::<Vec<u64>>();
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let below = levels.last().unwrap();
+            let level = (0..below.len().div_ceil(2))
+                .map(|i| Self::combine(algorithm, below, i))
+                .collect();
+            levels.push(level);
+        }
+        Self {
+            algorithm,
+            block_size,
+            levels,
+        }
+    }
+
+    /// Hashes the children of node `index` from the level below it, a lone child is hashed on its own
+    fn combine(algorithm: ChecksumAlgorithm, below: &[u64], index: usize) -> u64 {
+        let bytes = below[index * 2..(index * 2 + 2).min(below.len())]
+            .iter()
+            .flat_map(|h| h.to_le_bytes())
+            .collect::<Vec<u8>>();
+        algorithm.checksum(&bytes)
+    }
+
+    /// Returns the hash of the whole tree, zero for a tree over no data
+    pub fn root(&self) -> u64 {
+        self.levels.last().unwrap().first().copied().unwrap_or(0)
+    }
+
+    /// Returns the size of the blocks the tree hashes
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the number of blocks the tree covers
+    pub fn blocks(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Returns the number of levels in the tree, including the leaves and the root
+    pub fn height(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the range of blocks under node `index` of `level`
+    fn blocks_under(&self, level: usize, index: usize) -> std::ops::Range<usize> {
+        let width = 1 << level;
+        (index * width).min(self.blocks())..((index + 1) * width).min(self.blocks())
+    }
+
+    /// Rehashes the blocks covering `offset..offset + len` of `data` and every node above them
+    pub fn update(&mut self, data: &[u8], offset: usize, len: usize) {
+        if self.blocks() == 0 {
+            return;
+        }
+        let first = offset / self.block_size;
+        let last = (offset + len.max(1) - 1) / self.block_size;
+        for block in first..=last.min(self.blocks() - 1) {
+            self.levels[0][block] = self.algorithm.checksum(self.block(data, block));
+        }
+        let (mut first, mut last) = (first, last);
+        for level in 1..self.levels.len() {
+            first /= 2;
+            last /= 2;
+            for index in first..=last.min(self.levels[level].len() - 1) {
+                self.levels[level][index] =
+                    Self::combine(self.algorithm, &self.levels[level - 1], index);
+            }
+        }
+    }
+
+    /// Returns the bytes of `block` in `data`
+    fn block<'a>(&self, data: &'a [u8], block: usize) -> &'a [u8] {
+        let start = block * self.block_size;
+        &data[start..(start + self.block_size).min(data.len())]
+    }
+
+    /// Checks a single block of `data` against the root, hashing only the block and one node per level
+    pub fn verify_block(&self, data: &[u8], block: usize) -> bool {
+        let mut hash = self.algorithm.checksum(self.block(data, block));
+        let mut index = block;
+        for level in 0..self.levels.len() - 1 {
+            let nodes = &self.levels[level];
+            let pair = index & !1;
+            let mut bytes = vec![];
+            for (i, node) in nodes.iter().enumerate().take(pair + 2).skip(pair) {
+                let h = if i == index { hash } else { *node };
+                bytes.extend_from_slice(&h.to_le_bytes());
+            }
+            hash = self.algorithm.checksum(&bytes);
+            index /= 2;
+        }
+        hash == self.root()
+    }
+
+    /// Checks whether the blocks of `data` under node `index` of `level` still hash to that node
+    pub fn verify_subtree(&self, data: &[u8], level: usize, index: usize) -> bool {
+        let blocks = self.blocks_under(level, index);
+        let start = blocks.start * self.block_size;
+        let end = (blocks.end * self.block_size).min(data.len());
+        let subtree = MerkleTree::build(&data[start..end], self.block_size, self.algorithm);
+        // The last subtree can be short of blocks, its root is then a lone child all the way up to `level`
+        let mut hash = subtree.root();
+        for _ in subtree.height() - 1..level {
+            hash = self.algorithm.checksum(&hash.to_le_bytes());
+        }
+        hash == self.levels[level][index]
+    }
+
+    /// Finds every block of `data` that no longer matches the tree.
+    /// The data is hashed once, then only subtrees whose hashes differ are descended into.
+    pub fn find_corrupt_blocks(&self, data: &[u8]) -> Vec<usize> {
+        let current = MerkleTree::build(data, self.block_size, self.algorithm);
+        let mut corrupt = vec![];
+        let mut pending = vec![(self.levels.len() - 1, 0)];
+        while let Some((level, index)) = pending.pop() {
+            if index >= self.levels[level].len()
+                || current.levels[level][index] == self.levels[level][index]
+            {
+                continue;
+            }
+            if level == 0 {
+                corrupt.push(index);
+            } else {
+                pending.push((level - 1, index * 2 + 1));
+                pending.push((level - 1, index * 2));
+            }
+        }
+        corrupt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..1000u32).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn finds_corrupt_blocks() {
+        let mut data = data();
+        let tree = MerkleTree::build(&data, 64, ChecksumAlgorithm::Crc64Nvme);
+        assert_eq!(tree.blocks(), 16);
+        assert_eq!(tree.height(), 5);
+        assert!(tree.find_corrupt_blocks(&data).is_empty());
+
+        data[70] ^= 1;
+        data[999] ^= 0x80;
+        assert_eq!(tree.find_corrupt_blocks(&data), vec![1, 15]);
+        assert!(!tree.verify_block(&data, 1));
+        assert!(tree.verify_block(&data, 2));
+        assert!(!tree.verify_subtree(&data, 4, 0));
+        assert!(tree.verify_subtree(&data, 1, 1));
+        assert!(!tree.verify_subtree(&data, 2, 3));
+    }
+
+    #[test]
+    fn updates_match_a_rebuild() {
+        let mut data = data();
+        let mut tree = MerkleTree::build(&data, 100, ChecksumAlgorithm::Crc32c);
+        data[150..420].fill(0xee);
+        tree.update(&data, 150, 270);
+        assert_eq!(
+            tree,
+            MerkleTree::build(&data, 100, ChecksumAlgorithm::Crc32c)
+        );
+        for block in 0..tree.blocks() {
+            assert!(tree.verify_block(&data, block));
+        }
+        // Blocks 8 and 9 are the only ones under the second node of level 3
+        assert!(tree.verify_subtree(&data, 3, 1));
+        data[950] ^= 1;
+        assert!(!tree.verify_subtree(&data, 3, 1));
+    }
+}
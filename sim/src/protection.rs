@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+
+use crate::checksum::crc16_t10dif;
+
+/// Protection information carried alongside a sector, in the style of T10 DIF.
+///
+/// The guard catches data damaged in flight, the reference tag catches data written to or read from
+/// the wrong sector, and the application tag belongs to whoever wrote the sector and is passed along untouched.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ProtectionInfo {
+    /// CRC-16/T10-DIF of the sector's data
+    pub guard: u16,
+    /// Owned by the application, never checked along the way
+    pub app_tag: u16,
+    /// Low 32 bits of the sector number the data belongs in
+    pub ref_tag: u32,
+}
+
+/// The fields of protection information a mismatch can be injected into
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TagField {
+    Guard,
+    App,
+    Ref,
+}
+
+impl ProtectionInfo {
+    /// Generates the protection information for `data` written to `sector`
+    pub fn generate(data: &[u8], sector: usize, app_tag: u16) -> Self {
+        Self {
+            guard: crc16_t10dif(data),
+            app_tag,
+            ref_tag: sector as u32,
+        }
+    }
+
+    /// Checks `data` found at `sector` against the protection information, naming the hop it was checked at
+    pub fn verify(&self, data: &[u8], sector: usize, hop: &str) -> Result<()> {
+        if self.guard != crc16_t10dif(data) {
+            bail!("Guard tag mismatch in sector {} at the {}", sector, hop);
+        }
+        if self.ref_tag != sector as u32 {
+            bail!(
+                "Reference tag mismatch in sector {} at the {}, tagged for sector {}",
+                sector,
+                hop,
+                self.ref_tag
+            );
+        }
+        Ok(())
+    }
+
+    /// Flips every bit of `field`, so it no longer matches what it protects
+    pub fn inject(&mut self, field: TagField) {
+        match field {
+            TagField::Guard => self.guard = !self.guard,
+            TagField::App => self.app_tag = !self.app_tag,
+            TagField::Ref => self.ref_tag = !self.ref_tag,
+        }
+    }
+}
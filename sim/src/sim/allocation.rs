@@ -0,0 +1,100 @@
+use super::{RaidSim, STRIPE_HEIGHT};
+
+impl RaidSim {
+    /// Returns the allocation bitmap, whether each stripe has ever been written, indexed by stripe.
+    ///
+    /// A stripe that never was still holds the zeros every member was created with, and so does its parity,
+    /// so repair and scrub have nothing to work out there. Changes made straight to a drive through
+    /// [`RaidSim::drive_mut`] don't count as writes. An assembled array's history is unknown, all of it counts as written.
+    pub fn allocation_bitmap(&self) -> Vec<bool> {
+        (0..self.drive_size.div_ceil(STRIPE_HEIGHT))
+            .map(|s| self.written.contains(&s))
+            .collect()
+    }
+
+    /// Returns the stripes that have ever been written, in order
+    pub fn written_stripes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.written.iter().copied()
+    }
+
+    /// Whether stripe `stripe` has ever been written
+    pub fn is_written(&self, stripe: usize) -> bool {
+        self.written.contains(&stripe)
+    }
+
+    /// Records the stripes holding array offsets `start..end` as written
+    pub(super) fn mark_written(&mut self, start: usize, end: usize) {
+        let end = end.max(start + 1);
+        let mut offset = start;
+        // A chunk at a time, each one is a run of a single data drive
+        while offset < end && self.written.len() < self.stripe_count() {
+            let (_, drive_offset) = self.locate(offset);
+            let len = self.chunk_run(drive_offset, end - offset);
+            self.written
+                .extend(drive_offset / STRIPE_HEIGHT..=(drive_offset + len - 1) / STRIPE_HEIGHT);
+            offset += len;
+        }
+    }
+
+    /// Records every stripe as written, for when what's on the members can't be accounted for
+    pub(super) fn mark_all_written(&mut self) {
+        self.written.extend(0..self.stripe_count());
+    }
+
+    fn stripe_count(&self) -> usize {
+        self.drive_size.div_ceil(STRIPE_HEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_repair_skips_never_written_stripes() {
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        assert!(sim.allocation_bitmap().iter().all(|w| !w));
+
+        // Runs off the end of the first data drive into the start of the second
+        let data = [0xa5; 8];
+        let offset = DRIVE_SIZE as u64 - 4;
+        sim.write_slice(offset, &data).unwrap();
+        assert_eq!(sim.written_stripes().collect::<Vec<usize>>(), vec![0, 1]);
+        assert_eq!(sim.allocation_bitmap(), vec![true, true]);
+
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.write_slice(0, &data).unwrap();
+        assert!(sim.is_written(0) && !sim.is_written(1));
+        sim.fail_random_data();
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        // Only the written stripe is read, for each of the two replacements
+        let plan = sim.repair_plan().unwrap();
+        assert_eq!(plan.reads[&P_INDEX], 2 * STRIPE_HEIGHT as u64);
+        assert_eq!(plan.writes[&plan.rebuilt[0]], DRIVE_SIZE as u64);
+        sim.repair().unwrap();
+        for (i, byte) in (0..sim.size()).map(|i| (i, sim.read(i).unwrap())) {
+            let expected = if i < data.len() as u64 { 0xa5 } else { 0 };
+            assert_eq!(byte, expected, "offset {}", i);
+        }
+
+        // Scrub doesn't look at stripes nobody wrote
+        let p = sim.drive(P_INDEX).read(600).unwrap();
+        sim.drive_mut(P_INDEX).write(600, !p).unwrap();
+        assert!(sim.scrub_step(DRIVE_SIZE).unwrap().mismatches.is_empty());
+        sim.drive_mut(P_INDEX).write(3, !data[0]).unwrap();
+        assert_eq!(
+            sim.scrub_step(DRIVE_SIZE).unwrap().mismatches,
+            vec![(P_INDEX, 0)]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+use crate::generator::{op_counts, OpCounts};
+
+use super::RaidSim;
+
+/// What the array was doing when it did field arithmetic
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum OpCategory {
+    /// Writing to the array, bringing parity up to date with what was written
+    Write,
+    /// Reading data that had to be reconstructed from parity
+    DegradedRead,
+    /// Rebuilding members onto replacements
+    Rebuild,
+}
+
+/// Field operations the array has done, by what they were done for
+#[derive(Debug, Clone, Default)]
+pub(super) struct FieldOps {
+    counts: RefCell<BTreeMap<OpCategory, OpCounts>>,
+    /// Set while an operation is being counted, so the operations it's made of aren't counted again
+    busy: Cell<bool>,
+}
+
+impl FieldOps {
+    pub(super) fn counts(&self) -> BTreeMap<OpCategory, OpCounts> {
+        self.counts.borrow().clone()
+    }
+}
+
+/// Counts the field operations done on this thread towards a category until it's dropped
+pub(super) struct Counting {
+    ops: Rc<FieldOps>,
+    category: OpCategory,
+    before: OpCounts,
+}
+
+impl Drop for Counting {
+    fn drop(&mut self) {
+        let spent = op_counts().since(self.before);
+        if !spent.is_empty() {
+            let mut counts = self.ops.counts.borrow_mut();
+            counts.entry(self.category).or_default().add(spent);
+        }
+        self.ops.busy.set(false);
+    }
+}
+
+impl RaidSim {
+    /// Starts counting the field operations done for `category`, unless they're already being counted
+    /// for an operation this one is part of. Nothing is counted without the `op-counts` feature.
+    pub(super) fn count_ops(&self, category: OpCategory) -> Option<Counting> {
+        if !cfg!(feature = "op-counts") || self.field_ops.busy.replace(true) {
+            return None;
+        }
+        Some(Counting {
+            ops: Rc::clone(&self.field_ops),
+            category,
+            before: op_counts(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "op-counts"))]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn field_operations_are_counted_by_category() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        let base = sim.stats().field_ops;
+        sim.write(0, 1).unwrap();
+        let writes = sim.stats().field_ops[&OpCategory::Write].since(base[&OpCategory::Write]);
+        // A Q update multiplies the change in data by one power of g
+        assert_eq!(writes.muls, 1);
+        assert!(!sim
+            .stats()
+            .field_ops
+            .contains_key(&OpCategory::DegradedRead));
+
+        // Reading a healthy array does no arithmetic at all
+        sim.read(0).unwrap();
+        assert!(!sim
+            .stats()
+            .field_ops
+            .contains_key(&OpCategory::DegradedRead));
+        sim.fail_drive(2);
+        sim.fail_drive(3);
+        sim.read(0).unwrap();
+        let degraded = sim.stats().field_ops[&OpCategory::DegradedRead];
+        assert!(degraded.muls > 0 && degraded.divs > 0);
+
+        sim.replace_failed_drives();
+        sim.rebuild().unwrap();
+        let rebuild = sim.stats().field_ops[&OpCategory::Rebuild];
+        assert!(rebuild.muls >= 2 * DRIVE_SIZE as u64);
+        assert_eq!(sim.stats().field_ops[&OpCategory::DegradedRead], degraded);
+    }
+}
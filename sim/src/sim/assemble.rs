@@ -0,0 +1,707 @@
+use std::{borrow::Cow, path::Path};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    drive::{Drive, SECTOR_SIZE},
+    generator::{GfEngine, ParityEngine},
+    superblock::{MdSuperblock, Superblock},
+};
+
+use super::{ArrayEvent, RaidSim, StorageLayout, Trigger, Q_INDEX, STRIPE_HEIGHT};
+
+/// Why a drive was left out of an assembled array
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExclusionReason {
+    /// The drive has failed and can't be read
+    Faulty,
+    /// The drive has no superblock, it was never part of an array
+    NoSuperblock,
+    /// The drive was securely erased, whatever array it was part of is gone from it
+    Erased,
+    /// The drive belongs to a different array
+    Foreign { array_id: u64 },
+    /// The drive missed changes to the array while it was out, its data can't be trusted
+    Stale { events: u64, expected: u64 },
+    /// Another drive claims the same slot with more recent metadata
+    Duplicate { slot: usize },
+}
+
+/// A drive that was handed to `assemble()` but left out of the array
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Exclusion {
+    /// Position of the drive in the list given to `assemble()`
+    pub index: usize,
+    pub reason: ExclusionReason,
+    pub drive: Drive,
+}
+
+/// Describes how an array was put back together
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AssemblyReport {
+    /// Drives that were left out, and why
+    pub excluded: Vec<Exclusion>,
+    /// Slots that no drive could fill, these start out failed
+    pub missing: Vec<usize>,
+    /// Stale drives that were let in anyway because of `force`, as positions in the list given to `assemble()`
+    pub forced: Vec<usize>,
+    /// The array wasn't shut down cleanly, so a resync of every stripe's parity was scheduled
+    pub resync: bool,
+}
+
+impl RaidSim {
+    /// Writes an up to date superblock to every usable member if the array's membership has changed since they were last written
+    pub(super) fn sync_superblocks(&mut self) {
+        let members = self
+            .drives
+            .iter()
+            .map(|d| d.usable())
+            .collect::<Vec<bool>>();
+        if members == self.members {
+            return;
+        }
+        let changed = |usable: bool| {
+            (0..members.len())
+                .filter(|i| members[*i] == usable && self.members.get(*i) != Some(&usable))
+                .collect::<Vec<usize>>()
+        };
+        let (lost, joined) = (changed(false), changed(true));
+        self.note_state(|| Trigger::Membership { lost, joined });
+        self.members = members;
+        self.bump_events();
+    }
+
+    /// Records a change to the array by bumping the event count and rewriting the superblocks
+    pub(super) fn bump_events(&mut self) {
+        self.events += 1;
+        self.clear_bitmap();
+        self.write_superblocks();
+        self.emit(ArrayEvent::MetadataUpdated {
+            events: self.events,
+        });
+    }
+
+    /// Writes the current superblock to every usable member
+    fn write_superblocks(&mut self) {
+        let num_drives = self.drives.len();
+        for (slot, d) in self.drives.iter_mut().enumerate() {
+            if d.usable() {
+                d.set_superblock(Some(Superblock {
+                    array_id: self.array_id,
+                    mode: self.mode,
+                    slot,
+                    num_drives,
+                    drive_size: self.drive_size,
+                    chunk_size: self.chunk_size,
+                    events: self.events,
+                    clean: self.clean,
+                }));
+            }
+        }
+    }
+
+    /// Marks the array as dirty on disk before the first write since it was started
+    pub(super) fn mark_unclean(&mut self) {
+        if self.clean {
+            self.clean = false;
+            self.write_superblocks();
+        }
+    }
+
+    /// Stops the array, writing out its metadata and handing back the members so they can be assembled again.
+    /// The array is only marked clean if cached writes could be flushed and every stripe's parity brought up to date first.
+    pub fn stop(mut self) -> Vec<Drive> {
+        let flushed = self.flush().is_ok();
+        self.sync_superblocks();
+        if flushed && self.flush_parity().is_ok() && self.dirty.is_empty() {
+            self.clean = true;
+            self.write_superblocks();
+        }
+        self.drives
+    }
+
+    /// Securely erases a drive taken out of the array, so it can be decommissioned or reused elsewhere.
+    /// Assembly never takes an erased drive back as a stale member.
+    /// Nothing about the array itself changes, so there's nothing for the op log to record.
+    pub fn secure_erase(&mut self, drive: &mut Drive) -> Result<()> {
+        let slot = drive
+            .superblock()
+            .filter(|sb| sb.array_id == self.array_id)
+            .map(|sb| sb.slot);
+        drive.secure_erase()?;
+        self.emit(ArrayEvent::DriveErased { slot });
+        Ok(())
+    }
+
+    /// Hands back the members as they are, as if the machine lost power.
+    /// Writes made since the array was started may have only partially reached the drives,
+    /// and anything still in the write-back cache is lost.
+    pub fn crash(self) -> Vec<Drive> {
+        self.drives
+    }
+
+    /// Assembles an array from images of Linux md members like `Drive::write_md_image()` writes, or `mdadm --create` makes,
+    /// see `Drive::from_md_image()`. Any image that can't be imported fails the whole import, the rest is as for `assemble()`.
+    ///
+    /// Arrays whose parity rotates, as md's do by default, have each row of chunks moved to where the sim keeps it.
+    /// Every member of those is needed and nothing else, as a member's chunks end up spread over all of them.
+    pub fn import_md(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        force: bool,
+    ) -> Result<(RaidSim, AssemblyReport)> {
+        let members = paths
+            .into_iter()
+            .map(|path| Drive::md_member_file(path.as_ref()))
+            .collect::<Result<Vec<(Drive, MdSuperblock)>>>()?;
+        let drives = match members.iter().find(|(_, md)| md.rotates()) {
+            Some((_, md)) => {
+                let md = md.clone();
+                unrotate(members, &md)?
+            }
+            None => members.into_iter().map(|(drive, _)| drive).collect(),
+        };
+        RaidSim::assemble(drives, force)
+    }
+
+    /// Puts an array back together from drives in any order, using the superblocks written when it was stopped.
+    ///
+    /// The array may be started with members missing as long as it is within its redundancy.
+    /// Members that missed changes while they were out are stale and left out, unless `force` is set
+    /// and they are needed to get the array running, in which case the most recent of them are let in.
+    ///
+    /// After an unclean shutdown parity may not match the data, so a resync of every stripe is scheduled to run
+    /// on the next tick. A degraded array can't be resynced and its parity has to be trusted as is,
+    /// so it's only started with `force`.
+    pub fn assemble(drives: Vec<Drive>, force: bool) -> Result<(RaidSim, AssemblyReport)> {
+        // The array is whichever one most of the drives belong to
+        let superblocks = drives
+            .iter()
+            .filter(|d| !d.has_failed())
+            .filter_map(|d| d.superblock())
+            .collect::<Vec<&Superblock>>();
+        let array_id = superblocks
+            .iter()
+            .max_by_key(|sb| {
+                superblocks
+                    .iter()
+                    .filter(|other| other.array_id == sb.array_id)
+                    .count()
+            })
+            .map(|sb| sb.array_id);
+        let newest = superblocks
+            .iter()
+            .filter(|sb| Some(sb.array_id) == array_id)
+            .max_by_key(|sb| sb.events)
+            .map(|sb| (*sb).clone());
+        let newest = match newest {
+            Some(sb) => sb,
+            None => bail!("No drive has a readable superblock, unable to assemble"),
+        };
+
+        let mut report = AssemblyReport::default();
+        let mut slots: Vec<Option<(usize, Drive)>> = vec![None; newest.num_drives];
+        let mut stale = vec![];
+        for (index, drive) in drives.into_iter().enumerate() {
+            let reason = match drive.superblock() {
+                _ if drive.has_failed() => Some(ExclusionReason::Faulty),
+                _ if drive.is_erased() => Some(ExclusionReason::Erased),
+                None => Some(ExclusionReason::NoSuperblock),
+                Some(sb) if sb.array_id != newest.array_id => Some(ExclusionReason::Foreign {
+                    array_id: sb.array_id,
+                }),
+                Some(sb) if sb.slot >= newest.num_drives => Some(ExclusionReason::Foreign {
+                    array_id: sb.array_id,
+                }),
+                Some(sb) if sb.events < newest.events => Some(ExclusionReason::Stale {
+                    events: sb.events,
+                    expected: newest.events,
+                }),
+                Some(sb) if slots[sb.slot].is_some() => {
+                    Some(ExclusionReason::Duplicate { slot: sb.slot })
+                }
+                Some(_) => None,
+            };
+            match reason {
+                Some(ExclusionReason::Stale { .. }) => stale.push((index, drive)),
+                Some(reason) => report.excluded.push(Exclusion {
+                    index,
+                    reason,
+                    drive,
+                }),
+                None => {
+                    let slot = drive.superblock().unwrap().slot;
+                    slots[slot] = Some((index, drive));
+                }
+            }
+        }
+
+        // Let the most recent stale members in when forced, but only as many as are needed to start
+        let redundancy = newest.mode.redundancy();
+        stale.sort_by_key(|(_, d)| std::cmp::Reverse(d.superblock().unwrap().events));
+        for (index, drive) in stale {
+            let sb = drive.superblock().unwrap().clone();
+            let missing = slots.iter().filter(|s| s.is_none()).count();
+            if force && missing > redundancy && slots[sb.slot].is_none() {
+                report.forced.push(index);
+                slots[sb.slot] = Some((index, drive));
+            } else {
+                report.excluded.push(Exclusion {
+                    index,
+                    reason: ExclusionReason::Stale {
+                        events: sb.events,
+                        expected: newest.events,
+                    },
+                    drive,
+                });
+            }
+        }
+        report.excluded.sort_by_key(|e| e.index);
+
+        report.missing = (0..slots.len()).filter(|i| slots[*i].is_none()).collect();
+        if report.missing.len() > redundancy {
+            bail!(
+                "Missing {} members but only able to lose {}, excluded: {:?}",
+                report.missing.len(),
+                redundancy,
+                report
+                    .excluded
+                    .iter()
+                    .map(|e| (e.index, &e.reason))
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        if !newest.clean && !report.missing.is_empty() && !force {
+            bail!(
+                "Array is degraded and wasn't shut down cleanly, its parity may be out of date, missing: {:?}",
+                report.missing
+            );
+        }
+
+        let mut sim = RaidSim::new(
+            newest.mode,
+            newest.num_drives,
+            newest.drive_size,
+            StorageLayout::default(),
+        );
+        sim.chunk_size = newest.chunk_size;
+        sim.array_id = newest.array_id;
+        sim.events = newest.events;
+        sim.clean = newest.clean;
+        for (slot, member) in slots.into_iter().enumerate() {
+            match member {
+                Some((_, mut drive)) => {
+                    drive.format();
+                    sim.drives[slot] = drive;
+                }
+                None => {
+                    sim.drives[slot].format();
+                    sim.drives[slot].fail();
+                }
+            }
+        }
+        sim.sync_superblocks();
+        sim.mark_all_written();
+        if !newest.clean && report.missing.is_empty() {
+            let stripes = newest.drive_size.div_ceil(STRIPE_HEIGHT);
+            sim.dirty.extend(0..stripes);
+            sim.resync = true;
+            report.resync = true;
+        }
+        sim.note_state(|| Trigger::Assembled);
+        Ok((sim, report))
+    }
+}
+
+/// Moves every row of chunks of the md array `md` describes from the members md put them on to the slots the sim
+/// keeps them in, P and Q first and then data in order. Q is worked out again wherever md weighs the data differently.
+fn unrotate(members: Vec<(Drive, MdSuperblock)>, md: &MdSuperblock) -> Result<Vec<Drive>> {
+    let n = md.raid_disks as usize;
+    let mut slots: Vec<Option<Drive>> = vec![None; n];
+    for (drive, other) in members {
+        if other.set_uuid != md.set_uuid
+            || other.layout != md.layout
+            || other.chunk_size != md.chunk_size
+        {
+            bail!("md members disagree on how their array is laid out, unable to import");
+        }
+        // Every md member has a superblock, and `MdSuperblock::to_superblock()` only hands out slots in the array
+        let slot = drive
+            .superblock()
+            .expect("md members have superblocks")
+            .slot;
+        if slots[slot].replace(drive).is_some() {
+            bail!("More than one md member is in slot {}", slot);
+        }
+    }
+    let Some(mut drives) = slots.into_iter().collect::<Option<Vec<Drive>>>() else {
+        bail!(
+            "md layout {} rotates parity across the members, all {} are needed to import it",
+            md.layout,
+            n
+        );
+    };
+    let chunk = md.chunk_size as usize * SECTOR_SIZE;
+    for row in 0..md.size as usize * SECTOR_SIZE / chunk {
+        let range = row * chunk..(row + 1) * chunk;
+        let chunks = drives
+            .iter()
+            .map(|drive| drive.read_range(range.clone()).map(Cow::into_owned))
+            .collect::<Result<Vec<Vec<u8>>>>()?;
+        let mut moved = vec![vec![]; n];
+        for (chunk, slot) in chunks.into_iter().zip(md.chunk_slots(row)) {
+            moved[slot] = chunk;
+        }
+        if !md.q_in_data_order() {
+            let data = moved[Q_INDEX + 1..]
+                .iter()
+                .map(Vec::as_slice)
+                .collect::<Vec<&[u8]>>();
+            let (mut p, mut q) = (vec![0; chunk], vec![0; chunk]);
+            GfEngine.gen_syndrome(&data, &mut p, &mut q);
+            moved[Q_INDEX] = q;
+        }
+        for (drive, chunk) in drives.iter_mut().zip(moved) {
+            drive.write_slice(range.start, &chunk)?;
+        }
+    }
+    Ok(drives)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::generator::{GfEngine, ParityEngine};
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::superblock::{md_checksum, MD_DATA_OFFSET, MD_SUPER_OFFSET};
+
+    #[test]
+    fn raid6_assemble_any_order() {
+        let (sim, data) = init_random(RaidMode::Raid6);
+        let mut drives = sim.stop();
+        drives.reverse();
+        let (sim, report) = RaidSim::assemble(drives, false).unwrap();
+        assert_eq!(report, AssemblyReport::default());
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_md_images_import_degraded() {
+        let mut sim = RaidSim::builder().drives(8, 4096).build().unwrap();
+        sim.init().unwrap();
+        let data = (0..sim.size()).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        sim.write_slice(0, &data).unwrap();
+        let paths = sim
+            .stop()
+            .iter()
+            .enumerate()
+            .map(|(slot, drive)| {
+                let path = std::env::temp_dir().join(format!(
+                    "raid-fun-{}-import{}.img",
+                    std::process::id(),
+                    slot
+                ));
+                drive.write_md_image(&path).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        // Corrupt one member's superblock, and lose another
+        let mut image = std::fs::read(&paths[4]).unwrap();
+        image[4096 + 80] ^= 1;
+        std::fs::write(&paths[4], image).unwrap();
+        let imported = RaidSim::import_md(&paths, false);
+        let (sim, report) = RaidSim::import_md(
+            paths
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| ![4, 6].contains(i))
+                .map(|(_, p)| p),
+            false,
+        )
+        .unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(format!("{:#}", imported.unwrap_err()).contains("checksum"));
+        assert_eq!(report.missing, vec![4, 6]);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+    }
+
+    /// Writes images of the md array `drives` were stopped from, but in md layout `layout`, holding `data` as `rows` says.
+    /// Each row of chunks is a letter per member: P, Q, or the chunk of data it holds counting from `a`.
+    /// Q weighs the data in member order from the member after it, wrapping around, as md does.
+    fn md_images(drives: &[Drive], layout: u32, rows: &[&str], data: &[u8]) -> Vec<PathBuf> {
+        let chunk = drives[0].superblock().unwrap().chunk_size;
+        let n = drives.len();
+        let mut images = drives
+            .iter()
+            .map(|drive| {
+                let mut sb = drive.superblock().unwrap().to_md().unwrap();
+                sb[76..80].copy_from_slice(&layout.to_le_bytes());
+                sb[116..120].copy_from_slice(&layout.to_le_bytes());
+                let csum = md_checksum(&sb);
+                sb[216..220].copy_from_slice(&csum.to_le_bytes());
+                let mut image = vec![0; MD_DATA_OFFSET];
+                image[MD_SUPER_OFFSET..MD_SUPER_OFFSET + sb.len()].copy_from_slice(&sb);
+                image
+            })
+            .collect::<Vec<Vec<u8>>>();
+        for row in rows {
+            let roles = row.as_bytes();
+            let chunk_of = |role: u8| {
+                let start = (role - b'a') as usize * chunk;
+                &data[start..start + chunk]
+            };
+            let last_parity = roles.iter().position(|r| *r == b'Q');
+            let last_parity = last_parity.or_else(|| roles.iter().position(|r| *r == b'P'));
+            let weighed = (1..n)
+                .map(|i| roles[(last_parity.unwrap() + i) % n])
+                .filter(|role| role.is_ascii_lowercase())
+                .map(chunk_of)
+                .collect::<Vec<&[u8]>>();
+            let (mut p, mut q) = (vec![0; chunk], vec![0; chunk]);
+            GfEngine.gen_syndrome(&weighed, &mut p, &mut q);
+            for (image, role) in images.iter_mut().zip(roles) {
+                image.extend_from_slice(match role {
+                    b'P' => &p,
+                    b'Q' => &q,
+                    _ => chunk_of(*role),
+                });
+            }
+        }
+        images
+            .iter()
+            .enumerate()
+            .map(|(member, image)| {
+                let path = std::env::temp_dir().join(format!(
+                    "raid-fun-{}-layout{}-{}.img",
+                    std::process::id(),
+                    layout,
+                    member
+                ));
+                std::fs::write(&path, image).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    #[test]
+    fn raid5_imports_mdadm_default_layout() {
+        // What `mdadm --create` makes unless told otherwise: left-symmetric, with 512K chunks
+        let chunk = 512 * 1024;
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid5)
+            .drives(4, 4 * chunk)
+            .chunk_size(chunk)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let data = write_random(&mut sim);
+        let expected = sim.clone();
+        let paths = md_images(&sim.stop(), 2, &["abcP", "efPd", "iPgh", "Pjkl"], &data);
+        let alone = Drive::from_md_file(&paths[0]);
+        let missing = RaidSim::import_md(&paths[1..], false);
+        let (sim, report) = RaidSim::import_md(&paths, false).unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(format!("{:#}", alone.unwrap_err()).contains("rotates parity"));
+        assert!(format!("{:#}", missing.unwrap_err()).contains("all 4 are needed"));
+        assert_eq!(report, AssemblyReport::default());
+        assert_eq!(sim.detail().chunk_size, chunk);
+        for slot in 0..4 {
+            assert_eq!(
+                sim.drive(slot).read_range(0..4 * chunk).unwrap(),
+                expected.drive(slot).read_range(0..4 * chunk).unwrap()
+            );
+        }
+        for offset in (0..data.len()).step_by(4099) {
+            assert_eq!(sim.read(offset as u64).unwrap(), data[offset]);
+        }
+    }
+
+    #[test]
+    fn raid6_imports_left_asymmetric_layout() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid6)
+            .drives(5, 5 * 4096)
+            .chunk_size(4096)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let data = write_random(&mut sim);
+        let rows = ["QabcP", "defPQ", "ghPQi", "jPQkl", "PQmno"];
+        let paths = md_images(&sim.stop(), 0, &rows, &data);
+        let (mut sim, _) = RaidSim::import_md(&paths, false).unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        // md weighs the data in some rows differently for Q, so it's worked out again
+        assert!(sim.stripes().all(|s| s.is_consistent().unwrap()));
+        sim.fail_drive(0);
+        sim.fail_drive(3);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_assemble_with_missing_members() {
+        let (sim, data) = init_random(RaidMode::Raid6);
+        let mut drives = sim.stop();
+        drives.remove(7);
+        drives.remove(3);
+        let (sim, report) = RaidSim::assemble(drives.clone(), false).unwrap();
+        assert_eq!(report.missing, vec![3, 7]);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+
+        drives.remove(0);
+        assert!(RaidSim::assemble(drives, false).is_err());
+    }
+
+    #[test]
+    fn raid5_assemble_excludes_faulty_and_foreign() {
+        let (sim, data) = init_random(RaidMode::Raid5);
+        let (other, _) = init_random(RaidMode::Raid5);
+        let mut drives = sim.stop();
+        drives[2].fail();
+        drives.push(other.stop().remove(0));
+        drives.push(Drive::empty(DRIVE_SIZE));
+        let (sim, report) = RaidSim::assemble(drives, false).unwrap();
+        let reasons = report
+            .excluded
+            .iter()
+            .map(|e| (e.index, e.reason.clone()))
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            reasons[..],
+            [
+                (2, ExclusionReason::Faulty),
+                (NUM_DRIVES, ExclusionReason::Foreign { .. }),
+                (65, ExclusionReason::NoSuperblock)
+            ]
+        ));
+        assert_eq!(report.missing, vec![2]);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_erased_drive_is_never_a_stale_member() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = seen.clone();
+        sim.add_observer(move |event: &ArrayEvent| log.borrow_mut().push(event.clone()));
+        let mut removed = sim.remove_drive(5);
+        sim.secure_erase(&mut removed).unwrap();
+        assert!(removed.is_erased());
+        assert!(removed.superblock().is_none());
+        assert!(removed
+            .read_slice(0, DRIVE_SIZE)
+            .unwrap()
+            .iter()
+            .all(|b| *b == 0));
+        assert_eq!(
+            seen.borrow().last(),
+            Some(&ArrayEvent::DriveErased { slot: Some(5) })
+        );
+        assert!(sim.re_add(removed.clone()).is_err());
+
+        let mut drives = sim.stop();
+        drives.push(removed);
+        let (sim, report) = RaidSim::assemble(drives, true).unwrap();
+        // The failed placeholder left in the slot is excluded too
+        let reasons = report
+            .excluded
+            .iter()
+            .map(|e| (e.index, e.reason.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                (5, ExclusionReason::Faulty),
+                (NUM_DRIVES, ExclusionReason::Erased)
+            ]
+        );
+        assert_eq!(report.missing, vec![5]);
+        assert!(report.forced.is_empty());
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_forced_assembly_with_stale_member() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.drive_mut(4).fail();
+        sim.write(0, !data[0]).unwrap();
+        sim.drive_mut(9).fail();
+        assert_eq!(sim.state(), RaidState::Failed);
+        assert!(sim.write(0, data[0]).is_err());
+
+        // Drive 9 only dropped out because of a loose cable
+        let mut drives = sim.stop();
+        drives[9].recover();
+        drives[4].recover();
+        assert!(RaidSim::assemble(drives.clone(), false).is_err());
+
+        let (sim, report) = RaidSim::assemble(drives, true).unwrap();
+        assert_eq!(report.forced, vec![9]);
+        assert_eq!(report.missing, vec![4]);
+        assert!(matches!(
+            report.excluded[..],
+            [Exclusion {
+                index: 4,
+                reason: ExclusionReason::Stale { .. },
+                ..
+            }]
+        ));
+        assert_eq!(sim.state(), RaidState::Degraded);
+        let mut expected = data.clone();
+        expected[0] = !data[0];
+        assert_sim_equal(&sim, &expected);
+    }
+
+    #[test]
+    fn raid5_unclean_shutdown_resyncs_parity() {
+        let (sim, data) = init_random(RaidMode::Raid5);
+        let (mut sim, report) = RaidSim::assemble(sim.stop(), false).unwrap();
+        assert!(!report.resync);
+        assert_eq!(sim.state(), RaidState::Ok);
+
+        // The power went out after a write's data reached the drive but before its parity did
+        let mut expected = data.clone();
+        expected[5] = !data[5];
+        sim.write(4, data[4]).unwrap();
+        sim.drive_mut(1).write(5, expected[5]).unwrap();
+        let (mut sim, report) = RaidSim::assemble(sim.crash(), false).unwrap();
+        assert!(report.resync);
+        assert_eq!(sim.state(), RaidState::Unprotected);
+        sim.tick(1).unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_eq!(
+            sim.stats().mismatches_by_cause[&MismatchCause::UncleanShutdown],
+            MismatchCount {
+                found: 1,
+                corrected: 1
+            }
+        );
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &expected);
+    }
+
+    #[test]
+    fn raid6_unclean_degraded_assembly_needs_force() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        let drives = sim.crash();
+        assert!(RaidSim::assemble(drives.clone(), false).is_err());
+
+        let (sim, report) = RaidSim::assemble(drives, true).unwrap();
+        assert!(!report.resync);
+        assert_eq!(report.missing, vec![P_INDEX]);
+        assert_sim_equal(&sim, &data);
+    }
+}
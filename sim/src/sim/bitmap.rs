@@ -0,0 +1,157 @@
+use anyhow::{bail, Result};
+
+use crate::drive::Drive;
+
+use super::{Op, RaidSim, STRIPE_HEIGHT};
+
+/// How a previously failed member was brought back into the array
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ReAdd {
+    /// Only the stripes written while the drive was out were resynced
+    Fast { stripes: Vec<usize> },
+    /// The write-intent bitmap doesn't go back far enough, the drive has to be rebuilt with `repair()`
+    Full,
+}
+
+impl RaidSim {
+    /// Returns the stripes written while a member was missing, which a returning member would need resynced
+    pub fn bitmap(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bitmap.iter().copied()
+    }
+
+    /// Records drive offsets `start..end` in the write-intent bitmap if any member is missing out on the write
+    pub(super) fn mark_bitmap(&mut self, start: usize, end: usize) {
+        if self.drives.iter().any(|d| !d.usable()) {
+            self.bitmap
+                .extend(start / STRIPE_HEIGHT..=(end.max(start + 1) - 1) / STRIPE_HEIGHT);
+        }
+    }
+
+    /// Clears the write-intent bitmap once every member is back in sync
+    pub(super) fn clear_bitmap(&mut self) {
+        if self.drives.iter().all(|d| d.usable()) {
+            self.bitmap.clear();
+            self.bitmap_since = self.events;
+        }
+    }
+
+    /// Pulls the drive at `index` out of the array, leaving a failed slot behind
+    pub fn remove_drive(&mut self, index: usize) -> Drive {
+        let _recording = self.record(|| Op::RemoveDrive { index });
+        let mut missing = Drive::empty(self.drive_size);
+        missing.format();
+        missing.fail();
+        let drive = std::mem::replace(&mut self.drives[index], missing);
+        self.sync_superblocks();
+        drive
+    }
+
+    /// Puts a drive that was previously part of this array back into its old slot.
+    ///
+    /// If every write it missed is covered by the write-intent bitmap, only those stripes are resynced.
+    /// Otherwise its contents can't be trusted and it goes back in unformatted, waiting on `repair()`.
+    pub fn re_add(&mut self, mut drive: Drive) -> Result<ReAdd> {
+        self.record_unreplayable("re_add");
+        if drive.has_failed() {
+            bail!("Drive has failed, unable to re-add");
+        }
+        if drive.is_erased() {
+            bail!("Drive was securely erased, unable to re-add");
+        }
+        let sb = match drive.superblock() {
+            Some(sb) if sb.array_id == self.array_id && sb.slot < self.drives.len() => sb.clone(),
+            _ => bail!("Drive was never part of this array, unable to re-add"),
+        };
+        if self.drives[sb.slot].usable() {
+            bail!("Slot {} is already in use, unable to re-add", sb.slot);
+        }
+
+        if sb.events < self.bitmap_since {
+            let mut replacement = Drive::empty(self.drive_size);
+            self.seed_drive(sb.slot, &mut replacement);
+            self.prepare_member(&mut replacement);
+            self.drives[sb.slot] = replacement;
+            self.sync_superblocks();
+            return Ok(ReAdd::Full);
+        }
+
+        let stripes = self.bitmap.iter().copied().collect::<Vec<usize>>();
+        for stripe in &stripes {
+            let start = stripe * STRIPE_HEIGHT;
+            let end = (start + STRIPE_HEIGHT).min(self.drive_size);
+            let contents = self.member_range(sb.slot, start, end)?;
+            drive.write_slice(start, &contents)?;
+        }
+        drive.format();
+        self.prepare_member(&mut drive);
+        self.drives[sb.slot] = drive;
+        self.sync_superblocks();
+        Ok(ReAdd::Fast { stripes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_fast_re_add_resyncs_bitmap() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let mut drive = sim.remove_drive(5);
+        assert_eq!(sim.state(), RaidState::Degraded);
+
+        let mut expected = data.clone();
+        for offset in [
+            3 * DRIVE_SIZE,
+            3 * DRIVE_SIZE + STRIPE_HEIGHT + 1,
+            40 * DRIVE_SIZE,
+        ] {
+            expected[offset] = !expected[offset];
+            sim.write(offset as u64, expected[offset]).unwrap();
+        }
+        assert_eq!(sim.bitmap().collect::<Vec<_>>(), vec![0, 1]);
+
+        drive.recover();
+        assert_eq!(
+            sim.re_add(drive).unwrap(),
+            ReAdd::Fast {
+                stripes: vec![0, 1]
+            }
+        );
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_eq!(sim.bitmap().count(), 0);
+
+        // The short resync left the drive exactly as a full rebuild would have
+        for offset in 0..DRIVE_SIZE {
+            assert_eq!(
+                sim.drive(5).read(offset).unwrap(),
+                sim.member_contents(5, offset).unwrap()
+            );
+        }
+        sim.fail_p_parity();
+        sim.fail_q_parity();
+        assert_sim_equal(&sim, &expected);
+    }
+
+    #[test]
+    fn raid5_re_add_after_bitmap_cleared_needs_full_rebuild() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        let mut drive = sim.remove_drive(5);
+        sim.write(0, !data[0]).unwrap();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.bitmap().count(), 0);
+
+        drive.recover();
+        assert!(sim.re_add(drive.clone()).is_err());
+        sim.drive_mut(5).fail();
+        assert_eq!(sim.re_add(drive).unwrap(), ReAdd::Full);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        sim.repair().unwrap();
+
+        let mut expected = data.clone();
+        expected[0] = !data[0];
+        assert_sim_equal(&sim, &expected);
+    }
+}
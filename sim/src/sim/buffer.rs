@@ -0,0 +1,195 @@
+use std::{
+    cell::{Cell, RefCell},
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use anyhow::{bail, Result};
+
+use super::{Op, RaidSim};
+
+/// Counters kept by the scratch buffer pool
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct BufferStats {
+    /// Buffers that had to be allocated because none were free
+    pub allocations: u64,
+    /// Buffers handed out again after being returned
+    pub reuses: u64,
+    /// Bytes currently handed out
+    pub in_use: usize,
+    /// Most bytes ever handed out at once
+    pub peak: usize,
+}
+
+/// Scratch buffers for reconstruction, parity computation, and rebuild, kept around between uses
+#[derive(Debug, Default)]
+pub(super) struct BufferPool {
+    /// Most bytes that may be handed out at once by rebuild and scrub, unlimited if `None`
+    budget: Cell<Option<usize>>,
+    /// Set while a rebuild or scrub is running, the only time the budget applies
+    budgeted: Cell<bool>,
+    free: RefCell<Vec<Vec<u8>>>,
+    stats: Cell<BufferStats>,
+}
+
+/// A zeroed scratch buffer that goes back to its pool when dropped
+#[derive(Debug)]
+pub(super) struct Buffer {
+    pool: Rc<BufferPool>,
+    data: Vec<u8>,
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for Buffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        let mut stats = self.pool.stats.get();
+        stats.in_use -= data.capacity();
+        self.pool.stats.set(stats);
+        self.pool.free.borrow_mut().push(data);
+    }
+}
+
+/// Holds whatever is taken from the pool to the memory budget until it's dropped
+pub(super) struct Budgeted {
+    pool: Rc<BufferPool>,
+    was: bool,
+}
+
+impl Drop for Budgeted {
+    fn drop(&mut self) {
+        self.pool.budgeted.set(self.was);
+    }
+}
+
+impl BufferPool {
+    /// Applies the memory budget to every buffer taken until what's returned is dropped
+    pub(super) fn budgeted(self: &Rc<Self>) -> Budgeted {
+        Budgeted {
+            pool: self.clone(),
+            was: self.budgeted.replace(true),
+        }
+    }
+
+    /// Hands out a zeroed buffer of `len` bytes, failing if a rebuild or scrub would go over the memory budget
+    pub(super) fn take(self: &Rc<Self>, len: usize) -> Result<Buffer> {
+        let mut stats = self.stats.get();
+        let mut free = self.free.borrow_mut();
+        // Use the smallest free buffer that fits so the large ones are left for large requests
+        let fit = (0..free.len())
+            .filter(|i| free[*i].capacity() >= len)
+            .min_by_key(|i| free[*i].capacity());
+        let data = match fit {
+            Some(i) => {
+                stats.reuses += 1;
+                free.swap_remove(i)
+            }
+            None => {
+                stats.allocations += 1;
+                Vec::with_capacity(len)
+            }
+        };
+        if let Some(budget) = self.budget.get().filter(|_| self.budgeted.get()) {
+            if stats.in_use + data.capacity() > budget {
+                free.push(data);
+                bail!(
+                    "Memory budget of {} bytes exhausted, {} bytes already in use",
+                    budget,
+                    stats.in_use
+                );
+            }
+        }
+        stats.in_use += data.capacity();
+        stats.peak = stats.peak.max(stats.in_use);
+        self.stats.set(stats);
+
+        let mut data = data;
+        data.clear();
+        data.resize(len, 0);
+        Ok(Buffer {
+            pool: self.clone(),
+            data,
+        })
+    }
+}
+
+impl RaidSim {
+    /// Limits how many bytes of scratch buffers scrub and rebuild may use at once, or lifts the limit with `None`.
+    /// Foreground reads and writes reconstruct what they need whatever the budget.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        let _recording = self.record(|| Op::SetMemoryBudget { budget });
+        self.buffers.budget.set(budget);
+    }
+
+    /// Returns the scratch buffer pool's counters
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.buffers.stats.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_rebuild_reuses_buffers() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_p_parity();
+        sim.replace_failed_drives();
+        let before = sim.buffer_stats();
+        sim.repair().unwrap();
+        let stats = sim.buffer_stats();
+        assert_eq!(stats.in_use, 0);
+        assert!(stats.allocations - before.allocations <= 2);
+        assert!(stats.reuses - before.reuses > DRIVE_SIZE as u64);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_degraded_reads_allocate_nothing_new() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_random_data();
+        // The first reconstruction fills the pool, every one after reuses it
+        assert_sim_equal(&sim, &data);
+        let before = sim.buffer_stats();
+        assert_sim_equal(&sim, &data);
+        let stats = sim.buffer_stats();
+        assert_eq!(stats.allocations, before.allocations);
+        assert_eq!(stats.in_use, 0);
+    }
+
+    #[test]
+    fn raid5_memory_budget_bounds_rebuild() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.set_memory_budget(Some(STRIPE_HEIGHT));
+        assert!(sim.repair().is_err());
+        assert_eq!(sim.buffer_stats().in_use, 0);
+
+        // Reads have to get through whatever the budget
+        sim.set_memory_budget(Some(2));
+        assert_sim_equal(&sim, &data);
+
+        sim.set_memory_budget(Some(STRIPE_HEIGHT + NUM_DRIVES));
+        sim.repair().unwrap();
+        assert!(sim.buffer_stats().peak <= STRIPE_HEIGHT + NUM_DRIVES);
+        assert_sim_equal(&sim, &data);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{fmt, mem, rc::Rc};
+
+use anyhow::bail;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    checksum::ChecksumAlgorithm,
+    codec::{Decoder, Encoder},
+    drive::Drive,
+    generator::{GfEngine, ParityEngine},
+};
+
+use super::{
+    oplog, spare, OpLog, ParityUpdate, PromotionOrder, RaidMode, RaidSim, RetryPolicy,
+    STRIPE_HEIGHT,
+};
+
+/// Why a `RaidSimBuilder` refused to build an array
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigError {
+    /// Not enough members for the mode's parity and at least two data drives
+    TooFewDrives {
+        mode: RaidMode,
+        num_drives: usize,
+        min: usize,
+    },
+    /// More data drives than the field has distinct powers of the generator for Q
+    TooManyDrives {
+        mode: RaidMode,
+        num_drives: usize,
+        max: usize,
+    },
+    /// Members have to hold at least a byte
+    ZeroDriveSize,
+    /// Chunks have to be a whole number of stripes, and members a whole number of chunks
+    InvalidChunkSize {
+        chunk_size: usize,
+        drive_size: usize,
+    },
+    /// A dedicated spare too small to stand in for a member
+    SpareTooSmall { size: usize, drive_size: usize },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::TooFewDrives {
+                mode,
+                num_drives,
+                min,
+            } => write!(
+                f,
+                "{:?} needs at least {} drives, got {}",
+                mode, min, num_drives
+            ),
+            ConfigError::TooManyDrives {
+                mode,
+                num_drives,
+                max,
+            } => write!(
+                f,
+                "{:?} supports at most {} drives in GF(2^8), got {}",
+                mode, max, num_drives
+            ),
+            ConfigError::ZeroDriveSize => write!(f, "Drives must hold at least one byte"),
+            ConfigError::InvalidChunkSize {
+                chunk_size,
+                drive_size,
+            } => write!(
+                f,
+                "Chunks of {} bytes must be a multiple of {} bytes that divides drives of {} bytes",
+                chunk_size, STRIPE_HEIGHT, drive_size
+            ),
+            ConfigError::SpareTooSmall { size, drive_size } => write!(
+                f,
+                "Spare of size {} is smaller than members of size {}",
+                size, drive_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How the members' bytes are arranged in the allocation they share
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum StorageLayout {
+    /// Each member's bytes one after another, then the next member's
+    #[default]
+    DriveMajor,
+    /// A stripe of every member next to each other, then the next stripe, so stripe-wise loops like scrubs and rebuilds
+    /// stream straight through memory. Anything that wants a member in one piece, like its checksum, Merkle tree
+    /// or image, has to copy its stripes together first.
+    StripeMajor,
+}
+
+/// Configures a `RaidSim` one setting at a time, checking they make sense together when it's built
+#[derive(Debug, Clone)]
+pub struct RaidSimBuilder {
+    mode: RaidMode,
+    num_drives: usize,
+    drive_size: usize,
+    chunk_size: Option<usize>,
+    layout: StorageLayout,
+    spares: Vec<Drive>,
+    blank_spares: usize,
+    seed: Option<u64>,
+    retry_policy: RetryPolicy,
+    promotion_order: PromotionOrder,
+    parity_update: ParityUpdate,
+    drive_timeout: Option<u64>,
+    checksum: Option<ChecksumAlgorithm>,
+    engine: Rc<dyn ParityEngine>,
+    record: bool,
+}
+
+impl Default for RaidSimBuilder {
+    fn default() -> Self {
+        Self {
+            mode: RaidMode::Raid6,
+            num_drives: 0,
+            drive_size: 0,
+            chunk_size: None,
+            layout: StorageLayout::DriveMajor,
+            spares: vec![],
+            blank_spares: 0,
+            seed: None,
+            retry_policy: RetryPolicy::default(),
+            promotion_order: PromotionOrder::DedicatedFirst,
+            parity_update: ParityUpdate::Immediate,
+            drive_timeout: None,
+            checksum: None,
+            engine: Rc::new(GfEngine),
+            record: false,
+        }
+    }
+}
+
+impl RaidSimBuilder {
+    /// Sets the RAID level, RAID6 by default
+    pub fn mode(mut self, mode: RaidMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the number of members, parity included, and the size of each in bytes.
+    ///
+    /// The members start out in one allocation, a drive's worth of bytes apart, see `Drive::arena()`.
+    /// Only the drives the array is built with share it: replacements, spares, members that are resized,
+    /// and clones of the array each get an allocation of their own.
+    pub fn drives(mut self, num_drives: usize, drive_size: usize) -> Self {
+        self.num_drives = num_drives;
+        self.drive_size = drive_size;
+        self
+    }
+
+    /// Stripes the array across its data drives `chunk_size` bytes at a time, the first chunk of every data drive
+    /// and then the second. By default each data drive holds a single run of the array, one chunk as big as the drive.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Sets how the members' bytes are laid out in memory, drive-major by default.
+    /// Like the parity engine it only changes how fast the array runs, so it isn't carried in an op log.
+    pub fn layout(mut self, layout: StorageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Dedicates `count` blank drives the size of the members to the array as spares
+    pub fn spares(mut self, count: usize) -> Self {
+        self.blank_spares = count;
+        self
+    }
+
+    /// Dedicates `drive` to the array as a spare, after any blank ones. Whatever was on it is treated as stale.
+    pub fn spare(mut self, drive: Drive) -> Self {
+        self.spares.push(drive);
+        self
+    }
+
+    /// Seeds everything the array picks at random, like its id and which drive `fail_random()` fails,
+    /// so the same seed builds the same array
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets how reads that fail on a working drive are retried and recovered
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Sets which spares `RaidSim::promote_spares()` takes first
+    pub fn promotion_order(mut self, order: PromotionOrder) -> Self {
+        self.promotion_order = order;
+        self
+    }
+
+    /// Sets when writes bring parity up to date, straight away by default
+    pub fn parity_update(mut self, update: ParityUpdate) -> Self {
+        self.parity_update = update;
+        self
+    }
+
+    /// Sets how long a member may hang, in simulated milliseconds, before it's kicked from the array
+    pub fn drive_timeout(mut self, timeout: Option<u64>) -> Self {
+        self.drive_timeout = timeout;
+        self
+    }
+
+    /// Has every member keep a checksum of each of its sectors with `algorithm`
+    pub fn checksum(mut self, algorithm: Option<ChecksumAlgorithm>) -> Self {
+        self.checksum = algorithm;
+        self
+    }
+
+    /// Sets what full stripe writes compute their parity with, the built-in `GfEngine` by default.
+    /// The engine isn't carried in an op log, a replay uses the built-in one and so checks it came out the same.
+    pub fn parity_engine(mut self, engine: impl ParityEngine + 'static) -> Self {
+        self.engine = Rc::new(engine);
+        self
+    }
+
+    /// Has the array keep a log of every operation applied to it that `RaidSim::replay()` can reproduce it from.
+    /// An array with no seed set is given a random one so the log has it.
+    pub fn record(mut self, record: bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Builds the array, still to be initialized with `init()`
+    pub fn build(self) -> Result<RaidSim, ConfigError> {
+        let mode = self.mode;
+        if self.num_drives < mode.min_drives() {
+            return Err(ConfigError::TooFewDrives {
+                mode,
+                num_drives: self.num_drives,
+                min: mode.min_drives(),
+            });
+        }
+        if let Some(max) = mode.max_drives().filter(|max| self.num_drives > *max) {
+            return Err(ConfigError::TooManyDrives {
+                mode,
+                num_drives: self.num_drives,
+                max,
+            });
+        }
+        if self.drive_size == 0 {
+            return Err(ConfigError::ZeroDriveSize);
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            if chunk_size == 0
+                || !chunk_size.is_multiple_of(STRIPE_HEIGHT)
+                || !self.drive_size.is_multiple_of(chunk_size)
+            {
+                return Err(ConfigError::InvalidChunkSize {
+                    chunk_size,
+                    drive_size: self.drive_size,
+                });
+            }
+        }
+        if let Some(spare) = self.spares.iter().find(|d| d.size() < self.drive_size) {
+            return Err(ConfigError::SpareTooSmall {
+                size: spare.size(),
+                drive_size: self.drive_size,
+            });
+        }
+
+        let seed = self
+            .seed
+            .or_else(|| self.record.then(|| rand::rng().random()));
+        let log = self.record.then(|| {
+            OpLog::new(RaidSimBuilder {
+                seed,
+                ..self.clone()
+            })
+        });
+        let mut sim = RaidSim::new(self.mode, self.num_drives, self.drive_size, self.layout);
+        sim.chunk_size = self.chunk_size.unwrap_or(self.drive_size);
+        if let Some(seed) = seed {
+            sim.rng = StdRng::seed_from_u64(seed);
+            sim.array_id = sim.rng.random();
+            sim.fault_seed = Some(sim.rng.random());
+        }
+        let mut drives = mem::take(&mut sim.drives);
+        for (slot, drive) in drives.iter_mut().enumerate() {
+            sim.seed_drive(slot, drive);
+        }
+        sim.drives = drives;
+        sim.spares = (0..self.blank_spares)
+            .map(|i| {
+                let mut drive = Drive::empty(sim.drive_size);
+                sim.seed_drive(sim.drives.len() + i, &mut drive);
+                drive
+            })
+            .chain(self.spares.into_iter().map(|mut drive| {
+                spare::clear_stale(&mut drive);
+                drive
+            }))
+            .collect();
+        sim.retry_policy = self.retry_policy;
+        sim.promotion_order = self.promotion_order;
+        sim.parity_update = self.parity_update;
+        sim.set_drive_timeout(self.drive_timeout);
+        sim.set_checksum(self.checksum);
+        sim.engine = self.engine;
+        sim.op_log = log;
+        Ok(sim)
+    }
+}
+
+impl RaidSimBuilder {
+    /// Writes the builder's settings into a serialized op log
+    pub(super) fn encode(&self, out: &mut Encoder) -> anyhow::Result<()> {
+        if !self.spares.is_empty() {
+            bail!("Spare drives handed to the builder can't be written into an op log");
+        }
+        out.u8(match self.mode {
+            RaidMode::Raid5 => 5,
+            RaidMode::Raid6 => 6,
+        });
+        for value in [self.num_drives, self.drive_size, self.blank_spares] {
+            out.u64(value as u64);
+        }
+        out.option(self.chunk_size.map(|size| size as u64));
+        out.option(self.seed);
+        oplog::encode_retry_policy(out, self.retry_policy);
+        oplog::encode_promotion_order(out, self.promotion_order);
+        oplog::encode_parity_update(out, self.parity_update);
+        out.option(self.drive_timeout);
+        oplog::encode_checksum(out, self.checksum);
+        out.bool(self.record);
+        Ok(())
+    }
+
+    /// Reads back the settings `encode()` wrote into an op log of version `version`
+    pub(super) fn decode(input: &mut Decoder, version: u16) -> anyhow::Result<Self> {
+        let mode = match input.u8()? {
+            5 => RaidMode::Raid5,
+            6 => RaidMode::Raid6,
+            other => bail!("Unknown RAID mode {} in op log", other),
+        };
+        let mut builder = RaidSimBuilder::default()
+            .mode(mode)
+            .drives(input.usize()?, input.usize()?);
+        if version < 3 {
+            // A chunk size nothing used, every array had a chunk per member
+            input.usize()?;
+        }
+        builder.blank_spares = input.usize()?;
+        if version >= 3 {
+            builder.chunk_size = input.option()?.map(|size| size as usize);
+        }
+        builder.seed = input.option()?;
+        builder.retry_policy = oplog::decode_retry_policy(input)?;
+        builder.promotion_order = oplog::decode_promotion_order(input)?;
+        builder.parity_update = oplog::decode_parity_update(input)?;
+        builder.drive_timeout = input.option()?;
+        builder.checksum = oplog::decode_checksum(input)?;
+        builder.record = input.bool()?;
+        Ok(builder)
+    }
+}
+
+impl RaidSim {
+    /// Starts configuring an array
+    pub fn builder() -> RaidSimBuilder {
+        RaidSimBuilder::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::Drive;
+
+    #[test]
+    fn seeded_members_fail_alike_in_any_order() {
+        let build = || {
+            let mut sim = RaidSim::builder()
+                .drives(NUM_DRIVES, DRIVE_SIZE)
+                .seed(7)
+                .build()
+                .unwrap();
+            for index in [3, 4] {
+                sim.drive_mut(index).set_flaky(Some(4));
+            }
+            sim
+        };
+        let pattern = |sim: &RaidSim, index: usize| {
+            (0..64)
+                .map(|i| sim.drive(index).read(i).is_err())
+                .collect::<Vec<bool>>()
+        };
+        let (a, b) = (build(), build());
+        let a3 = pattern(&a, 3);
+        let a4 = pattern(&a, 4);
+        let b4 = pattern(&b, 4);
+        let b3 = pattern(&b, 3);
+        assert_eq!((a3, a4), (b3, b4));
+
+        // A replacement draws its own faults, the same ones in every run
+        let (mut a, mut b) = (build(), build());
+        for sim in [&mut a, &mut b] {
+            sim.fail_drive(3);
+            sim.replace_failed_drives();
+            sim.drive_mut(3).set_flaky(Some(4));
+        }
+        assert_eq!(pattern(&a, 3), pattern(&b, 3));
+        assert_ne!(pattern(&a, 3), pattern(&build(), 3));
+    }
+
+    #[test]
+    fn builder_configures_and_rejects() {
+        let build = || {
+            RaidSim::builder()
+                .mode(RaidMode::Raid5)
+                .drives(NUM_DRIVES, DRIVE_SIZE)
+                .spares(2)
+                .seed(42)
+                .drive_timeout(Some(500))
+        };
+        let (a, b) = (build().build().unwrap(), build().build().unwrap());
+        assert_eq!(a.detail().array_id, b.detail().array_id);
+        assert_eq!(a.detail().mode, RaidMode::Raid5);
+        assert_eq!(a.spares().count(), 2);
+
+        assert_eq!(
+            build().drives(2, DRIVE_SIZE).build().unwrap_err(),
+            ConfigError::TooFewDrives {
+                mode: RaidMode::Raid5,
+                num_drives: 2,
+                min: 3
+            }
+        );
+        assert!(build().drives(300, DRIVE_SIZE).build().is_ok());
+        assert_eq!(
+            build()
+                .mode(RaidMode::Raid6)
+                .drives(258, DRIVE_SIZE)
+                .build()
+                .unwrap_err(),
+            ConfigError::TooManyDrives {
+                mode: RaidMode::Raid6,
+                num_drives: 258,
+                max: 257
+            }
+        );
+        assert_eq!(
+            build().drives(4, 0).build().unwrap_err(),
+            ConfigError::ZeroDriveSize
+        );
+        assert_eq!(
+            build().chunk_size(512).build().unwrap().detail().chunk_size,
+            512
+        );
+        for chunk_size in [0, 100, 1536] {
+            assert_eq!(
+                build().chunk_size(chunk_size).build().unwrap_err(),
+                ConfigError::InvalidChunkSize {
+                    chunk_size,
+                    drive_size: DRIVE_SIZE
+                }
+            );
+        }
+        assert_eq!(
+            build()
+                .spare(Drive::empty(DRIVE_SIZE - 1))
+                .build()
+                .unwrap_err(),
+            ConfigError::SpareTooSmall {
+                size: DRIVE_SIZE - 1,
+                drive_size: DRIVE_SIZE
+            }
+        );
+    }
+}
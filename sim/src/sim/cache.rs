@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use super::{CachePolicy, EvictionPolicy, RaidSim, STRIPE_HEIGHT};
+
+/// Sizes the read cache, both in blocks of `STRIPE_HEIGHT` bytes of the array, and picks how it evicts
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReadCacheConfig {
+    /// Number of blocks kept before one is evicted
+    pub capacity: usize,
+    /// Number of blocks fetched ahead once reads are found to be sequential
+    pub read_ahead: usize,
+    pub policy: EvictionPolicy,
+}
+
+impl Default for ReadCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            read_ahead: 4,
+            policy: EvictionPolicy::Lru,
+        }
+    }
+}
+
+/// Counters kept by the read cache
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Reads answered from the cache
+    pub hits: u64,
+    /// Reads that had to go to the drives
+    pub misses: u64,
+    /// Blocks fetched ahead of being read
+    pub prefetched: u64,
+    /// Prefetched blocks that were read before being evicted
+    pub prefetch_hits: u64,
+    /// Blocks dropped to make room
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub(super) fn merge(&mut self, other: &CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.prefetched += other.prefetched;
+        self.prefetch_hits += other.prefetch_hits;
+        self.evictions += other.evictions;
+    }
+}
+
+/// Blocks of the array recently read or fetched ahead
+#[derive(Debug)]
+pub(super) struct ReadCache {
+    config: ReadCacheConfig,
+    blocks: BTreeMap<usize, Vec<u8>>,
+    policy: Box<dyn CachePolicy>,
+    /// Prefetched blocks that haven't been read yet
+    unread: BTreeSet<usize>,
+    /// Block of the previous read, for spotting sequential reads
+    last: Option<usize>,
+    stats: CacheStats,
+}
+
+/// A copy starts out empty with the same config, the policy's bookkeeping can't be copied along with the blocks
+impl Clone for ReadCache {
+    fn clone(&self) -> Self {
+        Self::new(self.config)
+    }
+}
+
+impl ReadCache {
+    pub(super) fn new(config: ReadCacheConfig) -> Self {
+        Self {
+            config,
+            blocks: BTreeMap::new(),
+            policy: config.policy.build(),
+            unread: BTreeSet::new(),
+            last: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub(super) fn policy_name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    pub(super) fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Looks up a byte, letting the policy know its block was read
+    fn get(&mut self, block: usize, offset: usize) -> Option<u8> {
+        let data = self.blocks.get(&block)?[offset - block * STRIPE_HEIGHT];
+        self.policy.accessed(block);
+        if self.unread.remove(&block) {
+            self.stats.prefetch_hits += 1;
+        }
+        Some(data)
+    }
+
+    /// Adds a block, evicting whatever the policy picks if the cache is full
+    fn insert(&mut self, block: usize, data: Vec<u8>) {
+        while self.blocks.len() >= self.config.capacity.max(1) {
+            let evicted = match self.policy.evict() {
+                Some(evicted) => evicted,
+                None => break,
+            };
+            self.blocks.remove(&evicted);
+            self.unread.remove(&evicted);
+            self.stats.evictions += 1;
+        }
+        self.blocks.insert(block, data);
+        self.policy.inserted(block);
+    }
+}
+
+impl RaidSim {
+    /// Turns the read cache on with the given config, or off with `None`, starting out empty either way.
+    /// The old cache's counters are kept in `stats()` under the name of its policy.
+    pub fn set_read_cache(&mut self, config: Option<ReadCacheConfig>) {
+        self.retire_read_cache();
+        *self.read_cache.borrow_mut() = config.map(ReadCache::new);
+    }
+
+    /// Swaps the read cache's eviction policy for a custom one, starting the cache out empty
+    pub fn set_read_cache_policy(&mut self, policy: Box<dyn CachePolicy>) {
+        self.retire_read_cache();
+        if let Some(cache) = self.read_cache.get_mut() {
+            *cache = ReadCache {
+                policy,
+                ..ReadCache::new(cache.config)
+            };
+        }
+    }
+
+    /// Folds the current read cache's counters into the array's per policy counters
+    fn retire_read_cache(&mut self) {
+        if let Some(cache) = self.read_cache.get_mut() {
+            self.stats
+                .read_cache
+                .entry(cache.policy.name())
+                .or_default()
+                .merge(&cache.stats);
+        }
+    }
+
+    /// Returns the read cache's counters, or `None` if there is no read cache
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.read_cache.borrow().as_ref().map(|c| c.stats)
+    }
+
+    /// Reads all of `block` from the array
+    fn read_block(&self, block: usize) -> Result<Vec<u8>> {
+        let start = block * STRIPE_HEIGHT;
+        let end = (start + STRIPE_HEIGHT).min(self.data_len());
+        (start..end).map(|o| self.read_uncached(o)).collect()
+    }
+
+    /// Reads a byte through the read cache, fetching ahead when reads are sequential
+    pub(super) fn read_cached(&self, offset: usize) -> Result<u8> {
+        let mut cache = self.read_cache.borrow_mut();
+        let cache = cache.as_mut().unwrap();
+        let block = offset / STRIPE_HEIGHT;
+        if cache.last != Some(block) {
+            let sequential = cache.last.is_some_and(|last| block == last + 1);
+            cache.last = Some(block);
+            if sequential {
+                let blocks = self.data_len().div_ceil(STRIPE_HEIGHT);
+                for ahead in block + 1..=(block + cache.config.read_ahead).min(blocks - 1) {
+                    if cache.blocks.contains_key(&ahead) {
+                        continue;
+                    }
+                    // Read-ahead is only a guess, it's fine if it can't be read
+                    if let Ok(data) = self.read_block(ahead) {
+                        cache.insert(ahead, data);
+                        cache.unread.insert(ahead);
+                        cache.stats.prefetched += 1;
+                    }
+                }
+            }
+        }
+
+        if let Some(data) = cache.get(block, offset) {
+            cache.stats.hits += 1;
+            return Ok(data);
+        }
+        cache.stats.misses += 1;
+        match self.read_block(block) {
+            Ok(data) => {
+                let byte = data[offset - block * STRIPE_HEIGHT];
+                cache.insert(block, data);
+                Ok(byte)
+            }
+            // Part of the block can't be read, leave it uncached and just read what was asked for
+            Err(_) => self.read_uncached(offset),
+        }
+    }
+
+    /// Drops cached blocks covering array offsets `start..end` so reads don't see data from before a write
+    pub(super) fn invalidate_cache(&self, start: usize, end: usize) {
+        if let Some(cache) = self.read_cache.borrow_mut().as_mut() {
+            for block in start / STRIPE_HEIGHT..=(end.max(start + 1) - 1) / STRIPE_HEIGHT {
+                if cache.blocks.remove(&block).is_some() {
+                    cache.policy.removed(block);
+                    cache.unread.remove(&block);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid5_sequential_reads_fetch_ahead() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        sim.set_read_cache(Some(ReadCacheConfig {
+            capacity: 8,
+            read_ahead: 2,
+            policy: EvictionPolicy::Lru,
+        }));
+        for (offset, byte) in data.iter().enumerate().take(8 * STRIPE_HEIGHT) {
+            assert_eq!(sim.read(offset as u64).unwrap(), *byte);
+        }
+        let stats = sim.cache_stats().unwrap();
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.hits, 8 * STRIPE_HEIGHT as u64 - 2);
+        assert_eq!(stats.prefetched, 8);
+        assert_eq!(stats.prefetch_hits, 6);
+        assert_eq!(stats.evictions, 2);
+
+        // Writes don't leave stale data behind in the cache
+        data[STRIPE_HEIGHT * 7] = !data[STRIPE_HEIGHT * 7];
+        sim.write(STRIPE_HEIGHT as u64 * 7, data[STRIPE_HEIGHT * 7])
+            .unwrap();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_cache_stats_per_policy() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        for policy in [EvictionPolicy::Lru, EvictionPolicy::Fifo] {
+            sim.set_read_cache(Some(ReadCacheConfig {
+                capacity: 2,
+                read_ahead: 0,
+                policy,
+            }));
+            for block in [0, 1, 0, 2, 0, 3, 0] {
+                assert_eq!(
+                    sim.read((block * STRIPE_HEIGHT) as u64).unwrap(),
+                    data[block * STRIPE_HEIGHT]
+                );
+            }
+        }
+        sim.set_read_cache(None);
+        let stats = sim.stats().read_cache;
+        assert_eq!(stats["lru"].hits, 3);
+        assert_eq!(stats["fifo"].hits, 2);
+        assert_eq!(stats["fifo"].misses, 5);
+    }
+}
@@ -0,0 +1,64 @@
+use super::RaidMode;
+
+/// How much of an array's raw space holds data, and what the rest buys
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Capacity {
+    /// Bytes across every member
+    pub raw: u64,
+    /// Bytes storable in the array
+    pub usable: u64,
+    /// Members' worth of space given over to parity
+    pub parity_drives: usize,
+    /// Members the array can lose without losing data
+    pub tolerated_failures: usize,
+    /// Stripes across the array, the last one short if the drive size isn't a multiple of the chunk size
+    pub stripes: usize,
+}
+
+impl Capacity {
+    /// Works out the capacity of an array of `num_drives` members of `drive_size` bytes, striped in chunks of
+    /// `chunk_size` bytes. Every byte of a member is used, a short last stripe included.
+    pub fn new(mode: RaidMode, num_drives: usize, drive_size: usize, chunk_size: usize) -> Self {
+        let parity_drives = mode.redundancy().min(num_drives);
+        Self {
+            raw: (num_drives * drive_size) as u64,
+            usable: ((num_drives - parity_drives) * drive_size) as u64,
+            parity_drives,
+            tolerated_failures: parity_drives,
+            stripes: drive_size.div_ceil(chunk_size.max(1)),
+        }
+    }
+
+    /// Returns the percentage of the raw space taken up by parity
+    pub fn parity_overhead(&self) -> f64 {
+        if self.raw == 0 {
+            0.0
+        } else {
+            (self.raw - self.usable) as f64 * 100.0 / self.raw as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn capacity_matches_the_array() {
+        let capacity = Capacity::new(RaidMode::Raid6, 8, 1000, 512);
+        assert_eq!(capacity.raw, 8000);
+        assert_eq!(capacity.usable, 6000);
+        assert_eq!(capacity.parity_overhead(), 25.0);
+        assert_eq!(capacity.tolerated_failures, 2);
+        assert_eq!(capacity.stripes, 2);
+
+        for mode in [RaidMode::Raid5, RaidMode::Raid6] {
+            let (sim, _) = init_random(mode);
+            let detail = sim.detail();
+            assert_eq!(detail.capacity.usable, sim.size());
+            assert_eq!(detail.capacity.tolerated_failures, mode.redundancy());
+            assert!(detail.to_string().contains("Parity Overhead"));
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::{fmt, time::Instant};
+
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Op, RaidSim, RaidState, RunReport};
+
+/// How often a chaos run picks each kind of operation, and how far it may push the array
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ChaosConfig {
+    pub writes: u32,
+    pub reads: u32,
+    pub failures: u32,
+    pub replacements: u32,
+    pub repairs: u32,
+    pub scrubs: u32,
+    /// Longest write or read, in bytes
+    pub max_len: usize,
+    /// Most members that may be missing at once, `None` for as many as the mode can survive
+    pub max_missing: Option<usize>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            writes: 40,
+            reads: 40,
+            failures: 5,
+            replacements: 5,
+            repairs: 5,
+            scrubs: 5,
+            max_len: 64,
+            max_missing: None,
+        }
+    }
+}
+
+/// One step of a chaos run
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChaosOp {
+    Write { offset: u64, len: usize },
+    Read { offset: u64, len: usize },
+    Fail { member: usize },
+    Replace,
+    Repair,
+    Scrub { bytes: usize },
+}
+
+/// A chaos run that found the array not holding what was written to it, or breaking an invariant.
+/// Running again with the same seed and config on an identical array repeats `trace` exactly.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChaosFailure {
+    pub seed: u64,
+    /// Every operation run, the last one is where things went wrong
+    pub trace: Vec<ChaosOp>,
+    pub error: String,
+    pub report: Box<RunReport>,
+}
+
+/// A chaos run that got through every step
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChaosRun {
+    pub trace: Vec<ChaosOp>,
+    pub report: RunReport,
+}
+
+impl fmt::Display for ChaosFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Chaos run with seed {} failed at step {}, {:?}: {}",
+            self.seed,
+            self.trace.len() - 1,
+            self.trace.last().unwrap(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for ChaosFailure {}
+
+impl RaidSim {
+    /// Picks the next operation of a chaos run, falling back to a read when the pick can't be run right now
+    fn chaos_op(&self, rng: &mut StdRng, config: &ChaosConfig) -> ChaosOp {
+        let size = self.size();
+        let range = |rng: &mut StdRng| {
+            let offset = rng.random_range(0..size);
+            let len = rng.random_range(1..=config.max_len.max(1));
+            (offset, len.min((size - offset) as usize))
+        };
+        let weights = [
+            config.writes,
+            config.reads,
+            config.failures,
+            config.replacements,
+            config.repairs,
+            config.scrubs,
+        ];
+        let mut pick = rng.random_range(0..weights.iter().sum::<u32>().max(1));
+        let kind = weights
+            .iter()
+            .position(|w| {
+                let hit = pick < *w;
+                pick = pick.saturating_sub(*w);
+                hit
+            })
+            .unwrap_or(1);
+
+        let missing = self.unusable().count();
+        let max_missing = config.max_missing.unwrap_or(self.mode.redundancy());
+        match kind {
+            0 => {
+                let (offset, len) = range(rng);
+                ChaosOp::Write { offset, len }
+            }
+            2 if missing < max_missing => ChaosOp::Fail {
+                member: rng.random_range(0..self.drives.len()),
+            },
+            3 if self.failed().count() > 0 => ChaosOp::Replace,
+            4 if self.unformatted().any(|d| !d.has_failed()) => ChaosOp::Repair,
+            5 if missing == 0 => ChaosOp::Scrub {
+                bytes: rng.random_range(1..=self.drive_size),
+            },
+            _ => {
+                let (offset, len) = range(rng);
+                ChaosOp::Read { offset, len }
+            }
+        }
+    }
+
+    /// Runs `op`, keeping `model` up to date with what the array should hold
+    fn chaos_step(&mut self, op: ChaosOp, rng: &mut StdRng, model: &mut [u8]) -> Result<()> {
+        match op {
+            ChaosOp::Write { offset, len } => {
+                let data = (0..len).map(|_| rng.random()).collect::<Vec<u8>>();
+                self.write_slice(offset, &data)?;
+                model[offset as usize..offset as usize + len].copy_from_slice(&data);
+            }
+            ChaosOp::Read { offset, len } => {
+                for i in offset..offset + len as u64 {
+                    let byte = self.read(i)?;
+                    if byte != model[i as usize] {
+                        bail!(
+                            "Read {:#04x} at offset {}, expected {:#04x}",
+                            byte,
+                            i,
+                            model[i as usize]
+                        );
+                    }
+                }
+            }
+            ChaosOp::Fail { member } => {
+                self.drives[member].fail();
+                self.sync_superblocks();
+            }
+            ChaosOp::Replace => self.replace_failed_drives(),
+            ChaosOp::Repair => self.repair()?,
+            ChaosOp::Scrub { bytes } => {
+                self.scrub_step(bytes)?;
+            }
+        }
+        if self.state() == RaidState::Failed {
+            bail!("Array failed");
+        }
+        self.check_invariants()
+    }
+
+    /// Runs `steps` seeded random operations against the array, checking after each one that it still holds
+    /// everything written to it and that its invariants hold. Returns the operations run and a report on them,
+    /// or the trace up to the first one that went wrong.
+    pub fn chaos(
+        &mut self,
+        seed: u64,
+        steps: usize,
+        config: &ChaosConfig,
+    ) -> Result<ChaosRun, ChaosFailure> {
+        let _recording = self.record(|| Op::Chaos {
+            seed,
+            steps,
+            config: *config,
+        });
+        let started = (Instant::now(), self.clock);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut trace = vec![];
+        let mut report = RunReport {
+            seed,
+            max_missing: self.unusable().count(),
+            ..RunReport::default()
+        };
+        let mut model = vec![];
+        let mut result = Ok(());
+        for offset in 0..self.size() {
+            match self.read(offset) {
+                Ok(byte) => model.push(byte),
+                Err(e) => {
+                    trace.push(ChaosOp::Read { offset, len: 1 });
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        if result.is_ok() {
+            for _ in 0..steps {
+                let op = self.chaos_op(&mut rng, config);
+                trace.push(op);
+                let rebuilding = self.unformatted().any(|d| !d.has_failed());
+                result = self.chaos_step(op, &mut rng, &mut model);
+                report.ops += 1;
+                match op {
+                    ChaosOp::Fail { .. } => report.failures_injected += 1,
+                    ChaosOp::Repair if rebuilding && self.unformatted().count() == 0 => {
+                        report.rebuilds_completed += 1
+                    }
+                    _ => {}
+                }
+                report.max_missing = report.max_missing.max(self.unusable().count());
+                if result.is_err() {
+                    // Whatever can't be read back as written any more is lost
+                    report.bytes_lost = (0..model.len())
+                        .filter(|i| self.read(*i as u64).ok() != Some(model[*i]))
+                        .count() as u64;
+                    break;
+                }
+            }
+        }
+
+        report.sim_time_ms = self.clock - started.1;
+        report.wall_time_ms = started.0.elapsed().as_millis() as u64;
+        match result {
+            Ok(()) => Ok(ChaosRun { trace, report }),
+            Err(e) => {
+                report.error = Some(e.to_string());
+                Err(ChaosFailure {
+                    seed,
+                    trace,
+                    error: e.to_string(),
+                    report: Box::new(report),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_survives_chaos() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        let ChaosRun { trace, report } = sim.chaos(1, 300, &ChaosConfig::default()).unwrap();
+        assert_eq!(trace.len(), 300);
+        assert!(trace.iter().any(|op| matches!(op, ChaosOp::Fail { .. })));
+        assert!(trace.iter().any(|op| matches!(op, ChaosOp::Repair)));
+        assert!(report.passed());
+        assert_eq!(report.ops, 300);
+        assert!(report.failures_injected > 0 && report.rebuilds_completed > 0);
+        assert!(report.max_missing <= 2);
+        assert_eq!(report.bytes_lost, 0);
+    }
+
+    #[test]
+    fn raid5_chaos_failures_reproduce() {
+        // Letting a second member go takes the array down, and the same seed gets there the same way
+        let config = ChaosConfig {
+            failures: 30,
+            max_missing: Some(2),
+            ..ChaosConfig::default()
+        };
+        let run = |seed| {
+            let (mut sim, _) = init_random(RaidMode::Raid5);
+            sim.chaos(seed, 500, &config).unwrap_err()
+        };
+        let failure = run(7);
+        assert_eq!(failure.seed, 7);
+        assert_eq!(failure.trace, run(7).trace);
+        assert!(failure.to_string().contains("seed 7"));
+
+        let report = &failure.report;
+        assert!(!report.passed());
+        assert_eq!(report.ops, failure.trace.len() as u64);
+        assert_eq!(report.max_missing, 2);
+        assert!(report.bytes_lost > 0);
+        let json = report.to_json();
+        assert!(json.starts_with("{\"seed\":7,"));
+        assert!(json.contains("\"error\":\"Array failed\""));
+    }
+}
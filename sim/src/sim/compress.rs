@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use super::RaidSim;
+
+/// Run-length encodes `data` as pairs of a run length and the byte repeated
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(u8::MAX as usize)
+            .take_while(|b| **b == data[i])
+            .count();
+        out.extend_from_slice(&[run as u8, data[i]]);
+        i += run;
+    }
+    out
+}
+
+/// Expands what `compress()` produced
+fn decompress(data: &[u8]) -> Vec<u8> {
+    data.chunks(2)
+        .flat_map(|pair| std::iter::repeat_n(pair[1], pair[0] as usize))
+        .collect()
+}
+
+/// Where an extent was put in the array
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Allocation {
+    offset: usize,
+    len: usize,
+    /// Extents that don't shrink are stored as they are
+    compressed: bool,
+}
+
+/// How much space the extents of a compressed volume take up
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CompressionStats {
+    /// Bytes of the extents that have been written, before compression
+    pub logical_bytes: u64,
+    /// Bytes the extents take up in the array after compression
+    pub stored_bytes: u64,
+    /// Bytes the stored extents take up across the members once parity is counted
+    pub physical_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Returns how many bytes were written for every byte stored, if anything has been written
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.stored_bytes > 0).then(|| self.logical_bytes as f64 / self.stored_bytes as f64)
+    }
+
+    /// Returns how many bytes ended up on the drives for every byte written, if anything has been written.
+    /// Below one, compression saved more than parity costs.
+    pub fn space_amplification(&self) -> Option<f64> {
+        (self.logical_bytes > 0).then(|| self.physical_bytes as f64 / self.logical_bytes as f64)
+    }
+}
+
+/// A volume of fixed-size extents compressed before they're striped across an array.
+///
+/// Compressed extents come out in all sizes, so each is allocated wherever it fits in the array and found again
+/// through the allocation map. The volume owns the whole array, anything else written to it gets overwritten.
+#[derive(Debug)]
+pub struct CompressedVolume {
+    array_id: u64,
+    extent_size: usize,
+    extents: Vec<Option<Allocation>>,
+    /// Free ranges of the array by offset, never adjacent to each other
+    free: BTreeMap<usize, usize>,
+}
+
+impl CompressedVolume {
+    /// Returns the size of the volume's extents
+    pub fn extent_size(&self) -> usize {
+        self.extent_size
+    }
+
+    /// Returns the number of extents the volume holds
+    pub fn extents(&self) -> usize {
+        self.extents.len()
+    }
+
+    fn check(&self, sim: &RaidSim, extent: usize) -> Result<()> {
+        if sim.array_id != self.array_id {
+            bail!("Volume was created on a different array");
+        }
+        if extent >= self.extents.len() {
+            bail!(
+                "No extent {} in a volume of {} extents",
+                extent,
+                self.extents.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Takes `offset..offset + len` out of the free range it lies in
+    fn reserve(&mut self, offset: usize, len: usize) {
+        let (&start, &free) = self.free.range(..=offset).next_back().unwrap();
+        self.free.remove(&start);
+        if offset > start {
+            self.free.insert(start, offset - start);
+        }
+        if start + free > offset + len {
+            self.free.insert(offset + len, start + free - offset - len);
+        }
+    }
+
+    /// Gives `offset..offset + len` back, merging it with the free ranges on either side
+    fn release(&mut self, offset: usize, len: usize) {
+        let (mut start, mut end) = (offset, offset + len);
+        if let Some(after) = self.free.remove(&end) {
+            end += after;
+        }
+        if let Some((&before, &free)) = self.free.range(..offset).next_back() {
+            if before + free == offset {
+                self.free.remove(&before);
+                start = before;
+            }
+        }
+        self.free.insert(start, end - start);
+    }
+
+    /// Finds the first free range `len` bytes fit in
+    fn allocate(&mut self, len: usize) -> Option<usize> {
+        let offset = self
+            .free
+            .iter()
+            .find(|(_, free)| **free >= len)
+            .map(|(offset, _)| *offset)?;
+        self.reserve(offset, len);
+        Some(offset)
+    }
+
+    /// Compresses `data` and writes it as extent `extent`, moving the extent wherever its new size fits
+    pub fn write(&mut self, sim: &mut RaidSim, extent: usize, data: &[u8]) -> Result<()> {
+        self.check(sim, extent)?;
+        if data.len() != self.extent_size {
+            bail!("Extents are {} bytes, got {}", self.extent_size, data.len());
+        }
+        let packed = compress(data);
+        let compressed = packed.len() < data.len();
+        let stored = if compressed { &packed[..] } else { data };
+
+        let old = self.extents[extent].take();
+        if let Some(old) = old {
+            self.release(old.offset, old.len);
+        }
+        let Some(offset) = self.allocate(stored.len()) else {
+            if let Some(old) = old {
+                self.reserve(old.offset, old.len);
+                self.extents[extent] = Some(old);
+            }
+            bail!("No room left in the array for {} more bytes", stored.len());
+        };
+        if let Err(e) = sim.write_slice(offset as u64, stored) {
+            self.release(offset, stored.len());
+            if let Some(old) = old {
+                self.reserve(old.offset, old.len);
+                self.extents[extent] = Some(old);
+            }
+            return Err(e);
+        }
+        self.extents[extent] = Some(Allocation {
+            offset,
+            len: stored.len(),
+            compressed,
+        });
+        Ok(())
+    }
+
+    /// Reads extent `extent` back and decompresses it, an extent never written reads as zeros
+    pub fn read(&self, sim: &RaidSim, extent: usize) -> Result<Vec<u8>> {
+        self.check(sim, extent)?;
+        let Some(allocation) = self.extents[extent] else {
+            return Ok(vec![0; self.extent_size]);
+        };
+        let stored = (allocation.offset..allocation.offset + allocation.len)
+            .map(|offset| sim.read(offset as u64))
+            .collect::<Result<Vec<u8>>>()?;
+        Ok(if allocation.compressed {
+            decompress(&stored)
+        } else {
+            stored
+        })
+    }
+
+    /// Discards extent `extent`, freeing the space it took up
+    pub fn trim(&mut self, sim: &RaidSim, extent: usize) -> Result<()> {
+        self.check(sim, extent)?;
+        if let Some(old) = self.extents[extent].take() {
+            self.release(old.offset, old.len);
+        }
+        Ok(())
+    }
+
+    /// Returns how much space the extents written so far take up, with and without compression and parity
+    pub fn stats(&self, sim: &RaidSim) -> CompressionStats {
+        let written = self.extents.iter().flatten();
+        let stored_bytes = written.clone().map(|a| a.len as u64).sum::<u64>();
+        CompressionStats {
+            logical_bytes: (written.count() * self.extent_size) as u64,
+            stored_bytes,
+            physical_bytes: stored_bytes * sim.drives.len() as u64
+                / sim.data_drives().count() as u64,
+        }
+    }
+}
+
+impl RaidSim {
+    /// Creates a compressed volume of `extents` extents of `extent_size` bytes on top of the array.
+    /// The volume can hold more than the array would uncompressed, writes fail once the array runs out of room.
+    pub fn compressed_volume(
+        &self,
+        extent_size: usize,
+        extents: usize,
+    ) -> Result<CompressedVolume> {
+        if extent_size == 0 {
+            bail!("Extents must hold at least a byte");
+        }
+        Ok(CompressedVolume {
+            array_id: self.array_id,
+            extent_size,
+            extents: vec![None; extents],
+            free: BTreeMap::from([(0, self.data_len())]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_compressed_volume_saves_more_than_parity_costs() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        let capacity = sim.size() as usize;
+        // Twice as many extents as the array holds uncompressed
+        let mut volume = sim.compressed_volume(512, 2 * capacity / 512).unwrap();
+
+        let extent = |i: usize| {
+            (0..512)
+                .map(|j| if j % 128 < 120 { i as u8 } else { j as u8 })
+                .collect::<Vec<u8>>()
+        };
+        for i in 0..volume.extents() {
+            volume.write(&mut sim, i, &extent(i)).unwrap();
+        }
+        for i in [0, 7, volume.extents() - 1] {
+            assert_eq!(volume.read(&sim, i).unwrap(), extent(i));
+        }
+        let stats = volume.stats(&sim);
+        assert_eq!(stats.logical_bytes, 2 * capacity as u64);
+        assert!(stats.compression_ratio().unwrap() > 2.0);
+        assert!(stats.space_amplification().unwrap() < 1.0);
+
+        // Noise doesn't compress, it's stored as is until the array is full
+        let noise = (0..512).map(|j| (j * 37 % 251) as u8).collect::<Vec<u8>>();
+        let mut written = 0;
+        while volume.write(&mut sim, written, &noise).is_ok() {
+            written += 1;
+        }
+        assert!(written > 0 && written < volume.extents());
+        assert_eq!(volume.read(&sim, 0).unwrap(), noise);
+        // A failed write leaves the extent as it was
+        assert_eq!(volume.read(&sim, written).unwrap(), extent(written));
+
+        // Trimming makes room again
+        volume.trim(&sim, 0).unwrap();
+        assert_eq!(volume.read(&sim, 0).unwrap(), vec![0; 512]);
+        volume.write(&mut sim, written, &noise).unwrap();
+        assert_eq!(volume.read(&sim, written).unwrap(), noise);
+        assert_eq!(volume.read(&sim, 1).unwrap(), noise);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::generator::{FromPower, Gen};
+
+use super::{Op, ParityUpdate, RaidMode, RaidSim, RaidState, P_INDEX, Q_INDEX, STRIPE_HEIGHT};
+
+/// Summary of a run of overlapping writes
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ConcurrentReport {
+    /// Number of writes that made it to the drives
+    pub completed: usize,
+    /// Number of writes that had to wait for another initiator to release a stripe lock
+    pub waits: usize,
+    /// Stripes whose parity no longer matches their data once every write finished
+    pub torn: Vec<usize>,
+}
+
+/// The part of a read-modify-write an initiator does next
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Stage {
+    Data,
+    P,
+    Q,
+}
+
+/// A write that has read the old data and parity but not yet written everything back
+#[derive(Debug)]
+struct InFlight {
+    drive_index: usize,
+    drive_offset: usize,
+    data: u8,
+    old_data: u8,
+    old_p: u8,
+    old_q: u8,
+    stage: Stage,
+}
+
+/// A simulated initiator working through its writes in order, one at a time
+#[derive(Debug)]
+struct Initiator {
+    queue: VecDeque<(usize, u8)>,
+    current: Option<InFlight>,
+    waiting: bool,
+}
+
+impl RaidSim {
+    /// Issues the writes of several initiators at once, each a list of (offset, byte) written in order.
+    ///
+    /// Every write is a read-modify-write split into steps: reading the old data and parity, then writing back
+    /// the data, P, and Q one at a time. The steps of different initiators are interleaved in whatever order `rng` picks.
+    /// With `locking` an initiator holds the lock on a stripe from its read until its last write,
+    /// without it two writes to the same stripe can both update parity from the same old value and one update is lost.
+    pub fn write_concurrent(
+        &mut self,
+        initiators: Vec<Vec<(u64, u8)>>,
+        locking: bool,
+        rng: &mut impl Rng,
+    ) -> Result<ConcurrentReport> {
+        self.write_concurrent_seeded(initiators, locking, rng.random())
+    }
+
+    /// Issues the writes of `write_concurrent()`, interleaved in the order a generator seeded with `seed` picks
+    pub(super) fn write_concurrent_seeded(
+        &mut self,
+        initiators: Vec<Vec<(u64, u8)>>,
+        locking: bool,
+        seed: u64,
+    ) -> Result<ConcurrentReport> {
+        let _recording = self.record(|| Op::WriteConcurrent {
+            initiators: initiators.clone(),
+            locking,
+            seed,
+        });
+        let mut rng = StdRng::seed_from_u64(seed);
+        if self.state() != RaidState::Ok {
+            bail!("Array not healthy, unable to write concurrently");
+        }
+        if self.parity_update != ParityUpdate::Immediate {
+            bail!("Parity isn't updated alongside writes, unable to write concurrently");
+        }
+        let mut initiators = initiators
+            .into_iter()
+            .map(|writes| {
+                Ok(Initiator {
+                    queue: writes
+                        .into_iter()
+                        .map(|(offset, data)| Ok((self.logical_offset(offset, 1)?, data)))
+                        .collect::<Result<_>>()?,
+                    current: None,
+                    waiting: false,
+                })
+            })
+            .collect::<Result<Vec<Initiator>>>()?;
+        self.flush()?;
+        self.mark_unclean();
+
+        let mut locks = BTreeSet::new();
+        let mut report = ConcurrentReport::default();
+        loop {
+            let runnable = (0..initiators.len())
+                .filter(|i| {
+                    let initiator = &initiators[*i];
+                    match (&initiator.current, initiator.queue.front()) {
+                        (Some(_), _) => true,
+                        (None, Some((offset, _))) => {
+                            !locking || !locks.contains(&self.stripe_of(*offset))
+                        }
+                        (None, None) => false,
+                    }
+                })
+                .collect::<Vec<usize>>();
+            for (i, initiator) in initiators.iter_mut().enumerate() {
+                if !initiator.queue.is_empty() && !runnable.contains(&i) {
+                    initiator.waiting = true;
+                }
+            }
+            if runnable.is_empty() {
+                break;
+            }
+
+            let initiator = &mut initiators[runnable[rng.random_range(0..runnable.len())]];
+            match initiator.current.take() {
+                None => {
+                    let (offset, data) = initiator.queue.pop_front().unwrap();
+                    if std::mem::take(&mut initiator.waiting) {
+                        report.waits += 1;
+                    }
+                    if locking {
+                        locks.insert(self.stripe_of(offset));
+                    }
+                    self.preserve_for_views(offset, offset + 1)?;
+                    self.invalidate_cache(offset, offset + 1);
+                    self.drop_protection(offset, offset + 1);
+                    self.mark_written(offset, offset + 1);
+                    self.record_integrity(offset, &[data])?;
+                    initiator.current = Some(self.start_write(offset, data)?);
+                    self.stats.logical_bytes_written += 1;
+                }
+                Some(mut write) => {
+                    if self.step_write(&mut write)? {
+                        if locking {
+                            locks.remove(&(write.drive_offset / STRIPE_HEIGHT));
+                        }
+                        report.completed += 1;
+                    } else {
+                        initiator.current = Some(write);
+                    }
+                }
+            }
+        }
+
+        report.torn = self.inconsistent_stripes()?;
+        Ok(report)
+    }
+
+    /// Returns the stripe an array offset falls in
+    fn stripe_of(&self, offset: usize) -> usize {
+        self.locate(offset).1 / STRIPE_HEIGHT
+    }
+
+    /// Reads the old data and parity a write needs
+    fn start_write(&self, offset: usize, data: u8) -> Result<InFlight> {
+        let (drive_index, drive_offset) = self.locate(offset);
+        let old_q = match self.mode {
+            RaidMode::Raid5 => 0,
+            RaidMode::Raid6 => self.read_retry(Q_INDEX, drive_offset)?,
+        };
+        Ok(InFlight {
+            drive_index,
+            drive_offset,
+            data,
+            old_data: self.read_uncached(offset)?,
+            old_p: self.read_retry(P_INDEX, drive_offset)?,
+            old_q,
+            stage: Stage::Data,
+        })
+    }
+
+    /// Writes back the next part of a write, returning whether it is finished
+    fn step_write(&mut self, write: &mut InFlight) -> Result<bool> {
+        let delta = write.old_data ^ write.data;
+        match write.stage {
+            Stage::Data => {
+                let drive = self.data_drives_mut().nth(write.drive_index).unwrap();
+                drive.write(write.drive_offset, write.data)?;
+                write.stage = Stage::P;
+                Ok(false)
+            }
+            Stage::P => {
+                self.p_parity_mut()
+                    .write(write.drive_offset, write.old_p ^ delta)?;
+                write.stage = Stage::Q;
+                Ok(self.mode == RaidMode::Raid5)
+            }
+            Stage::Q => {
+                let gk = Gen::from_power(write.drive_index);
+                self.q_parity_mut()
+                    .write(write.drive_offset, write.old_q ^ (gk * delta))?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns the stripes whose parity doesn't match their data, leaving out stripes already known to be dirty
+    pub fn inconsistent_stripes(&self) -> Result<Vec<usize>> {
+        if self.unusable().count() > 0 {
+            bail!("Array is missing members, unable to check parity");
+        }
+        let mut stripes = vec![];
+        for stripe in self.stripes() {
+            if !stripe.is_consistent()? {
+                stripes.push(stripe.index());
+            }
+        }
+        Ok(stripes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    /// Picks an interleaving where both writes read parity before either writes it back
+    const SEED: u64 = 1;
+
+    /// Two initiators each flipping a byte in the same row of the array, on different data drives
+    fn overlapping_writes(data: &[u8]) -> Vec<Vec<(u64, u8)>> {
+        [0, DRIVE_SIZE]
+            .iter()
+            .map(|&offset| vec![(offset as u64, !data[offset])])
+            .collect()
+    }
+
+    #[test]
+    fn raid5_unlocked_writes_tear_parity() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let report = sim
+            .write_concurrent(overlapping_writes(&data), false, &mut rng)
+            .unwrap();
+        assert_eq!(report.completed, 2);
+        assert_eq!(report.waits, 0);
+        assert_eq!(report.torn, vec![0]);
+    }
+
+    #[test]
+    fn raid6_locked_writes_keep_parity_consistent() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let report = sim
+            .write_concurrent(overlapping_writes(&data), true, &mut rng)
+            .unwrap();
+        assert_eq!(report.completed, 2);
+        assert_eq!(report.waits, 1);
+        assert!(report.torn.is_empty());
+
+        data[0] = !data[0];
+        data[DRIVE_SIZE] = !data[DRIVE_SIZE];
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+}
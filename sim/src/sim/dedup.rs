@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::checksum::ChecksumAlgorithm;
+
+use super::RaidSim;
+
+/// A block stored once in the array on behalf of every logical block with the same contents
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Stored {
+    hash: u64,
+    /// Number of logical blocks that point at it
+    refs: usize,
+}
+
+/// How much a dedup volume is sharing
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DedupStats {
+    /// Logical blocks that have been written
+    pub logical_blocks: usize,
+    /// Blocks actually stored in the array
+    pub unique_blocks: usize,
+    /// Most logical blocks sharing any one stored block, everything one bad block can take down at once
+    pub max_refs: usize,
+}
+
+impl DedupStats {
+    /// Returns how many logical blocks each stored block stands in for, if anything has been written
+    pub fn dedup_ratio(&self) -> Option<f64> {
+        (self.unique_blocks > 0).then(|| self.logical_blocks as f64 / self.unique_blocks as f64)
+    }
+}
+
+/// An experimental volume that stores blocks with the same contents once, keyed by their hash.
+///
+/// The array is split into slots of one block each. A logical block points at the slot holding its contents,
+/// and a slot is freed once nothing points at it. Sharing saves space but concentrates risk,
+/// a stored block that goes bad takes every logical block pointing at it with it.
+/// The volume owns the whole array, anything else written to it gets overwritten.
+#[derive(Debug)]
+pub struct DedupVolume {
+    array_id: u64,
+    block_size: usize,
+    /// Slot each logical block points at
+    blocks: Vec<Option<usize>>,
+    /// What's stored in each slot of the array
+    slots: Vec<Option<Stored>>,
+    /// Slots by the hash of what they hold, more than one if hashes collide
+    index: BTreeMap<u64, Vec<usize>>,
+}
+
+impl DedupVolume {
+    /// Hashes a block with CRC-64/NVME, matches are still compared byte for byte before they're shared
+    fn hash(data: &[u8]) -> u64 {
+        ChecksumAlgorithm::Crc64Nvme.checksum(data)
+    }
+
+    /// Returns the size of the volume's blocks
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the number of logical blocks the volume holds
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn check(&self, sim: &RaidSim, block: usize) -> Result<()> {
+        if sim.array_id != self.array_id {
+            bail!("Volume was created on a different array");
+        }
+        if block >= self.blocks.len() {
+            bail!(
+                "No block {} in a volume of {} blocks",
+                block,
+                self.blocks.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads the contents of `slot` from the array
+    fn read_slot(&self, sim: &RaidSim, slot: usize) -> Result<Vec<u8>> {
+        let start = slot * self.block_size;
+        (start..start + self.block_size)
+            .map(|offset| sim.read(offset as u64))
+            .collect()
+    }
+
+    /// Drops a reference to `slot`, freeing it once nothing points at it
+    fn release(&mut self, slot: usize) {
+        let stored = self.slots[slot].as_mut().unwrap();
+        stored.refs -= 1;
+        if stored.refs == 0 {
+            let hash = stored.hash;
+            self.slots[slot] = None;
+            let slots = self.index.get_mut(&hash).unwrap();
+            slots.retain(|s| *s != slot);
+            if slots.is_empty() {
+                self.index.remove(&hash);
+            }
+        }
+    }
+
+    /// Writes `data` as logical block `block`, pointing it at a stored copy if there is one
+    pub fn write(&mut self, sim: &mut RaidSim, block: usize, data: &[u8]) -> Result<()> {
+        self.check(sim, block)?;
+        if data.len() != self.block_size {
+            bail!("Blocks are {} bytes, got {}", self.block_size, data.len());
+        }
+        let hash = Self::hash(data);
+        let mut found = None;
+        for &slot in self.index.get(&hash).into_iter().flatten() {
+            if self.read_slot(sim, slot)? == data {
+                found = Some(slot);
+                break;
+            }
+        }
+
+        let slot = match found {
+            Some(slot) => {
+                self.slots[slot].as_mut().unwrap().refs += 1;
+                slot
+            }
+            None => {
+                // The block's old slot can only be reused once it's been let go of
+                let old = self.blocks[block].take();
+                if let Some(old) = old {
+                    self.release(old);
+                }
+                let Some(slot) = self.slots.iter().position(|s| s.is_none()) else {
+                    // Had the old slot been freed it would have been free, so it's still there to go back to
+                    if let Some(old) = old {
+                        self.slots[old].as_mut().unwrap().refs += 1;
+                        self.blocks[block] = Some(old);
+                    }
+                    bail!("No free slots left in the array");
+                };
+                sim.write_slice((slot * self.block_size) as u64, data)?;
+                self.slots[slot] = Some(Stored { hash, refs: 1 });
+                self.index.entry(hash).or_default().push(slot);
+                slot
+            }
+        };
+        if let Some(old) = self.blocks[block].replace(slot) {
+            self.release(old);
+        }
+        Ok(())
+    }
+
+    /// Reads logical block `block` back, a block never written reads as zeros.
+    /// Stored blocks that no longer match their hash are refused rather than handed out.
+    pub fn read(&self, sim: &RaidSim, block: usize) -> Result<Vec<u8>> {
+        self.check(sim, block)?;
+        let Some(slot) = self.blocks[block] else {
+            return Ok(vec![0; self.block_size]);
+        };
+        let data = self.read_slot(sim, slot)?;
+        if Self::hash(&data) != self.slots[slot].unwrap().hash {
+            bail!(
+                "Stored block {} no longer matches its hash, unable to read block {}",
+                slot,
+                block
+            );
+        }
+        Ok(data)
+    }
+
+    /// Discards logical block `block`
+    pub fn trim(&mut self, sim: &RaidSim, block: usize) -> Result<()> {
+        self.check(sim, block)?;
+        if let Some(old) = self.blocks[block].take() {
+            self.release(old);
+        }
+        Ok(())
+    }
+
+    /// Returns the logical blocks that share the stored block holding array offset `offset`
+    pub fn affected_by(&self, offset: u64) -> Vec<usize> {
+        let slot = offset as usize / self.block_size;
+        (0..self.blocks.len())
+            .filter(|b| self.blocks[*b] == Some(slot))
+            .collect()
+    }
+
+    /// Returns how much the volume is sharing
+    pub fn stats(&self) -> DedupStats {
+        let stored = self.slots.iter().flatten();
+        DedupStats {
+            logical_blocks: self.blocks.iter().flatten().count(),
+            unique_blocks: stored.clone().count(),
+            max_refs: stored.map(|s| s.refs).max().unwrap_or(0),
+        }
+    }
+}
+
+impl RaidSim {
+    /// Creates a dedup volume of `blocks` logical blocks of `block_size` bytes on top of the array.
+    /// The volume can hold more than the array would without sharing, writes fail once there are no free slots.
+    pub fn dedup_volume(&self, block_size: usize, blocks: usize) -> Result<DedupVolume> {
+        if block_size == 0 {
+            bail!("Blocks must hold at least a byte");
+        }
+        Ok(DedupVolume {
+            array_id: self.array_id,
+            block_size,
+            blocks: vec![None; blocks],
+            slots: vec![None; self.data_len() / block_size],
+            index: BTreeMap::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_corrupt_shared_block_hits_every_sharer() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        let mut volume = sim.dedup_volume(256, 1000).unwrap();
+        let block = |i: usize| vec![(i % 10) as u8; 256];
+        for i in 0..volume.blocks() {
+            volume.write(&mut sim, i, &block(i)).unwrap();
+        }
+        let stats = volume.stats();
+        assert_eq!(stats.logical_blocks, 1000);
+        assert_eq!(stats.unique_blocks, 10);
+        assert_eq!(stats.max_refs, 100);
+        assert_eq!(stats.dedup_ratio(), Some(100.0));
+
+        // Rewriting a block moves it to another stored copy, or a new one
+        volume.write(&mut sim, 3, &block(4)).unwrap();
+        volume.write(&mut sim, 5, &[0xee; 256]).unwrap();
+        assert_eq!(volume.read(&sim, 3).unwrap(), block(4));
+        assert_eq!(volume.stats().unique_blocks, 11);
+
+        // Rot a byte of the first stored block, the zeros, behind the array's back
+        let offset = 0;
+        let sharers = volume.affected_by(offset);
+        assert_eq!(sharers.len(), 100);
+        let mapping = sim.map(offset + 10).unwrap();
+        sim.drive_mut(mapping.drive)
+            .corrupt(mapping.physical_offset, 0x01);
+        for b in sharers {
+            assert!(volume.read(&sim, b).is_err());
+        }
+        assert_eq!(volume.read(&sim, 1).unwrap(), block(1));
+
+        // Trimming every sharer frees the stored block
+        for b in volume.affected_by(offset) {
+            volume.trim(&sim, b).unwrap();
+        }
+        assert_eq!(volume.stats().unique_blocks, 10);
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use super::{Op, RaidSim};
+
+impl RaidSim {
+    /// Has every member and spare encrypt what it stores with a key of its own, or stop.
+    /// Drives pulled from an encrypted array give away nothing but ciphertext, see [`crate::Drive::media`].
+    pub fn set_encryption(&mut self, enabled: bool) {
+        let _recording = self.record(|| Op::SetEncryption { enabled });
+        self.encryption = enabled;
+        for i in 0..self.drives.len() + self.spares.len() {
+            let key = enabled.then(|| self.rng.random());
+            match self.drives.get_mut(i) {
+                Some(drive) => drive.set_encryption_key(key),
+                None => self.spares[i - self.drives.len()].set_encryption_key(key),
+            }
+        }
+    }
+
+    /// Returns whether the members encrypt what they store
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption
+    }
+
+    /// Loses the key of the member at `index`. Nothing on it can be decrypted any more, so it fails
+    /// and has to be rebuilt from the rest of the array just like a dead drive.
+    pub fn lose_key(&mut self, index: usize) -> Result<()> {
+        let _recording = self.record(|| Op::LoseKey { index });
+        if index >= self.drives.len() {
+            bail!(
+                "No member {} in an array of {} drives",
+                index,
+                self.drives.len()
+            );
+        }
+        if self.drives[index].encryption_key().is_none() {
+            bail!("Member {} isn't encrypted, it has no key to lose", index);
+        }
+        self.drives[index].lose_key(&mut self.rng);
+        self.sync_superblocks();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_removed_drive_leaks_no_plaintext() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_encryption(true);
+        assert!(sim.is_encrypted());
+        assert_sim_equal(&sim, &data);
+
+        // Member 4 is the third data drive
+        let plaintext = &data[2 * DRIVE_SIZE..3 * DRIVE_SIZE];
+        let drive = sim.remove_drive(4);
+        assert_eq!(drive.read_slice(0, DRIVE_SIZE).unwrap(), plaintext);
+        let media = drive.media();
+        let same = media.iter().zip(plaintext).filter(|(a, b)| a == b).count();
+        assert!(same < DRIVE_SIZE / 16);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_lost_key_is_a_failed_drive() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        assert!(sim.lose_key(3).is_err());
+        sim.set_encryption(true);
+        let key = sim.drive(3).encryption_key();
+
+        sim.lose_key(3).unwrap();
+        sim.lose_key(P_INDEX).unwrap();
+        assert!(sim.drive(3).has_failed());
+        assert_ne!(sim.drive(3).encryption_key(), key);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+
+        // The drives themselves are fine, once recovered under their new keys they're rebuilt onto
+        sim.drive_mut(3).recover();
+        sim.drive_mut(P_INDEX).recover();
+        assert_eq!(sim.rebuild().unwrap().rebuilt, vec![P_INDEX, 3]);
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_q_parity();
+        sim.drive_mut(8).fail();
+        assert_sim_equal(&sim, &data);
+
+        // With two members already gone, one more lost key is one too many
+        sim.lose_key(10).unwrap();
+        assert_eq!(sim.state(), RaidState::Failed);
+    }
+}
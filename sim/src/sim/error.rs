@@ -0,0 +1,170 @@
+use std::fmt;
+
+use super::STRIPE_HEIGHT;
+
+/// Which way an access to the array or one of its members was going
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// What stopped an access to the array and where, carried inside the `anyhow::Error` it fails with.
+/// Handlers can find it with `downcast_ref::<RaidError>()` instead of matching on the message, and
+/// a `Member` error has the `DriveError` that caused it underneath.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RaidError {
+    /// Too many members are gone for the array to be used at all
+    Failed { access: Access },
+    /// The access runs past the end of the array
+    OutOfBounds { offset: u64, len: usize, size: u64 },
+    /// Parity of the stripe is out of date, so nothing on it can be reconstructed
+    DirtyParity { stripe: usize },
+    /// More of a stripe's data drives are missing at `offset` than there's parity to make up for.
+    /// `missing` holds the first two of them, `lost` counts them all.
+    NotEnoughRedundancy {
+        offset: usize,
+        stripe: usize,
+        missing: Vec<usize>,
+        lost: usize,
+    },
+    /// A member couldn't be accessed at drive offset `offset`
+    Member {
+        access: Access,
+        drive: usize,
+        offset: usize,
+        stripe: usize,
+    },
+}
+
+impl RaidError {
+    /// Describes an access to member `drive` at drive offset `offset` that went wrong
+    pub(super) fn member(access: Access, drive: usize, offset: usize) -> Self {
+        RaidError::Member {
+            access,
+            drive,
+            offset,
+            stripe: offset / STRIPE_HEIGHT,
+        }
+    }
+}
+
+impl fmt::Display for RaidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RaidError::Failed { access } => write!(f, "Array failed, unable to {}", access),
+            RaidError::OutOfBounds { offset, len, size } => write!(
+                f,
+                "Out of bounds access, at offset {} and length {} in array of size {}",
+                offset, len, size
+            ),
+            RaidError::DirtyParity { stripe } => write!(
+                f,
+                "Stripe {} has out of date parity, unable to reconstruct",
+                stripe
+            ),
+            RaidError::NotEnoughRedundancy {
+                offset,
+                missing,
+                lost,
+                ..
+            } => match (lost, missing.as_slice()) {
+                (1, [x]) => write!(
+                    f,
+                    "Not enough redundancy to reconstruct offset {} of data drive {}",
+                    offset, x
+                ),
+                (2, [x, y]) => write!(
+                    f,
+                    "Not enough redundancy to reconstruct offset {} of data drives {} and {}",
+                    offset, x, y
+                ),
+                _ => write!(
+                    f,
+                    "Not enough redundancy to reconstruct offset {}, {} data drives missing",
+                    offset, lost
+                ),
+            },
+            RaidError::Member {
+                access,
+                drive,
+                offset,
+                stripe,
+            } => write!(
+                f,
+                "Unable to {} drive {} at offset {} in stripe {}",
+                access, drive, offset, stripe
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RaidError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::drive::DriveError;
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn errors_say_what_failed_and_where() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        let offset = STRIPE_HEIGHT + 7;
+        sim.drives[P_INDEX].add_latent_error(offset / SECTOR_SIZE);
+        let error = sim.read_retry(P_INDEX, offset).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RaidError>(),
+            Some(&RaidError::Member {
+                access: Access::Read,
+                drive: P_INDEX,
+                offset,
+                stripe: 1,
+            })
+        );
+        assert_eq!(
+            error.downcast_ref::<DriveError>(),
+            Some(&DriveError::Unreadable {
+                sector: offset / SECTOR_SIZE
+            })
+        );
+
+        // Losing the same sector on two data drives leaves P with too much to make up for
+        sim.drives[2].add_latent_error(0);
+        sim.drives[5].add_latent_error(0);
+        let error = sim.read(DRIVE_SIZE as u64).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RaidError>(),
+            Some(&RaidError::NotEnoughRedundancy {
+                offset: 0,
+                stripe: 0,
+                missing: vec![1, 4],
+                lost: 2,
+            })
+        );
+
+        let error = sim.read(sim.size()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RaidError>(),
+            Some(RaidError::OutOfBounds { len: 1, .. })
+        ));
+        sim.fail_drive(3);
+        sim.fail_drive(4);
+        let error = sim.read(0).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<RaidError>(),
+            Some(&RaidError::Failed {
+                access: Access::Read
+            })
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+use super::RaidSim;
+
+/// Something that happened to the array, handed to every observer as it happens
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArrayEvent {
+    /// The superblocks were rewritten with a new event count
+    MetadataUpdated { events: u64 },
+    /// A drive was hot-added as a spare dedicated to the array
+    SpareAdded { spares: usize },
+    /// A drive was hot-added to join the array as a member when it's next grown
+    GrowPending { pending: usize },
+    /// The array was grown onto the drives waiting for it, and now has `drives` members
+    Grown { drives: usize },
+    /// A drive was securely erased, along with the slot it held in this array if it was a member
+    DriveErased { slot: Option<usize> },
+}
+
+/// Gets told about everything that happens to an array
+pub trait Observer {
+    fn notify(&mut self, event: &ArrayEvent);
+}
+
+impl<F: FnMut(&ArrayEvent)> Observer for F {
+    fn notify(&mut self, event: &ArrayEvent) {
+        self(event)
+    }
+}
+
+/// The observers registered on an array
+#[derive(Default)]
+pub(super) struct Observers(Vec<Box<dyn Observer>>);
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl RaidSim {
+    /// Registers an observer to be told about everything that happens to the array from now on
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        self.observers.0.push(Box::new(observer));
+    }
+
+    /// Hands an event to every observer
+    pub(super) fn emit(&mut self, event: ArrayEvent) {
+        for observer in &mut self.observers.0 {
+            observer.notify(&event);
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use crate::generator::{FromPower, Gen};
+
+use std::fmt::Write;
+
+use super::{RaidMode, RaidSim, RecoveryFormula, P_INDEX, Q_INDEX};
+
+/// One value worked out on the way to a reconstructed byte, in the order they were worked out
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Step {
+    /// XOR of the data bytes that could be read, P as if the missing ones were zero
+    PRest(u8),
+    /// Q of the data bytes that could be read, as if the missing ones were zero
+    QRest(u8),
+    /// Solving for data drives `x` and `y` from both parities, `D_x = a(P + P_rest) + b(Q + Q_rest)` where
+    /// `a = g^(y-x) / (g^(y-x) + 1)` and `b = g^-x / (g^(y-x) + 1)`
+    Coefficients { a: u8, b: u8 },
+    /// A data byte was reconstructed, `drive` counts data drives only, it's the power of g the drive has in Q
+    Data { drive: usize, value: u8 },
+    /// A parity byte was recomputed from the data, `member` is the index of the parity drive
+    Parity { member: usize, value: u8 },
+}
+
+/// How a row of the array was reconstructed, from what was read down to what was worked out
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Explanation {
+    pub mode: RaidMode,
+    /// Drive offset of the row
+    pub offset: usize,
+    pub formula: RecoveryFormula,
+    /// Members that were read and the bytes they held, by index
+    pub reads: Vec<(usize, u8)>,
+    pub steps: Vec<Step>,
+}
+
+/// Writes the pieces of an equation as plain text or as LaTeX math
+#[derive(Debug, Clone, Copy)]
+struct Notation {
+    latex: bool,
+}
+
+impl Notation {
+    fn byte(self, value: u8) -> String {
+        if self.latex {
+            format!("\\mathtt{{0x{:02x}}}", value)
+        } else {
+            format!("0x{:02x}", value)
+        }
+    }
+
+    fn plus(self) -> &'static str {
+        if self.latex {
+            " \\oplus "
+        } else {
+            " + "
+        }
+    }
+
+    fn sub(self, name: &str, sub: impl std::fmt::Display) -> String {
+        if self.latex {
+            format!("{}_{{{}}}", name, sub)
+        } else {
+            format!("{}_{}", name, sub)
+        }
+    }
+
+    fn power(self, power: impl std::fmt::Display) -> String {
+        if self.latex {
+            format!("g^{{{}}}", power)
+        } else {
+            format!("g^{}", power)
+        }
+    }
+
+    fn frac(self, num: &str, den: &str) -> String {
+        if self.latex {
+            format!("\\frac{{{}}}{{{}}}", num, den)
+        } else {
+            format!("({}) / ({})", num, den)
+        }
+    }
+
+    /// Sums over every data drive but the ones in `except`
+    fn sum(self, except: &[usize]) -> String {
+        let except = except
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        match (self.latex, except.is_empty()) {
+            (true, true) => "\\bigoplus_{i}".to_string(),
+            (true, false) => format!("\\bigoplus_{{i \\notin \\{{{}\\}}}}", except),
+            (false, true) => "Σ[i]".to_string(),
+            (false, false) => format!("Σ[i ∉ {{{}}}]", except),
+        }
+    }
+}
+
+impl Explanation {
+    /// Returns the name of the member at `index` as it appears in the equations
+    fn member(&self, index: usize, n: Notation) -> String {
+        match index {
+            P_INDEX => "P".to_string(),
+            Q_INDEX if self.mode == RaidMode::Raid6 => "Q".to_string(),
+            _ => n.sub("D", index - self.mode.redundancy()),
+        }
+    }
+
+    /// Returns what was read from the member at `index`, or zero if it wasn't
+    fn read(&self, index: usize) -> u8 {
+        self.reads
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map_or(0, |(_, b)| *b)
+    }
+
+    /// Returns the data drives that were reconstructed
+    fn missing(&self) -> Vec<usize> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Data { drive, .. } => Some(*drive),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Writes out `step` as an equation, in symbols and then with the bytes that went into it
+    fn equation(&self, step: Step, n: Notation) -> String {
+        let plus = n.plus();
+        let missing = self.missing();
+        let (p_rest, q_rest) = (n.sub("P", "rest"), n.sub("Q", "rest"));
+        let rest = |want: fn(&Step) -> Option<u8>| self.steps.iter().find_map(want).unwrap_or(0);
+        let p_rest_value = rest(|s| match s {
+            Step::PRest(v) => Some(*v),
+            _ => None,
+        });
+        let q_rest_value = rest(|s| match s {
+            Step::QRest(v) => Some(*v),
+            _ => None,
+        });
+        let (p, q) = (n.byte(self.read(P_INDEX)), n.byte(self.read(Q_INDEX)));
+        let d = |i: usize| n.sub("D", i);
+
+        match step {
+            Step::PRest(value) => {
+                format!("{} = {} D_i = {}", p_rest, n.sum(&missing), n.byte(value))
+            }
+            Step::QRest(value) => format!(
+                "{} = {} {} D_i = {}",
+                q_rest,
+                n.sum(&missing),
+                n.power("i"),
+                n.byte(value)
+            ),
+            Step::Coefficients { a, b } => {
+                let (x, y) = (missing[0] as i64, missing[1] as i64);
+                let den = format!("{}{}1", n.power(y - x), plus);
+                format!(
+                    "a = {} = {}, b = {} = {}",
+                    n.frac(&n.power(y - x), &den),
+                    n.byte(a),
+                    n.frac(&n.power(-x), &den),
+                    n.byte(b)
+                )
+            }
+            Step::Data { drive, value } => match self.formula {
+                RecoveryFormula::POnly => format!(
+                    "{} = P{}{} = {}{}{} = {}",
+                    d(drive),
+                    plus,
+                    p_rest,
+                    p,
+                    plus,
+                    n.byte(p_rest_value),
+                    n.byte(value)
+                ),
+                RecoveryFormula::QOnly => format!(
+                    "{} = {} = {} = {}",
+                    d(drive),
+                    n.frac(&format!("Q{}{}", plus, q_rest), &n.power(drive)),
+                    n.frac(
+                        &format!("{}{}{}", q, plus, n.byte(q_rest_value)),
+                        &n.power(drive)
+                    ),
+                    n.byte(value)
+                ),
+                _ if drive == missing[0] => format!(
+                    "{} = a(P{}{}){}b(Q{}{}) = {}",
+                    d(drive),
+                    plus,
+                    p_rest,
+                    plus,
+                    plus,
+                    q_rest,
+                    n.byte(value)
+                ),
+                _ => format!(
+                    "{} = P{}{}{}{} = {}",
+                    d(drive),
+                    plus,
+                    p_rest,
+                    plus,
+                    d(missing[0]),
+                    n.byte(value)
+                ),
+            },
+            Step::Parity { member, value } => {
+                let weight = if member == P_INDEX {
+                    String::new()
+                } else {
+                    format!("{} ", n.power("i"))
+                };
+                format!(
+                    "{} = {} {}D_i = {}",
+                    self.member(member, n),
+                    n.sum(&[]),
+                    weight,
+                    n.byte(value)
+                )
+            }
+        }
+    }
+
+    /// Renders the explanation as a worked example in Markdown, the bytes that were read in a table and
+    /// every step as an equation. Equations are LaTeX math if `latex` is set, plain text otherwise.
+    pub fn to_markdown(&self, latex: bool) -> String {
+        let n = Notation { latex };
+        let mut out = String::new();
+        writeln!(
+            out,
+            "### Row {} of a {:?} array, {:?}\n",
+            self.offset, self.mode, self.formula
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Arithmetic is in GF(2^8), where adding is XOR and g is the generator.\n"
+        )
+        .unwrap();
+        writeln!(out, "| Member | Byte |\n| --- | --- |").unwrap();
+        for (index, value) in &self.reads {
+            let plain = Notation { latex: false };
+            writeln!(
+                out,
+                "| {} | {} |",
+                self.member(*index, plain),
+                plain.byte(*value)
+            )
+            .unwrap();
+        }
+        writeln!(out).unwrap();
+        for (i, step) in self.steps.iter().enumerate() {
+            let equation = self.equation(*step, n);
+            if latex {
+                writeln!(out, "{}. ${}$", i + 1, equation).unwrap();
+            } else {
+                writeln!(out, "{}. `{}`", i + 1, equation).unwrap();
+            }
+        }
+        out
+    }
+}
+
+impl RaidSim {
+    /// Has degraded reads and repairs log how they reconstructed every byte, or stop with `false`.
+    /// The log fills up fast, a rebuild explains every byte it writes.
+    pub fn set_explain(&mut self, enabled: bool) {
+        *self.explain.get_mut() = enabled.then(Vec::new);
+    }
+
+    /// Returns the explanations logged since they were last taken, oldest first
+    pub fn take_explanations(&self) -> Vec<Explanation> {
+        self.explain
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub(super) fn is_explaining(&self) -> bool {
+        self.explain.borrow().is_some()
+    }
+
+    /// Logs how the data drives in `missing` were reconstructed at `offset`, given the row they ended up in
+    /// and the parity and partial syndromes that went into it
+    pub(super) fn explain_recovery(
+        &self,
+        offset: usize,
+        missing: &[usize],
+        data: &[u8],
+        (p, q): (Option<u8>, Option<u8>),
+        (p_rest, q_rest): (u8, u8),
+    ) {
+        let start = self.data_start();
+        let mut reads = (0..data.len())
+            .filter(|i| !missing.contains(i))
+            .map(|i| (start + i, data[i]))
+            .collect::<Vec<(usize, u8)>>();
+        reads.extend(p.map(|p| (P_INDEX, p)));
+        reads.extend(q.map(|q| (Q_INDEX, q)));
+
+        let solved = |drive: usize| Step::Data {
+            drive,
+            value: data[drive],
+        };
+        let (formula, steps) = match (missing, p) {
+            ([x], Some(_)) => (
+                RecoveryFormula::POnly,
+                vec![Step::PRest(p_rest), solved(*x)],
+            ),
+            ([x], None) => (
+                RecoveryFormula::QOnly,
+                vec![Step::QRest(q_rest), solved(*x)],
+            ),
+            ([x, y], _) => {
+                let (x, y) = (*x as i16, *y as i16);
+                let a = Gen::from_power(y - x) / (Gen::from_power(y - x) + 1);
+                let b = Gen::from_power(-x) / (Gen::from_power(y - x) + 1);
+                (
+                    RecoveryFormula::TwoErasure,
+                    vec![
+                        Step::PRest(p_rest),
+                        Step::QRest(q_rest),
+                        Step::Coefficients {
+                            a: a.value(),
+                            b: b.value(),
+                        },
+                        solved(x as usize),
+                        solved(y as usize),
+                    ],
+                )
+            }
+            _ => return,
+        };
+        self.log_explanation(Explanation {
+            mode: self.mode,
+            offset,
+            formula,
+            reads,
+            steps,
+        });
+    }
+
+    /// Logs that parity member `member` was recomputed at `offset` from the data row `data`
+    pub(super) fn explain_parity(&self, offset: usize, member: usize, data: &[u8], value: u8) {
+        let start = self.data_start();
+        self.log_explanation(Explanation {
+            mode: self.mode,
+            offset,
+            formula: RecoveryFormula::Recompute,
+            reads: data
+                .iter()
+                .enumerate()
+                .map(|(i, d)| (start + i, *d))
+                .collect(),
+            steps: vec![Step::Parity { member, value }],
+        });
+    }
+
+    fn log_explanation(&self, explanation: Explanation) {
+        if let Some(log) = self.explain.borrow_mut().as_mut() {
+            log.push(explanation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::syndrome;
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_explains_two_erasure_read() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_explain(true);
+        let offset = 3 * DRIVE_SIZE + 100;
+        assert_eq!(sim.read(offset as u64).unwrap(), data[offset]);
+        // Nothing to explain while the array is whole
+        assert!(sim.take_explanations().is_empty());
+
+        sim.fail_drive(2 + 3);
+        sim.fail_drive(2 + 7);
+        assert_eq!(sim.read(offset as u64).unwrap(), data[offset]);
+        let [explanation] = &sim.take_explanations()[..] else {
+            panic!("Expected one explanation");
+        };
+        assert_eq!(explanation.offset, 100);
+        assert_eq!(explanation.formula, RecoveryFormula::TwoErasure);
+        assert_eq!(explanation.reads.len(), NUM_DRIVES - 2);
+        let row = (0..NUM_DRIVES - 2)
+            .map(|i| data[i * DRIVE_SIZE + 100])
+            .collect::<Vec<u8>>();
+        let mut rest = row.clone();
+        rest[3] = 0;
+        rest[7] = 0;
+        assert_eq!(
+            explanation.steps[..2],
+            [
+                Step::PRest(syndrome(&rest, 0)),
+                Step::QRest(syndrome(&rest, 1))
+            ]
+        );
+        assert_eq!(
+            explanation.steps[3..],
+            [
+                Step::Data {
+                    drive: 3,
+                    value: row[3]
+                },
+                Step::Data {
+                    drive: 7,
+                    value: row[7]
+                }
+            ]
+        );
+        let markdown = explanation.to_markdown(false);
+        assert!(markdown.contains("`a = (g^4) / (g^4 + 1) = "));
+        assert!(markdown.contains("`D_3 = a(P + P_rest) + b(Q + Q_rest) = "));
+        assert!(markdown.contains("`D_7 = P + P_rest + D_3 = "));
+        assert!(sim.take_explanations().is_empty());
+
+        // Repairing a lost P recomputes it
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.replace_failed_drives();
+        sim.set_explain(true);
+        sim.repair().unwrap();
+        let explanations = sim.take_explanations();
+        assert_eq!(explanations.len(), DRIVE_SIZE);
+        assert_eq!(explanations[0].formula, RecoveryFormula::Recompute);
+        assert!(matches!(
+            explanations[0].steps[..],
+            [Step::Parity { member: 0, .. }]
+        ));
+    }
+
+    #[test]
+    fn raid5_renders_worked_example() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_explain(true);
+        sim.fail_drive(1 + 2);
+        assert_eq!(
+            sim.read(2 * DRIVE_SIZE as u64 + 5).unwrap(),
+            data[2 * DRIVE_SIZE + 5]
+        );
+        let explanation = sim.take_explanations().pop().unwrap();
+        let value = format!("0x{:02x}", data[2 * DRIVE_SIZE + 5]);
+
+        let markdown = explanation.to_markdown(false);
+        assert!(markdown.starts_with("### Row 5 of a Raid5 array, POnly"));
+        assert!(markdown.contains(&format!("| D_0 | 0x{:02x} |", data[5])));
+        assert!(markdown.contains("`P_rest = Σ[i ∉ {2}] D_i = "));
+        assert!(markdown.contains("`D_2 = P + P_rest = "));
+        assert!(markdown.trim_end().ends_with(&format!("= {}`", value)));
+
+        let latex = explanation.to_markdown(true);
+        assert!(latex.contains("$P_{rest} = \\bigoplus_{i \\notin \\{2\\}} D_i = "));
+        assert!(latex.contains(&format!("\\mathtt{{{}}}$", value)));
+    }
+}
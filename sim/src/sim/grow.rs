@@ -0,0 +1,100 @@
+use anyhow::{bail, Result};
+
+use super::{ArrayEvent, Op, RaidSim, RaidState};
+
+impl RaidSim {
+    /// Takes every drive hot-added with `HotAdd::Grow` into the array as a new data member, returning how many joined.
+    ///
+    /// Data members are laid end to end, so new ones only add room after what's already stored and nothing moves.
+    /// They're zeroed as they join, which leaves parity exactly as it was.
+    /// An array striped in chunks smaller than its members would have to be reshaped instead, and can't be grown.
+    pub fn grow(&mut self) -> Result<usize> {
+        let _recording = self.record(|| Op::Grow);
+        if self.grow_pending.is_empty() {
+            return Ok(0);
+        }
+        if self.state() != RaidState::Ok || self.rebuilding.is_some() {
+            bail!("Array isn't healthy, unable to grow");
+        }
+        if self.chunk_size < self.drive_size {
+            bail!(
+                "Array is striped in chunks of {} bytes, unable to grow without reshaping",
+                self.chunk_size
+            );
+        }
+        let num_drives = self.drives.len() + self.grow_pending.len();
+        if let Some(max) = self.mode.max_drives().filter(|max| num_drives > *max) {
+            bail!(
+                "{:?} supports at most {} drives, unable to grow to {}",
+                self.mode,
+                max,
+                num_drives
+            );
+        }
+
+        let old_len = self.data_len();
+        let zeroes = vec![0; self.drive_size];
+        let mut joining = std::mem::take(&mut self.grow_pending);
+        for drive in &mut joining {
+            self.prepare_member(drive);
+            drive.write_slice(0, &zeroes)?;
+            drive.format();
+        }
+        let grown = joining.len();
+        self.drives.extend(joining);
+
+        // The last sector and cache block may have been cut short by the old end of the array
+        self.invalidate_cache(old_len.saturating_sub(1), self.data_len());
+        self.grow_protection(old_len);
+        self.grow_integrity(old_len)?;
+        self.sync_superblocks();
+        self.emit(ArrayEvent::Grown {
+            drives: self.drives.len(),
+        });
+        self.assert_invariants();
+        Ok(grown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::{ChecksumAlgorithm, Drive};
+
+    #[test]
+    fn raid6_grow_adds_room_after_the_data() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        sim.set_integrity(Some(ChecksumAlgorithm::Crc32c)).unwrap();
+        assert_eq!(sim.grow().unwrap(), 0);
+
+        // Drives pulled from another array have its data on them, which mustn't show through
+        let (other, _) = init_random(RaidMode::Raid6);
+        let mut foreign = other.stop();
+        sim.hot_add(foreign.pop().unwrap(), HotAdd::Grow).unwrap();
+        sim.hot_add(foreign.pop().unwrap(), HotAdd::Grow).unwrap();
+        assert_eq!(sim.grow().unwrap(), 2);
+        assert_eq!(sim.grow_pending().count(), 0);
+        assert_eq!(sim.size(), (NUM_DRIVES * DRIVE_SIZE) as u64);
+        assert_eq!(sim.state(), RaidState::Ok);
+        data.resize(sim.size() as usize, 0);
+        assert_sim_equal(&sim, &data);
+
+        let end = sim.size() - 1;
+        sim.write(end, 7).unwrap();
+        assert_eq!(sim.read_verified(end, 1).unwrap(), vec![7]);
+        *data.last_mut().unwrap() = 7;
+        sim.fail_drive(NUM_DRIVES + 1);
+        sim.fail_p_parity();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_grow_needs_a_healthy_array() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.hot_add(Drive::empty(DRIVE_SIZE), HotAdd::Grow).unwrap();
+        sim.fail_random_data();
+        assert!(sim.grow().is_err());
+        assert_eq!(sim.grow_pending().count(), 1);
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{bail, Result};
+
+use crate::drive::{Drive, DriveStats};
+
+use super::{RaidMode, RaidSim, P_INDEX, Q_INDEX, STRIPE_HEIGHT};
+
+/// What a drive does for the array
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DriveRole {
+    /// Holds data, the number is its position among the data drives and its power of the generator in Q
+    Data(usize),
+    P,
+    Q,
+    /// Dedicated to the array, waiting to replace a failed member
+    Spare,
+}
+
+/// Whether a drive can currently be used
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DriveHealth {
+    Healthy,
+    /// Read from but not written to, see `RaidSim::set_write_protected()`
+    WriteProtected,
+    /// In the array but unformatted, its contents still have to be rebuilt
+    Rebuilding,
+    Failed,
+}
+
+/// A view of one of the array's drives, members first and then dedicated spares
+#[derive(Debug, Clone, Copy)]
+pub struct DriveHandle<'a> {
+    index: usize,
+    role: DriveRole,
+    drive: &'a Drive,
+}
+
+impl<'a> DriveHandle<'a> {
+    /// Returns the drive's slot in the array, or its position among the spares for a spare
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn role(&self) -> DriveRole {
+        self.role
+    }
+
+    pub fn health(&self) -> DriveHealth {
+        if self.drive.has_failed() {
+            DriveHealth::Failed
+        } else if self.role != DriveRole::Spare && !self.drive.is_formatted() {
+            DriveHealth::Rebuilding
+        } else if self.drive.is_read_only() {
+            DriveHealth::WriteProtected
+        } else {
+            DriveHealth::Healthy
+        }
+    }
+
+    /// Returns the size of the drive in bytes
+    pub fn size(&self) -> usize {
+        self.drive.size()
+    }
+
+    /// Returns whether the drive is a member holding everything it should, with no writes missed or still to rebuild.
+    /// Spares are never in sync.
+    pub fn in_sync(&self) -> bool {
+        self.role != DriveRole::Spare
+            && self.drive.usable()
+            && self.drive.missed_writes().next().is_none()
+    }
+
+    pub fn stats(&self) -> DriveStats {
+        self.drive.stats()
+    }
+
+    /// Returns the drive itself
+    pub fn drive(&self) -> &'a Drive {
+        self.drive
+    }
+}
+
+impl RaidSim {
+    /// Returns the role a member plays, by its slot in the array.
+    /// Parity isn't rotated, so every stripe has the same layout.
+    fn role(&self, index: usize) -> DriveRole {
+        match index {
+            P_INDEX => DriveRole::P,
+            Q_INDEX if self.mode == RaidMode::Raid6 => DriveRole::Q,
+            _ => DriveRole::Data(index - self.data_start()),
+        }
+    }
+
+    /// Checks `stripe` and `member` exist in the array
+    fn check_stripe_member(&self, stripe: usize, member: usize) -> Result<()> {
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        if stripe >= stripes {
+            bail!("No stripe {} in an array of {} stripes", stripe, stripes);
+        }
+        if member >= self.drives.len() {
+            bail!(
+                "No member {} in an array of {} drives",
+                member,
+                self.drives.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the role the member at `member` plays in `stripe`
+    pub fn role_at(&self, stripe: usize, member: usize) -> Result<DriveRole> {
+        self.check_stripe_member(stripe, member)?;
+        Ok(self.role(member))
+    }
+
+    /// Returns the members holding parity for `stripe`, P first and then Q
+    pub fn parity_members(&self, stripe: usize) -> Result<Vec<usize>> {
+        self.check_stripe_member(stripe, 0)?;
+        Ok((0..self.data_start()).collect())
+    }
+
+    /// Returns a handle to every member of the array and then every dedicated spare
+    pub fn drives(&self) -> impl Iterator<Item = DriveHandle<'_>> {
+        let members = self
+            .drives
+            .iter()
+            .enumerate()
+            .map(move |(index, drive)| DriveHandle {
+                index,
+                role: self.role(index),
+                drive,
+            });
+        let spares = self
+            .spares
+            .iter()
+            .enumerate()
+            .map(|(index, drive)| DriveHandle {
+                index,
+                role: DriveRole::Spare,
+                drive,
+            });
+        members.chain(spares)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_drive_handles_describe_members_and_spares() {
+        let mut sim = RaidSim::builder()
+            .drives(5, DRIVE_SIZE)
+            .spares(1)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.drive_mut(3).fail();
+        sim.set_write_protected(4, true).unwrap();
+        sim.write(3 * DRIVE_SIZE as u64 - 1, 7).unwrap();
+
+        let handles = sim
+            .drives()
+            .map(|d| (d.index(), d.role(), d.health(), d.in_sync()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            handles,
+            vec![
+                (0, DriveRole::P, DriveHealth::Healthy, true),
+                (1, DriveRole::Q, DriveHealth::Healthy, true),
+                (2, DriveRole::Data(0), DriveHealth::Healthy, true),
+                (3, DriveRole::Data(1), DriveHealth::Failed, false),
+                (4, DriveRole::Data(2), DriveHealth::WriteProtected, false),
+                (0, DriveRole::Spare, DriveHealth::Healthy, false),
+            ]
+        );
+        assert!(sim.drives().all(|d| d.size() == DRIVE_SIZE));
+        assert_eq!(sim.drives().nth(2).unwrap().stats().bytes_written, 0);
+    }
+
+    #[test]
+    fn raid5_parity_roles_per_stripe() {
+        let (sim, _) = init_random(RaidMode::Raid5);
+        let stripes = DRIVE_SIZE / SECTOR_SIZE;
+        for stripe in 0..stripes {
+            assert_eq!(sim.parity_members(stripe).unwrap(), vec![P_INDEX]);
+            assert_eq!(sim.role_at(stripe, P_INDEX).unwrap(), DriveRole::P);
+            assert_eq!(sim.role_at(stripe, 1).unwrap(), DriveRole::Data(0));
+        }
+        assert!(sim.parity_members(stripes).is_err());
+        assert!(sim.role_at(0, NUM_DRIVES).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use super::{RaidSim, RaidState};
+
+/// What moved the array from one state to another
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Trigger {
+    /// Every member was formatted by `init()`
+    Initialized,
+    /// The array was put back together from existing drives
+    Assembled,
+    /// Members stopped or started being usable, by slot.
+    /// Drives kicked partway through an access are noticed the next time membership is checked, at the latest on `tick()`.
+    Membership {
+        lost: Vec<usize>,
+        joined: Vec<usize>,
+    },
+    /// A rebuild finished and its replacements joined the array
+    Rebuilt { members: Vec<usize> },
+    /// A member was write-protected or made writeable again
+    WriteProtected { index: usize, protected: bool },
+    /// Data was written with its parity left to be brought up to date later
+    ParityDeferred,
+    /// Out of date parity was brought up to date
+    ParityFlushed,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trigger::Initialized => write!(f, "initialized"),
+            Trigger::Assembled => write!(f, "assembled"),
+            Trigger::Membership { lost, joined } => {
+                if !lost.is_empty() {
+                    write!(f, "lost {:?}", lost)?;
+                }
+                if !lost.is_empty() && !joined.is_empty() {
+                    write!(f, ", ")?;
+                }
+                if !joined.is_empty() {
+                    write!(f, "joined {:?}", joined)?;
+                }
+                Ok(())
+            }
+            Trigger::Rebuilt { members } => write!(f, "rebuilt {:?}", members),
+            Trigger::WriteProtected { index, protected } => write!(
+                f,
+                "member {} {}",
+                index,
+                if *protected {
+                    "write-protected"
+                } else {
+                    "writeable"
+                }
+            ),
+            Trigger::ParityDeferred => write!(f, "parity deferred"),
+            Trigger::ParityFlushed => write!(f, "parity flushed"),
+        }
+    }
+}
+
+/// The array changing state, and why
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Transition {
+    /// Simulated milliseconds since the array was created
+    pub at: u64,
+    pub from: RaidState,
+    pub to: RaidState,
+    pub trigger: Trigger,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>8}ms {:?} -> {:?}: {}",
+            self.at, self.from, self.to, self.trigger
+        )
+    }
+}
+
+impl RaidSim {
+    /// Returns every state the array has been through, oldest first
+    pub fn history(&self) -> &[Transition] {
+        &self.history
+    }
+
+    /// Records a transition if the array is no longer in the state it was last seen in, blaming `trigger`
+    pub(super) fn note_state(&mut self, trigger: impl FnOnce() -> Trigger) {
+        let from = self
+            .history
+            .last()
+            .map_or(RaidState::Uninit, |t| t.to.clone());
+        let to = self.state();
+        if to != from {
+            self.history.push(Transition {
+                at: self.clock,
+                from,
+                to,
+                trigger: trigger(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid5_history_explains_each_transition() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid5)
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .parity_update(ParityUpdate::Lazy {
+                flush_interval: None,
+            })
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.write(0, 1).unwrap();
+        sim.flush_parity().unwrap();
+        sim.tick(100).unwrap();
+        sim.fail_drive(4);
+        sim.tick(100).unwrap();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        sim.tick(100).unwrap();
+        sim.fail_drive(1);
+        sim.fail_drive(2);
+
+        let history = sim
+            .history()
+            .iter()
+            .map(|t| (t.at, t.to.clone(), t.trigger.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            history,
+            vec![
+                (0, RaidState::Ok, Trigger::Initialized),
+                (0, RaidState::Unprotected, Trigger::ParityDeferred),
+                (0, RaidState::Ok, Trigger::ParityFlushed),
+                (
+                    100,
+                    RaidState::Degraded,
+                    Trigger::Membership {
+                        lost: vec![4],
+                        joined: vec![]
+                    }
+                ),
+                (200, RaidState::Ok, Trigger::Rebuilt { members: vec![4] }),
+                (
+                    300,
+                    RaidState::Degraded,
+                    Trigger::Membership {
+                        lost: vec![1],
+                        joined: vec![]
+                    }
+                ),
+                (
+                    300,
+                    RaidState::Failed,
+                    Trigger::Membership {
+                        lost: vec![2],
+                        joined: vec![]
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            sim.history()[3].to_string(),
+            "     100ms Ok -> Degraded: lost [4]"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Result};
+
+use crate::{checksum::ChecksumAlgorithm, drive::SECTOR_SIZE};
+
+use super::{Access, Op, RaidError, RaidSim, RaidState};
+
+/// Checksums of the array's sectors as the user wrote them
+#[derive(Debug, Clone)]
+pub(super) struct Integrity {
+    algorithm: ChecksumAlgorithm,
+    sums: Vec<u64>,
+}
+
+impl RaidSim {
+    /// Turns end to end integrity on, checksumming every sector of the array with `algorithm`, or off with `None`.
+    ///
+    /// The checksums are taken from what the user writes, before it's striped across the members, and kept apart
+    /// from the drives. `read_verified()` checks data against them once it has been read or reconstructed,
+    /// so a reconstruction that returned the wrong bytes is caught instead of handed back.
+    pub fn set_integrity(&mut self, algorithm: Option<ChecksumAlgorithm>) -> Result<()> {
+        let _recording = self.record(|| Op::SetIntegrity { algorithm });
+        self.integrity = None;
+        if let Some(algorithm) = algorithm {
+            let sums = (0..self.data_len().div_ceil(SECTOR_SIZE))
+                .map(|sector| Ok(algorithm.checksum(&self.read_sector(sector)?)))
+                .collect::<Result<Vec<u64>>>()?;
+            self.integrity = Some(Integrity { algorithm, sums });
+        }
+        Ok(())
+    }
+
+    /// Reads every byte of array sector `sector`
+    fn read_sector(&self, sector: usize) -> Result<Vec<u8>> {
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Read,
+            }
+            .into());
+        }
+        let start = sector * SECTOR_SIZE;
+        (start..(start + SECTOR_SIZE).min(self.data_len()))
+            .map(|offset| self.read_at(offset))
+            .collect()
+    }
+
+    /// Checksums the sectors from the one holding array offset `old_len` on, after the array grew from that length
+    pub(super) fn grow_integrity(&mut self, old_len: usize) -> Result<()> {
+        let algorithm = match &self.integrity {
+            Some(integrity) => integrity.algorithm,
+            None => return Ok(()),
+        };
+        let first = old_len / SECTOR_SIZE;
+        let sums = (first..self.data_len().div_ceil(SECTOR_SIZE))
+            .map(|sector| Ok(algorithm.checksum(&self.read_sector(sector)?)))
+            .collect::<Result<Vec<u64>>>()?;
+        let integrity = self.integrity.as_mut().unwrap();
+        integrity.sums.truncate(first);
+        integrity.sums.extend(sums);
+        Ok(())
+    }
+
+    /// Checksums the sectors `data` is about to be written over at array offset `offset`,
+    /// filling in the parts of them it doesn't cover from what the array holds now
+    pub(super) fn record_integrity(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        let algorithm = match &self.integrity {
+            Some(integrity) => integrity.algorithm,
+            None => return Ok(()),
+        };
+        let end = offset + data.len();
+        for sector in offset / SECTOR_SIZE..=(end.max(offset + 1) - 1) / SECTOR_SIZE {
+            let start = sector * SECTOR_SIZE;
+            let sector_end = (start + SECTOR_SIZE).min(self.data_len());
+            let contents = if offset <= start && sector_end <= end {
+                data[start - offset..sector_end - offset].to_vec()
+            } else {
+                let mut contents = self.read_sector(sector)?;
+                for (i, byte) in contents.iter_mut().enumerate() {
+                    if (offset..end).contains(&(start + i)) {
+                        *byte = data[start + i - offset];
+                    }
+                }
+                contents
+            };
+            self.integrity.as_mut().unwrap().sums[sector] = algorithm.checksum(&contents);
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes at array offset `offset` and checks every sector they touch against the checksum
+    /// the user wrote it with, failing rather than returning data that parity got wrong
+    pub fn read_verified(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let start = self.logical_offset(offset, len)?;
+        let integrity = match &self.integrity {
+            Some(integrity) => integrity,
+            None => bail!("End to end integrity is off, nothing to verify against"),
+        };
+        let mut data = vec![];
+        for sector in start / SECTOR_SIZE..=(start + len.max(1) - 1) / SECTOR_SIZE {
+            let contents = self.read_sector(sector)?;
+            if integrity.algorithm.checksum(&contents) != integrity.sums[sector] {
+                bail!(
+                    "Sector {} of the array doesn't match what was written to it",
+                    sector
+                );
+            }
+            data.extend_from_slice(&contents);
+        }
+        let skip = start % SECTOR_SIZE;
+        Ok(data[skip..skip + len].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::{ChecksumAlgorithm, SECTOR_SIZE};
+
+    #[test]
+    fn raid5_integrity_catches_wrong_reconstruction() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid5);
+        assert!(sim.read_verified(0, 1).is_err());
+        sim.set_integrity(Some(ChecksumAlgorithm::Crc32c)).unwrap();
+        assert_eq!(sim.read_verified(0, data.len()).unwrap(), data);
+
+        // Partial sector writes keep the checksums current
+        sim.write(700, 0x42).unwrap();
+        sim.write_slice(1000, &[1, 2, 3, 4]).unwrap();
+        data[700] = 0x42;
+        data[1000..1004].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(sim.read_verified(690, 400).unwrap(), data[690..1090]);
+
+        // Bit rot on one member and the loss of another make parity reconstruct the wrong byte
+        let offset = 10;
+        sim.drive_mut(P_INDEX + 2).corrupt(offset, 0x04);
+        sim.drive_mut(P_INDEX + 1).fail();
+        assert_ne!(sim.read(offset as u64).unwrap(), data[offset]);
+        assert!(sim.read_verified(offset as u64, 1).is_err());
+        // Sectors the rot didn't reach still check out
+        assert_eq!(
+            sim.read_verified(SECTOR_SIZE as u64, 8).unwrap(),
+            data[SECTOR_SIZE..SECTOR_SIZE + 8]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{bail, Result};
+
+use crate::{drive::SECTOR_SIZE, generator::syndrome};
+
+use super::{RaidMode, RaidSim, RaidState, P_INDEX, Q_INDEX, STRIPE_HEIGHT};
+
+/// Settings and progress of the invariant checks run after mutations
+#[derive(Debug, Clone, Default)]
+pub(super) struct Invariants {
+    /// Number of stripes to check parity of each time, `None` when checks are off
+    sample: Option<usize>,
+    /// Stripe the next parity check starts at, so every stripe comes up in turn
+    next: usize,
+}
+
+impl RaidSim {
+    /// Has the array check its invariants after every mutation and panic on the first one that doesn't hold,
+    /// checking the parity of `sample` stripes each time, or stop with `None`.
+    /// Meant for fuzzing and chaos runs, where finding corruption at the operation that caused it beats finding it at the end.
+    pub fn set_invariant_checks(&mut self, sample: Option<usize>) {
+        self.invariants.sample = sample;
+    }
+
+    /// Checks the invariants the array should always hold, without disturbing any of its drives:
+    /// the state agrees with its members, every sector matches its checksum, and the next sampled stripes
+    /// match their parity. With checks off, the parity of every stripe is checked.
+    pub fn check_invariants(&mut self) -> Result<()> {
+        self.check_state()?;
+        self.check_checksums()?;
+
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        let sample = self.invariants.sample.unwrap_or(stripes).min(stripes);
+        for i in 0..sample {
+            self.check_parity((self.invariants.next + i) % stripes.max(1))?;
+        }
+        self.invariants.next = (self.invariants.next + sample) % stripes.max(1);
+        Ok(())
+    }
+
+    /// Runs the invariant checks if they're on, panicking if any fails
+    pub(super) fn assert_invariants(&mut self) {
+        if self.invariants.sample.is_some() {
+            if let Err(e) = self.check_invariants() {
+                panic!("Array invariant broken: {}", e);
+            }
+        }
+    }
+
+    fn check_state(&self) -> Result<()> {
+        let state = self.state();
+        if self.members.len() != self.drives.len() {
+            bail!(
+                "Superblocks track {} members of an array of {}",
+                self.members.len(),
+                self.drives.len()
+            );
+        }
+        if state == RaidState::Ok && self.drives.iter().any(|d| !d.usable()) {
+            bail!("Array is Ok with a member missing");
+        }
+        if self.rebuilding.is_some() && !matches!(state, RaidState::Degraded | RaidState::Failed) {
+            bail!("A rebuild is running on an array that is {:?}", state);
+        }
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        if let Some(stripe) = self.dirty.iter().find(|s| **s >= stripes) {
+            bail!(
+                "Stripe {} is dirty in an array of {} stripes",
+                stripe,
+                stripes
+            );
+        }
+        Ok(())
+    }
+
+    fn check_checksums(&self) -> Result<()> {
+        for (index, drive) in self.drives.iter().enumerate() {
+            if !drive.usable() {
+                continue;
+            }
+            let sectors = self.drive_size.div_ceil(SECTOR_SIZE);
+            if let Some(sector) = (0..sectors).find(|s| !drive.verify_sector(*s)) {
+                bail!(
+                    "Sector {} of member {} doesn't match its checksum",
+                    sector,
+                    index
+                );
+            }
+            if let Some(block) = drive.find_corrupt_blocks().first() {
+                bail!(
+                    "Block {} of member {} doesn't match its Merkle tree",
+                    block,
+                    index
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the rows of `stripe` against their parity, when every member holds what it should there
+    fn check_parity(&self, stripe: usize) -> Result<()> {
+        let start = stripe * STRIPE_HEIGHT;
+        let end = (start + STRIPE_HEIGHT).min(self.drive_size);
+        let stale = |offset: usize| {
+            self.drives
+                .iter()
+                .any(|d| !d.usable() || d.is_read_only() || d.missed_write(offset))
+        };
+        for offset in start..end {
+            if self.is_dirty(offset) || stale(offset) {
+                continue;
+            }
+            let data = self
+                .data_drives()
+                .map(|d| d.peek(offset, 1)[0])
+                .collect::<Vec<u8>>();
+            let mut parity = vec![(P_INDEX, 0)];
+            if self.mode == RaidMode::Raid6 {
+                parity.push((Q_INDEX, 1));
+            }
+            for (member, j) in parity {
+                if self.drives[member].peek(offset, 1)[0] != syndrome(&data, j) {
+                    bail!(
+                        "Parity on member {} doesn't match the data at offset {} of stripe {}",
+                        member,
+                        offset,
+                        stripe
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_invariant_checks_catch_the_breaking_write() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        sim.set_checksum(Some(ChecksumAlgorithm::Crc32c));
+        sim.set_invariant_checks(Some(1));
+        sim.check_invariants().unwrap();
+
+        sim.drive_mut(6).fail();
+        data[10..20].fill(7);
+        sim.write_slice(10, &data[10..20]).unwrap();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        sim.write(3000, 1).unwrap();
+        data[3000] = 1;
+        assert_sim_equal(&sim, &data);
+
+        // Parity that doesn't match is found on the next mutation, not later on
+        let p = sim.drive(P_INDEX).read(700).unwrap();
+        sim.drive_mut(P_INDEX).write(700, !p).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            sim.tick(1).unwrap();
+            sim.tick(1).unwrap();
+        }));
+        assert!(result.is_err());
+        sim.set_invariant_checks(None);
+        assert!(sim.check_invariants().is_err());
+    }
+}
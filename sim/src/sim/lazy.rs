@@ -0,0 +1,218 @@
+use anyhow::{bail, Result};
+
+use super::{
+    MismatchCause, Op, RaidMode, RaidSim, RaidState, Trigger, P_INDEX, Q_INDEX, STRIPE_HEIGHT,
+};
+
+/// Describes when parity is brought up to date after a write
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParityUpdate {
+    /// Parity is updated alongside every write
+    Immediate,
+    /// Writes only mark their stripes dirty and parity is recomputed later, leaving those stripes unprotected until then.
+    /// Parity is recomputed on `flush_parity()`, on repair, and every `flush_interval` simulated milliseconds if set.
+    Lazy { flush_interval: Option<u64> },
+}
+
+impl RaidSim {
+    /// Sets when parity is brought up to date after a write.
+    /// Switching back to immediate updates flushes every dirty stripe first.
+    pub fn set_parity_update(&mut self, update: ParityUpdate) -> Result<()> {
+        let _recording = self.record(|| Op::SetParityUpdate { update });
+        if update == ParityUpdate::Immediate {
+            self.flush_parity()?;
+            if !self.dirty.is_empty() {
+                bail!(
+                    "{} stripes can't have their parity recomputed",
+                    self.dirty.len()
+                );
+            }
+        }
+        self.parity_update = update;
+        Ok(())
+    }
+
+    /// Returns the stripes whose parity is out of date
+    pub fn dirty_stripes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.dirty.iter().copied()
+    }
+
+    /// Returns whether the stripe containing `drive_offset` has out of date parity
+    pub(super) fn is_dirty(&self, drive_offset: usize) -> bool {
+        self.dirty.contains(&(drive_offset / STRIPE_HEIGHT))
+    }
+
+    /// Returns whether a write may leave parity to be recomputed later.
+    /// Parity is never deferred while a member is missing, as it's the only copy of that member's data.
+    pub(super) fn defer_parity(&self) -> bool {
+        matches!(self.parity_update, ParityUpdate::Lazy { .. })
+            && matches!(self.state(), RaidState::Ok | RaidState::Unprotected)
+    }
+
+    /// Marks the stripes covering drive offsets `start..end` as having out of date parity
+    pub(super) fn mark_dirty(&mut self, start: usize, end: usize) {
+        self.dirty
+            .extend(start / STRIPE_HEIGHT..=(end.max(start + 1) - 1) / STRIPE_HEIGHT);
+        self.note_state(|| Trigger::ParityDeferred);
+    }
+
+    /// Recomputes parity for every dirty stripe, returning how many were brought up to date.
+    /// A stripe whose data can't all be read stays dirty.
+    pub fn flush_parity(&mut self) -> Result<usize> {
+        let _recording = self.record(|| Op::FlushParity);
+        let dirty = std::mem::take(&mut self.dirty);
+        let mut flushed = 0;
+        for stripe in dirty {
+            if self.flush_stripe(stripe).is_ok() {
+                flushed += 1;
+            } else {
+                self.dirty.insert(stripe);
+            }
+        }
+        self.note_state(|| Trigger::ParityFlushed);
+        Ok(flushed)
+    }
+
+    /// Recomputes P and Q parity of a single stripe from its data, all of its rows at once with the parity engine.
+    /// While resyncing after an unclean shutdown, parity that turns out not to have matched is counted as a mismatch.
+    fn flush_stripe(&mut self, stripe: usize) -> Result<()> {
+        let start = stripe * STRIPE_HEIGHT;
+        let end = (start + STRIPE_HEIGHT).min(self.drive_size);
+        if self.data_drives().any(|d| !d.usable()) {
+            bail!(
+                "Stripe {} is missing data, unable to recompute parity",
+                stripe
+            );
+        }
+        let chunks = (self.data_start()..self.drives.len())
+            .map(|index| {
+                (start..end)
+                    .map(|offset| self.read_retry(index, offset))
+                    .collect::<Result<Vec<u8>>>()
+            })
+            .collect::<Result<Vec<Vec<u8>>>>()?;
+        let chunks = chunks.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>();
+        let (mut p, mut q) = (vec![0; end - start], vec![0; end - start]);
+        self.engine.gen_syndrome(&chunks, &mut p, &mut q);
+
+        let mut parity = vec![(P_INDEX, p)];
+        if self.mode == RaidMode::Raid6 {
+            parity.push((Q_INDEX, q));
+        }
+        for (index, parity) in parity {
+            if !self.drives[index].usable() {
+                continue;
+            }
+            if self.resync
+                && (start..end).any(|offset| {
+                    self.read_retry(index, offset).ok() != Some(parity[offset - start])
+                })
+            {
+                self.record_mismatch(index, MismatchCause::UncleanShutdown, true);
+            }
+            self.write_member(index, start, &parity)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes dirty stripes whenever the clock passes a multiple of the flush interval,
+    /// or straight away while resyncing after an unclean shutdown
+    pub(super) fn tick_parity(&mut self, ms: u64) -> Result<()> {
+        if self.resync {
+            self.flush_parity()?;
+            self.resync = !self.dirty.is_empty();
+            return Ok(());
+        }
+        if let ParityUpdate::Lazy {
+            flush_interval: Some(interval),
+        } = self.parity_update
+        {
+            let interval = interval.max(1);
+            if self.clock / interval != (self.clock - ms) / interval {
+                self.flush_parity()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::ParallelEngine;
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_lazy_parity_flush() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        sim.set_parity_update(ParityUpdate::Lazy {
+            flush_interval: None,
+        })
+        .unwrap();
+        let data = write_random(&mut sim);
+        assert_eq!(sim.state(), RaidState::Unprotected);
+        assert_eq!(sim.dirty_stripes().count(), DRIVE_SIZE / STRIPE_HEIGHT);
+        assert_sim_equal(&sim, &data);
+
+        assert_eq!(sim.flush_parity().unwrap(), DRIVE_SIZE / STRIPE_HEIGHT);
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_flush_with_parallel_engine() {
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .parity_engine(ParallelEngine::with_threads(100, 4))
+            .parity_update(ParityUpdate::Lazy {
+                flush_interval: None,
+            })
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let data = write_random(&mut sim);
+        sim.flush_parity().unwrap();
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_lazy_parity_loses_dirty_stripes() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_parity_update(ParityUpdate::Lazy {
+            flush_interval: None,
+        })
+        .unwrap();
+        sim.write(DRIVE_SIZE as u64, !data[DRIVE_SIZE]).unwrap();
+        assert_eq!(sim.dirty_stripes().collect::<Vec<_>>(), vec![0]);
+
+        // The first data drive's dirty stripe is lost, the clean one is still protected
+        sim.drive_mut(1).fail();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert!(sim.read(0).is_err());
+        assert_eq!(sim.read(STRIPE_HEIGHT as u64).unwrap(), data[STRIPE_HEIGHT]);
+        sim.replace_failed_drives();
+        assert!(sim.repair().is_err());
+    }
+
+    #[test]
+    fn raid6_lazy_parity_background_flush() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        sim.set_parity_update(ParityUpdate::Lazy {
+            flush_interval: Some(5_000),
+        })
+        .unwrap();
+        let data = write_random(&mut sim);
+        sim.tick(4_000).unwrap();
+        assert_eq!(sim.state(), RaidState::Unprotected);
+        sim.tick(1_000).unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_p_parity();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+}
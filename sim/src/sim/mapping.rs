@@ -0,0 +1,85 @@
+use anyhow::{bail, Result};
+
+use super::{DriveRole, RaidSim, STRIPE_HEIGHT};
+
+/// Where a byte of the array lives on its members
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Mapping {
+    /// Slot of the member holding the byte
+    pub drive: usize,
+    /// Offset of the byte on that member
+    pub physical_offset: usize,
+    pub stripe: usize,
+    pub role: DriveRole,
+}
+
+impl RaidSim {
+    /// Works out which member and offset hold the byte at array offset `offset`
+    pub fn map(&self, offset: u64) -> Result<Mapping> {
+        let offset = self.logical_offset(offset, 1)?;
+        let (index, physical_offset) = self.locate(offset);
+        let drive = self.data_start() + index;
+        let stripe = physical_offset / STRIPE_HEIGHT;
+        Ok(Mapping {
+            drive,
+            physical_offset,
+            stripe,
+            role: self.role_at(stripe, drive)?,
+        })
+    }
+
+    /// Works out which array offset is held by the member at `drive` at `physical_offset`,
+    /// or `None` if that byte is parity
+    pub fn unmap(&self, drive: usize, physical_offset: usize) -> Result<Option<u64>> {
+        if physical_offset >= self.drive_size {
+            bail!(
+                "Offset {} in drive of size {}",
+                physical_offset,
+                self.drive_size
+            );
+        }
+        Ok(
+            match self.role_at(physical_offset / STRIPE_HEIGHT, drive)? {
+                DriveRole::Data(index) => Some(self.logical_at(index, physical_offset) as u64),
+                _ => None,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_map_round_trips() {
+        let (sim, data) = init_random(RaidMode::Raid6);
+        let mapping = sim.map(3 * DRIVE_SIZE as u64 + 600).unwrap();
+        assert_eq!(
+            mapping,
+            Mapping {
+                drive: Q_INDEX + 4,
+                physical_offset: 600,
+                stripe: 1,
+                role: DriveRole::Data(3),
+            }
+        );
+        for offset in (0..sim.size()).step_by(97) {
+            let mapping = sim.map(offset).unwrap();
+            assert_eq!(
+                sim.drive(mapping.drive)
+                    .read(mapping.physical_offset)
+                    .unwrap(),
+                data[offset as usize]
+            );
+            assert_eq!(
+                sim.unmap(mapping.drive, mapping.physical_offset).unwrap(),
+                Some(offset)
+            );
+        }
+        assert_eq!(sim.unmap(Q_INDEX, 5).unwrap(), None);
+        assert!(sim.unmap(Q_INDEX, DRIVE_SIZE).is_err());
+        assert!(sim.map(sim.size()).is_err());
+    }
+}
@@ -0,0 +1,1897 @@
+mod allocation;
+mod arithmetic;
+mod assemble;
+mod bitmap;
+mod buffer;
+mod builder;
+mod cache;
+mod capacity;
+mod chaos;
+mod compress;
+mod concurrent;
+mod dedup;
+mod encryption;
+mod error;
+mod events;
+mod explain;
+mod grow;
+mod handle;
+mod history;
+mod integrity;
+mod invariants;
+mod lazy;
+mod mapping;
+mod oplog;
+mod patrol;
+mod policy;
+mod protection;
+mod readonly;
+mod rebuild;
+mod reliability;
+mod report;
+mod rescue;
+mod scrub;
+mod snapshot;
+mod spare;
+mod stats;
+mod status;
+mod stripe;
+mod view;
+mod workload;
+mod writeback;
+
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    ops::{Index, Not},
+    rc::{Rc, Weak},
+};
+
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+
+use crate::{
+    checksum::ChecksumAlgorithm,
+    cipher::mix,
+    drive::{Drive, DriveError, SECTOR_SIZE},
+    generator::{syndrome, FromPower, Gen, GfEngine, ParityEngine},
+    protection::ProtectionInfo,
+};
+
+use anyhow::{bail, Context, Result};
+use buffer::{Buffer, BufferPool};
+
+pub use arithmetic::OpCategory;
+pub use assemble::{AssemblyReport, Exclusion, ExclusionReason};
+pub use bitmap::ReAdd;
+pub use buffer::BufferStats;
+pub use builder::{ConfigError, RaidSimBuilder, StorageLayout};
+pub use cache::{CacheStats, ReadCacheConfig};
+pub use capacity::Capacity;
+pub use chaos::{ChaosConfig, ChaosFailure, ChaosOp, ChaosRun};
+pub use compress::{CompressedVolume, CompressionStats};
+pub use concurrent::ConcurrentReport;
+pub use dedup::{DedupStats, DedupVolume};
+pub use error::{Access, RaidError};
+pub use events::{ArrayEvent, Observer};
+pub use explain::{Explanation, Step};
+pub use handle::{DriveHandle, DriveHealth, DriveRole};
+pub use history::{Transition, Trigger};
+pub use lazy::ParityUpdate;
+pub use mapping::Mapping;
+pub use oplog::{Op, OpLog, OP_LOG_VERSION};
+pub use patrol::PatrolReport;
+pub use policy::{ArcLite, CachePolicy, EvictionPolicy, Fifo, Lru};
+pub use rebuild::{RebuildReport, RecoveryFormula, RepairPlan};
+pub use reliability::{ReliabilityEstimate, ReliabilityModel};
+pub use report::RunReport;
+pub use scrub::ScrubReport;
+pub use spare::{HotAdd, PromotionOrder, SparePool};
+pub use stats::{ArrayStats, Detail, MismatchCause, MismatchCount};
+pub use stripe::{StripeView, StripeViewMut, StripeWriter};
+pub use view::FrozenView;
+pub use workload::{Io, Sequential, UniformRandom, Workload, WorkloadReport, Zipfian};
+pub use writeback::{WriteBackConfig, WriteBackStats};
+
+const P_INDEX: usize = 0;
+const Q_INDEX: usize = 1;
+
+/// Number of bytes of each member that make up a stripe
+const STRIPE_HEIGHT: usize = SECTOR_SIZE;
+
+/// What to do with a sector that still can't be read once its retries are exhausted
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OnExhausted {
+    /// Reconstruct the data from parity, the sector is tried again on the next read
+    Reconstruct,
+    /// Reconstruct the data from parity and record the sector in the drive's bad block list,
+    /// so it is skipped until it gets rewritten
+    MarkBad,
+}
+
+/// Describes how the array deals with drive reads that fail without the drive failing outright
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of times a failed read is retried
+    pub retries: u32,
+    /// Simulated milliseconds to wait before the first retry, doubled on each subsequent retry up to `u64::MAX`
+    pub backoff: u64,
+    /// What to do once the retries are exhausted
+    pub exhausted: OnExhausted,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: 10,
+            exhausted: OnExhausted::Reconstruct,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RaidMode {
+    Raid5,
+    Raid6,
+}
+
+impl RaidMode {
+    /// Number of members an array can lose without losing data
+    pub fn redundancy(self) -> usize {
+        match self {
+            RaidMode::Raid5 => 1,
+            RaidMode::Raid6 => 2,
+        }
+    }
+
+    /// Fewest members an array can be built with, its parity and at least two data drives
+    pub fn min_drives(self) -> usize {
+        self.redundancy() + 2
+    }
+
+    /// Most members an array can be built with, if there's a limit. Q gives each data drive its own power
+    /// of the generator, and there are only 255 of them before they repeat.
+    pub fn max_drives(self) -> Option<usize> {
+        match self {
+            RaidMode::Raid5 => None,
+            RaidMode::Raid6 => Some(255 + 2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RaidState {
+    /// Array has not been initialized yet
+    Uninit,
+    /// Array is functioning as normal
+    Ok,
+    /// Array is functioning but some stripes have out of date parity, those stripes won't survive a failure
+    Unprotected,
+    /// One or more drives has failed, extra computation is needed to retrieve some data
+    Degraded,
+    /// Too many drives have failed, data has been lost
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct RaidSim {
+    drives: Vec<Drive>,
+    drive_size: usize,
+    /// Bytes of the array laid on one data drive before moving on to the next, all of a drive unless set smaller
+    chunk_size: usize,
+    mode: RaidMode,
+    drive_timeout: Option<u64>,
+    /// Checksum every member keeps of its sectors
+    checksum: Option<ChecksumAlgorithm>,
+    /// Block size of the Merkle tree every member keeps, if any
+    merkle: Option<usize>,
+    /// Whether every member encrypts what it stores with a key of its own
+    encryption: bool,
+    /// Checksum of what the user last wrote to each sector of the array, if end to end integrity is on
+    integrity: Option<integrity::Integrity>,
+    /// Protection information the host wrote with each sector of the array, if it's being kept
+    protection: Option<Vec<Option<ProtectionInfo>>>,
+    retry_policy: RetryPolicy,
+    /// Simulated milliseconds since the array was created
+    clock: u64,
+    patrol: patrol::Patrol,
+    scrub: scrub::Scrub,
+    parity_update: ParityUpdate,
+    /// Stripes whose parity is out of date
+    dirty: BTreeSet<usize>,
+    /// Spares dedicated to this array
+    spares: Vec<Drive>,
+    /// Hot-added drives waiting for the array to be grown onto them
+    grow_pending: Vec<Drive>,
+    promotion_order: PromotionOrder,
+    /// Identifies the array in its members' superblocks
+    array_id: u64,
+    /// Picks everything random about the array, seeded by the builder for a reproducible run
+    rng: StdRng,
+    /// What the faults of drives the array creates are seeded from, if the builder was given a seed
+    fault_seed: Option<u64>,
+    /// How many drives the array has seeded for each slot, so a replacement doesn't draw what the drive before it did
+    seeded: Vec<u64>,
+    /// Number of times the array's membership or metadata has changed
+    events: u64,
+    /// Which members were usable when the superblocks were last written
+    members: Vec<bool>,
+    /// Write-intent bitmap, stripes written while a member was missing
+    bitmap: BTreeSet<usize>,
+    /// Event count when the bitmap was last cleared, a member that left before this can't be resynced from it
+    bitmap_since: u64,
+    /// Allocation bitmap, stripes that have ever been written
+    written: BTreeSet<usize>,
+    /// The rebuild in progress, if one has been started and not finished
+    rebuilding: Option<rebuild::Rebuild>,
+    invariants: invariants::Invariants,
+    /// Whether the superblocks say the array was shut down cleanly
+    clean: bool,
+    /// Set after an unclean shutdown until every stripe's parity has been recomputed
+    resync: bool,
+    stats: ArrayStats,
+    /// Views frozen from the array that may still need old data saved for them
+    views: Vec<Weak<view::Preserved>>,
+    read_cache: RefCell<Option<cache::ReadCache>>,
+    write_back: Option<writeback::WriteBack>,
+    /// Scratch buffers for reconstruction, parity computation, and rebuild
+    buffers: Rc<BufferPool>,
+    /// Computes the parity of full stripe writes
+    engine: Rc<dyn ParityEngine>,
+    /// Field arithmetic done so far, if it's being counted
+    field_ops: Rc<arithmetic::FieldOps>,
+    observers: events::Observers,
+    /// Every operation applied to the array, if it was built to record them
+    op_log: Option<OpLog>,
+    /// How reconstructed bytes were worked out, if they're being explained
+    explain: RefCell<Option<Vec<Explanation>>>,
+    /// Every state the array has been through
+    history: Vec<Transition>,
+}
+
+/// Swallows the error of a drive that got kicked partway through an access, or is refusing writes.
+/// The array carries on without it like it would any other failed member.
+fn ignore_ejected(drive: &Drive, result: Result<()>) -> Result<()> {
+    match result {
+        Err(_) if drive.has_failed() || drive.is_read_only() => Ok(()),
+        result => result,
+    }
+}
+
+impl RaidSim {
+    /// Creates a new instance of a Raid Simulation, configured further by `RaidSimBuilder`
+    fn new(mode: RaidMode, num_drives: usize, drive_size: usize, layout: StorageLayout) -> Self {
+        let mut rng = StdRng::from_rng(&mut rand::rng());
+        let drives = match layout {
+            StorageLayout::DriveMajor => Drive::arena(num_drives, drive_size),
+            StorageLayout::StripeMajor => {
+                Drive::striped_arena(num_drives, drive_size, STRIPE_HEIGHT)
+            }
+        };
+        RaidSim {
+            drives,
+            drive_size,
+            chunk_size: drive_size,
+            mode,
+            drive_timeout: None,
+            checksum: None,
+            merkle: None,
+            encryption: false,
+            integrity: None,
+            protection: None,
+            retry_policy: RetryPolicy::default(),
+            clock: 0,
+            patrol: patrol::Patrol::default(),
+            scrub: scrub::Scrub::default(),
+            parity_update: ParityUpdate::Immediate,
+            dirty: BTreeSet::new(),
+            spares: vec![],
+            grow_pending: vec![],
+            promotion_order: PromotionOrder::DedicatedFirst,
+            array_id: rng.random(),
+            rng,
+            fault_seed: None,
+            seeded: vec![],
+            events: 0,
+            members: vec![],
+            bitmap: BTreeSet::new(),
+            bitmap_since: 0,
+            written: BTreeSet::new(),
+            rebuilding: None,
+            invariants: invariants::Invariants::default(),
+            clean: true,
+            resync: false,
+            stats: ArrayStats::default(),
+            views: vec![],
+            read_cache: RefCell::new(None),
+            write_back: None,
+            buffers: Rc::default(),
+            field_ops: Rc::default(),
+            engine: Rc::new(GfEngine),
+            observers: events::Observers::default(),
+            op_log: None,
+            explain: RefCell::new(None),
+            history: vec![],
+        }
+    }
+
+    /// Gets the total number of bytes storable in the array
+    pub fn size(&self) -> u64 {
+        self.data_len() as u64
+    }
+
+    /// Gets the total number of bytes storable in the array, for indexing in memory
+    fn data_len(&self) -> usize {
+        self.data_drives().count() * self.drive_size
+    }
+
+    /// Checks that `len` bytes starting at array offset `offset` are all within the array,
+    /// returning the offset in the host's word size
+    fn logical_offset(&self, offset: u64, len: usize) -> Result<usize> {
+        let out_of_bounds = RaidError::OutOfBounds {
+            offset,
+            len,
+            size: self.size(),
+        };
+        if offset >= self.size() {
+            return Err(out_of_bounds.into());
+        }
+        // Anything inside the array fits, the array itself is held in memory
+        if len > self.data_len() - offset as usize {
+            return Err(out_of_bounds.into());
+        }
+        Ok(offset as usize)
+    }
+
+    /// Returns the data drive holding the byte at logical offset `offset`, and its offset on that drive.
+    /// The array is laid across the data drives a chunk at a time, the first chunk of every drive and then the second.
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let width = self.data_drives().count();
+        let (chunk, within) = (offset / self.chunk_size, offset % self.chunk_size);
+        (chunk % width, chunk / width * self.chunk_size + within)
+    }
+
+    /// Returns the logical offset of the byte at `drive_offset` on data drive `drive_index`, undoing `locate()`
+    fn logical_at(&self, drive_index: usize, drive_offset: usize) -> usize {
+        let width = self.data_drives().count();
+        (drive_offset / self.chunk_size * width + drive_index) * self.chunk_size
+            + drive_offset % self.chunk_size
+    }
+
+    /// Returns how many of the `len` bytes from `drive_offset` on fit in the rest of its chunk,
+    /// those are the ones that follow on from each other in the array
+    fn chunk_run(&self, drive_offset: usize, len: usize) -> usize {
+        (self.chunk_size - drive_offset % self.chunk_size).min(len)
+    }
+
+    /// Gets the current state of the array
+    pub fn state(&self) -> RaidState {
+        let unformatted = self.unformatted().count();
+        // A write-protected member takes no part in keeping new writes redundant
+        let read_only = self
+            .drives
+            .iter()
+            .filter(|d| d.is_read_only() && d.usable())
+            .count();
+        let count = self.unusable().count() + read_only;
+        if unformatted == self.drives.len() {
+            RaidState::Uninit
+        } else if count > 2 || (count > 1 && self.mode == RaidMode::Raid5) {
+            RaidState::Failed
+        } else if count > 0 {
+            RaidState::Degraded
+        } else if !self.dirty.is_empty() {
+            RaidState::Unprotected
+        } else {
+            RaidState::Ok
+        }
+    }
+
+    /// Initializes the array by formatting all drives
+    pub fn init(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::Init);
+        for d in &mut self.drives {
+            d.format();
+        }
+        self.note_state(|| Trigger::Initialized);
+        self.sync_superblocks();
+        self.assert_invariants();
+        Ok(())
+    }
+
+    /// Returns the simulated time in milliseconds
+    pub fn now(&self) -> u64 {
+        self.clock
+    }
+
+    /// Advances the simulated clock by `ms` milliseconds, giving background tasks a chance to run
+    pub fn tick(&mut self, ms: u64) -> Result<()> {
+        let _recording = self.record(|| Op::Tick { ms });
+        self.sync_superblocks();
+        self.clock += ms;
+        let bytes = self.patrol.budget(ms);
+        if bytes > 0 && self.state() != RaidState::Uninit {
+            self.patrol_step(bytes)?;
+        }
+        let bytes = self.scrub.budget(ms);
+        if bytes > 0 && matches!(self.state(), RaidState::Ok | RaidState::Unprotected) {
+            self.scrub_step(bytes)?;
+        }
+        self.tick_parity(ms)?;
+        self.assert_invariants();
+        Ok(())
+    }
+
+    pub fn write_slice_nth_drive(
+        &mut self,
+        drive_index: usize,
+        drive_offset: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let _recording = self.record(|| Op::WriteSliceNthDrive {
+            drive_index,
+            drive_offset,
+            data: data.to_vec(),
+        });
+        if drive_offset >= self.drive_size {
+            bail!(
+                "Offset {} in drive of size {}",
+                drive_offset,
+                self.drive_size
+            );
+        }
+        if drive_index >= self.data_drives().count() {
+            bail!(
+                "No data drive {} in an array of {}",
+                drive_index,
+                self.data_drives().count()
+            );
+        }
+        if drive_offset + data.len() > self.drive_size {
+            bail!(
+                "Out of bounds write, at offset {} and data length {} in drive of size {}",
+                drive_offset,
+                data.len(),
+                self.drive_size
+            );
+        }
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Write,
+            }
+            .into());
+        }
+        self.mark_unclean();
+        self.stats.logical_bytes_written += data.len() as u64;
+        // Each chunk of the drive holds a different run of the array
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = self.chunk_run(drive_offset + pos, data.len() - pos);
+            self.write_drive_run(drive_index, drive_offset + pos, &data[pos..pos + len])?;
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// Writes `data`, which fits in one chunk, to data drive `drive_index` at `drive_offset` and brings parity up to date
+    fn write_drive_run(
+        &mut self,
+        drive_index: usize,
+        drive_offset: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let offset = self.logical_at(drive_index, drive_offset);
+        self.preserve_for_views(offset, offset + data.len())?;
+        self.invalidate_cache(offset, offset + data.len());
+        self.drop_protection(offset, offset + data.len());
+        self.mark_written(offset, offset + data.len());
+        self.record_integrity(offset, data)?;
+        if self.write_back.is_some() {
+            for (i, byte) in data.iter().enumerate() {
+                self.absorb_write(offset + i, *byte)?;
+            }
+            return Ok(());
+        }
+        if self.defer_parity() {
+            self.write_member(self.data_start() + drive_index, drive_offset, data)?;
+            self.mark_dirty(drive_offset, drive_offset + data.len());
+            return Ok(());
+        }
+
+        self.mark_bitmap(drive_offset, drive_offset + data.len());
+
+        let mut old_data = vec![0u8; data.len()];
+        for (i, old) in old_data.iter_mut().enumerate() {
+            // TODO: read_slice_nth_drive would be reallllly nice right about now
+            *old = self.read_uncached(offset + i)?;
+        }
+
+        let member = self.data_start() + drive_index;
+        self.note_rebuild_write(member, drive_offset, drive_offset + data.len());
+        if !self.drives[member].has_failed() {
+            let result = self.write_member(member, drive_offset, data);
+            ignore_ejected(&self.drives[member], result)?;
+        }
+
+        // Compute new P parity wherever it's current, on a replacement that's only where the rebuild has been
+        // Formally, if p is the original P parity byte and p_k is the new P parity byte where d_k (the byte on drive k) becomes d'
+        // Then it follows that
+        // p   = d_0 + d_1 + ... + d_n-1
+        // p_k = d_0 + d_1 + ... + d' + ... + d_n-1
+        // Then
+        // p + p_k = d_k + d_'
+        // Therefore
+        // p_k = p + d_k + d'
+        // Which means XORing the P parity byte, the old data on the drive, and the new data will yield the new P parity byte
+        for (i, (old, new)) in old_data.iter().zip(data).enumerate() {
+            let offset = drive_offset + i;
+            if !self.current_at(P_INDEX, offset) {
+                continue;
+            }
+            let result = self
+                .read_retry(P_INDEX, offset)
+                .and_then(|p| self.write_member(P_INDEX, offset, &[p ^ old ^ new]));
+            ignore_ejected(self.p_parity(), result)?;
+        }
+
+        // Compute new Q parity
+        if self.mode == RaidMode::Raid6 {
+            // Formally, if q is the original Q parity byte and q_k is the new Q parity byte where d_k (the byte on drive k) becomes d'
+            // Then it follows that
+            // q   = (g^0 * d_0) + (g^1 * d_1) + ... + (g^n-1 * d_n-1)
+            // q_k = (g^0 * d_0) + (g^1 * d_1) + ... + (g^k * d') + ... + (g^n-1 * d_n-1)
+            // Then
+            // q + q_k = (g^k * d_k) + (g^k * d')
+            // Therefore
+            // q_k = q + g^k * (d_k + d')
+            // Which means XORing the old and new data, applying the generator g^k, then XORing the original Q parity byte will yield the new P parity byte
+            let gk = Gen::from_power(drive_index);
+            for (i, (old, new)) in old_data.iter().zip(data).enumerate() {
+                let offset = drive_offset + i;
+                if !self.current_at(Q_INDEX, offset) {
+                    continue;
+                }
+                let result = self
+                    .read_retry(Q_INDEX, offset)
+                    .and_then(|q| self.write_member(Q_INDEX, offset, &[q ^ (gk * (old ^ new))]));
+                ignore_ejected(self.q_parity(), result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a slice at a specific offset in the array
+    pub fn write_slice(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        let _recording = self.record(|| Op::WriteSlice {
+            offset,
+            data: data.to_vec(),
+        });
+        let _counting = self.count_ops(OpCategory::Write);
+        let offset = self.logical_offset(offset, data.len())?;
+        self.sync_superblocks();
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Write,
+            }
+            .into());
+        }
+
+        // Stripes the write fills are written whole, the rows they cover are left out of the drive by drive writes
+        let full = self.full_stripes(offset, data.len());
+        let rows = full.start * STRIPE_HEIGHT..(full.end * STRIPE_HEIGHT).min(self.drive_size);
+        let mut data_pos = 0;
+        while data_pos < data.len() {
+            // Write up to the end of the chunk
+            let (drive_index, drive_offset) = self.locate(offset + data_pos);
+            let len = self.chunk_run(drive_offset, data.len() - data_pos);
+            let end = drive_offset + len;
+            for piece in [
+                drive_offset..end.min(rows.start),
+                drive_offset.max(rows.end)..end,
+            ] {
+                if !piece.is_empty() {
+                    let start = data_pos + piece.start - drive_offset;
+                    self.write_slice_nth_drive(
+                        drive_index,
+                        piece.start,
+                        &data[start..start + piece.len()],
+                    )?;
+                }
+            }
+            data_pos += len;
+        }
+
+        for stripe in full {
+            let start = stripe * STRIPE_HEIGHT;
+            let height = (start + STRIPE_HEIGHT).min(self.drive_size) - start;
+            let chunks = (0..self.data_drives().count())
+                .flat_map(|i| {
+                    let chunk = self.logical_at(i, start) - offset;
+                    data[chunk..chunk + height].iter().copied()
+                })
+                .collect::<Vec<u8>>();
+            self.stripe_writer(stripe)?.write(&chunks)?;
+        }
+        self.assert_invariants();
+        Ok(())
+    }
+
+    /// Writes a byte at a specific offset in the array
+    pub fn write(&mut self, offset: u64, data: u8) -> Result<()> {
+        let _recording = self.record(|| Op::Write { offset, data });
+        let _counting = self.count_ops(OpCategory::Write);
+        let offset = self.logical_offset(offset, 1)?;
+        self.sync_superblocks();
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Write,
+            }
+            .into());
+        }
+        self.write_at(offset, data)
+    }
+
+    /// Writes a byte like `write()`, but leaves checking the offset is in the array and the array hasn't failed
+    /// to the caller, and doesn't look for members that came or went since the last access.
+    /// Meant for hot loops that have checked all of that once for every byte they write,
+    /// the checks are only debug assertions here. An offset past the end of the array panics.
+    pub fn write_unchecked(&mut self, offset: u64, data: u8) -> Result<()> {
+        let _recording = self.record(|| Op::Write { offset, data });
+        debug_assert!(offset < self.size(), "Offset {} out of bounds", offset);
+        debug_assert!(self.state() != RaidState::Failed, "Array failed");
+        self.write_at(offset as usize, data)
+    }
+
+    /// Writes a byte at logical offset `offset`, already known to be in the array
+    fn write_at(&mut self, offset: usize, data: u8) -> Result<()> {
+        self.mark_unclean();
+        self.stats.logical_bytes_written += 1;
+        self.preserve_for_views(offset, offset + 1)?;
+        self.invalidate_cache(offset, offset + 1);
+        self.drop_protection(offset, offset + 1);
+        self.mark_written(offset, offset + 1);
+        self.record_integrity(offset, &[data])?;
+        if self.absorb_write(offset, data)? {
+            return Ok(());
+        }
+        let (drive_index, drive_offset) = self.locate(offset);
+        let result = self.write_byte(drive_index, drive_offset, data);
+        self.assert_invariants();
+        result
+    }
+
+    /// Writes a byte to a data drive and brings parity up to date, or leaves it dirty if parity is deferred
+    fn write_byte(&mut self, drive_index: usize, drive_offset: usize, data: u8) -> Result<()> {
+        let offset = self.logical_at(drive_index, drive_offset);
+        if self.defer_parity() {
+            self.write_member(self.data_start() + drive_index, drive_offset, &[data])?;
+            self.mark_dirty(drive_offset, drive_offset + 1);
+            return Ok(());
+        }
+        self.mark_bitmap(drive_offset, drive_offset + 1);
+        let old_data = self.read_uncached(offset)?;
+        let member = self.data_start() + drive_index;
+        self.note_rebuild_write(member, drive_offset, drive_offset + 1);
+        if !self.drives[member].has_failed() {
+            let result = self.write_member(member, drive_offset, &[data]);
+            ignore_ejected(&self.drives[member], result)?;
+        }
+
+        // Compute new P parity
+        if self.current_at(P_INDEX, drive_offset) {
+            // Formally, if p is the original P parity byte and p_k is the new P parity byte where d_k (the byte on drive k) becomes d'
+            // Then it follows that
+            // p   = d_0 + d_1 + ... + d_n-1
+            // p_k = d_0 + d_1 + ... + d' + ... + d_n-1
+            // Then
+            // p + p_k = d_k + d_'
+            // Therefore
+            // p_k = p + d_k + d'
+            // Which means XORing the P parity byte, the old data on the drive, and the new data will yield the new P parity byte
+            let result = self
+                .read_retry(P_INDEX, drive_offset)
+                .and_then(|p| self.write_member(P_INDEX, drive_offset, &[p ^ old_data ^ data]));
+            ignore_ejected(self.p_parity(), result)?;
+        }
+
+        // Compute new Q parity
+        if self.mode == RaidMode::Raid6 && self.current_at(Q_INDEX, drive_offset) {
+            // Formally, if q is the original Q parity byte and q_k is the new Q parity byte where d_k (the byte on drive k) becomes d'
+            // Then it follows that
+            // q   = (g^0 * d_0) + (g^1 * d_1) + ... + (g^n-1 * d_n-1)
+            // q_k = (g^0 * d_0) + (g^1 * d_1) + ... + (g^k * d') + ... + (g^n-1 * d_n-1)
+            // Then
+            // q + q_k = (g^k * d_k) + (g^k * d')
+            // Therefore
+            // q_k = q + g^k * (d_k + d')
+            // Which means XORing the old and new data, applying the generator g^k, then XORing the original Q parity byte will yield the new P parity byte
+            let gk = Gen::from_power(drive_index);
+            let result = self.read_retry(Q_INDEX, drive_offset).and_then(|q| {
+                self.write_member(Q_INDEX, drive_offset, &[q ^ (gk * (old_data ^ data))])
+            });
+            ignore_ejected(self.q_parity(), result)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a byte at a specific offset in the array
+    pub fn read(&self, offset: u64) -> Result<u8> {
+        let offset = self.logical_offset(offset, 1)?;
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Read,
+            }
+            .into());
+        }
+        self.read_at(offset)
+    }
+
+    /// Reads a byte like `read()`, but leaves checking the offset is in the array and the array hasn't failed
+    /// to the caller. The checks are only debug assertions here, and an offset past the end of the array panics.
+    pub fn read_unchecked(&self, offset: u64) -> Result<u8> {
+        debug_assert!(offset < self.size(), "Offset {} out of bounds", offset);
+        debug_assert!(self.state() != RaidState::Failed, "Array failed");
+        self.read_at(offset as usize)
+    }
+
+    /// Reads a byte like `read()`, or returns `None` if it's past the end of the array or can't be read
+    pub fn get(&self, offset: u64) -> Option<u8> {
+        self.read(offset).ok()
+    }
+
+    /// Reads a byte at logical offset `offset`, already known to be in the array
+    pub(super) fn read_at(&self, offset: usize) -> Result<u8> {
+        if let Some(data) = self.pending_byte(offset) {
+            return Ok(data);
+        }
+        if self.read_cache.borrow().is_some() {
+            return self.read_cached(offset);
+        }
+        self.read_uncached(offset)
+    }
+
+    /// Reads a byte from the drives, falling back to parity if its drive can't be read
+    fn read_uncached(&self, offset: usize) -> Result<u8> {
+        let (drive_index, drive_offset) = self.locate(offset);
+        let drive = self.data_drives().nth(drive_index).unwrap();
+        // A replacement can be read wherever the rebuild has already been, elsewhere it's reconstructed
+        if self.current_at(self.data_start() + drive_index, drive_offset) {
+            match self.read_retry(self.data_start() + drive_index, drive_offset) {
+                Ok(byte) => return Ok(byte),
+                // The drive got kicked while we were waiting on it, fall back to parity
+                Err(_) if drive.has_failed() => {
+                    if self.state() == RaidState::Failed {
+                        return Err(RaidError::Failed {
+                            access: Access::Read,
+                        }
+                        .into());
+                    }
+                }
+                // Out of retries, the sector can't be read so fall back to parity
+                Err(_) => {
+                    drive.record(|s| s.fallbacks += 1);
+                    if self.retry_policy.exhausted == OnExhausted::MarkBad
+                        && !drive.is_bad(drive_offset)
+                    {
+                        drive.mark_bad(drive_offset / SECTOR_SIZE);
+                    }
+                }
+            }
+        }
+        let _counting = self.count_ops(OpCategory::DegradedRead);
+        self.reconstruct(drive_index, drive_offset)
+    }
+
+    /// Reads a byte from member `index`, retrying failed reads according to the retry policy.
+    /// Sectors the array has marked bad are not touched at all.
+    fn read_retry(&self, index: usize, offset: usize) -> Result<u8> {
+        self.read_retry_drive(&self.drives[index], offset)
+            .context(RaidError::member(Access::Read, index, offset))
+    }
+
+    /// Writes `data` to member `index` at drive offset `offset`, saying where if it fails
+    fn write_member(&mut self, index: usize, offset: usize, data: &[u8]) -> Result<()> {
+        self.drives[index]
+            .write_slice(offset, data)
+            .context(RaidError::member(Access::Write, index, offset))
+    }
+
+    fn read_retry_drive(&self, drive: &Drive, offset: usize) -> Result<u8> {
+        let sector = offset / SECTOR_SIZE;
+        if drive.is_bad(offset) {
+            return Err(DriveError::MarkedBad { sector }.into());
+        }
+        // Retrying won't bring back a write the drive never took
+        if drive.missed_write(offset) {
+            return Err(DriveError::MissedWrites { sector }.into());
+        }
+        let mut result = drive.read(offset);
+        let mut backoff = self.retry_policy.backoff;
+        for _ in 0..self.retry_policy.retries {
+            if result.is_ok() || drive.has_failed() {
+                break;
+            }
+            drive.record(|s| {
+                s.retries += 1;
+                s.backoff_time = s.backoff_time.saturating_add(backoff);
+            });
+            backoff = backoff.saturating_mul(2);
+            result = drive.read(offset);
+        }
+        result
+    }
+
+    /// Reconstructs the byte at `drive_offset` of data drive `drive_index` from parity
+    fn reconstruct(&self, drive_index: usize, drive_offset: usize) -> Result<u8> {
+        Ok(self.recover_data(drive_offset, &[drive_index])?[drive_index])
+    }
+
+    /// Reads the byte at `offset` from every data drive, reconstructing the ones in `skip`
+    /// along with any that are unusable or can't be read, as long as there is enough parity to do so
+    fn recover_data(&self, offset: usize, skip: &[usize]) -> Result<Buffer> {
+        let mut data = self.buffers.take(self.data_drives().count())?;
+        // No more than two can be recovered, so only those are kept, the rest are just counted
+        let (mut missing, mut lost) = ([0; 2], 0);
+        for (i, d) in self.data_drives().enumerate() {
+            let byte = if skip.contains(&i) || !d.usable() {
+                None
+            } else {
+                self.read_retry(self.data_start() + i, offset).ok()
+            };
+            match byte {
+                Some(byte) => data[i] = byte,
+                None => {
+                    if let Some(slot) = missing.get_mut(lost) {
+                        *slot = i;
+                    }
+                    lost += 1;
+                }
+            }
+        }
+        if lost == 0 {
+            return Ok(data);
+        }
+        if self.is_dirty(offset) {
+            return Err(RaidError::DirtyParity {
+                stripe: offset / STRIPE_HEIGHT,
+            }
+            .into());
+        }
+
+        // Parity drives are only of use if they can be read at this offset.
+        // With one missing data drive, we read using P parity if possible and Q parity otherwise.
+        // With two missing data drives, both P and Q parity are needed.
+        // Anything more and the data has been lost.
+        let read_parity = |index: usize| {
+            self.drives[index]
+                .usable()
+                .then(|| self.read_retry(index, offset).ok())
+                .flatten()
+        };
+        let p = read_parity(P_INDEX);
+        let q = (self.mode == RaidMode::Raid6)
+            .then(|| read_parity(Q_INDEX))
+            .flatten();
+        // P and Q syndromes of the data bytes we do have, the missing ones are still zero
+        let p_rest = syndrome(&data, 0);
+        let q_rest = syndrome(&data, 1);
+
+        let missing = &missing[..lost.min(2)];
+        let not_enough = || RaidError::NotEnoughRedundancy {
+            offset,
+            stripe: offset / STRIPE_HEIGHT,
+            missing: missing.to_vec(),
+            lost,
+        };
+        match (lost, missing) {
+            (1, &[x]) => {
+                data[x] = match (p, q) {
+                    (Some(p), _) => p ^ p_rest,
+                    (None, Some(q)) => ((q ^ q_rest) / Gen::from_power(x)).value(),
+                    _ => return Err(not_enough().into()),
+                };
+            }
+            (2, &[x, y]) => {
+                let (p, q) = match (p, q) {
+                    (Some(p), Some(q)) => (p, q),
+                    _ => return Err(not_enough().into()),
+                };
+                let (x, y) = (x as i16, y as i16);
+                let a = Gen::from_power(y - x) / (Gen::from_power(y - x) + 1);
+                let b = Gen::from_power(-x) / (Gen::from_power(y - x) + 1);
+                let dx = (a * (p ^ p_rest)) ^ (b * (q ^ q_rest));
+                data[x as usize] = dx;
+                data[y as usize] = p ^ p_rest ^ dx;
+            }
+            _ => return Err(not_enough().into()),
+        }
+        if self.is_explaining() {
+            self.explain_recovery(offset, missing, &data, (p, q), (p_rest, q_rest));
+        }
+        Ok(data)
+    }
+
+    /// Works out what the member at `index` should hold at `drive_offset` from the rest of the array
+    fn member_contents(&self, index: usize, drive_offset: usize) -> Result<u8> {
+        let j = match index {
+            P_INDEX => 0,
+            Q_INDEX if self.mode == RaidMode::Raid6 => 1,
+            _ => return self.reconstruct(index - self.data_start(), drive_offset),
+        };
+        let data = self.recover_data(drive_offset, &[])?;
+        let parity = syndrome(&data, j);
+        if self.is_explaining() {
+            self.explain_parity(drive_offset, index, &data, parity);
+        }
+        Ok(parity)
+    }
+
+    /// Works out what the member at `index` should hold at drive offsets `start..end`
+    fn member_range(&self, index: usize, start: usize, end: usize) -> Result<Buffer> {
+        let mut contents = self.buffers.take(end - start)?;
+        for (byte, offset) in contents.iter_mut().zip(start..end) {
+            *byte = self.member_contents(index, offset)?;
+        }
+        Ok(contents)
+    }
+
+    /// Reads the byte at `offset` from every data drive, failing if any of them can't be read
+    fn read_data_row(&self, offset: usize) -> Result<Buffer> {
+        let mut data = self.buffers.take(self.data_drives().count())?;
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = self.read_retry(self.data_start() + i, offset)?;
+        }
+        Ok(data)
+    }
+
+    /// Returns an immutable reference to the drive used for P parity
+    pub fn p_parity(&self) -> &Drive {
+        &self.drives[P_INDEX]
+    }
+    /// Returns a mutable reference to the drive used for P parity
+    fn p_parity_mut(&mut self) -> &mut Drive {
+        &mut self.drives[P_INDEX]
+    }
+
+    /// Returns an immutable reference to the drive used for Q parity
+    pub fn q_parity(&self) -> &Drive {
+        &self.drives[Q_INDEX]
+    }
+    /// Returns a mutable reference to the drive used for Q parity
+    fn q_parity_mut(&mut self) -> &mut Drive {
+        &mut self.drives[Q_INDEX]
+    }
+
+    /// Returns an immutable reference to the drive at `index` in the drives array
+    pub fn drive(&self, index: usize) -> &Drive {
+        &self.drives[index]
+    }
+    /// Returns a mutable reference to the drive at `index` in the drives array
+    pub fn drive_mut(&mut self, index: usize) -> &mut Drive {
+        &mut self.drives[index]
+    }
+
+    /// Sets how long an access may hang, in simulated milliseconds, before the drive is kicked from the array.
+    /// This applies to every member, including replacements added later.
+    pub fn set_drive_timeout(&mut self, timeout: Option<u64>) {
+        let _recording = self.record(|| Op::SetDriveTimeout { timeout });
+        self.drive_timeout = timeout;
+        for d in &mut self.drives {
+            d.set_timeout(timeout);
+        }
+    }
+
+    /// Has every member keep a checksum of each of its sectors with `algorithm`, or stop with `None`.
+    /// A sector that no longer matches its checksum fails to read, so the array falls back to parity for it.
+    pub fn set_checksum(&mut self, algorithm: Option<ChecksumAlgorithm>) {
+        let _recording = self.record(|| Op::SetChecksum { algorithm });
+        self.checksum = algorithm;
+        for d in &mut self.drives {
+            d.set_checksum(algorithm);
+        }
+    }
+
+    /// Has every member keep a Merkle tree over blocks of `block_size` bytes, or stop with `None`.
+    /// The trees let `repair_corrupt_blocks()` find silently corrupted blocks without checking every row's parity.
+    pub fn set_merkle(&mut self, block_size: Option<usize>) {
+        let _recording = self.record(|| Op::SetMerkle { block_size });
+        self.merkle = block_size;
+        for d in &mut self.drives {
+            d.set_merkle(block_size);
+        }
+    }
+
+    /// Sets a drive joining the array up like the rest of the members
+    fn prepare_member(&mut self, drive: &mut Drive) {
+        drive.set_timeout(self.drive_timeout);
+        if drive.checksum_algorithm() != self.checksum {
+            drive.set_checksum(self.checksum);
+        }
+        if drive.merkle().map(|tree| tree.block_size()) != self.merkle {
+            drive.set_merkle(self.merkle);
+        }
+        if drive.protection_info(0).is_some() != self.protection.is_some() {
+            drive.set_protection(self.protection.is_some());
+        }
+        if drive.encryption_key().is_some() != self.encryption {
+            drive.set_encryption_key(self.encryption.then(|| self.rng.random()));
+        }
+    }
+
+    /// Seeds the faults of `drive`, about to go in `slot`, from the array's seed, the slot, and how many drives the slot
+    /// has had before. Each member then fails the same way whatever order the array's operations come in.
+    /// Slots past the last member are for spares.
+    fn seed_drive(&mut self, slot: usize, drive: &mut Drive) {
+        let Some(seed) = self.fault_seed else {
+            return;
+        };
+        if self.seeded.len() <= slot {
+            self.seeded.resize(slot + 1, 0);
+        }
+        drive.seed_faults(mix(mix(seed ^ slot as u64) ^ self.seeded[slot]));
+        self.seeded[slot] += 1;
+    }
+
+    /// Sets how reads that fail on a working drive are retried and recovered
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        let _recording = self.record(|| Op::SetRetryPolicy { policy });
+        self.retry_policy = policy;
+    }
+
+    /// Returns an iterator of tuples (I, D) where I is the absolute index in the drives array and D is an immutable reference to the corresponding data drive
+    pub fn data_drives(&self) -> impl Iterator<Item = &Drive> {
+        self.drives[self.data_start()..].iter()
+    }
+    /// Returns an iterator of tuples (I, D) where I is the absolute index in the drives array and D is a mutable reference to the corresponding data drive
+    fn data_drives_mut(&mut self) -> impl Iterator<Item = &mut Drive> {
+        let start = self.data_start();
+        self.drives[start..].iter_mut()
+    }
+    /// Returns the index in the drives array of the first data drive
+    fn data_start(&self) -> usize {
+        match self.mode {
+            RaidMode::Raid5 => 1,
+            RaidMode::Raid6 => 2,
+        }
+    }
+
+    /// Returns an iterator of immutable references to drives that have failed
+    pub fn failed(&self) -> impl Iterator<Item = &Drive> {
+        self.drives.iter().filter(|d| d.has_failed())
+    }
+    /// Returns an iterator of immutable references to drives that cannot be used.
+    /// A drive is unusable if it has either failed or hasn't been formatted
+    /// This is useful for standard read or writes which shouldn't ever touch failed or unformatted drives.
+    pub fn unusable(&self) -> impl Iterator<Item = &Drive> {
+        self.drives
+            .iter()
+            .filter(|d| d.has_failed() || !d.is_formatted())
+    }
+    /// Returns an iterator of immutable references to drives that are unformatted
+    pub fn unformatted(&self) -> impl Iterator<Item = &Drive> {
+        self.drives.iter().filter(|d| d.is_formatted().not())
+    }
+    /// Returns an iterator of mutable references to drives that are unformatted
+    pub fn unformatted_mut(&mut self) -> impl Iterator<Item = &mut Drive> {
+        self.drives
+            .iter_mut()
+            .filter_map(|d| d.is_formatted().not().then_some(d))
+    }
+    /// Returns an iterator of immutable references to drives that haven't failed
+    pub fn not_failed(&self) -> impl Iterator<Item = &Drive> {
+        self.drives.iter().filter(|d| d.has_failed().not())
+    }
+    /// Returns an iterator of mutable references to drives that haven't failed
+    pub fn not_failed_mut(&mut self) -> impl Iterator<Item = &mut Drive> {
+        self.drives
+            .iter_mut()
+            .filter_map(|d| d.has_failed().not().then_some(d))
+    }
+    /// Chooses a random drive that hasn't failed yet and marks it as failed
+    pub fn fail_random(&mut self) {
+        let _recording = self.record(|| Op::FailRandom);
+        let candidates = (0..self.drives.len())
+            .filter(|i| !self.drives[*i].has_failed())
+            .collect::<Vec<usize>>();
+        let index = candidates.into_iter().choose(&mut self.rng).unwrap();
+        self.drives[index].fail();
+        self.sync_superblocks();
+    }
+    /// Chooses a random data drive that hasn't failed yet and marks it as failed
+    pub fn fail_random_data(&mut self) {
+        let _recording = self.record(|| Op::FailRandomData);
+        let candidates = (self.data_start()..self.drives.len())
+            .filter(|i| !self.drives[*i].has_failed())
+            .collect::<Vec<usize>>();
+        let index = candidates.into_iter().choose(&mut self.rng).unwrap();
+        self.drives[index].fail();
+        self.sync_superblocks();
+    }
+    /// Marks the drive at `index` as failed
+    pub fn fail_drive(&mut self, index: usize) {
+        let _recording = self.record(|| Op::FailDrive { index });
+        self.drives[index].fail();
+        self.sync_superblocks();
+    }
+    /// Mark the P parity drive as failed
+    pub fn fail_p_parity(&mut self) {
+        let _recording = self.record(|| Op::FailPParity);
+        self.p_parity_mut().fail();
+        self.sync_superblocks();
+    }
+    /// Mark the Q parity drive as failed
+    pub fn fail_q_parity(&mut self) {
+        let _recording = self.record(|| Op::FailQParity);
+        self.q_parity_mut().fail();
+        self.sync_superblocks();
+    }
+    /// Replaces failed drives with empty, functioning drives.
+    /// Writes made before they're repaired land on them and in parity alike, so the rebuild works out the same bytes.
+    pub fn replace_failed_drives(&mut self) {
+        let _recording = self.record(|| Op::ReplaceFailedDrives);
+        for i in 0..self.drives.len() {
+            if self.drives[i].has_failed() {
+                let mut drive = Drive::empty(self.drive_size);
+                self.seed_drive(i, &mut drive);
+                self.prepare_member(&mut drive);
+                self.drives[i] = drive;
+            }
+        }
+        self.sync_superblocks();
+        self.assert_invariants();
+    }
+
+    /// Resizes the member at `index` to `size` bytes, as if its disk had been swapped for a bigger or smaller one.
+    /// Members only hold the array's first `drive_size` bytes, anything past that is left unused until the
+    /// array is grown, so a member can't be made smaller than that.
+    pub fn resize_member(&mut self, index: usize, size: usize) -> Result<()> {
+        let _recording = self.record(|| Op::ResizeMember { index, size });
+        if size < self.drive_size {
+            bail!(
+                "Member of size {} can't hold the array's {} bytes",
+                size,
+                self.drive_size
+            );
+        }
+        self.drives[index].resize(size)
+    }
+
+    /// Repairs data for all unformatted drives with original data
+    pub fn repair(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::Repair);
+        if self.start_repair()? {
+            self.rebuild()?;
+        }
+        self.assert_invariants();
+        Ok(())
+    }
+
+    /// Gets a repair going without finishing it, returning whether there's a rebuild left to step through.
+    /// The array stays online in the meantime, see [`RaidSim::rebuild_step`].
+    pub fn start_repair(&mut self) -> Result<bool> {
+        let _recording = self.record(|| Op::StartRepair);
+        match self.state() {
+            RaidState::Ok => Ok(false),
+            RaidState::Unprotected => self.flush_parity().map(|_| false),
+            RaidState::Failed => bail!("Array failed, unable to repair"),
+            RaidState::Uninit => bail!("Array uninitialized, unable to repair"),
+            RaidState::Degraded => {
+                self.flush_parity()?;
+                if !self.dirty.is_empty() {
+                    bail!(
+                        "{} stripes have out of date parity, unable to repair",
+                        self.dirty.len()
+                    );
+                }
+                if self.rebuilding.is_none() {
+                    self.start_rebuild()?;
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// Every byte value, so indexing can hand out a reference to a byte that was reconstructed rather than stored
+static BYTES: [u8; 256] = {
+    let mut bytes = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        bytes[i] = i as u8;
+        i += 1;
+    }
+    bytes
+};
+
+/// Reads the byte at a logical offset like `read()`, for tests and quick looks where `sim[i]` reads better.
+/// Panics where `read()` would fail, `get()` is the checked way in.
+impl Index<u64> for RaidSim {
+    type Output = u8;
+
+    fn index(&self, offset: u64) -> &u8 {
+        match self.read(offset) {
+            Ok(data) => &BYTES[data as usize],
+            Err(e) => panic!("Can't read offset {} of the array: {:#}", offset, e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::drive::Hang;
+
+    pub(crate) const NUM_DRIVES: usize = 64;
+    pub(crate) const DRIVE_SIZE: usize = 1024;
+
+    pub(crate) fn init_random(mode: RaidMode) -> (RaidSim, Vec<u8>) {
+        let mut sim = RaidSim::builder()
+            .mode(mode)
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .build()
+            .unwrap();
+        sim.init().expect("Shit");
+        let data = write_random(&mut sim);
+        (sim, data)
+    }
+
+    pub(crate) fn write_random(sim: &mut RaidSim) -> Vec<u8> {
+        let mut data = vec![0u8; sim.size() as usize];
+        rand::rng().fill(data.as_mut_slice());
+        sim.write_slice(0, data.as_slice()).unwrap();
+        // for i in 0..sim.size() {
+        //     sim.write(i, data[i]).unwrap();
+        // }
+        data
+    }
+
+    pub(crate) fn assert_sim_equal(sim: &RaidSim, data: &[u8]) {
+        for (i, expected) in data.iter().enumerate() {
+            let actual = sim.read(i as u64).unwrap();
+            if actual != *expected {
+                panic!(
+                    "sim.read(i) != data[i], i={}, {} != {}",
+                    i, actual, expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn raid5_test_init() {
+        let (sim, data) = init_random(RaidMode::Raid5);
+        assert_sim_equal(&sim, &data);
+        assert_eq!(sim.state(), RaidState::Ok);
+    }
+
+    #[test]
+    fn raid5_one_data_drive_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_p_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_p_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_one_data_drive_and_p_parity_failure() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        sim.fail_p_parity();
+        assert_eq!(sim.state(), RaidState::Failed);
+        assert!(sim.write(0, 0).is_err());
+        assert!(sim.read(0).is_err());
+    }
+
+    #[test]
+    fn raid5_offsets_past_the_end() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        assert_eq!(sim.size(), ((NUM_DRIVES - 1) * DRIVE_SIZE) as u64);
+        assert!(sim.read(sim.size()).is_err());
+        assert!(sim.read(u64::MAX).is_err());
+        assert!(sim.write(u64::MAX, 0).is_err());
+        assert!(sim.write_slice(sim.size() - 1, &[0; 2]).is_err());
+        assert_eq!(sim.read(sim.size() - 1).unwrap(), data[data.len() - 1]);
+    }
+
+    #[test]
+    fn indexing_reads_like_read() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_drive(5);
+        for i in [0, 1000, data.len() - 1] {
+            assert_eq!(sim[i as u64], data[i]);
+            assert_eq!(sim.get(i as u64), Some(data[i]));
+        }
+        assert_eq!(sim.get(sim.size()), None);
+        sim.fail_drive(6);
+        sim.fail_drive(7);
+        assert_eq!(sim.get(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't read offset 0")]
+    fn indexing_a_failed_array_panics() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.fail_drive(1);
+        sim.fail_drive(2);
+        let _ = sim[0];
+    }
+
+    #[test]
+    fn raid5_two_data_drive_failure() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_eq!(sim.state(), RaidState::Failed);
+        assert!(sim.write(0, 0).is_err());
+        assert!(sim.read(0).is_err());
+    }
+
+    #[test]
+    fn raid5_unformatted_still_degraded() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_random();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        sim.replace_failed_drives();
+        assert_eq!(sim.unformatted().count(), 1);
+        assert_eq!(sim.state(), RaidState::Degraded);
+    }
+
+    #[test]
+    fn raid5_read_after_replace_before_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_drive(3);
+        sim.replace_failed_drives();
+        // The replacement is all zeros, every read of it has to come from parity
+        assert_sim_equal(&sim, &data);
+        let stripe = sim.stripes().next().unwrap();
+        assert!(stripe.chunk(3).is_err());
+        assert!(stripe.chunk(4).is_ok());
+
+        sim.set_read_cache(Some(ReadCacheConfig::default()));
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+        sim.repair().unwrap();
+        assert_sim_equal(&sim, &data);
+        assert!(sim.stripes().next().unwrap().chunk(3).is_ok());
+    }
+
+    #[test]
+    fn raid6_members_can_be_resized() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        assert!(sim.resize_member(5, DRIVE_SIZE - 1).is_err());
+        sim.resize_member(5, 2 * DRIVE_SIZE).unwrap();
+        assert_eq!(sim.drive(5).size(), 2 * DRIVE_SIZE);
+        assert_sim_equal(&sim, &data);
+
+        // Only the array's part of the bigger member is used
+        let data = write_random(&mut sim);
+        assert!(sim
+            .drive(5)
+            .read_slice(DRIVE_SIZE, DRIVE_SIZE)
+            .unwrap()
+            .iter()
+            .all(|b| *b == 0));
+        sim.fail_drive(5);
+        sim.fail_drive(6);
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_sim_equal(&sim, &data);
+        assert_eq!(sim.drive(5).size(), DRIVE_SIZE);
+    }
+
+    #[test]
+    fn raid5_nth_drive_writes_stay_on_their_drive() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid5);
+        sim.write_slice_nth_drive(2, DRIVE_SIZE - 4, &[1; 4])
+            .unwrap();
+        data[3 * DRIVE_SIZE - 4..3 * DRIVE_SIZE].fill(1);
+
+        let written = sim.stats().logical_bytes_written;
+        assert!(sim
+            .write_slice_nth_drive(2, DRIVE_SIZE - 4, &[2; 5])
+            .is_err());
+        assert!(sim.write_slice_nth_drive(NUM_DRIVES - 1, 0, &[2]).is_err());
+        assert_eq!(sim.stats().logical_bytes_written, written);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_chunks_stripe_across_data_drives() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid6)
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .chunk_size(512)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let mut data = write_random(&mut sim);
+        // The array's second chunk starts the second data drive, and a whole row of chunks comes before the first's next one
+        let row = 512 * (NUM_DRIVES - 2);
+        assert_eq!(sim.drive(3).read_slice(0, 512).unwrap(), &data[512..1024]);
+        assert_eq!(
+            sim.drive(2).read_slice(512, 512).unwrap(),
+            &data[row..row + 512]
+        );
+
+        sim.write_slice_nth_drive(1, 500, &[7; 24]).unwrap();
+        data[1012..1024].fill(7);
+        data[row + 512..row + 524].fill(7);
+        assert_sim_equal(&sim, &data);
+        sim.fail_drive(3);
+        sim.fail_drive(10);
+        assert_sim_equal(&sim, &data);
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+
+        let (sim, _) = RaidSim::assemble(sim.stop(), false).unwrap();
+        assert_eq!(sim.detail().chunk_size, 512);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn stripe_major_arrays_match_drive_major() {
+        let build = |layout| {
+            let mut sim = RaidSim::builder()
+                .mode(RaidMode::Raid6)
+                .drives(NUM_DRIVES, DRIVE_SIZE)
+                .chunk_size(512)
+                .layout(layout)
+                .checksum(Some(ChecksumAlgorithm::Crc32c))
+                .seed(7)
+                .build()
+                .unwrap();
+            sim.init().unwrap();
+            sim
+        };
+        let mut sim = build(StorageLayout::StripeMajor);
+        let mut other = build(StorageLayout::DriveMajor);
+        let data = write_random(&mut sim);
+        other.write_slice(0, &data).unwrap();
+        // Reads that span stripes are copied together
+        assert_eq!(
+            sim.drive(2).read_slice(500, 24).unwrap(),
+            other.drive(2).read_slice(500, 24).unwrap()
+        );
+        assert_eq!(sim, other);
+
+        for sim in [&mut sim, &mut other] {
+            sim.fail_drive(0);
+            sim.fail_drive(5);
+            assert_sim_equal(sim, &data);
+            sim.replace_failed_drives();
+            sim.repair().unwrap();
+            assert_eq!(sim.state(), RaidState::Ok);
+        }
+        assert_eq!(sim, other);
+        assert_eq!(sim.drive(0).checksum(), other.drive(0).checksum());
+        let (sim, _) = RaidSim::assemble(sim.stop(), false).unwrap();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_unchecked_access_matches_checked() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        sim.fail_drive(4);
+        for offset in (0..data.len()).step_by(97) {
+            sim.write_unchecked(offset as u64, !data[offset]).unwrap();
+            data[offset] = !data[offset];
+        }
+        for (offset, byte) in data.iter().enumerate() {
+            assert_eq!(sim.read_unchecked(offset as u64).unwrap(), *byte);
+        }
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    #[should_panic]
+    fn raid5_unchecked_read_past_the_end_panics() {
+        let (sim, _) = init_random(RaidMode::Raid5);
+        let _ = sim.read_unchecked(sim.size());
+    }
+
+    #[test]
+    fn raid5_one_data_drive_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_p_parity_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_p_parity();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_test_init() {
+        let (sim, data) = init_random(RaidMode::Raid6);
+        assert_sim_equal(&sim, &data);
+        assert_eq!(sim.state(), RaidState::Ok);
+    }
+
+    #[test]
+    fn raid6_one_data_drive_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_p_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_p_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_q_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.fail_q_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_one_data_drive_and_p_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_p_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_one_data_drive_and_q_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_q_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_p_parity_and_q_parity_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.fail_q_parity();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_two_data_drive_failure() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_eq!(sim.failed().count(), 2);
+        assert_sim_equal(&sim, &data);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_unformatted_still_degraded() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_random();
+        sim.fail_random();
+        assert_eq!(sim.state(), RaidState::Degraded);
+        sim.replace_failed_drives();
+        assert_eq!(sim.unformatted().count(), 2);
+        assert_eq!(sim.state(), RaidState::Degraded);
+    }
+
+    #[test]
+    fn raid6_one_data_drive_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_p_parity_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_q_parity_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_q_parity();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_q_parity_and_one_data_drive_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_q_parity();
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_p_parity_and_one_data_drive_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_p_parity_and_q_parity_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.fail_q_parity();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_two_data_drive_repair() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_random_data();
+        sim.fail_random_data();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_repair_keeps_writes_made_after_replacing() {
+        let mut rng = rand::rng();
+        for failed in [[P_INDEX, 5], [4, 9], [Q_INDEX, 2]] {
+            let (mut sim, mut data) = init_random(RaidMode::Raid6);
+            for index in failed {
+                sim.fail_drive(index);
+            }
+            sim.replace_failed_drives();
+            // A byte, a slice across two data drives, a whole stripe, and a byte through the write-back cache
+            let offset = 9 * DRIVE_SIZE + 100;
+            data[offset] = !data[offset];
+            sim.write(offset as u64, data[offset]).unwrap();
+            let offset = 3 * DRIVE_SIZE - 50;
+            rng.fill(&mut data[offset..offset + 100]);
+            sim.write_slice(offset as u64, &data[offset..offset + 100])
+                .unwrap();
+            let writer = sim.stripe_writer(1).unwrap();
+            let stripe = (0..writer.data_len())
+                .map(|_| rng.random())
+                .collect::<Vec<u8>>();
+            writer.write(&stripe).unwrap();
+            for (i, chunk) in stripe.chunks(DRIVE_SIZE - STRIPE_HEIGHT).enumerate() {
+                let offset = i * DRIVE_SIZE + STRIPE_HEIGHT;
+                data[offset..offset + chunk.len()].copy_from_slice(chunk);
+            }
+            sim.set_write_back(Some(WriteBackConfig { capacity: 16 }))
+                .unwrap();
+            data[7] = !data[7];
+            sim.write(7, data[7]).unwrap();
+            assert_sim_equal(&sim, &data);
+
+            sim.repair().unwrap();
+            assert_eq!(sim.state(), RaidState::Ok, "{:?}", failed);
+            assert_sim_equal(&sim, &data);
+            sim.flush().unwrap();
+            assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn raid6_hang_below_timeout_is_waited_on() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_drive_timeout(Some(30_000));
+        sim.drive_mut(2).set_hang(Some(Hang {
+            one_in: 1,
+            duration: 5_000,
+        }));
+        assert_sim_equal(&sim, &data);
+        assert_eq!(sim.state(), RaidState::Ok);
+        let stats = sim.drive(2).stats();
+        assert_eq!(stats.hangs, DRIVE_SIZE as u64);
+        assert_eq!(stats.hang_time, DRIVE_SIZE as u64 * 5_000);
+        assert_eq!(stats.timeouts, 0);
+    }
+
+    #[test]
+    fn raid6_hang_past_timeout_kicks_drive() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_drive_timeout(Some(30_000));
+        sim.drive_mut(2).set_hang(Some(Hang {
+            one_in: 1,
+            duration: 60_000,
+        }));
+        // The healthy but slow drive gets kicked, its data is served from parity instead
+        assert_sim_equal(&sim, &data);
+        assert!(sim.drive(2).has_failed());
+        assert_eq!(sim.drive(2).stats().timeouts, 1);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        let data = write_random(&mut sim);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_latent_error_falls_back_to_parity() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.drive_mut(2).add_latent_error(1);
+        assert_sim_equal(&sim, &data);
+        let stats = sim.drive(2).stats();
+        assert_eq!(stats.fallbacks, (DRIVE_SIZE - SECTOR_SIZE) as u64);
+        assert_eq!(stats.retries, 3 * stats.fallbacks);
+        assert!(sim.drive(2).bad_sectors().is_empty());
+        assert_eq!(sim.state(), RaidState::Ok);
+    }
+
+    #[test]
+    fn raid6_latent_error_marked_bad_until_rewritten() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_retry_policy(RetryPolicy {
+            retries: 1,
+            backoff: 10,
+            exhausted: OnExhausted::MarkBad,
+        });
+        sim.drive_mut(2).add_latent_error(0);
+        assert_sim_equal(&sim, &data);
+        let stats = sim.drive(2).stats();
+        // Only the first read tried the drive, the rest went straight to parity
+        assert_eq!(stats.retries, 1);
+        assert_eq!(stats.backoff_time, 10);
+        assert_eq!(stats.marked_bad, 1);
+        assert_eq!(sim.drive(2).bad_sectors(), vec![0]);
+
+        let data = write_random(&mut sim);
+        assert!(sim.drive(2).bad_sectors().is_empty());
+        assert_eq!(sim.drive(2).latent_errors().count(), 0);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_long_backoff_saturates() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_retry_policy(RetryPolicy {
+            retries: 70,
+            backoff: 10,
+            exhausted: OnExhausted::MarkBad,
+        });
+        sim.drive_mut(2).add_latent_error(0);
+        assert_sim_equal(&sim, &data);
+        let stats = sim.drive(2).stats();
+        assert_eq!(stats.retries, 70);
+        assert_eq!(stats.backoff_time, u64::MAX);
+    }
+
+    #[test]
+    fn raid5_flaky_drive_recovers_with_retries() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_retry_policy(RetryPolicy {
+            retries: 64,
+            backoff: 0,
+            exhausted: OnExhausted::Reconstruct,
+        });
+        sim.drive_mut(3).set_flaky(Some(2));
+        assert_sim_equal(&sim, &data);
+        let stats = sim.drive(3).stats();
+        assert!(stats.retries > 0);
+        assert_eq!(stats.fallbacks, 0);
+    }
+
+    #[test]
+    fn raid5_checksums_catch_compensating_flips() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_checksum(Some(ChecksumAlgorithm::Crc64Nvme));
+        // Two flips of the same bit cancel out in an XOR checksum
+        sim.drive_mut(4).corrupt(10, 0x01);
+        sim.drive_mut(4).corrupt(20, 0x01);
+        assert!(!sim.drive(4).verify_sector(0));
+        assert_sim_equal(&sim, &data);
+        assert!(sim.drive(4).stats().fallbacks > 0);
+
+        // Replacements keep checksums like the rest of the members
+        sim.drive_mut(4).fail();
+        sim.replace_failed_drives();
+        sim.repair().unwrap();
+        assert_eq!(
+            sim.drive(4).checksum_algorithm(),
+            Some(ChecksumAlgorithm::Crc64Nvme)
+        );
+        assert!(sim.drive(4).verify_sector(0));
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_battle_test() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        // Check initial state
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+
+        // Do this 10 times just for good measure
+        for _ in 0..10 {
+            // Fail one drive, test degraded read and write works
+            sim.fail_random();
+            assert_eq!(sim.state(), RaidState::Degraded);
+            let data = write_random(&mut sim);
+            assert_sim_equal(&sim, &data);
+
+            // Fail another drive, test degraded read and write works
+            sim.fail_random();
+            assert_eq!(sim.state(), RaidState::Degraded);
+            let data = write_random(&mut sim);
+            assert_sim_equal(&sim, &data);
+
+            // Replace dead drives
+            sim.replace_failed_drives();
+            assert_eq!(sim.state(), RaidState::Degraded);
+
+            // Test we can still (degraded) write despite the drives not being failed anymore
+            assert_sim_equal(&sim, &data);
+            let data = write_random(&mut sim);
+            assert_sim_equal(&sim, &data);
+
+            // Repair
+            sim.repair().unwrap();
+            assert_eq!(sim.state(), RaidState::Ok);
+            assert_sim_equal(&sim, &data);
+            let data = write_random(&mut sim);
+            assert_sim_equal(&sim, &data);
+        }
+    }
+}
@@ -0,0 +1,1060 @@
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use super::{
+    ChaosConfig, OnExhausted, ParityUpdate, PromotionOrder, RaidSim, RaidSimBuilder, RetryPolicy,
+    WriteBackConfig,
+};
+use crate::{
+    checksum::ChecksumAlgorithm,
+    codec::{read_header, write_header, Decoder, Encoder},
+    protection::{ProtectionInfo, TagField},
+};
+
+/// Marks the start of a serialized op log
+const OP_LOG_MAGIC: &[u8; 8] = b"RAIDOPS\0";
+
+/// Version of the serialized op log format written by `OpLog::to_bytes()`, older versions can still be read.
+///
+/// Version 3 records the chunk size the array was built with, before it every array had a chunk per member
+/// and the size kept in its place went unused.
+pub const OP_LOG_VERSION: u16 = 3;
+
+/// A public operation applied to an array, with everything needed to apply it again
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Op {
+    Init,
+    Tick {
+        ms: u64,
+    },
+    Write {
+        offset: u64,
+        data: u8,
+    },
+    WriteSlice {
+        offset: u64,
+        data: Vec<u8>,
+    },
+    FailDrive {
+        index: usize,
+    },
+    FailRandom,
+    FailRandomData,
+    FailPParity,
+    FailQParity,
+    ReplaceFailedDrives,
+    Repair,
+    StartRepair,
+    StartRebuild,
+    RebuildStep {
+        stripes: usize,
+    },
+    PauseRebuild,
+    ResumeRebuild,
+    CancelRebuild,
+    Rebuild,
+    ScrubStep {
+        bytes: usize,
+    },
+    PatrolStep {
+        bytes: usize,
+    },
+    Flush,
+    FlushParity,
+    SetWriteProtected {
+        index: usize,
+        protected: bool,
+    },
+    LoseKey {
+        index: usize,
+    },
+    Chaos {
+        seed: u64,
+        steps: usize,
+        config: ChaosConfig,
+    },
+    Evacuate {
+        index: usize,
+    },
+    ResizeMember {
+        index: usize,
+        size: usize,
+    },
+    WriteStripe {
+        index: usize,
+        data: Vec<u8>,
+    },
+    /// The interleaving is picked by a generator seeded with `seed`, drawn from the one the writes were issued with
+    WriteConcurrent {
+        initiators: Vec<Vec<(u64, u8)>>,
+        locking: bool,
+        seed: u64,
+    },
+    /// Spares taken from a global pool aren't in the log, promoting one leaves it unable to be replayed
+    PromoteSpares,
+    RemoveDrive {
+        index: usize,
+    },
+    SetParityUpdate {
+        update: ParityUpdate,
+    },
+    SetWriteBack {
+        config: Option<WriteBackConfig>,
+    },
+    CorrectRow {
+        drive_offset: usize,
+    },
+    WriteSliceNthDrive {
+        drive_index: usize,
+        drive_offset: usize,
+        data: Vec<u8>,
+    },
+    SetChecksum {
+        algorithm: Option<ChecksumAlgorithm>,
+    },
+    SetMerkle {
+        block_size: Option<usize>,
+    },
+    SetEncryption {
+        enabled: bool,
+    },
+    SetIntegrity {
+        algorithm: Option<ChecksumAlgorithm>,
+    },
+    SetProtection {
+        enabled: bool,
+    },
+    WriteProtected {
+        offset: u64,
+        data: Vec<u8>,
+        info: Vec<ProtectionInfo>,
+    },
+    InjectTagError {
+        sector: usize,
+        field: TagField,
+    },
+    RepairCorruptBlocks,
+    SetRetryPolicy {
+        policy: RetryPolicy,
+    },
+    SetDriveTimeout {
+        timeout: Option<u64>,
+    },
+    SetPromotionOrder {
+        order: PromotionOrder,
+    },
+    SetScrubRate {
+        rate: usize,
+    },
+    PauseScrub,
+    ResumeScrub,
+    SetPatrolRate {
+        rate: usize,
+    },
+    SetMemoryBudget {
+        budget: Option<usize>,
+    },
+    Grow,
+}
+
+/// Every operation applied to an array since it was built, along with how it was built.
+///
+/// The builder always carries a seed, so everything the array picked at random comes out the same when the log
+/// is replayed. Drives reached into with `drive_mut()` and faults that fire at random on their own aren't recorded,
+/// and neither are settings that only change how the array is watched, like the read cache and observers.
+/// Drives handed to the array from outside, as spares or to re-add, can't be recorded, once one has been
+/// the log can no longer be replayed or serialized.
+#[derive(Debug, Clone)]
+pub struct OpLog {
+    builder: RaidSimBuilder,
+    ops: Vec<Op>,
+    /// The first operation that took a drive from outside the array, if there was one
+    unreplayable: Option<&'static str>,
+    /// Set while an operation is running, so the operations it's made of aren't recorded as well
+    busy: Rc<Cell<bool>>,
+}
+
+/// Marks an operation as running until it's dropped
+pub(super) struct Recording(Rc<Cell<bool>>);
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+impl OpLog {
+    pub(super) fn new(builder: RaidSimBuilder) -> Self {
+        Self {
+            builder,
+            ops: vec![],
+            unreplayable: None,
+            busy: Rc::default(),
+        }
+    }
+
+    /// Fails if an operation took a drive from outside the array, which the log has no way of reproducing
+    fn check_replayable(&self) -> Result<()> {
+        if let Some(op) = self.unreplayable {
+            bail!(
+                "{} took a drive from outside the array, the op log can't reproduce it",
+                op
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the builder the array was built with
+    pub fn builder(&self) -> &RaidSimBuilder {
+        &self.builder
+    }
+
+    /// Returns the operations in the order they were applied
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+}
+
+impl Op {
+    fn encode(&self, out: &mut Encoder) {
+        match self {
+            Op::Init => out.u8(0),
+            Op::Tick { ms } => {
+                out.u8(1);
+                out.u64(*ms);
+            }
+            Op::Write { offset, data } => {
+                out.u8(2);
+                out.u64(*offset);
+                out.u8(*data);
+            }
+            Op::WriteSlice { offset, data } => {
+                out.u8(3);
+                out.u64(*offset);
+                out.bytes(data);
+            }
+            Op::FailDrive { index } => {
+                out.u8(4);
+                out.u64(*index as u64);
+            }
+            Op::FailRandom => out.u8(5),
+            Op::FailRandomData => out.u8(6),
+            Op::FailPParity => out.u8(7),
+            Op::FailQParity => out.u8(8),
+            Op::ReplaceFailedDrives => out.u8(9),
+            Op::Repair => out.u8(10),
+            Op::StartRepair => out.u8(11),
+            Op::StartRebuild => out.u8(12),
+            Op::RebuildStep { stripes } => {
+                out.u8(13);
+                out.u64(*stripes as u64);
+            }
+            Op::PauseRebuild => out.u8(14),
+            Op::ResumeRebuild => out.u8(15),
+            Op::CancelRebuild => out.u8(16),
+            Op::Rebuild => out.u8(17),
+            Op::ScrubStep { bytes } => {
+                out.u8(18);
+                out.u64(*bytes as u64);
+            }
+            Op::PatrolStep { bytes } => {
+                out.u8(19);
+                out.u64(*bytes as u64);
+            }
+            Op::Flush => out.u8(20),
+            Op::FlushParity => out.u8(21),
+            Op::SetWriteProtected { index, protected } => {
+                out.u8(22);
+                out.u64(*index as u64);
+                out.bool(*protected);
+            }
+            Op::LoseKey { index } => {
+                out.u8(23);
+                out.u64(*index as u64);
+            }
+            Op::Chaos {
+                seed,
+                steps,
+                config,
+            } => {
+                out.u8(24);
+                out.u64(*seed);
+                out.u64(*steps as u64);
+                for weight in [
+                    config.writes,
+                    config.reads,
+                    config.failures,
+                    config.replacements,
+                    config.repairs,
+                    config.scrubs,
+                ] {
+                    out.u64(weight as u64);
+                }
+                out.u64(config.max_len as u64);
+                out.option(config.max_missing.map(|m| m as u64));
+            }
+            Op::Evacuate { index } => {
+                out.u8(25);
+                out.u64(*index as u64);
+            }
+            Op::ResizeMember { index, size } => {
+                out.u8(26);
+                out.u64(*index as u64);
+                out.u64(*size as u64);
+            }
+            Op::WriteStripe { index, data } => {
+                out.u8(27);
+                out.u64(*index as u64);
+                out.bytes(data);
+            }
+            Op::WriteConcurrent {
+                initiators,
+                locking,
+                seed,
+            } => {
+                out.u8(28);
+                out.u64(initiators.len() as u64);
+                for writes in initiators {
+                    out.u64(writes.len() as u64);
+                    for &(offset, data) in writes {
+                        out.u64(offset);
+                        out.u8(data);
+                    }
+                }
+                out.bool(*locking);
+                out.u64(*seed);
+            }
+            Op::PromoteSpares => out.u8(29),
+            Op::RemoveDrive { index } => {
+                out.u8(30);
+                out.u64(*index as u64);
+            }
+            Op::SetParityUpdate { update } => {
+                out.u8(31);
+                encode_parity_update(out, *update);
+            }
+            Op::SetWriteBack { config } => {
+                out.u8(32);
+                out.option(config.map(|c| c.capacity as u64));
+            }
+            Op::CorrectRow { drive_offset } => {
+                out.u8(33);
+                out.u64(*drive_offset as u64);
+            }
+            Op::WriteSliceNthDrive {
+                drive_index,
+                drive_offset,
+                data,
+            } => {
+                out.u8(34);
+                out.u64(*drive_index as u64);
+                out.u64(*drive_offset as u64);
+                out.bytes(data);
+            }
+            Op::SetChecksum { algorithm } => {
+                out.u8(35);
+                encode_checksum(out, *algorithm);
+            }
+            Op::SetMerkle { block_size } => {
+                out.u8(36);
+                out.option(block_size.map(|b| b as u64));
+            }
+            Op::SetEncryption { enabled } => {
+                out.u8(37);
+                out.bool(*enabled);
+            }
+            Op::SetIntegrity { algorithm } => {
+                out.u8(38);
+                encode_checksum(out, *algorithm);
+            }
+            Op::SetProtection { enabled } => {
+                out.u8(39);
+                out.bool(*enabled);
+            }
+            Op::WriteProtected { offset, data, info } => {
+                out.u8(40);
+                out.u64(*offset);
+                out.bytes(data);
+                out.u64(info.len() as u64);
+                for info in info {
+                    out.u64(info.guard as u64);
+                    out.u64(info.app_tag as u64);
+                    out.u64(info.ref_tag as u64);
+                }
+            }
+            Op::InjectTagError { sector, field } => {
+                out.u8(41);
+                out.u64(*sector as u64);
+                out.u8(match field {
+                    TagField::Guard => 0,
+                    TagField::App => 1,
+                    TagField::Ref => 2,
+                });
+            }
+            Op::RepairCorruptBlocks => out.u8(42),
+            Op::SetRetryPolicy { policy } => {
+                out.u8(43);
+                encode_retry_policy(out, *policy);
+            }
+            Op::SetDriveTimeout { timeout } => {
+                out.u8(44);
+                out.option(*timeout);
+            }
+            Op::SetPromotionOrder { order } => {
+                out.u8(45);
+                encode_promotion_order(out, *order);
+            }
+            Op::SetScrubRate { rate } => {
+                out.u8(46);
+                out.u64(*rate as u64);
+            }
+            Op::PauseScrub => out.u8(47),
+            Op::ResumeScrub => out.u8(48),
+            Op::SetPatrolRate { rate } => {
+                out.u8(49);
+                out.u64(*rate as u64);
+            }
+            Op::SetMemoryBudget { budget } => {
+                out.u8(50);
+                out.option(budget.map(|b| b as u64));
+            }
+            Op::Grow => out.u8(51),
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Op> {
+        Ok(match input.u8()? {
+            0 => Op::Init,
+            1 => Op::Tick { ms: input.u64()? },
+            2 => Op::Write {
+                offset: input.u64()?,
+                data: input.u8()?,
+            },
+            3 => Op::WriteSlice {
+                offset: input.u64()?,
+                data: input.bytes()?.to_vec(),
+            },
+            4 => Op::FailDrive {
+                index: input.usize()?,
+            },
+            5 => Op::FailRandom,
+            6 => Op::FailRandomData,
+            7 => Op::FailPParity,
+            8 => Op::FailQParity,
+            9 => Op::ReplaceFailedDrives,
+            10 => Op::Repair,
+            11 => Op::StartRepair,
+            12 => Op::StartRebuild,
+            13 => Op::RebuildStep {
+                stripes: input.usize()?,
+            },
+            14 => Op::PauseRebuild,
+            15 => Op::ResumeRebuild,
+            16 => Op::CancelRebuild,
+            17 => Op::Rebuild,
+            18 => Op::ScrubStep {
+                bytes: input.usize()?,
+            },
+            19 => Op::PatrolStep {
+                bytes: input.usize()?,
+            },
+            20 => Op::Flush,
+            21 => Op::FlushParity,
+            22 => Op::SetWriteProtected {
+                index: input.usize()?,
+                protected: input.bool()?,
+            },
+            23 => Op::LoseKey {
+                index: input.usize()?,
+            },
+            24 => Op::Chaos {
+                seed: input.u64()?,
+                steps: input.usize()?,
+                config: ChaosConfig {
+                    writes: input.u32()?,
+                    reads: input.u32()?,
+                    failures: input.u32()?,
+                    replacements: input.u32()?,
+                    repairs: input.u32()?,
+                    scrubs: input.u32()?,
+                    max_len: input.usize()?,
+                    max_missing: input.option()?.map(|m| m as usize),
+                },
+            },
+            25 => Op::Evacuate {
+                index: input.usize()?,
+            },
+            26 => Op::ResizeMember {
+                index: input.usize()?,
+                size: input.usize()?,
+            },
+            27 => Op::WriteStripe {
+                index: input.usize()?,
+                data: input.bytes()?.to_vec(),
+            },
+            28 => Op::WriteConcurrent {
+                initiators: (0..input.u64()?)
+                    .map(|_| {
+                        (0..input.u64()?)
+                            .map(|_| Ok((input.u64()?, input.u8()?)))
+                            .collect()
+                    })
+                    .collect::<Result<_>>()?,
+                locking: input.bool()?,
+                seed: input.u64()?,
+            },
+            29 => Op::PromoteSpares,
+            30 => Op::RemoveDrive {
+                index: input.usize()?,
+            },
+            31 => Op::SetParityUpdate {
+                update: decode_parity_update(input)?,
+            },
+            32 => Op::SetWriteBack {
+                config: input.option()?.map(|capacity| WriteBackConfig {
+                    capacity: capacity as usize,
+                }),
+            },
+            33 => Op::CorrectRow {
+                drive_offset: input.usize()?,
+            },
+            34 => Op::WriteSliceNthDrive {
+                drive_index: input.usize()?,
+                drive_offset: input.usize()?,
+                data: input.bytes()?.to_vec(),
+            },
+            35 => Op::SetChecksum {
+                algorithm: decode_checksum(input)?,
+            },
+            36 => Op::SetMerkle {
+                block_size: input.option()?.map(|b| b as usize),
+            },
+            37 => Op::SetEncryption {
+                enabled: input.bool()?,
+            },
+            38 => Op::SetIntegrity {
+                algorithm: decode_checksum(input)?,
+            },
+            39 => Op::SetProtection {
+                enabled: input.bool()?,
+            },
+            40 => Op::WriteProtected {
+                offset: input.u64()?,
+                data: input.bytes()?.to_vec(),
+                info: (0..input.u64()?)
+                    .map(|_| {
+                        Ok(ProtectionInfo {
+                            guard: input.u16()?,
+                            app_tag: input.u16()?,
+                            ref_tag: input.u32()?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            },
+            41 => Op::InjectTagError {
+                sector: input.usize()?,
+                field: match input.u8()? {
+                    0 => TagField::Guard,
+                    1 => TagField::App,
+                    2 => TagField::Ref,
+                    other => bail!("Unknown protection field {} in op log", other),
+                },
+            },
+            42 => Op::RepairCorruptBlocks,
+            43 => Op::SetRetryPolicy {
+                policy: decode_retry_policy(input)?,
+            },
+            44 => Op::SetDriveTimeout {
+                timeout: input.option()?,
+            },
+            45 => Op::SetPromotionOrder {
+                order: decode_promotion_order(input)?,
+            },
+            46 => Op::SetScrubRate {
+                rate: input.usize()?,
+            },
+            47 => Op::PauseScrub,
+            48 => Op::ResumeScrub,
+            49 => Op::SetPatrolRate {
+                rate: input.usize()?,
+            },
+            50 => Op::SetMemoryBudget {
+                budget: input.option()?.map(|b| b as usize),
+            },
+            51 => Op::Grow,
+            tag => bail!("Unknown operation {} in op log", tag),
+        })
+    }
+}
+
+pub(super) fn encode_retry_policy(out: &mut Encoder, policy: RetryPolicy) {
+    out.u64(policy.retries as u64);
+    out.u64(policy.backoff);
+    out.u8(match policy.exhausted {
+        OnExhausted::Reconstruct => 0,
+        OnExhausted::MarkBad => 1,
+    });
+}
+
+pub(super) fn decode_retry_policy(input: &mut Decoder) -> Result<RetryPolicy> {
+    Ok(RetryPolicy {
+        retries: input.u32()?,
+        backoff: input.u64()?,
+        exhausted: match input.u8()? {
+            0 => OnExhausted::Reconstruct,
+            1 => OnExhausted::MarkBad,
+            other => bail!("Unknown retry policy {} in op log", other),
+        },
+    })
+}
+
+pub(super) fn encode_promotion_order(out: &mut Encoder, order: PromotionOrder) {
+    out.u8(match order {
+        PromotionOrder::DedicatedFirst => 0,
+        PromotionOrder::GlobalFirst => 1,
+        PromotionOrder::DedicatedOnly => 2,
+    });
+}
+
+pub(super) fn decode_promotion_order(input: &mut Decoder) -> Result<PromotionOrder> {
+    Ok(match input.u8()? {
+        0 => PromotionOrder::DedicatedFirst,
+        1 => PromotionOrder::GlobalFirst,
+        2 => PromotionOrder::DedicatedOnly,
+        other => bail!("Unknown promotion order {} in op log", other),
+    })
+}
+
+pub(super) fn encode_parity_update(out: &mut Encoder, update: ParityUpdate) {
+    match update {
+        ParityUpdate::Immediate => out.u8(0),
+        ParityUpdate::Lazy { flush_interval } => {
+            out.u8(1);
+            out.option(flush_interval);
+        }
+    }
+}
+
+pub(super) fn decode_parity_update(input: &mut Decoder) -> Result<ParityUpdate> {
+    Ok(match input.u8()? {
+        0 => ParityUpdate::Immediate,
+        1 => ParityUpdate::Lazy {
+            flush_interval: input.option()?,
+        },
+        other => bail!("Unknown parity update {} in op log", other),
+    })
+}
+
+pub(super) fn encode_checksum(out: &mut Encoder, algorithm: Option<ChecksumAlgorithm>) {
+    out.u8(match algorithm {
+        None => 0,
+        Some(ChecksumAlgorithm::Crc32c) => 1,
+        Some(ChecksumAlgorithm::Crc64Nvme) => 2,
+    });
+}
+
+pub(super) fn decode_checksum(input: &mut Decoder) -> Result<Option<ChecksumAlgorithm>> {
+    Ok(match input.u8()? {
+        0 => None,
+        1 => Some(ChecksumAlgorithm::Crc32c),
+        2 => Some(ChecksumAlgorithm::Crc64Nvme),
+        other => bail!("Unknown checksum {} in op log", other),
+    })
+}
+
+impl OpLog {
+    /// Serializes the log into a compact, versioned form that can be read back on any machine with `from_bytes()`.
+    /// Fails if spare drives were handed to the builder or any operation took a drive from outside the array,
+    /// their contents aren't carried in the log.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.check_replayable()?;
+        let mut out = Encoder::default();
+        write_header(&mut out, OP_LOG_MAGIC, OP_LOG_VERSION);
+        self.builder.encode(&mut out)?;
+        out.u64(self.ops.len() as u64);
+        for op in &self.ops {
+            op.encode(&mut out);
+        }
+        Ok(out.0)
+    }
+
+    /// Reads a log serialized by `to_bytes()`
+    pub fn from_bytes(bytes: &[u8]) -> Result<OpLog> {
+        let (version, rest) = read_header(bytes, OP_LOG_MAGIC, OP_LOG_VERSION, "an op log")?;
+        let mut input = Decoder(rest);
+        let builder = RaidSimBuilder::decode(&mut input, version)?;
+        let ops = (0..input.u64()?)
+            .map(|_| Op::decode(&mut input))
+            .collect::<Result<Vec<Op>>>()?;
+        if !input.0.is_empty() {
+            bail!("{} bytes left over after the op log", input.0.len());
+        }
+        Ok(OpLog {
+            builder,
+            ops,
+            unreplayable: None,
+            busy: Rc::default(),
+        })
+    }
+}
+
+impl RaidSim {
+    /// Records `op` if the array is keeping a log and isn't already in the middle of an operation.
+    /// The operation counts as running until what's returned is dropped.
+    pub(super) fn record(&mut self, op: impl FnOnce() -> Op) -> Option<Recording> {
+        let log = self.op_log.as_mut().filter(|log| !log.busy.get())?;
+        log.ops.push(op());
+        log.busy.set(true);
+        Some(Recording(log.busy.clone()))
+    }
+
+    /// Notes that `op` took a drive from outside the array, so the log can't be replayed any more
+    pub(super) fn record_unreplayable(&mut self, op: &'static str) {
+        if let Some(log) = self.op_log.as_mut() {
+            log.unreplayable.get_or_insert(op);
+        }
+    }
+
+    /// Returns every operation applied to the array, if it was built to record them
+    pub fn op_log(&self) -> Option<&OpLog> {
+        self.op_log.as_ref()
+    }
+
+    /// Applies `op` to the array
+    fn apply(&mut self, op: &Op) -> Result<()> {
+        match op {
+            Op::Init => self.init(),
+            Op::Tick { ms } => self.tick(*ms),
+            Op::Write { offset, data } => self.write(*offset, *data),
+            Op::WriteSlice { offset, data } => self.write_slice(*offset, data),
+            Op::FailDrive { index } => {
+                self.fail_drive(*index);
+                Ok(())
+            }
+            Op::FailRandom => {
+                self.fail_random();
+                Ok(())
+            }
+            Op::FailRandomData => {
+                self.fail_random_data();
+                Ok(())
+            }
+            Op::FailPParity => {
+                self.fail_p_parity();
+                Ok(())
+            }
+            Op::FailQParity => {
+                self.fail_q_parity();
+                Ok(())
+            }
+            Op::ReplaceFailedDrives => {
+                self.replace_failed_drives();
+                Ok(())
+            }
+            Op::Repair => self.repair(),
+            Op::StartRepair => self.start_repair().map(|_| ()),
+            Op::StartRebuild => self.start_rebuild(),
+            Op::RebuildStep { stripes } => self.rebuild_step(*stripes).map(|_| ()),
+            Op::PauseRebuild => self.pause_rebuild(),
+            Op::ResumeRebuild => self.resume_rebuild(),
+            Op::CancelRebuild => self.cancel_rebuild(),
+            Op::Rebuild => self.rebuild().map(|_| ()),
+            Op::ScrubStep { bytes } => self.scrub_step(*bytes).map(|_| ()),
+            Op::PatrolStep { bytes } => self.patrol_step(*bytes).map(|_| ()),
+            Op::Flush => self.flush(),
+            Op::FlushParity => self.flush_parity().map(|_| ()),
+            Op::SetWriteProtected { index, protected } => {
+                self.set_write_protected(*index, *protected)
+            }
+            Op::LoseKey { index } => self.lose_key(*index),
+            Op::Chaos {
+                seed,
+                steps,
+                config,
+            } => {
+                // A failed run was recorded failing too, replaying it only has to fail the same way
+                let _ = self.chaos(*seed, *steps, config);
+                Ok(())
+            }
+            Op::Evacuate { index } => self.evacuate(*index).map(|_| ()),
+            Op::ResizeMember { index, size } => self.resize_member(*index, *size),
+            Op::WriteStripe { index, data } => self.stripe_writer(*index)?.write(data),
+            Op::WriteConcurrent {
+                initiators,
+                locking,
+                seed,
+            } => self
+                .write_concurrent_seeded(initiators.clone(), *locking, *seed)
+                .map(|_| ()),
+            Op::PromoteSpares => {
+                self.promote_spares(None);
+                Ok(())
+            }
+            Op::RemoveDrive { index } => {
+                self.remove_drive(*index);
+                Ok(())
+            }
+            Op::SetParityUpdate { update } => self.set_parity_update(*update),
+            Op::SetWriteBack { config } => self.set_write_back(*config),
+            Op::CorrectRow { drive_offset } => self.correct_row(*drive_offset).map(|_| ()),
+            Op::WriteSliceNthDrive {
+                drive_index,
+                drive_offset,
+                data,
+            } => self.write_slice_nth_drive(*drive_index, *drive_offset, data),
+            Op::SetChecksum { algorithm } => {
+                self.set_checksum(*algorithm);
+                Ok(())
+            }
+            Op::SetMerkle { block_size } => {
+                self.set_merkle(*block_size);
+                Ok(())
+            }
+            Op::SetEncryption { enabled } => {
+                self.set_encryption(*enabled);
+                Ok(())
+            }
+            Op::SetIntegrity { algorithm } => self.set_integrity(*algorithm),
+            Op::SetProtection { enabled } => {
+                self.set_protection(*enabled);
+                Ok(())
+            }
+            Op::WriteProtected { offset, data, info } => self.write_protected(*offset, data, info),
+            Op::InjectTagError { sector, field } => {
+                self.inject_tag_error(*sector, *field);
+                Ok(())
+            }
+            Op::RepairCorruptBlocks => self.repair_corrupt_blocks().map(|_| ()),
+            Op::SetRetryPolicy { policy } => {
+                self.set_retry_policy(*policy);
+                Ok(())
+            }
+            Op::SetDriveTimeout { timeout } => {
+                self.set_drive_timeout(*timeout);
+                Ok(())
+            }
+            Op::SetPromotionOrder { order } => {
+                self.set_promotion_order(*order);
+                Ok(())
+            }
+            Op::SetScrubRate { rate } => {
+                self.set_scrub_rate(*rate);
+                Ok(())
+            }
+            Op::PauseScrub => {
+                self.pause_scrub();
+                Ok(())
+            }
+            Op::ResumeScrub => {
+                self.resume_scrub();
+                Ok(())
+            }
+            Op::SetPatrolRate { rate } => {
+                self.set_patrol_rate(*rate);
+                Ok(())
+            }
+            Op::SetMemoryBudget { budget } => {
+                self.set_memory_budget(*budget);
+                Ok(())
+            }
+            Op::Grow => self.grow().map(|_| ()),
+        }
+    }
+
+    /// Builds the array `log` was recorded from again and applies every operation in it,
+    /// ending up in exactly the state the original was in when the log was taken.
+    /// Operations that failed when they were recorded fail again and are carried on past, like they were then.
+    /// Fails without replaying anything if an operation took a drive from outside the array.
+    pub fn replay(log: &OpLog) -> Result<RaidSim> {
+        log.check_replayable()?;
+        let mut sim = log.builder.clone().build()?;
+        for op in &log.ops {
+            let _ = sim.apply(op);
+        }
+        Ok(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::{ChecksumAlgorithm, Drive};
+
+    #[test]
+    fn raid6_replay_reproduces_end_state() {
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .record(true)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.write_slice(0, &[0xab; 3000]).unwrap();
+        sim.fail_random_data();
+        sim.fail_drive(0);
+        sim.write(50_000, 0x5c).unwrap();
+        sim.replace_failed_drives();
+        sim.start_repair().unwrap();
+        sim.rebuild_step(1).unwrap();
+        sim.write_slice(100, &[0x11; 600]).unwrap();
+        sim.rebuild().unwrap();
+        // Nothing to cancel, the failure is replayed along with everything else
+        assert!(sim.cancel_rebuild().is_err());
+        sim.chaos(3, 50, &ChaosConfig::default()).unwrap();
+        sim.tick(1000).unwrap();
+
+        let log = sim.op_log().unwrap();
+        // What an operation does on the way isn't recorded separately
+        assert_eq!(log.ops().len(), 13);
+        assert_eq!(log.ops()[5], Op::ReplaceFailedDrives);
+
+        let replayed = RaidSim::replay(log).unwrap();
+        assert_eq!(replayed.detail().array_id, sim.detail().array_id);
+        for i in 0..NUM_DRIVES {
+            assert_eq!(replayed.drive(i), sim.drive(i));
+        }
+        assert_eq!(replayed.op_log().unwrap().ops(), log.ops());
+        let data = (0..sim.size())
+            .map(|i| sim.read(i).unwrap())
+            .collect::<Vec<u8>>();
+        assert_sim_equal(&replayed, &data);
+    }
+
+    #[test]
+    fn raid6_replay_covers_every_mutator() {
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .spares(1)
+            .seed(7)
+            .record(true)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let len = sim.stripe_writer(0).unwrap().data_len();
+        sim.stripe_writer(0).unwrap().write(&vec![5; len]).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        sim.write_concurrent(vec![vec![(1, 1)], vec![(2, 2)]], false, &mut rng)
+            .unwrap();
+        sim.write_slice_nth_drive(3, 10, &[9; 4]).unwrap();
+        sim.set_checksum(Some(ChecksumAlgorithm::Crc32c));
+        sim.set_encryption(true);
+        sim.set_retry_policy(RetryPolicy {
+            retries: 1,
+            ..RetryPolicy::default()
+        });
+        sim.set_write_back(Some(WriteBackConfig { capacity: 8 }))
+            .unwrap();
+        sim.write(20, 20).unwrap();
+        sim.set_write_back(None).unwrap();
+        assert_eq!(sim.correct_row(30).unwrap(), None);
+        sim.lose_key(5).unwrap();
+        sim.remove_drive(6);
+        assert_eq!(sim.promote_spares(None), vec![5]);
+        sim.set_parity_update(ParityUpdate::Lazy {
+            flush_interval: None,
+        })
+        .unwrap();
+        sim.write(40, 40).unwrap();
+        sim.set_scrub_rate(100);
+        sim.pause_scrub();
+
+        let log = OpLog::from_bytes(&sim.op_log().unwrap().to_bytes().unwrap()).unwrap();
+        assert_eq!(log.ops(), sim.op_log().unwrap().ops());
+        let replayed = RaidSim::replay(&log).unwrap();
+        assert_eq!(replayed.read(0).unwrap(), 5);
+        assert!(replayed == sim);
+    }
+
+    #[test]
+    fn raid5_drives_from_outside_stop_replay() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid5)
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .record(true)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let mut pool = SparePool::new();
+        assert!(sim.promote_spares(Some(&mut pool)).is_empty());
+        assert!(RaidSim::replay(sim.op_log().unwrap()).is_ok());
+
+        sim.add_spare(Drive::empty(DRIVE_SIZE)).unwrap();
+        let log = sim.op_log().unwrap();
+        assert!(RaidSim::replay(log)
+            .unwrap_err()
+            .to_string()
+            .contains("add_spare"));
+        assert!(log.to_bytes().is_err());
+    }
+
+    #[test]
+    fn raid5_op_log_round_trips_through_bytes() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid5)
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .spares(1)
+            .seed(5)
+            .parity_update(ParityUpdate::Lazy {
+                flush_interval: Some(100),
+            })
+            .record(true)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let config = ChaosConfig {
+            max_missing: Some(1),
+            ..ChaosConfig::default()
+        };
+        // Parity left out of date with a member missing can't be repaired, the kind of trace that gets reported
+        let failure = sim.chaos(11, 100, &config).unwrap_err();
+        assert!(failure.error.contains("out of date parity"));
+        sim.set_write_protected(3, true).unwrap();
+        sim.tick(250).unwrap();
+
+        let bytes = sim.op_log().unwrap().to_bytes().unwrap();
+        assert!(bytes.starts_with(b"RAIDOPS\0"));
+        let log = OpLog::from_bytes(&bytes).unwrap();
+        assert_eq!(log.ops(), sim.op_log().unwrap().ops());
+        let replayed = RaidSim::replay(&log).unwrap();
+        for i in 0..NUM_DRIVES {
+            assert_eq!(replayed.drive(i), sim.drive(i));
+        }
+
+        // Anything cut short, from a newer version, or not an op log at all is refused
+        assert!(OpLog::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut newer = bytes.clone();
+        newer[8] = OP_LOG_VERSION as u8 + 1;
+        assert!(OpLog::from_bytes(&newer)
+            .unwrap_err()
+            .to_string()
+            .contains("version"));
+        assert!(OpLog::from_bytes(b"not a log").is_err());
+
+        let with_spare = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .spare(crate::Drive::empty(DRIVE_SIZE))
+            .record(true)
+            .build()
+            .unwrap();
+        assert!(with_spare.op_log().unwrap().to_bytes().is_err());
+
+        let mut chunked = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .chunk_size(512)
+            .seed(5)
+            .record(true)
+            .build()
+            .unwrap();
+        chunked.init().unwrap();
+        chunked.write_slice(300, &[9; 1000]).unwrap();
+        let log = OpLog::from_bytes(&chunked.op_log().unwrap().to_bytes().unwrap()).unwrap();
+        let replayed = RaidSim::replay(&log).unwrap();
+        assert_eq!(replayed.detail().chunk_size, 512);
+        for i in 0..NUM_DRIVES {
+            assert_eq!(replayed.drive(i), chunked.drive(i));
+        }
+    }
+}
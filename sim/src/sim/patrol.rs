@@ -0,0 +1,164 @@
+use anyhow::Result;
+
+use crate::drive::SECTOR_SIZE;
+
+use super::{Op, RaidSim};
+
+/// Summary of what a patrol read came across
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PatrolReport {
+    /// Number of bytes scanned on each member
+    pub scanned: usize,
+    /// Number of times the patrol wrapped around to the start of the drives
+    pub passes: u64,
+    /// Unreadable sectors found, as (drive index, sector)
+    pub found: Vec<(usize, usize)>,
+    /// Unreadable sectors that were rewritten from parity, as (drive index, sector)
+    pub fixed: Vec<(usize, usize)>,
+}
+
+impl PatrolReport {
+    fn merge(&mut self, other: &PatrolReport) {
+        self.scanned += other.scanned;
+        self.passes += other.passes;
+        self.found.extend_from_slice(&other.found);
+        self.fixed.extend_from_slice(&other.fixed);
+    }
+}
+
+/// Progress of the background media scan
+#[derive(Debug, Clone, Default)]
+pub(super) struct Patrol {
+    /// Bytes scanned per simulated second on each member, zero when not scheduled
+    rate: usize,
+    /// Drive offset the next step starts scanning from
+    position: usize,
+    /// Scan budget carried over between ticks, in bytes per thousand
+    credit: usize,
+    /// Everything found since the array was created
+    total: PatrolReport,
+}
+
+impl Patrol {
+    /// Works out how many bytes `ms` simulated milliseconds pays for at the scheduled rate
+    pub(super) fn budget(&mut self, ms: u64) -> usize {
+        self.credit += self.rate * ms as usize;
+        let bytes = self.credit / 1000;
+        self.credit %= 1000;
+        bytes
+    }
+}
+
+impl RaidSim {
+    /// Schedules the patrol read to scan `rate` bytes of every member per simulated second as the clock ticks.
+    /// A rate of zero stops it.
+    pub fn set_patrol_rate(&mut self, rate: usize) {
+        let _recording = self.record(|| Op::SetPatrolRate { rate });
+        self.patrol.rate = rate;
+        self.patrol.credit = 0;
+    }
+
+    /// Returns everything the patrol read has found since the array was created
+    pub fn patrol_report(&self) -> &PatrolReport {
+        &self.patrol.total
+    }
+
+    /// Scans the next `bytes` bytes of every usable member for sectors that can't be read.
+    /// Unreadable sectors are rewritten from parity so they are fixed before a rebuild needs them.
+    pub fn patrol_step(&mut self, bytes: usize) -> Result<PatrolReport> {
+        let _recording = self.record(|| Op::PatrolStep { bytes });
+        let mut report = PatrolReport::default();
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let start = self.patrol.position;
+            let end = (start + remaining).min(self.drive_size);
+            self.patrol_range(start, end, &mut report)?;
+            report.scanned += end - start;
+            remaining -= end - start;
+            if end == self.drive_size {
+                self.patrol.position = 0;
+                report.passes += 1;
+            } else {
+                self.patrol.position = end;
+            }
+        }
+        self.patrol.total.merge(&report);
+        Ok(report)
+    }
+
+    /// Scans drive offsets `start..end` of every usable member one sector at a time
+    fn patrol_range(&mut self, start: usize, end: usize, report: &mut PatrolReport) -> Result<()> {
+        for index in 0..self.drives.len() {
+            let mut offset = start;
+            while offset < end {
+                let len = (SECTOR_SIZE - offset % SECTOR_SIZE).min(end - offset);
+                let drive = &self.drives[index];
+                // A drive that gets kicked while being scanned hasn't got a bad sector, it's just gone
+                if drive.usable()
+                    && drive.read_range(offset..offset + len).is_err()
+                    && drive.usable()
+                {
+                    let sector = offset / SECTOR_SIZE;
+                    report.found.push((index, sector));
+                    if self.patrol_fix(index, sector).is_ok() {
+                        report.fixed.push((index, sector));
+                    }
+                }
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites `sector` of the drive at `index` with what parity says it should contain
+    fn patrol_fix(&mut self, index: usize, sector: usize) -> Result<()> {
+        let start = sector * SECTOR_SIZE;
+        let end = (start + SECTOR_SIZE).min(self.drive_size);
+        let contents = self.member_range(index, start, end)?;
+        self.write_member(index, start, &contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_patrol_fixes_latent_errors() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.drive_mut(P_INDEX).add_latent_error(1);
+        sim.drive_mut(Q_INDEX).add_latent_error(0);
+        sim.drive_mut(5).add_latent_error(1);
+        let report = sim.patrol_step(DRIVE_SIZE).unwrap();
+        assert_eq!(report.scanned, DRIVE_SIZE);
+        assert_eq!(report.passes, 1);
+        assert_eq!(report.found, vec![(P_INDEX, 1), (Q_INDEX, 0), (5, 1)]);
+        assert_eq!(report.found, report.fixed);
+        for i in 0..NUM_DRIVES {
+            assert_eq!(sim.drive(i).latent_errors().count(), 0);
+        }
+
+        // With the errors gone, losing two drives doesn't run into them
+        sim.fail_p_parity();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_patrol_follows_clock() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.drive_mut(3).add_latent_error(1);
+        sim.set_patrol_rate(256);
+        sim.tick(2_000).unwrap();
+        assert_eq!(sim.patrol_report().scanned, 512);
+        assert!(sim.patrol_report().found.is_empty());
+        // Half a second only pays for half of the remaining sector
+        sim.tick(1_000).unwrap();
+        sim.tick(500).unwrap();
+        assert_eq!(sim.patrol_report().scanned, 896);
+        assert_eq!(sim.patrol_report().fixed, vec![(3, 1)]);
+        assert_eq!(sim.now(), 3_500);
+        assert_sim_equal(&sim, &data);
+    }
+}
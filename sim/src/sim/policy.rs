@@ -0,0 +1,224 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Debug,
+};
+
+/// Decides which block a cache drops when it runs out of room.
+///
+/// The cache tells the policy about every block it adds, reads, and drops, and asks it for a victim when full.
+pub trait CachePolicy: Debug {
+    /// Name the policy's statistics are kept under
+    fn name(&self) -> &'static str;
+    /// A block was added to the cache
+    fn inserted(&mut self, block: usize);
+    /// A cached block was read
+    fn accessed(&mut self, block: usize);
+    /// A block was dropped from the cache without being evicted, like when it's overwritten
+    fn removed(&mut self, block: usize);
+    /// Picks a cached block to evict and forgets about it, `None` if nothing is cached
+    fn evict(&mut self) -> Option<usize>;
+}
+
+/// The eviction policies that come with the simulator
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EvictionPolicy {
+    /// Evicts the block that was read least recently
+    Lru,
+    /// Evicts the block that was added first, no matter how often it's read
+    Fifo,
+    /// A simplified adaptive replacement cache, which balances blocks read once against blocks read again
+    /// so a single long scan doesn't flush out the blocks that keep getting read
+    ArcLite,
+}
+
+impl EvictionPolicy {
+    pub(super) fn build(self) -> Box<dyn CachePolicy> {
+        match self {
+            EvictionPolicy::Lru => Box::<Lru>::default(),
+            EvictionPolicy::Fifo => Box::<Fifo>::default(),
+            EvictionPolicy::ArcLite => Box::<ArcLite>::default(),
+        }
+    }
+}
+
+/// Removes `block` from `list`, returning whether it was there
+fn take(list: &mut VecDeque<usize>, block: usize) -> bool {
+    match list.iter().position(|b| *b == block) {
+        Some(i) => {
+            list.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Least recently used
+#[derive(Debug, Default)]
+pub struct Lru {
+    /// Cached blocks from least to most recently used
+    order: VecDeque<usize>,
+}
+
+impl CachePolicy for Lru {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn inserted(&mut self, block: usize) {
+        self.order.push_back(block);
+    }
+
+    fn accessed(&mut self, block: usize) {
+        take(&mut self.order, block);
+        self.order.push_back(block);
+    }
+
+    fn removed(&mut self, block: usize) {
+        take(&mut self.order, block);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        self.order.pop_front()
+    }
+}
+
+/// First in, first out
+#[derive(Debug, Default)]
+pub struct Fifo {
+    /// Cached blocks in the order they were added
+    order: VecDeque<usize>,
+}
+
+impl CachePolicy for Fifo {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn inserted(&mut self, block: usize) {
+        self.order.push_back(block);
+    }
+
+    fn accessed(&mut self, _block: usize) {}
+
+    fn removed(&mut self, block: usize) {
+        take(&mut self.order, block);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        self.order.pop_front()
+    }
+}
+
+/// Adaptive replacement without the bookkeeping of the full algorithm.
+///
+/// Blocks read once live in `recent` and move to `frequent` when read again. Evicted blocks are remembered
+/// in a ghost list for a while, and a ghost coming back nudges `target`, the share of the cache given to `recent`,
+/// towards whichever list it was evicted from.
+#[derive(Debug, Default)]
+pub struct ArcLite {
+    recent: VecDeque<usize>,
+    frequent: VecDeque<usize>,
+    recent_ghosts: VecDeque<usize>,
+    frequent_ghosts: VecDeque<usize>,
+    /// Number of cached blocks `recent` may hold before it's evicted from first
+    target: usize,
+    /// Blocks that have been cached, to bound the ghost lists
+    resident: BTreeSet<usize>,
+}
+
+impl CachePolicy for ArcLite {
+    fn name(&self) -> &'static str {
+        "arc-lite"
+    }
+
+    fn inserted(&mut self, block: usize) {
+        self.resident.insert(block);
+        if take(&mut self.recent_ghosts, block) {
+            // Evicted from `recent` too soon, give it more room
+            self.target = (self.target + 1).min(self.resident.len());
+            self.frequent.push_back(block);
+        } else if take(&mut self.frequent_ghosts, block) {
+            self.target = self.target.saturating_sub(1);
+            self.frequent.push_back(block);
+        } else {
+            self.recent.push_back(block);
+        }
+    }
+
+    fn accessed(&mut self, block: usize) {
+        if take(&mut self.recent, block) || take(&mut self.frequent, block) {
+            self.frequent.push_back(block);
+        }
+    }
+
+    fn removed(&mut self, block: usize) {
+        self.resident.remove(&block);
+        take(&mut self.recent, block);
+        take(&mut self.frequent, block);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        let (block, ghosts) = if !self.recent.is_empty()
+            && (self.recent.len() > self.target || self.frequent.is_empty())
+        {
+            (self.recent.pop_front()?, &mut self.recent_ghosts)
+        } else {
+            (self.frequent.pop_front()?, &mut self.frequent_ghosts)
+        };
+        ghosts.push_back(block);
+        let limit = self.resident.len().max(1);
+        while ghosts.len() > limit {
+            ghosts.pop_front();
+        }
+        self.resident.remove(&block);
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a cache of `capacity` blocks over `reads`, returning how many were hits
+    fn hits(policy: EvictionPolicy, capacity: usize, reads: &[usize]) -> usize {
+        let mut policy = policy.build();
+        let mut cached = BTreeSet::new();
+        let mut hits = 0;
+        for &block in reads {
+            if cached.contains(&block) {
+                hits += 1;
+                policy.accessed(block);
+                continue;
+            }
+            if cached.len() == capacity {
+                cached.remove(&policy.evict().unwrap());
+            }
+            cached.insert(block);
+            policy.inserted(block);
+        }
+        hits
+    }
+
+    #[test]
+    fn lru_keeps_reread_blocks_fifo_does_not() {
+        let reads = [0, 1, 0, 2, 0, 3, 0];
+        assert_eq!(hits(EvictionPolicy::Lru, 2, &reads), 3);
+        assert_eq!(hits(EvictionPolicy::Fifo, 2, &reads), 2);
+    }
+
+    #[test]
+    fn arc_lite_survives_a_scan() {
+        // A small hot set read over and over, with a long one-off scan through the middle
+        let mut reads = vec![];
+        for _ in 0..4 {
+            reads.extend([0, 1, 2]);
+        }
+        reads.extend(100..120);
+        for _ in 0..4 {
+            reads.extend([0, 1, 2]);
+        }
+        let lru = hits(EvictionPolicy::Lru, 6, &reads);
+        let arc = hits(EvictionPolicy::ArcLite, 6, &reads);
+        assert!(arc > lru, "arc-lite {} lru {}", arc, lru);
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    drive::SECTOR_SIZE,
+    protection::{ProtectionInfo, TagField},
+};
+
+use super::{Access, Op, RaidError, RaidSim, RaidState};
+
+impl RaidSim {
+    /// Starts or stops keeping protection information end to end.
+    ///
+    /// Every member stores it with each of its sectors and checks it as they're read, so a damaged sector
+    /// falls back to parity like one with a bad checksum. The array keeps what the host handed it with
+    /// `write_protected()` and checks what it hands back from `read_protected()`, caught at whichever hop broke it.
+    pub fn set_protection(&mut self, enabled: bool) {
+        let _recording = self.record(|| Op::SetProtection { enabled });
+        self.protection = enabled.then(|| vec![None; self.data_len().div_ceil(SECTOR_SIZE)]);
+        for d in &mut self.drives {
+            d.set_protection(enabled);
+        }
+    }
+
+    /// Checks that protection information is being kept and `offset..offset + len` lines up with the array's sectors,
+    /// returning the first sector
+    fn protected_sectors(&self, offset: u64, len: usize) -> Result<usize> {
+        if self.protection.is_none() {
+            bail!("Array isn't keeping protection information");
+        }
+        let offset = self.logical_offset(offset, len)?;
+        if offset % SECTOR_SIZE != 0 {
+            bail!(
+                "Offset {} doesn't start a sector, protection information covers whole sectors",
+                offset
+            );
+        }
+        Ok(offset / SECTOR_SIZE)
+    }
+
+    /// Writes `data` at array offset `offset` along with the protection information of each sector it covers.
+    /// The information is checked against the data before anything is written, and kept to check reads against.
+    pub fn write_protected(
+        &mut self,
+        offset: u64,
+        data: &[u8],
+        info: &[ProtectionInfo],
+    ) -> Result<()> {
+        let _recording = self.record(|| Op::WriteProtected {
+            offset,
+            data: data.to_vec(),
+            info: info.to_vec(),
+        });
+        let first = self.protected_sectors(offset, data.len())?;
+        if info.len() != data.len().div_ceil(SECTOR_SIZE) {
+            bail!(
+                "{} bytes cover {} sectors but {} came with protection information",
+                data.len(),
+                data.len().div_ceil(SECTOR_SIZE),
+                info.len()
+            );
+        }
+        for (i, (sector, info)) in data.chunks(SECTOR_SIZE).zip(info).enumerate() {
+            info.verify(sector, first + i, "array")?;
+        }
+        self.write_slice(offset, data)?;
+        let protection = self.protection.as_mut().unwrap();
+        for (i, info) in info.iter().enumerate() {
+            protection[first + i] = Some(*info);
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes at array offset `offset` along with the protection information of each sector they cover.
+    /// Sectors written with `write_protected()` are checked against what they were written with, wherever the data
+    /// came from, and the rest get protection information generated for them with an application tag of zero.
+    pub fn read_protected(
+        &self,
+        offset: u64,
+        len: usize,
+    ) -> Result<(Vec<u8>, Vec<ProtectionInfo>)> {
+        let first = self.protected_sectors(offset, len)?;
+        if self.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Read,
+            }
+            .into());
+        }
+        let start = first * SECTOR_SIZE;
+        let data = (start..start + len)
+            .map(|i| self.read_at(i))
+            .collect::<Result<Vec<u8>>>()?;
+        let protection = self.protection.as_ref().unwrap();
+        let mut info = vec![];
+        for (i, sector) in data.chunks(SECTOR_SIZE).enumerate() {
+            // A partial last sector can only be checked in full
+            let stored = protection[first + i].filter(|_| sector.len() == SECTOR_SIZE);
+            match stored {
+                Some(stored) => {
+                    stored.verify(sector, first + i, "cache")?;
+                    info.push(stored);
+                }
+                None => info.push(ProtectionInfo::generate(sector, first + i, 0)),
+            }
+        }
+        Ok((data, info))
+    }
+
+    /// Damages `field` of the protection information the array keeps for `sector`, leaving the data alone
+    pub fn inject_tag_error(&mut self, sector: usize, field: TagField) {
+        let _recording = self.record(|| Op::InjectTagError { sector, field });
+        if let Some(Some(info)) = self.protection.as_mut().map(|p| &mut p[sector]) {
+            info.inject(field);
+        }
+    }
+
+    /// Makes room for the protection information of the sectors after array offset `old_len`, after the array grew
+    /// from that length. A last sector cut short by the old end is forgotten, it's longer now.
+    pub(super) fn grow_protection(&mut self, old_len: usize) {
+        let sectors = self.data_len().div_ceil(SECTOR_SIZE);
+        if let Some(protection) = &mut self.protection {
+            protection.truncate(old_len / SECTOR_SIZE);
+            protection.resize(sectors, None);
+        }
+    }
+
+    /// Forgets the protection information of the sectors in array offsets `start..end`, they were written without any
+    pub(super) fn drop_protection(&mut self, start: usize, end: usize) {
+        if let Some(protection) = &mut self.protection {
+            let (first, last) = (start / SECTOR_SIZE, (end.max(start + 1) - 1) / SECTOR_SIZE);
+            protection[first..=last].fill(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+    use crate::{ProtectionInfo, TagField, SECTOR_SIZE};
+
+    #[test]
+    fn raid6_protection_information_is_checked_at_every_hop() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        let data = vec![0x3cu8; 2 * SECTOR_SIZE];
+        let info = [
+            ProtectionInfo::generate(&data[..SECTOR_SIZE], 4, 7),
+            ProtectionInfo::generate(&data[SECTOR_SIZE..], 5, 7),
+        ];
+        let offset = 4 * SECTOR_SIZE as u64;
+        assert!(sim.write_protected(offset, &data, &info).is_err());
+        sim.set_protection(true);
+        assert!(sim.write_protected(offset + 1, &data, &info).is_err());
+
+        // Information for the wrong sector is refused before anything is written
+        let mut misdirected = info;
+        misdirected.swap(0, 1);
+        assert!(sim.write_protected(offset, &data, &misdirected).is_err());
+        sim.write_protected(offset, &data, &info).unwrap();
+        assert_eq!(
+            sim.read_protected(offset, data.len()).unwrap(),
+            (data.clone(), info.to_vec())
+        );
+
+        // A damaged tag on a member is caught by the drive and read around from parity
+        let index = sim.data_start() + 4 * SECTOR_SIZE / DRIVE_SIZE;
+        let sector = (4 * SECTOR_SIZE % DRIVE_SIZE) / SECTOR_SIZE;
+        sim.drive_mut(index).inject_tag_error(sector, TagField::Ref);
+        assert!(sim.drive(index).read(sector * SECTOR_SIZE).is_err());
+        assert_eq!(sim.read_protected(offset, data.len()).unwrap().0, data);
+
+        // The application tag is never checked, the rest are
+        sim.inject_tag_error(4, TagField::App);
+        assert!(sim.read_protected(offset, data.len()).is_ok());
+        sim.inject_tag_error(5, TagField::Guard);
+        assert!(sim.read_protected(offset, data.len()).is_err());
+
+        // A plain write leaves the sector without host protection information
+        sim.write(offset + SECTOR_SIZE as u64, 1).unwrap();
+        let (_, info) = sim.read_protected(offset, data.len()).unwrap();
+        assert_eq!(info[1].app_tag, 0);
+    }
+}
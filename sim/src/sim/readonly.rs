@@ -0,0 +1,67 @@
+use anyhow::{bail, Result};
+
+use crate::drive::SECTOR_SIZE;
+
+use super::{Op, RaidSim, Trigger};
+
+impl RaidSim {
+    /// Write-protects the member at `index`, or lifts the protection.
+    ///
+    /// While protected the member is still read from, but writes to it are dropped like writes to a failed member,
+    /// and the sectors they were meant for are reconstructed from parity. Lifting the protection rewrites those
+    /// sectors from the rest of the array so the member is back in sync.
+    pub fn set_write_protected(&mut self, index: usize, protected: bool) -> Result<()> {
+        let _recording = self.record(|| Op::SetWriteProtected { index, protected });
+        if index >= self.drives.len() {
+            bail!(
+                "No member {} in an array of {} drives",
+                index,
+                self.drives.len()
+            );
+        }
+        self.drives[index].set_read_only(protected);
+        if !protected {
+            let missed = self.drives[index].missed_writes().collect::<Vec<usize>>();
+            for sector in missed {
+                let start = sector * SECTOR_SIZE;
+                let end = (start + SECTOR_SIZE).min(self.drive_size);
+                let contents = self.member_range(index, start, end)?;
+                self.write_member(index, start, &contents)?;
+            }
+        }
+        self.note_state(|| Trigger::WriteProtected { index, protected });
+        self.sync_superblocks();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_write_protected_member_is_read_around() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        let index = Q_INDEX + 3;
+        sim.set_write_protected(index, true).unwrap();
+        assert_eq!(sim.state(), RaidState::Degraded);
+
+        // The member is the third data drive
+        let before = sim.drive(index).read(700).unwrap();
+        let offset = 2 * DRIVE_SIZE + 700;
+        data[offset] = !before;
+        sim.write(offset as u64, !before).unwrap();
+        assert!(sim.drive(index).missed_write(700));
+        assert!(sim.drive(index).read(700).is_err());
+        // Untouched sectors are still read straight from the member
+        assert!(sim.drive(index).read(10).is_ok());
+        assert_sim_equal(&sim, &data);
+
+        sim.set_write_protected(index, false).unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_eq!(sim.drive(index).read(700).unwrap(), !before);
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        assert_sim_equal(&sim, &data);
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+
+use super::{Op, OpCategory, RaidMode, RaidSim, Trigger, P_INDEX, Q_INDEX, STRIPE_HEIGHT};
+
+/// Describes how unformatted members were rebuilt
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RebuildReport {
+    /// Indices of the members that were rebuilt
+    pub rebuilt: Vec<usize>,
+    /// Stripes in the order they were rebuilt
+    pub order: Vec<usize>,
+    /// Stripes with no redundancy left when the rebuild started, these were rebuilt first
+    pub critical: Vec<usize>,
+    /// Stripes written to before the rebuild reached them, what was written was kept over the reconstruction
+    pub written_ahead: Vec<usize>,
+}
+
+/// How the contents of the members being rebuilt are worked out
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecoveryFormula {
+    /// No data is missing, only parity is recomputed from it
+    Recompute,
+    /// One data member is missing and is the XOR of P with the rest
+    POnly,
+    /// One data member is missing along with P, it's solved for from Q alone
+    QOnly,
+    /// Two data members are missing, both P and Q are needed to solve for them
+    TwoErasure,
+}
+
+/// What a rebuild would do if it were started now
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RepairPlan {
+    /// Indices of the members that would be rebuilt
+    pub rebuilt: Vec<usize>,
+    pub formula: RecoveryFormula,
+    /// Bytes that would be read from each surviving member, by index
+    pub reads: BTreeMap<usize, u64>,
+    /// Bytes that would be written to each member being rebuilt, by index
+    pub writes: BTreeMap<usize, u64>,
+    /// Simulated milliseconds the rebuild is expected to spend waiting on hung drives
+    pub estimated_ms: u64,
+}
+
+/// A rebuild that has been started but not finished, writes can come in between its steps
+#[derive(Debug, Clone)]
+pub(super) struct Rebuild {
+    members: Vec<usize>,
+    order: Vec<usize>,
+    redundancy: Vec<usize>,
+    /// How many stripes of `order` have been rebuilt
+    next: usize,
+    /// Member and drive offset of every byte written onto a replacement ahead of the rebuild
+    ahead: BTreeSet<(usize, usize)>,
+    paused: bool,
+}
+
+impl Rebuild {
+    /// Whether the stripe holding drive offset `offset` of `member` has been rebuilt yet
+    fn done(&self, member: usize, offset: usize) -> bool {
+        self.members.contains(&member)
+            && self.order[..self.next].contains(&(offset / STRIPE_HEIGHT))
+    }
+}
+
+impl RaidSim {
+    /// Returns how many more members a stripe could lose before its data is gone.
+    /// Known bad sectors on the remaining members count against it, latent ones can't be known until they are read.
+    fn stripe_redundancy(&self, stripe: usize) -> usize {
+        let offset = stripe * STRIPE_HEIGHT;
+        let lost = self
+            .drives
+            .iter()
+            .filter(|d| !d.usable() || d.is_bad(offset))
+            .count();
+        self.mode.redundancy().saturating_sub(lost)
+    }
+
+    /// Starts rebuilding every unformatted member from the rest of the array, one stripe per step.
+    /// Stripes with the least redundancy left go first so a further failure partway through loses as little as possible.
+    pub fn start_rebuild(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::StartRebuild);
+        if self.rebuilding.is_some() {
+            bail!("A rebuild is already running");
+        }
+        let members = (0..self.drives.len())
+            .filter(|i| !self.drives[*i].has_failed() && !self.drives[*i].is_formatted())
+            .collect::<Vec<usize>>();
+        if members.is_empty() {
+            bail!("No replacement drives to rebuild onto");
+        }
+
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        let redundancy = (0..stripes)
+            .map(|s| self.stripe_redundancy(s))
+            .collect::<Vec<usize>>();
+        let mut order = (0..stripes).collect::<Vec<usize>>();
+        order.sort_by_key(|s| redundancy[*s]);
+        self.rebuilding = Some(Rebuild {
+            members,
+            order,
+            redundancy,
+            next: 0,
+            ahead: BTreeSet::new(),
+            paused: false,
+        });
+        Ok(())
+    }
+
+    /// Rebuilds up to `stripes` more stripes of the running rebuild.
+    /// Returns the report once the last stripe is done and the replacements have joined the array.
+    pub fn rebuild_step(&mut self, stripes: usize) -> Result<Option<RebuildReport>> {
+        let _recording = self.record(|| Op::RebuildStep { stripes });
+        let _counting = self.count_ops(OpCategory::Rebuild);
+        let _budgeted = self.buffers.budgeted();
+        let Some(mut rebuild) = self.rebuilding.take() else {
+            bail!("No rebuild is running");
+        };
+        if rebuild.paused {
+            self.rebuilding = Some(rebuild);
+            bail!("The rebuild is paused");
+        }
+        if let Err(e) = self.rebuild_stripes(&mut rebuild, stripes) {
+            self.rebuilding = Some(rebuild);
+            return Err(e);
+        }
+        if rebuild.next < rebuild.order.len() {
+            self.rebuilding = Some(rebuild);
+            self.assert_invariants();
+            return Ok(None);
+        }
+
+        for &index in &rebuild.members {
+            self.drives[index].format();
+        }
+        let members = rebuild.members.clone();
+        self.note_state(|| Trigger::Rebuilt { members });
+        self.sync_superblocks();
+        let mut written_ahead = rebuild
+            .ahead
+            .iter()
+            .map(|(_, offset)| offset / STRIPE_HEIGHT)
+            .collect::<Vec<usize>>();
+        written_ahead.dedup();
+        self.assert_invariants();
+        Ok(Some(RebuildReport {
+            critical: rebuild
+                .order
+                .iter()
+                .copied()
+                .filter(|s| rebuild.redundancy[*s] == 0)
+                .collect(),
+            rebuilt: rebuild.members,
+            order: rebuild.order,
+            written_ahead,
+        }))
+    }
+
+    /// Reconstructs the next `stripes` stripes of `rebuild` onto its members, leaving alone bytes written ahead of it
+    fn rebuild_stripes(&mut self, rebuild: &mut Rebuild, stripes: usize) -> Result<()> {
+        let last = rebuild
+            .order
+            .len()
+            .min(rebuild.next.saturating_add(stripes));
+        while rebuild.next < last {
+            let stripe = rebuild.order[rebuild.next];
+            let start = stripe * STRIPE_HEIGHT;
+            let end = (start + STRIPE_HEIGHT).min(self.drive_size);
+            for &index in &rebuild.members {
+                // A stripe nobody wrote is zeros on every member, there's nothing to reconstruct
+                let contents = if self.is_written(stripe) {
+                    self.member_range(index, start, end)?
+                } else {
+                    self.buffers.take(end - start)?
+                };
+                if !rebuild
+                    .ahead
+                    .range((index, start)..(index, end))
+                    .any(|_| true)
+                {
+                    self.write_member(index, start, &contents)?;
+                    continue;
+                }
+                for (offset, byte) in (start..end).zip(contents.iter().copied()) {
+                    if !rebuild.ahead.contains(&(index, offset)) {
+                        self.write_member(index, offset, &[byte])?;
+                    }
+                }
+            }
+            rebuild.next += 1;
+        }
+        Ok(())
+    }
+
+    /// Works out what a rebuild started now would do, without touching the array.
+    /// Every byte of a replacement is worked out on its own, so each one reads a byte from every member it needs.
+    /// Stripes that were never written are zeroed without reading anything.
+    pub fn repair_plan(&self) -> Result<RepairPlan> {
+        let rebuilt = (0..self.drives.len())
+            .filter(|i| !self.drives[*i].has_failed() && !self.drives[*i].is_formatted())
+            .collect::<Vec<usize>>();
+        if rebuilt.is_empty() {
+            bail!("No replacement drives to rebuild onto");
+        }
+
+        let data_start = self.data_start();
+        let missing = self.data_drives().filter(|d| !d.usable()).count();
+        let p = self.drives[P_INDEX].usable();
+        let q = self.mode == RaidMode::Raid6 && self.drives[Q_INDEX].usable();
+        let formula = match (missing, p, q) {
+            (0, _, _) => RecoveryFormula::Recompute,
+            (1, true, _) => RecoveryFormula::POnly,
+            (1, false, true) => RecoveryFormula::QOnly,
+            (2, true, true) => RecoveryFormula::TwoErasure,
+            _ => bail!(
+                "Not enough redundancy to rebuild, {} data members missing",
+                missing
+            ),
+        };
+        // Parity is read whenever any data is missing, even when it isn't all needed
+        let mut sources = (data_start..self.drives.len())
+            .filter(|i| self.drives[*i].usable())
+            .collect::<Vec<usize>>();
+        if missing > 0 && p {
+            sources.push(P_INDEX);
+        }
+        if matches!(
+            formula,
+            RecoveryFormula::QOnly | RecoveryFormula::TwoErasure
+        ) {
+            sources.push(Q_INDEX);
+        }
+        sources.sort_unstable();
+
+        let size = self.drive_size as u64;
+        let written = self
+            .written_stripes()
+            .map(|s| ((s + 1) * STRIPE_HEIGHT).min(self.drive_size) - s * STRIPE_HEIGHT)
+            .sum::<usize>() as u64;
+        let reads = sources
+            .iter()
+            .map(|i| (*i, written * rebuilt.len() as u64))
+            .collect::<BTreeMap<usize, u64>>();
+        let writes = rebuilt
+            .iter()
+            .map(|i| (*i, size))
+            .collect::<BTreeMap<usize, u64>>();
+
+        // Reads go a byte at a time and writes a stripe at a time, any of them can hang
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT) as u64;
+        let accesses = reads
+            .iter()
+            .map(|(i, bytes)| (*i, *bytes))
+            .chain(rebuilt.iter().map(|i| (*i, stripes)));
+        let estimated_ms = accesses
+            .map(|(i, count)| match self.drives[i].hang() {
+                Some(hang) => count * hang.duration / hang.one_in.max(1) as u64,
+                None => 0,
+            })
+            .sum();
+
+        Ok(RepairPlan {
+            rebuilt,
+            formula,
+            reads,
+            writes,
+            estimated_ms,
+        })
+    }
+
+    /// Returns how many stripes the running rebuild has done out of how many it has to do
+    pub fn rebuild_progress(&self) -> Option<(usize, usize)> {
+        self.rebuilding.as_ref().map(|r| (r.next, r.order.len()))
+    }
+
+    /// Pauses the running rebuild, it keeps track of writes but won't take any steps until resumed
+    pub fn pause_rebuild(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::PauseRebuild);
+        match self.rebuilding.as_mut() {
+            Some(rebuild) => rebuild.paused = true,
+            None => bail!("No rebuild is running"),
+        }
+        Ok(())
+    }
+
+    /// Lets a paused rebuild take steps again
+    pub fn resume_rebuild(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::ResumeRebuild);
+        match self.rebuilding.as_mut() {
+            Some(rebuild) => rebuild.paused = false,
+            None => bail!("No rebuild is running"),
+        }
+        Ok(())
+    }
+
+    /// Returns whether there's a rebuild running that has been paused
+    pub fn is_rebuild_paused(&self) -> bool {
+        self.rebuilding.as_ref().is_some_and(|r| r.paused)
+    }
+
+    /// Abandons the running rebuild. Its replacements stay unformatted and out of the array,
+    /// whatever it had written to them is ignored and a new rebuild starts over from scratch.
+    pub fn cancel_rebuild(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::CancelRebuild);
+        if self.rebuilding.take().is_none() {
+            bail!("No rebuild is running");
+        }
+        Ok(())
+    }
+
+    /// Rebuilds every unformatted member in one go, or finishes the rebuild already running
+    pub fn rebuild(&mut self) -> Result<RebuildReport> {
+        let _recording = self.record(|| Op::Rebuild);
+        let _counting = self.count_ops(OpCategory::Rebuild);
+        let _budgeted = self.buffers.budgeted();
+        if self.rebuilding.is_none() {
+            self.start_rebuild()?;
+        }
+        Ok(self.rebuild_step(usize::MAX)?.unwrap())
+    }
+
+    /// Whether member `index` holds current contents at drive offset `offset`,
+    /// either because it's in sync or because the running rebuild has already been past it
+    pub(super) fn current_at(&self, index: usize, offset: usize) -> bool {
+        self.drives[index].usable()
+            || self
+                .rebuilding
+                .as_ref()
+                .is_some_and(|r| r.done(index, offset))
+    }
+
+    /// Records data written onto member `index` at drive offsets `start..end` that the running rebuild hasn't reached,
+    /// so it keeps what was written rather than reconstructing over it
+    pub(super) fn note_rebuild_write(&mut self, index: usize, start: usize, end: usize) {
+        if let Some(rebuild) = self.rebuilding.as_mut() {
+            if rebuild.members.contains(&index) {
+                for offset in start..end {
+                    if !rebuild.done(index, offset) {
+                        rebuild.ahead.insert((index, offset));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drive::Hang;
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_rebuilds_critical_stripes_first() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.drive(7).mark_bad(1);
+        sim.drive_mut(5).fail();
+        sim.replace_failed_drives();
+
+        let report = sim.rebuild().unwrap();
+        assert_eq!(report.rebuilt, vec![5]);
+        assert_eq!(report.order, vec![1, 0]);
+        assert_eq!(report.critical, vec![1]);
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_q_parity();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_writes_during_rebuild_land_on_replacements() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.drive_mut(5).fail();
+        sim.replace_failed_drives();
+
+        sim.start_rebuild().unwrap();
+        assert!(sim.start_rebuild().is_err());
+        assert!(sim.rebuild_step(1).unwrap().is_none());
+        assert_eq!(sim.rebuild_progress(), Some((1, 2)));
+
+        // Drive 5 is the fourth data drive, write a row of it in each stripe
+        let base = 3 * DRIVE_SIZE;
+        for offset in [base + 100, base + STRIPE_HEIGHT + 100] {
+            data[offset..offset + 50].fill(0xa5);
+            sim.write_slice(offset as u64, &data[offset..offset + 50])
+                .unwrap();
+        }
+        let report = sim.rebuild_step(1).unwrap().unwrap();
+        assert_eq!(report.rebuilt, vec![0, 5]);
+        assert_eq!(report.written_ahead, vec![1]);
+        assert_eq!(sim.rebuild_progress(), None);
+        assert_eq!(sim.state(), RaidState::Ok);
+
+        // Both replacements have to be right on their own
+        sim.fail_q_parity();
+        sim.drive_mut(2).fail();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_reads_during_repair_use_rebuilt_regions() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.drive_mut(3).fail();
+        sim.replace_failed_drives();
+        assert!(sim.start_repair().unwrap());
+        sim.rebuild_step(1).unwrap();
+
+        // Drive 3 is the third data drive, its first stripe is rebuilt and its second isn't yet
+        let base = 2 * DRIVE_SIZE;
+        let (rebuilt, pending) = (10, STRIPE_HEIGHT + 10);
+        sim.drive_mut(3).corrupt(rebuilt, 0xff);
+        sim.drive_mut(3).corrupt(pending, 0xff);
+        assert_eq!(
+            sim.read((base + rebuilt) as u64).unwrap(),
+            data[base + rebuilt] ^ 0xff
+        );
+        assert_eq!(
+            sim.read((base + pending) as u64).unwrap(),
+            data[base + pending]
+        );
+
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_eq!(
+            sim.read((base + pending) as u64).unwrap(),
+            data[base + pending]
+        );
+    }
+
+    #[test]
+    fn raid6_canceled_rebuild_can_restart() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        sim.fail_q_parity();
+        sim.drive_mut(9).fail();
+        sim.replace_failed_drives();
+
+        sim.start_rebuild().unwrap();
+        sim.rebuild_step(1).unwrap();
+        sim.pause_rebuild().unwrap();
+        assert!(sim.is_rebuild_paused());
+        assert!(sim.rebuild_step(1).is_err());
+        sim.resume_rebuild().unwrap();
+        sim.cancel_rebuild().unwrap();
+        assert!(sim.rebuild_step(1).is_err());
+        assert!(sim.cancel_rebuild().is_err());
+
+        // Nothing the canceled rebuild did counts, the array is degraded and the replacements still need rebuilding
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert!(!sim.drive(1).is_formatted());
+        assert!(!sim.drive(9).is_formatted());
+        assert_sim_equal(&sim, &data);
+
+        // Writes to stripes the canceled rebuild had done no longer reach Q
+        data[300..400].fill(0x3c);
+        sim.write_slice(300, &data[300..400]).unwrap();
+        let report = sim.rebuild().unwrap();
+        assert_eq!(report.rebuilt, vec![1, 9]);
+        assert!(report.written_ahead.is_empty());
+        assert_eq!(sim.state(), RaidState::Ok);
+        sim.fail_p_parity();
+        sim.drive_mut(4).fail();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_repair_plan_matches_the_rebuild() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.fail_p_parity();
+        sim.drive_mut(4).fail();
+        sim.replace_failed_drives();
+        sim.drive_mut(3).set_hang(Some(Hang {
+            one_in: 4,
+            duration: 100,
+        }));
+
+        let plan = sim.repair_plan().unwrap();
+        assert_eq!(plan.rebuilt, vec![0, 4]);
+        assert_eq!(plan.formula, RecoveryFormula::QOnly);
+        // Q and the 61 remaining data drives are read once for each of the two replacements
+        assert_eq!(plan.reads.len(), 62);
+        assert!(plan.reads.values().all(|b| *b == 2 * DRIVE_SIZE as u64));
+        assert!(!plan.reads.contains_key(&0) && !plan.reads.contains_key(&4));
+        assert_eq!(plan.writes.len(), 2);
+        assert_eq!(plan.estimated_ms, 2 * DRIVE_SIZE as u64 * 100 / 4);
+
+        // Planning didn't start anything
+        assert_eq!(sim.rebuild_progress(), None);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert_eq!(sim.rebuild().unwrap().rebuilt, plan.rebuilt);
+        assert!(sim.repair_plan().is_err());
+        assert_sim_equal(&sim, &data);
+    }
+}
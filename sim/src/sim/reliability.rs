@@ -0,0 +1,127 @@
+use super::{RaidMode, RaidSim};
+
+/// Hours in an average year, leap years included
+const HOURS_PER_YEAR: f64 = 8766.0;
+
+/// How often drives fail and how long the array takes to recover from it, what the closed-form estimates work from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReliabilityModel {
+    /// Fraction of drives expected to fail each year
+    pub afr: f64,
+    /// Hours from a member failing to its replacement being rebuilt
+    pub rebuild_hours: f64,
+    /// Chance of any one bit read turning out unreadable, drive datasheets quote 1e-14 or 1e-15
+    pub ure_per_bit: f64,
+}
+
+impl Default for ReliabilityModel {
+    fn default() -> Self {
+        Self {
+            afr: 0.02,
+            rebuild_hours: 24.0,
+            ure_per_bit: 1e-14,
+        }
+    }
+}
+
+/// Closed-form estimates of how long an array keeps its data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReliabilityEstimate {
+    /// Mean hours to data loss from drives failing outright, the classic Markov model
+    pub mttdl_hours: f64,
+    /// Mean hours to data loss once unreadable sectors met during the last rebuild that could still fix them count too
+    pub mttdl_hours_with_ure: f64,
+    /// Chance that rebuild runs into an unreadable sector
+    pub rebuild_ure_probability: f64,
+}
+
+impl ReliabilityEstimate {
+    /// Returns the chance of losing data within a year, counting unreadable sectors
+    pub fn annual_loss_probability(&self) -> f64 {
+        1.0 - (-HOURS_PER_YEAR / self.mttdl_hours_with_ure).exp()
+    }
+}
+
+impl ReliabilityModel {
+    /// Estimates the reliability of an array of `num_drives` members of `drive_size` bytes.
+    ///
+    /// With `n` members that each fail at rate `λ = afr / year` and are rebuilt in `r` hours, losing data takes
+    /// one failure more than the mode tolerates before the rebuild finishes:
+    /// RAID5 loses data at `n(n - 1)λ²r` and RAID6 at `n(n - 1)(n - 2)λ³r²`.
+    /// With unreadable sectors the last failure can instead be an unreadable sector met while reading every
+    /// surviving member during the rebuild that had no redundancy left.
+    pub fn estimate(
+        &self,
+        mode: RaidMode,
+        num_drives: usize,
+        drive_size: u64,
+    ) -> ReliabilityEstimate {
+        let n = num_drives as f64;
+        let lambda = self.afr / HOURS_PER_YEAR;
+        let r = self.rebuild_hours;
+        // Chance of a rebuild reading `survivors` whole members without hitting an unreadable sector
+        let clean_read =
+            |survivors: f64| (-(survivors * drive_size as f64 * 8.0) * self.ure_per_bit).exp();
+
+        let (loss_rate, ure_rate, rebuild_ure_probability) = match mode {
+            RaidMode::Raid5 => {
+                let p = 1.0 - clean_read(n - 1.0);
+                (n * (n - 1.0) * lambda.powi(2) * r, n * lambda * p, p)
+            }
+            RaidMode::Raid6 => {
+                let p = 1.0 - clean_read(n - 2.0);
+                (
+                    n * (n - 1.0) * (n - 2.0) * lambda.powi(3) * r.powi(2),
+                    n * (n - 1.0) * lambda.powi(2) * r * p,
+                    p,
+                )
+            }
+        };
+        ReliabilityEstimate {
+            mttdl_hours: 1.0 / loss_rate,
+            mttdl_hours_with_ure: 1.0 / (loss_rate + ure_rate),
+            rebuild_ure_probability,
+        }
+    }
+}
+
+impl RaidSim {
+    /// Estimates the reliability of an array configured like this one under `model`
+    pub fn reliability(&self, model: &ReliabilityModel) -> ReliabilityEstimate {
+        model.estimate(self.mode, self.drives.len(), self.drive_size as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn mttdl_matches_the_textbook() {
+        let model = ReliabilityModel {
+            ure_per_bit: 0.0,
+            ..ReliabilityModel::default()
+        };
+        // MTTF of 438,300 hours, eight drives rebuilt in a day
+        let raid5 = model.estimate(RaidMode::Raid5, 8, 4_000_000_000_000);
+        let expected = 438_300f64.powi(2) / (8.0 * 7.0 * 24.0);
+        assert!((raid5.mttdl_hours / expected - 1.0).abs() < 1e-9);
+        assert_eq!(raid5.mttdl_hours, raid5.mttdl_hours_with_ure);
+
+        // Unreadable sectors dominate RAID5 rebuilds of big drives, RAID6 still has parity to spare for them
+        let model = ReliabilityModel::default();
+        let raid5 = model.estimate(RaidMode::Raid5, 8, 4_000_000_000_000);
+        let raid6 = model.estimate(RaidMode::Raid6, 8, 4_000_000_000_000);
+        assert!(raid5.rebuild_ure_probability > 0.8);
+        assert!(raid5.mttdl_hours_with_ure < raid5.mttdl_hours / 100.0);
+        assert!(raid6.mttdl_hours_with_ure > raid5.mttdl_hours_with_ure * 1000.0);
+        assert!(raid5.annual_loss_probability() > raid6.annual_loss_probability());
+
+        let (sim, _) = init_random(RaidMode::Raid6);
+        assert_eq!(
+            sim.reliability(&model),
+            model.estimate(RaidMode::Raid6, NUM_DRIVES, DRIVE_SIZE as u64)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+/// A summary of a chaos or scenario run, meant to be collected across many runs and compared
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RunReport {
+    pub seed: u64,
+    /// Operations run, including the one that failed if any did
+    pub ops: u64,
+    /// Members failed on purpose
+    pub failures_injected: u64,
+    /// Rebuilds that ran to completion
+    pub rebuilds_completed: u64,
+    /// Most members missing from the array at any one time
+    pub max_missing: usize,
+    /// Bytes that could no longer be read back as written when the run ended
+    pub bytes_lost: u64,
+    /// Simulated milliseconds that passed during the run
+    pub sim_time_ms: u64,
+    /// Real milliseconds the run took
+    pub wall_time_ms: u64,
+    /// What went wrong, if anything did
+    pub error: Option<String>,
+}
+
+/// Quotes `s` as a JSON string
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl RunReport {
+    /// Returns whether the run got through without anything going wrong
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Serializes the report as a single line JSON object, so a run per line can be appended to a file and aggregated
+    pub fn to_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\"seed\":{},\"ops\":{},\"failures_injected\":{},\"rebuilds_completed\":{},",
+                "\"max_missing\":{},\"bytes_lost\":{},\"sim_time_ms\":{},\"wall_time_ms\":{},\"error\":{}}}"
+            ),
+            self.seed,
+            self.ops,
+            self.failures_injected,
+            self.rebuilds_completed,
+            self.max_missing,
+            self.bytes_lost,
+            self.sim_time_ms,
+            self.wall_time_ms,
+            self.error
+                .as_deref()
+                .map_or("null".to_string(), json_string)
+        )
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+
+use crate::drive::SECTOR_SIZE;
+
+use super::{Op, RaidSim};
+
+impl RaidSim {
+    /// Salvages the member at `index` before it dies outright, the way an admin would with ddrescue:
+    /// everything it can still read is cloned onto a fresh drive, the sectors it can't are worked out from parity,
+    /// and the copy takes its place. Returns the sectors that had to be filled in from parity.
+    ///
+    /// Unlike failing the member and rebuilding, the array never goes degraded, only the gaps lean on redundancy.
+    /// If any gap can't be worked out the array is left as it was.
+    pub fn evacuate(&mut self, index: usize) -> Result<Vec<usize>> {
+        let _recording = self.record(|| Op::Evacuate { index });
+        if index >= self.drives.len() {
+            bail!(
+                "No member {} in an array of {} drives",
+                index,
+                self.drives.len()
+            );
+        }
+        if !self.drives[index].usable() {
+            bail!("Member {} isn't in use, there's nothing to evacuate", index);
+        }
+        let mut bad_map = BTreeSet::new();
+        let mut copy = self.drives[index].clone_rescue(&mut bad_map);
+        for &sector in &bad_map {
+            let start = sector * SECTOR_SIZE;
+            let end = (start + SECTOR_SIZE).min(self.drive_size);
+            let contents = self.member_range(index, start, end)?;
+            copy.write_slice(start, &contents)?;
+        }
+        self.seed_drive(index, &mut copy);
+        self.prepare_member(&mut copy);
+        copy.format();
+        self.drives[index] = copy;
+        self.sync_superblocks();
+        self.assert_invariants();
+        Ok(bad_map.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_evacuate_fills_unreadable_sectors_from_parity() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let index = Q_INDEX + 5;
+        sim.drive_mut(index).add_latent_error(0);
+        sim.drive_mut(index).mark_bad(1);
+
+        // A map carried over from an earlier attempt isn't retried
+        let mut bad_map = BTreeSet::from([1]);
+        let copy = sim.drive(index).clone_rescue(&mut bad_map);
+        assert_eq!(bad_map, BTreeSet::from([0, 1]));
+        assert_eq!(
+            copy.read_slice(0, DRIVE_SIZE).unwrap()[..SECTOR_SIZE],
+            [0; SECTOR_SIZE]
+        );
+        assert!(!copy.is_formatted());
+        let diff = copy.compare(sim.drive(index));
+        assert!(diff.sectors().is_subset(&bad_map), "{}", diff);
+
+        assert_eq!(sim.evacuate(index).unwrap(), vec![0, 1]);
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert!(sim.drive(index).latent_errors().next().is_none());
+        assert!(sim.drive(index).bad_sectors().is_empty());
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        assert_sim_equal(&sim, &data);
+        sim.fail_p_parity();
+        sim.fail_q_parity();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_evacuate_needs_redundancy_for_the_gaps() {
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.drive_mut(5).add_latent_error(1);
+        sim.fail_drive(3);
+        assert!(sim.evacuate(5).is_err());
+        assert!(sim.evacuate(3).is_err());
+        assert_eq!(
+            sim.drive(5).latent_errors().collect::<Vec<usize>>(),
+            vec![1]
+        );
+
+        // Without the gap the clone alone is enough
+        let (mut sim, _) = init_random(RaidMode::Raid5);
+        sim.fail_drive(3);
+        assert!(sim.evacuate(5).unwrap().is_empty());
+        assert_eq!(sim.state(), RaidState::Degraded);
+    }
+}
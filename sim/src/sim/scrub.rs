@@ -0,0 +1,305 @@
+use anyhow::{bail, Result};
+
+use crate::generator::correct_errors;
+
+use super::{MismatchCause, Op, RaidMode, RaidSim, RaidState, P_INDEX, Q_INDEX, STRIPE_HEIGHT};
+
+/// Summary of what a scrub came across
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScrubReport {
+    /// Number of bytes checked on each member
+    pub scanned: usize,
+    /// Number of times the scrub wrapped around to the start of the drives
+    pub passes: u64,
+    /// Parity that didn't match its stripe's data, as (drive index, stripe)
+    pub mismatches: Vec<(usize, usize)>,
+    /// Mismatched parity that was rewritten from the data, as (drive index, stripe)
+    pub corrected: Vec<(usize, usize)>,
+}
+
+impl ScrubReport {
+    fn merge(&mut self, other: &ScrubReport) {
+        self.scanned += other.scanned;
+        self.passes += other.passes;
+        self.mismatches.extend_from_slice(&other.mismatches);
+        self.corrected.extend_from_slice(&other.corrected);
+    }
+}
+
+/// Progress of the background parity check
+#[derive(Debug, Clone, Default)]
+pub(super) struct Scrub {
+    /// Bytes checked per simulated second on each member, zero when not scheduled
+    rate: usize,
+    paused: bool,
+    /// Drive offset the next step starts checking from
+    position: usize,
+    /// Check budget carried over between ticks, in bytes per thousand
+    credit: usize,
+    /// Everything found since the array was created
+    total: ScrubReport,
+}
+
+impl Scrub {
+    /// Works out how many bytes `ms` simulated milliseconds pays for at the scheduled rate
+    pub(super) fn budget(&mut self, ms: u64) -> usize {
+        if self.paused {
+            return 0;
+        }
+        self.credit += self.rate * ms as usize;
+        let bytes = self.credit / 1000;
+        self.credit %= 1000;
+        bytes
+    }
+}
+
+impl RaidSim {
+    /// Schedules the scrub to check `rate` bytes of every member per simulated second as the clock ticks.
+    /// A rate of zero stops it.
+    pub fn set_scrub_rate(&mut self, rate: usize) {
+        let _recording = self.record(|| Op::SetScrubRate { rate });
+        self.scrub.rate = rate;
+        self.scrub.credit = 0;
+    }
+
+    /// Stops the scheduled scrub where it is until `resume_scrub()`
+    pub fn pause_scrub(&mut self) {
+        let _recording = self.record(|| Op::PauseScrub);
+        self.scrub.paused = true;
+    }
+
+    /// Carries on with a paused scrub from where it stopped
+    pub fn resume_scrub(&mut self) {
+        let _recording = self.record(|| Op::ResumeScrub);
+        self.scrub.paused = false;
+    }
+
+    /// Returns everything the scrub has found since the array was created
+    pub fn scrub_report(&self) -> &ScrubReport {
+        &self.scrub.total
+    }
+
+    /// Returns how many simulated milliseconds are left until the current scrub pass finishes,
+    /// or `None` if the scrub is paused or not scheduled
+    pub fn scrub_eta(&self) -> Option<u64> {
+        if self.scrub.paused || self.scrub.rate == 0 {
+            return None;
+        }
+        let remaining = (self.drive_size - self.scrub.position) * 1000;
+        Some(
+            remaining
+                .saturating_sub(self.scrub.credit)
+                .div_ceil(self.scrub.rate) as u64,
+        )
+    }
+
+    /// Checks a row of a RAID6 array against both its parities, and if they point at a single data member
+    /// holding the wrong byte, rewrites it. Returns the index of the member that was corrected, if any.
+    ///
+    /// Unlike a scrub, which trusts the data and rewrites parity, this can find a silently corrupted drive
+    /// without a checksum saying which one it is. A row more than one data byte off usually can't be located
+    /// and errors, though some combinations look just like a single corrupted byte and get miscorrected.
+    pub fn correct_row(&mut self, drive_offset: usize) -> Result<Option<usize>> {
+        let _recording = self.record(|| Op::CorrectRow { drive_offset });
+        if self.mode != RaidMode::Raid6 || self.state() != RaidState::Ok {
+            bail!("Only a healthy RAID6 array has the parity to locate a corrupted drive");
+        }
+        if drive_offset >= self.drive_size || self.is_dirty(drive_offset) {
+            bail!(
+                "Row {} has no up to date parity to check against",
+                drive_offset
+            );
+        }
+        let mut data = self.read_data_row(drive_offset)?.to_vec();
+        let parities = [
+            self.read_retry(P_INDEX, drive_offset)?,
+            self.read_retry(Q_INDEX, drive_offset)?,
+        ];
+        let position = match correct_errors(&mut data, &parities)?[..] {
+            [position] => position,
+            _ => return Ok(None),
+        };
+        let index = self.data_start() + position;
+        let corrected = self.drives[index]
+            .write(drive_offset, data[position])
+            .is_ok();
+        self.record_mismatch(index, MismatchCause::Unknown, corrected);
+        Ok(Some(index))
+    }
+
+    /// Walks the Merkle tree of every member down to the blocks that no longer match it,
+    /// and rewrites each of them from the rest of the array. Returns the blocks repaired, as (drive index, block).
+    ///
+    /// Only the subtrees that changed are looked at, so this finds bit rot far faster than checking every row,
+    /// and unlike a scrub it knows which member is wrong, parity or data.
+    pub fn repair_corrupt_blocks(&mut self) -> Result<Vec<(usize, usize)>> {
+        let _recording = self.record(|| Op::RepairCorruptBlocks);
+        let block_size = match self.merkle {
+            Some(block_size) => block_size,
+            None => bail!("Members don't keep Merkle trees, unable to find corrupt blocks"),
+        };
+        let corrupt = self
+            .drives
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.usable())
+            .flat_map(|(i, d)| d.find_corrupt_blocks().into_iter().map(move |b| (i, b)))
+            .collect::<Vec<(usize, usize)>>();
+        for &(index, block) in &corrupt {
+            let start = block * block_size;
+            let end = (start + block_size).min(self.drive_size);
+            let contents = self.member_range(index, start, end)?;
+            let corrected = self.drives[index].write_slice(start, &contents).is_ok();
+            self.record_mismatch(index, MismatchCause::Unknown, corrected);
+        }
+        Ok(corrupt)
+    }
+
+    /// Checks the next `bytes` bytes of every stripe's parity against its data, rewriting parity that doesn't match.
+    /// Stripes that are already known to be dirty are left to be flushed, and stripes never written are skipped.
+    pub fn scrub_step(&mut self, bytes: usize) -> Result<ScrubReport> {
+        let _recording = self.record(|| Op::ScrubStep { bytes });
+        let _budgeted = self.buffers.budgeted();
+        if self.unusable().count() > 0 {
+            bail!("Array is missing members, unable to scrub");
+        }
+        let mut report = ScrubReport::default();
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let start = self.scrub.position;
+            let end = (start + remaining).min(self.drive_size);
+            self.scrub_range(start, end, &mut report)?;
+            report.scanned += end - start;
+            remaining -= end - start;
+            if end == self.drive_size {
+                self.scrub.position = 0;
+                report.passes += 1;
+            } else {
+                self.scrub.position = end;
+            }
+        }
+        self.scrub.total.merge(&report);
+        Ok(report)
+    }
+
+    /// Checks parity at drive offsets `start..end`, noting each mismatched stripe once per parity drive
+    fn scrub_range(&mut self, start: usize, end: usize, report: &mut ScrubReport) -> Result<()> {
+        let parity = match self.mode {
+            RaidMode::Raid5 => vec![P_INDEX],
+            RaidMode::Raid6 => vec![P_INDEX, Q_INDEX],
+        };
+        for offset in start..end {
+            if self.is_dirty(offset) || !self.is_written(offset / STRIPE_HEIGHT) {
+                continue;
+            }
+            let stripe = offset / STRIPE_HEIGHT;
+            for &index in &parity {
+                let expected = self.member_contents(index, offset)?;
+                if self.read_retry(index, offset)? == expected {
+                    continue;
+                }
+                let corrected = self.drives[index].write(offset, expected).is_ok();
+                if !report.mismatches.contains(&(index, stripe)) {
+                    report.mismatches.push((index, stripe));
+                    if corrected {
+                        report.corrected.push((index, stripe));
+                    }
+                    self.record_mismatch(index, MismatchCause::Unknown, corrected);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_scrub_follows_clock_and_pauses() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let p = sim.drive(P_INDEX).read(3).unwrap();
+        sim.drive_mut(P_INDEX).write(3, !p).unwrap();
+        let q = sim.drive(Q_INDEX).read(600).unwrap();
+        sim.drive_mut(Q_INDEX).write(600, !q).unwrap();
+        assert_eq!(sim.scrub_eta(), None);
+
+        sim.set_scrub_rate(512);
+        assert_eq!(sim.scrub_eta(), Some(2_000));
+        sim.tick(1_000).unwrap();
+        assert_eq!(sim.scrub_report().mismatches, vec![(P_INDEX, 0)]);
+        assert_eq!(sim.scrub_eta(), Some(1_000));
+
+        sim.pause_scrub();
+        sim.tick(1_000).unwrap();
+        assert_eq!(sim.scrub_report().scanned, 512);
+        assert_eq!(sim.scrub_eta(), None);
+
+        sim.resume_scrub();
+        sim.tick(1_000).unwrap();
+        let report = sim.scrub_report();
+        assert_eq!(report.passes, 1);
+        assert_eq!(report.mismatches, vec![(P_INDEX, 0), (Q_INDEX, 1)]);
+        assert_eq!(report.corrected, report.mismatches);
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        let stats = sim.stats();
+        assert_eq!(stats.mismatches.found, 2);
+        assert_eq!(stats.mismatches.corrected, 2);
+        assert_eq!(stats.mismatches_by_drive[&Q_INDEX].found, 1);
+        assert_eq!(stats.mismatches_by_cause[&MismatchCause::Unknown].found, 2);
+        assert!(sim
+            .detail()
+            .to_string()
+            .contains("Mismatch Count : 2 found, 2 corrected"));
+
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_locates_a_silently_corrupted_drive() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let index = Q_INDEX + 10;
+        let byte = sim.drive(index).read(100).unwrap();
+        sim.drive_mut(index).write(100, !byte).unwrap();
+        assert_eq!(sim.correct_row(99).unwrap(), None);
+        assert_eq!(sim.correct_row(100).unwrap(), Some(index));
+        assert_eq!(sim.stats().mismatches_by_drive[&index].corrected, 1);
+        assert_sim_equal(&sim, &data);
+
+        // Two corrupted drives in one row can't be told apart from other combinations
+        sim.drive_mut(index).write(100, !byte).unwrap();
+        let other = sim.drive(index + 1).read(100).unwrap();
+        sim.drive_mut(index + 1).write(100, !other).unwrap();
+        assert!(sim.correct_row(100).is_err());
+    }
+
+    #[test]
+    fn raid6_merkle_trees_find_bit_rot() {
+        let (mut sim, _) = init_random(RaidMode::Raid6);
+        assert!(sim.repair_corrupt_blocks().is_err());
+        sim.set_merkle(Some(64));
+        assert!(sim.repair_corrupt_blocks().unwrap().is_empty());
+
+        // Writes through the array keep the trees current
+        let data = write_random(&mut sim);
+        assert!(sim.repair_corrupt_blocks().unwrap().is_empty());
+
+        let data_drive = Q_INDEX + 5;
+        sim.drive_mut(data_drive).corrupt(200, 0x10);
+        sim.drive_mut(P_INDEX).corrupt(1000, 0x01);
+        assert!(!sim.drive(data_drive).verify_subtree(3, 0));
+        assert!(sim.drive(data_drive).verify_subtree(3, 1));
+        assert_eq!(
+            sim.repair_corrupt_blocks().unwrap(),
+            vec![(P_INDEX, 15), (data_drive, 3)]
+        );
+        assert!(sim.repair_corrupt_blocks().unwrap().is_empty());
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        assert_eq!(sim.stats().mismatches.corrected, 2);
+        assert_sim_equal(&sim, &data);
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{bail, Result};
+
+use crate::drive::Drive;
+
+use super::{ArrayEvent, Op, RaidSim};
+
+/// Describes where an array looks for a spare when a member needs replacing
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PromotionOrder {
+    /// Use the array's dedicated spares, then the global pool
+    DedicatedFirst,
+    /// Use the global pool, then the array's dedicated spares
+    GlobalFirst,
+    /// Only ever use the array's dedicated spares
+    DedicatedOnly,
+}
+
+/// What a hot-added drive is for
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HotAdd {
+    /// A spare dedicated to the array, promoted when a member fails
+    Spare,
+    /// A member to be, held until the array is grown onto it with `RaidSim::grow()`
+    Grow,
+}
+
+/// A pool of global spares shared between arrays
+#[derive(Debug, Default)]
+pub struct SparePool {
+    spares: Vec<Drive>,
+}
+
+impl SparePool {
+    /// Creates an empty pool
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a spare to the pool
+    pub fn add(&mut self, drive: Drive) {
+        self.spares.push(drive);
+    }
+
+    /// Returns the number of spares left in the pool
+    pub fn len(&self) -> usize {
+        self.spares.len()
+    }
+
+    /// Returns whether the pool has run out of spares
+    pub fn is_empty(&self) -> bool {
+        self.spares.is_empty()
+    }
+
+    /// Takes the spare that has been in the pool the longest and is at least `size` bytes
+    fn take(&mut self, size: usize) -> Option<Drive> {
+        let index = self.spares.iter().position(|d| d.size() >= size)?;
+        Some(self.spares.remove(index))
+    }
+}
+
+/// Treats whatever is on a drive joining the array as stale, it's rebuilt before it's read and takes the array's superblock
+pub(super) fn clear_stale(drive: &mut Drive) {
+    drive.unformat();
+    drive.set_superblock(None);
+}
+
+impl RaidSim {
+    /// Adds a spare dedicated to this array. Whatever was on the drive is treated as stale.
+    pub fn add_spare(&mut self, mut drive: Drive) -> Result<()> {
+        self.record_unreplayable("add_spare");
+        if drive.size() < self.drive_size {
+            bail!(
+                "Spare of size {} is smaller than members of size {}",
+                drive.size(),
+                self.drive_size
+            );
+        }
+        clear_stale(&mut drive);
+        self.spares.push(drive);
+        Ok(())
+    }
+
+    /// Adds a drive to the running array, either as a dedicated spare or to be taken in by `grow()`.
+    /// Whatever was on the drive is treated as stale, and the array's superblocks are rewritten to record the change.
+    pub fn hot_add(&mut self, mut drive: Drive, role: HotAdd) -> Result<()> {
+        self.record_unreplayable("hot_add");
+        if drive.has_failed() {
+            bail!("Drive has failed, unable to hot-add");
+        }
+        clear_stale(&mut drive);
+        let event = match role {
+            HotAdd::Spare => {
+                self.add_spare(drive)?;
+                ArrayEvent::SpareAdded {
+                    spares: self.spares.len(),
+                }
+            }
+            HotAdd::Grow => {
+                if drive.size() < self.drive_size {
+                    bail!(
+                        "Drive of size {} is smaller than members of size {}",
+                        drive.size(),
+                        self.drive_size
+                    );
+                }
+                self.grow_pending.push(drive);
+                ArrayEvent::GrowPending {
+                    pending: self.grow_pending.len(),
+                }
+            }
+        };
+        self.bump_events();
+        self.emit(event);
+        Ok(())
+    }
+
+    /// Returns the drives waiting to join the array when it's next grown
+    pub fn grow_pending(&self) -> impl Iterator<Item = &Drive> {
+        self.grow_pending.iter()
+    }
+
+    /// Returns the spares dedicated to this array
+    pub fn spares(&self) -> impl Iterator<Item = &Drive> {
+        self.spares.iter()
+    }
+
+    /// Sets where the array looks for a spare when a member needs replacing
+    pub fn set_promotion_order(&mut self, order: PromotionOrder) {
+        let _recording = self.record(|| Op::SetPromotionOrder { order });
+        self.promotion_order = order;
+    }
+
+    /// Takes the spare that has been dedicated to this array the longest and is at least `size` bytes
+    fn take_dedicated(&mut self, size: usize) -> Option<Drive> {
+        let index = self.spares.iter().position(|d| d.size() >= size)?;
+        Some(self.spares.remove(index))
+    }
+
+    /// Replaces failed members with spares according to the promotion order, returning the indices that were replaced.
+    /// Promoted spares start out unformatted and still need to be repaired.
+    pub fn promote_spares(&mut self, mut pool: Option<&mut SparePool>) -> Vec<usize> {
+        let _recording = self.record(|| Op::PromoteSpares);
+        let pooled = pool.as_ref().map_or(0, |p| p.len());
+        let mut promoted = vec![];
+        for i in 0..self.drives.len() {
+            if !self.drives[i].has_failed() {
+                continue;
+            }
+            let size = self.drive_size;
+            let mut from_pool = || pool.as_deref_mut().and_then(|p| p.take(size));
+            let spare = match self.promotion_order {
+                PromotionOrder::DedicatedFirst => self.take_dedicated(size).or_else(from_pool),
+                PromotionOrder::GlobalFirst => from_pool().or_else(|| self.take_dedicated(size)),
+                PromotionOrder::DedicatedOnly => self.take_dedicated(size),
+            };
+            let mut spare = match spare {
+                Some(spare) => spare,
+                None => break,
+            };
+            clear_stale(&mut spare);
+            self.prepare_member(&mut spare);
+            self.drives[i] = spare;
+            promoted.push(i);
+        }
+        if pool.is_some_and(|p| p.len() < pooled) {
+            self.record_unreplayable("promote_spares");
+        }
+        self.sync_superblocks();
+        promoted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_dedicated_spares_before_global() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let mut pool = SparePool::new();
+        pool.add(Drive::empty(DRIVE_SIZE));
+        sim.add_spare(Drive::empty(DRIVE_SIZE)).unwrap();
+        assert!(sim.add_spare(Drive::empty(DRIVE_SIZE - 1)).is_err());
+
+        sim.fail_p_parity();
+        assert_eq!(sim.promote_spares(Some(&mut pool)), vec![P_INDEX]);
+        assert_eq!(sim.spares().count(), 0);
+        assert_eq!(pool.len(), 1);
+        sim.repair().unwrap();
+
+        // Two failures but only one spare left to go around
+        sim.fail_q_parity();
+        sim.fail_random_data();
+        assert_eq!(sim.promote_spares(Some(&mut pool)), vec![Q_INDEX]);
+        assert!(pool.is_empty());
+        assert_eq!(sim.failed().count(), 1);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        assert!(sim.promote_spares(Some(&mut pool)).is_empty());
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid5_global_pool_shared_between_arrays() {
+        let (mut a, a_data) = init_random(RaidMode::Raid5);
+        let (mut b, b_data) = init_random(RaidMode::Raid5);
+        a.set_promotion_order(PromotionOrder::GlobalFirst);
+        b.set_promotion_order(PromotionOrder::DedicatedOnly);
+        b.add_spare(Drive::empty(DRIVE_SIZE)).unwrap();
+        let mut pool = SparePool::new();
+        pool.add(Drive::empty(DRIVE_SIZE));
+
+        a.fail_random_data();
+        b.fail_random_data();
+        assert_eq!(a.promote_spares(Some(&mut pool)).len(), 1);
+        assert_eq!(b.promote_spares(Some(&mut pool)).len(), 1);
+        assert!(pool.is_empty());
+
+        a.repair().unwrap();
+        b.repair().unwrap();
+        assert_sim_equal(&a, &a_data);
+        assert_sim_equal(&b, &b_data);
+    }
+
+    #[test]
+    fn raid6_hot_add_emits_events() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = seen.clone();
+        sim.add_observer(move |event: &ArrayEvent| log.borrow_mut().push(event.clone()));
+
+        // A drive pulled from another array still has that array's superblock on it
+        let (other, _) = init_random(RaidMode::Raid6);
+        let mut foreign = other.stop();
+        sim.hot_add(foreign.pop().unwrap(), HotAdd::Spare).unwrap();
+        sim.hot_add(Drive::empty(DRIVE_SIZE), HotAdd::Grow).unwrap();
+        assert!(sim
+            .hot_add(Drive::empty(DRIVE_SIZE - 1), HotAdd::Grow)
+            .is_err());
+        assert_eq!(sim.grow_pending().count(), 1);
+        assert!(sim.spares().all(|d| d.superblock().is_none()));
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                ArrayEvent::MetadataUpdated { events: 2 },
+                ArrayEvent::SpareAdded { spares: 1 },
+                ArrayEvent::MetadataUpdated { events: 3 },
+                ArrayEvent::GrowPending { pending: 1 },
+            ]
+        );
+
+        sim.fail_random_data();
+        assert_eq!(sim.promote_spares(None).len(), 1);
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_formatted_spares_are_rebuilt() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let (other, _) = init_random(RaidMode::Raid6);
+        let mut foreign = other.stop();
+        sim.add_spare(foreign.pop().unwrap()).unwrap();
+        let mut pool = SparePool::new();
+        pool.add(foreign.pop().unwrap());
+
+        sim.fail_drive(3);
+        sim.fail_drive(4);
+        assert_eq!(sim.promote_spares(Some(&mut pool)), vec![3, 4]);
+        assert_eq!(sim.state(), RaidState::Degraded);
+        sim.repair().unwrap();
+        assert_eq!(sim.state(), RaidState::Ok);
+        assert_sim_equal(&sim, &data);
+    }
+}
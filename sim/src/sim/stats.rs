@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::generator::OpCounts;
+
+use super::{CacheStats, Capacity, OpCategory, RaidMode, RaidSim, RaidState, STRIPE_HEIGHT};
+
+/// What led to parity not matching its stripe's data
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MismatchCause {
+    /// Found by a scrub with nothing to say how it happened
+    Unknown,
+    /// Found while resyncing after the array wasn't shut down cleanly, a write only partially reached the drives
+    UncleanShutdown,
+}
+
+/// Number of parity mismatches found and corrected, counted once per stripe and parity drive
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MismatchCount {
+    pub found: u64,
+    pub corrected: u64,
+}
+
+impl MismatchCount {
+    fn add(&mut self, corrected: bool) {
+        self.found += 1;
+        if corrected {
+            self.corrected += 1;
+        }
+    }
+}
+
+/// Counters kept over the array's lifetime
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ArrayStats {
+    /// Parity mismatches across the whole array, like md's `mismatch_cnt`
+    pub mismatches: MismatchCount,
+    /// Parity mismatches by the index of the parity drive that didn't match
+    pub mismatches_by_drive: BTreeMap<usize, MismatchCount>,
+    /// Parity mismatches by what caused them
+    pub mismatches_by_cause: BTreeMap<MismatchCause, MismatchCount>,
+    /// Bytes written to the array by its user
+    pub logical_bytes_written: u64,
+    /// Bytes written to the current members, including parity, rebuilds, and repairs
+    pub physical_bytes_written: u64,
+    /// Read cache counters by the name of the eviction policy in use at the time
+    pub read_cache: BTreeMap<&'static str, CacheStats>,
+    /// Field arithmetic done by what it was done for, only counted with the `op-counts` feature
+    pub field_ops: BTreeMap<OpCategory, OpCounts>,
+}
+
+impl ArrayStats {
+    /// Returns how many bytes reached the drives for every byte the user wrote, if anything has been written
+    pub fn write_amplification(&self) -> Option<f64> {
+        (self.logical_bytes_written > 0)
+            .then(|| self.physical_bytes_written as f64 / self.logical_bytes_written as f64)
+    }
+}
+
+/// A snapshot of the array's configuration, health, and counters, like `mdadm --detail`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Detail {
+    pub mode: RaidMode,
+    pub state: RaidState,
+    pub num_drives: usize,
+    pub drive_size: usize,
+    /// Bytes of the array on a data member before the next member takes over
+    pub chunk_size: usize,
+    /// Number of bytes storable in the array
+    pub size: u64,
+    pub array_id: u64,
+    pub events: u64,
+    pub clean: bool,
+    /// Indices of the members that can't currently be used
+    pub unusable: Vec<usize>,
+    pub capacity: Capacity,
+    pub stats: ArrayStats,
+}
+
+impl fmt::Display for Detail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "     Raid Level : {:?}", self.mode)?;
+        writeln!(f, "     Array Size : {}", self.size)?;
+        writeln!(f, "   Raid Devices : {}", self.num_drives)?;
+        writeln!(f, "    Device Size : {}", self.drive_size)?;
+        writeln!(f, "     Chunk Size : {}", self.chunk_size)?;
+        writeln!(
+            f,
+            "Parity Overhead : {:.2}%",
+            self.capacity.parity_overhead()
+        )?;
+        writeln!(f, "Tolerated Fails : {}", self.capacity.tolerated_failures)?;
+        writeln!(f, "          State : {:?}", self.state)?;
+        writeln!(f, "          Clean : {}", self.clean)?;
+        writeln!(f, "           UUID : {:016x}", self.array_id)?;
+        writeln!(f, "         Events : {}", self.events)?;
+        writeln!(f, " Failed Devices : {:?}", self.unusable)?;
+        writeln!(
+            f,
+            " Mismatch Count : {} found, {} corrected",
+            self.stats.mismatches.found, self.stats.mismatches.corrected
+        )?;
+        writeln!(
+            f,
+            "  Bytes Written : {} logical, {} physical",
+            self.stats.logical_bytes_written, self.stats.physical_bytes_written
+        )?;
+        for (index, count) in &self.stats.mismatches_by_drive {
+            writeln!(
+                f,
+                "     Drive {:>4} : {} found, {} corrected",
+                index, count.found, count.corrected
+            )?;
+        }
+        for (cause, count) in &self.stats.mismatches_by_cause {
+            writeln!(
+                f,
+                "{:>15} : {} found, {} corrected",
+                format!("{:?}", cause),
+                count.found,
+                count.corrected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl RaidSim {
+    /// Returns the counters kept over the array's lifetime
+    pub fn stats(&self) -> ArrayStats {
+        let mut stats = ArrayStats {
+            physical_bytes_written: self.drives.iter().map(|d| d.stats().bytes_written).sum(),
+            field_ops: self.field_ops.counts(),
+            ..self.stats.clone()
+        };
+        if let Some(cache) = self.read_cache.borrow().as_ref() {
+            stats
+                .read_cache
+                .entry(cache.policy_name())
+                .or_default()
+                .merge(&cache.stats());
+        }
+        stats
+    }
+
+    /// Returns a snapshot of the array's configuration, health, and counters
+    pub fn detail(&self) -> Detail {
+        Detail {
+            mode: self.mode,
+            state: self.state(),
+            num_drives: self.drives.len(),
+            drive_size: self.drive_size,
+            chunk_size: self.chunk_size,
+            size: self.size(),
+            array_id: self.array_id,
+            events: self.events,
+            clean: self.clean,
+            unusable: (0..self.drives.len())
+                .filter(|i| !self.drives[*i].usable())
+                .collect(),
+            capacity: Capacity::new(self.mode, self.drives.len(), self.drive_size, STRIPE_HEIGHT),
+            stats: self.stats(),
+        }
+    }
+
+    /// Counts a stripe whose parity on the drive at `index` didn't match its data
+    pub(super) fn record_mismatch(&mut self, index: usize, cause: MismatchCause, corrected: bool) {
+        self.stats.mismatches.add(corrected);
+        self.stats
+            .mismatches_by_drive
+            .entry(index)
+            .or_default()
+            .add(corrected);
+        self.stats
+            .mismatches_by_cause
+            .entry(cause)
+            .or_default()
+            .add(corrected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn read_modify_write_amplification() {
+        for (mode, amplification) in [(RaidMode::Raid5, 2.0), (RaidMode::Raid6, 3.0)] {
+            let mut sim = RaidSim::builder()
+                .mode(mode)
+                .drives(NUM_DRIVES, DRIVE_SIZE)
+                .build()
+                .unwrap();
+            sim.init().unwrap();
+            assert_eq!(sim.stats().write_amplification(), None);
+
+            sim.write(DRIVE_SIZE as u64 + 7, 1).unwrap();
+            sim.write_slice(0, &[2; 100]).unwrap();
+            let stats = sim.stats();
+            assert_eq!(stats.logical_bytes_written, 101);
+            assert_eq!(stats.write_amplification(), Some(amplification));
+            assert_eq!(sim.p_parity().stats().bytes_written, 101);
+            assert_eq!(sim.data_drives().next().unwrap().stats().bytes_written, 100);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+
+use super::{DriveHandle, DriveHealth, DriveRole, RaidSim};
+
+/// Says how far a drive is from holding everything it should
+fn sync_status(drive: &DriveHandle, rebuild: Option<(usize, usize)>) -> String {
+    let missed = drive.drive().missed_writes().count();
+    match (drive.role(), drive.health()) {
+        (DriveRole::Spare, _) | (_, DriveHealth::Failed) => "-".to_string(),
+        (_, DriveHealth::Rebuilding) => match rebuild {
+            Some((done, total)) => format!("{}/{}", done, total),
+            None => "pending".to_string(),
+        },
+        _ if missed > 0 => format!("{} behind", missed),
+        _ => "in sync".to_string(),
+    }
+}
+
+/// A summary of the array for logs and test output: its geometry and state, then a row per member and spare
+/// with what it does, whether it can be used, whether it's caught up, and its counters.
+///
+/// Sync is how many stripes of a rebuild are done, or how many sectors a write-protected member missed.
+impl fmt::Display for RaidSim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rebuild = self.rebuild_progress();
+        writeln!(
+            f,
+            "{:?} array of {} drives, {} bytes each, {} bytes usable",
+            self.mode,
+            self.drives.len(),
+            self.drive_size,
+            self.size()
+        )?;
+        // Like mdadm, an array that may have writes in flight is active rather than clean
+        write!(
+            f,
+            "State {:?}, {}, events {}",
+            self.state(),
+            if self.clean { "clean" } else { "active" },
+            self.events
+        )?;
+        if let Some((done, total)) = rebuild {
+            write!(f, ", rebuilt {}/{} stripes", done, total)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>4}  {:<8} {:<14} {:<10} {:>10} {:>7} {:>7} {:>5} {:>5} {:>8}",
+            "Slot",
+            "Role",
+            "Health",
+            "Sync",
+            "Written",
+            "Errors",
+            "Retries",
+            "Bad",
+            "Hangs",
+            "Timeouts"
+        )?;
+        for drive in self.drives() {
+            let stats = drive.stats();
+            let index = match drive.role() {
+                DriveRole::Spare => format!("s{}", drive.index()),
+                _ => drive.index().to_string(),
+            };
+            writeln!(
+                f,
+                "{:>4}  {:<8} {:<14} {:<10} {:>10} {:>7} {:>7} {:>5} {:>5} {:>8}",
+                index,
+                format!("{:?}", drive.role()),
+                format!("{:?}", drive.health()),
+                sync_status(&drive, rebuild),
+                stats.bytes_written,
+                stats.read_errors,
+                stats.retries,
+                stats.marked_bad,
+                stats.hangs,
+                stats.timeouts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::*;
+
+    #[test]
+    fn status_has_a_row_per_drive() {
+        let mut sim = RaidSim::builder()
+            .mode(RaidMode::Raid6)
+            .drives(5, 1024)
+            .spares(1)
+            .seed(3)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.write(0, 1).unwrap();
+        sim.fail_drive(4);
+
+        let status = sim.to_string();
+        let lines = status.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "Raid6 array of 5 drives, 1024 bytes each, 3072 bytes usable"
+        );
+        assert!(lines[1].starts_with("State Degraded"), "{}", lines[1]);
+        assert_eq!(lines.len(), 3 + 5 + 1);
+        assert!(lines[3].contains("P ") && lines[3].contains("in sync"));
+        assert!(lines[7].contains("Data(2)") && lines[7].contains("Failed"));
+        assert!(lines[8].trim_start().starts_with("s0") && lines[8].contains("Spare"));
+    }
+}
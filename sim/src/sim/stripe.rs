@@ -0,0 +1,347 @@
+use std::{borrow::Cow, ops::Range};
+
+use anyhow::{bail, Result};
+
+use crate::generator::syndrome;
+
+use super::{
+    ignore_ejected, Access, Op, OpCategory, RaidError, RaidMode, RaidSim, RaidState, Trigger,
+    P_INDEX, Q_INDEX, STRIPE_HEIGHT,
+};
+
+/// One stripe of the array, the same `STRIPE_HEIGHT` bytes of every member
+#[derive(Debug, Clone, Copy)]
+pub struct StripeView<'a> {
+    sim: &'a RaidSim,
+    index: usize,
+}
+
+impl<'a> StripeView<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the offsets on each member the stripe covers
+    pub fn offsets(&self) -> Range<usize> {
+        let start = self.index * STRIPE_HEIGHT;
+        start..(start + STRIPE_HEIGHT).min(self.sim.drive_size)
+    }
+
+    /// Reads the stripe's chunk of the member at `member`.
+    /// A replacement has nothing to read until the rebuild has been through the stripe.
+    pub fn chunk(&self, member: usize) -> Result<Cow<'a, [u8]>> {
+        let offsets = self.offsets();
+        let drive = &self.sim.drives[member];
+        if !drive.has_failed() && !self.sim.current_at(member, offsets.start) {
+            bail!(
+                "Member {} hasn't been rebuilt as far as stripe {}",
+                member,
+                self.index
+            );
+        }
+        drive.read_range(offsets)
+    }
+
+    /// Reads the stripe's chunk of every data drive, in order
+    pub fn data_chunks(&self) -> impl Iterator<Item = Result<Cow<'a, [u8]>>> + '_ {
+        (self.sim.data_start()..self.sim.drives.len()).map(move |i| self.chunk(i))
+    }
+
+    /// Reads the stripe's chunk of P and then Q
+    pub fn parity_chunks(&self) -> impl Iterator<Item = Result<Cow<'a, [u8]>>> + '_ {
+        (0..self.sim.data_start()).map(move |i| self.chunk(i))
+    }
+
+    /// Checks every row of the stripe against its parity, leaving out rows already known to be dirty
+    pub fn is_consistent(&self) -> Result<bool> {
+        for offset in self.offsets() {
+            if self.sim.is_dirty(offset) {
+                continue;
+            }
+            let data = self.sim.read_data_row(offset)?;
+            if self.sim.read_retry(P_INDEX, offset)? != syndrome(&data, 0) {
+                return Ok(false);
+            }
+            if self.sim.mode == RaidMode::Raid6
+                && self.sim.read_retry(Q_INDEX, offset)? != syndrome(&data, 1)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// One stripe of the array that can be written through, see `StripeView`
+#[derive(Debug)]
+pub struct StripeViewMut<'a> {
+    sim: &'a mut RaidSim,
+    index: usize,
+}
+
+impl StripeViewMut<'_> {
+    /// Returns a read-only view of the same stripe
+    pub fn view(&self) -> StripeView<'_> {
+        StripeView {
+            sim: self.sim,
+            index: self.index,
+        }
+    }
+
+    /// Writes `chunk` to the stripe's chunk of data drive `index`, starting at the top of the stripe.
+    /// It goes through the array like any other write, parity included.
+    pub fn write_data(&mut self, index: usize, chunk: &[u8]) -> Result<()> {
+        let offsets = self.view().offsets();
+        if chunk.len() > offsets.len() {
+            bail!(
+                "Chunk of {} bytes doesn't fit in a stripe of {}",
+                chunk.len(),
+                offsets.len()
+            );
+        }
+        if index >= self.sim.data_drives().count() {
+            bail!("No data drive {}", index);
+        }
+        let offset = index * self.sim.drive_size + offsets.start;
+        self.sim.write_slice(offset as u64, chunk)
+    }
+}
+
+/// Writes one whole stripe at once, the fast path a filesystem takes when it fills a stripe.
+/// Parity is computed from the new data alone, so nothing has to be read first.
+#[derive(Debug)]
+pub struct StripeWriter<'a> {
+    sim: &'a mut RaidSim,
+    index: usize,
+}
+
+impl StripeWriter<'_> {
+    /// Returns how many bytes a write to the stripe takes, a chunk for every data drive
+    pub fn data_len(&self) -> usize {
+        let height = StripeView {
+            sim: self.sim,
+            index: self.index,
+        }
+        .offsets()
+        .len();
+        height * self.sim.data_drives().count()
+    }
+
+    /// Writes `data`, the stripe's chunk of each data drive one after another, along with its parity
+    pub fn write(self, data: &[u8]) -> Result<()> {
+        let index = self.index;
+        let _recording = self.sim.record(|| Op::WriteStripe {
+            index,
+            data: data.to_vec(),
+        });
+        if data.len() != self.data_len() {
+            bail!(
+                "A stripe holds {} bytes of data, got {}",
+                self.data_len(),
+                data.len()
+            );
+        }
+        let (sim, index) = (self.sim, self.index);
+        let _counting = sim.count_ops(OpCategory::Write);
+        let offsets = StripeView { sim, index }.offsets();
+        let height = offsets.len();
+        if sim.state() == RaidState::Failed {
+            return Err(RaidError::Failed {
+                access: Access::Write,
+            }
+            .into());
+        }
+        // Cached writes to the stripe would otherwise land on top of this one later
+        sim.flush()?;
+        sim.mark_unclean();
+        sim.stats.logical_bytes_written += data.len() as u64;
+        for (i, chunk) in data.chunks(height).enumerate() {
+            let offset = i * sim.drive_size + offsets.start;
+            sim.preserve_for_views(offset, offset + height)?;
+            sim.invalidate_cache(offset, offset + height);
+            sim.drop_protection(offset, offset + height);
+            sim.mark_written(offset, offset + height);
+            sim.record_integrity(offset, chunk)?;
+        }
+        sim.mark_bitmap(offsets.start, offsets.end);
+
+        let data_start = sim.data_start();
+        for (i, chunk) in data.chunks(height).enumerate() {
+            sim.note_rebuild_write(data_start + i, offsets.start, offsets.end);
+            if !sim.drives[data_start + i].has_failed() {
+                let result = sim.write_member(data_start + i, offsets.start, chunk);
+                ignore_ejected(&sim.drives[data_start + i], result)?;
+            }
+        }
+        let chunks = data.chunks(height).collect::<Vec<&[u8]>>();
+        let (mut p, mut q) = (vec![0; height], vec![0; height]);
+        sim.engine.gen_syndrome(&chunks, &mut p, &mut q);
+        let mut parity = vec![(P_INDEX, p)];
+        if sim.mode == RaidMode::Raid6 {
+            parity.push((Q_INDEX, q));
+        }
+        for (member, chunk) in parity {
+            if sim.current_at(member, offsets.start) {
+                let result = sim.write_member(member, offsets.start, &chunk);
+                ignore_ejected(&sim.drives[member], result)?;
+            }
+        }
+        // Parity was just computed from scratch, whatever was owed to the stripe is settled
+        sim.dirty.remove(&index);
+        sim.note_state(|| Trigger::ParityFlushed);
+        Ok(())
+    }
+}
+
+impl RaidSim {
+    /// Returns a writer for the whole of stripe `index`
+    pub fn stripe_writer(&mut self, index: usize) -> Result<StripeWriter<'_>> {
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        if index >= stripes {
+            bail!("No stripe {} in an array of {} stripes", index, stripes);
+        }
+        Ok(StripeWriter { sim: self, index })
+    }
+
+    /// Returns the stripes whose every data chunk lies inside array offsets `offset..offset + len`.
+    /// With write-back caching the write is absorbed by the cache instead, and with deferred parity it's left dirty.
+    pub(super) fn full_stripes(&self, offset: usize, len: usize) -> Range<usize> {
+        if self.write_back.is_some() || self.defer_parity() || len == 0 {
+            return 0..0;
+        }
+        // A stripe is covered if the write starts by its chunk on the first data drive and ends after the one on the last.
+        // Starting or ending on any other drive leaves out that whole row of chunks.
+        let (drive_index, drive_offset) = self.locate(offset);
+        let first = if drive_index == 0 {
+            drive_offset.div_ceil(STRIPE_HEIGHT)
+        } else {
+            ((drive_offset / self.chunk_size + 1) * self.chunk_size).div_ceil(STRIPE_HEIGHT)
+        };
+        let (drive_index, drive_offset) = self.locate(offset + len - 1);
+        let last = if drive_index + 1 < self.data_drives().count() {
+            drive_offset / self.chunk_size * self.chunk_size / STRIPE_HEIGHT
+        } else if drive_offset + 1 == self.drive_size {
+            self.drive_size.div_ceil(STRIPE_HEIGHT)
+        } else {
+            (drive_offset + 1) / STRIPE_HEIGHT
+        };
+        first..last.max(first)
+    }
+
+    /// Returns every stripe of the array in order
+    pub fn stripes(&self) -> impl Iterator<Item = StripeView<'_>> {
+        (0..self.drive_size.div_ceil(STRIPE_HEIGHT))
+            .map(move |index| StripeView { sim: self, index })
+    }
+
+    /// Returns stripe `index` to be written through
+    pub fn stripe_mut(&mut self, index: usize) -> Result<StripeViewMut<'_>> {
+        let stripes = self.drive_size.div_ceil(STRIPE_HEIGHT);
+        if index >= stripes {
+            bail!("No stripe {} in an array of {} stripes", index, stripes);
+        }
+        Ok(StripeViewMut { sim: self, index })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, cell::Cell, rc::Rc};
+
+    use crate::generator::{GfEngine, ParityEngine};
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_stripes_expose_chunks() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid6);
+        assert_eq!(sim.stripes().count(), DRIVE_SIZE / STRIPE_HEIGHT);
+        let stripe = sim.stripes().nth(1).unwrap();
+        assert_eq!(stripe.offsets(), STRIPE_HEIGHT..2 * STRIPE_HEIGHT);
+        let chunks = stripe
+            .data_chunks()
+            .collect::<Result<Vec<Cow<[u8]>>>>()
+            .unwrap();
+        assert_eq!(chunks.len(), NUM_DRIVES - 2);
+        assert_eq!(
+            chunks[3],
+            &data[3 * DRIVE_SIZE + STRIPE_HEIGHT..4 * DRIVE_SIZE]
+        );
+        assert_eq!(stripe.parity_chunks().count(), 2);
+        assert!(sim.stripes().all(|s| s.is_consistent().unwrap()));
+
+        let chunk = vec![0xa5; 16];
+        sim.stripe_mut(1).unwrap().write_data(3, &chunk).unwrap();
+        let offset = 3 * DRIVE_SIZE + STRIPE_HEIGHT;
+        data[offset..offset + 16].copy_from_slice(&chunk);
+        assert!(sim.stripes().all(|s| s.is_consistent().unwrap()));
+        assert_sim_equal(&sim, &data);
+        assert!(sim.stripe_mut(2).is_err());
+        assert!(sim.stripe_mut(1).unwrap().write_data(3, &[0; 513]).is_err());
+    }
+
+    #[test]
+    fn raid5_stripe_writer_computes_parity_from_scratch() {
+        let (mut sim, mut data) = init_random(RaidMode::Raid5);
+        let writer = sim.stripe_writer(0).unwrap();
+        let len = writer.data_len();
+        assert_eq!(len, STRIPE_HEIGHT * (NUM_DRIVES - 1));
+        assert!(writer.write(&[0; 10]).is_err());
+
+        let before = sim.stats().physical_bytes_written;
+        let stripe = (0..len).map(|i| (i * 13) as u8).collect::<Vec<u8>>();
+        sim.stripe_writer(0).unwrap().write(&stripe).unwrap();
+        // Every member, parity included, written once
+        assert_eq!(
+            sim.stats().physical_bytes_written - before,
+            (STRIPE_HEIGHT * NUM_DRIVES) as u64
+        );
+        for (i, chunk) in stripe.chunks(STRIPE_HEIGHT).enumerate() {
+            data[i * DRIVE_SIZE..i * DRIVE_SIZE + STRIPE_HEIGHT].copy_from_slice(chunk);
+        }
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+        assert_sim_equal(&sim, &data);
+
+        // A slice to the end of the array covers all of stripe 1, which takes the same path,
+        // and the rest of it is read-modify-write
+        sim.drive_mut(3).fail();
+        let offset = 100;
+        let slice = vec![0x77; DRIVE_SIZE * (NUM_DRIVES - 1) - offset];
+        assert_eq!(sim.full_stripes(offset, slice.len()), 1..2);
+        sim.write_slice(offset as u64, &slice).unwrap();
+        data[offset..offset + slice.len()].copy_from_slice(&slice);
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_stripe_writer_uses_the_parity_engine() {
+        /// Counts the stripes it computes parity for
+        #[derive(Debug, Default)]
+        struct Counting(Rc<Cell<usize>>);
+
+        impl ParityEngine for Counting {
+            fn gen_syndrome(&self, data: &[&[u8]], p: &mut [u8], q: &mut [u8]) {
+                self.0.set(self.0.get() + 1);
+                GfEngine.gen_syndrome(data, p, q);
+            }
+
+            fn recover(&self, chunks: &mut [&mut [u8]], lost: &[usize]) -> gf::Result<()> {
+                GfEngine.recover(chunks, lost)
+            }
+        }
+
+        let engine = Counting::default();
+        let calls = engine.0.clone();
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .parity_engine(engine)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        let len = sim.stripe_writer(1).unwrap().data_len();
+        let stripe = (0..len).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        sim.stripe_writer(1).unwrap().write(&stripe).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert!(sim.inconsistent_stripes().unwrap().is_empty());
+    }
+}
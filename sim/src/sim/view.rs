@@ -0,0 +1,102 @@
+use std::{
+    cell::RefCell,
+    collections::{btree_map::Entry, BTreeMap},
+    rc::{Rc, Weak},
+};
+
+use anyhow::{bail, Result};
+
+use super::RaidSim;
+
+/// Bytes overwritten on the live array since a view was frozen, keyed by array offset
+pub(super) type Preserved = RefCell<BTreeMap<usize, u8>>;
+
+/// A read-only view of an array as it was when `freeze_view()` was called.
+///
+/// Nothing is copied up front, the live array saves a byte for every open view the first time it's overwritten.
+/// Dropping the view stops the array from saving anything more for it.
+#[derive(Debug)]
+pub struct FrozenView {
+    array_id: u64,
+    preserved: Rc<Preserved>,
+}
+
+impl FrozenView {
+    /// Reads a byte at a specific offset in the array as it was when the view was frozen
+    pub fn read(&self, sim: &RaidSim, offset: u64) -> Result<u8> {
+        if sim.array_id != self.array_id {
+            bail!("View was frozen from a different array, unable to read");
+        }
+        let index = sim.logical_offset(offset, 1)?;
+        match self.preserved.borrow().get(&index) {
+            Some(data) => Ok(*data),
+            None => sim.read(offset),
+        }
+    }
+
+    /// Returns the number of bytes the live array has had to save for this view
+    pub fn preserved(&self) -> usize {
+        self.preserved.borrow().len()
+    }
+}
+
+impl RaidSim {
+    /// Freezes a read-only view of the array at this point in time, while writes carry on to the live array
+    pub fn freeze_view(&mut self) -> FrozenView {
+        let preserved = Rc::new(Preserved::default());
+        self.views.push(Rc::downgrade(&preserved));
+        FrozenView {
+            array_id: self.array_id,
+            preserved,
+        }
+    }
+
+    /// Saves the current contents of array offsets `start..end` for every open view that doesn't have them yet
+    pub(super) fn preserve_for_views(&mut self, start: usize, end: usize) -> Result<()> {
+        self.views.retain(|v| v.strong_count() > 0);
+        for view in self.views.iter().filter_map(Weak::upgrade) {
+            let mut preserved = view.borrow_mut();
+            for offset in start..end {
+                if let Entry::Vacant(entry) = preserved.entry(offset) {
+                    entry.insert(match self.pending_byte(offset) {
+                        Some(data) => data,
+                        None => self.read_uncached(offset)?,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_frozen_view_keeps_old_data() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        let view = sim.freeze_view();
+        assert_eq!(view.preserved(), 0);
+
+        sim.write(5, !data[5]).unwrap();
+        sim.write(5, data[5] ^ 1).unwrap();
+        sim.write_slice(DRIVE_SIZE as u64 - 2, &[0; 4]).unwrap();
+        assert_eq!(view.preserved(), 5);
+        assert_eq!(sim.read(5).unwrap(), data[5] ^ 1);
+
+        // The view still reads the old data, even from a degraded array
+        sim.fail_random_data();
+        for (offset, byte) in data.iter().enumerate() {
+            assert_eq!(view.read(&sim, offset as u64).unwrap(), *byte);
+        }
+
+        let (other, _) = init_random(RaidMode::Raid6);
+        assert!(view.read(&other, 0).is_err());
+
+        drop(view);
+        sim.write(6, 0).unwrap();
+        assert!(sim.views.is_empty());
+    }
+}
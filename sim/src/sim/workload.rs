@@ -0,0 +1,264 @@
+use anyhow::{bail, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Access, RaidError, RaidSim, RaidState};
+
+/// One I/O a workload asks of the array
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Io {
+    Read { offset: u64, len: usize },
+    Write { offset: u64, len: usize },
+}
+
+/// A pattern of reads and writes to drive an array with.
+/// Anything random is drawn from `rng`, so the same seed asks for the same I/Os.
+pub trait Workload {
+    /// Returns the next I/O to run against an array of `size` bytes
+    fn next_io(&mut self, size: u64, rng: &mut StdRng) -> Io;
+}
+
+/// Turns the start of an I/O into a read or a write, `read_percent` of the time a read
+fn io(offset: u64, io_size: usize, size: u64, read_percent: u32, rng: &mut StdRng) -> Io {
+    let len = io_size.min((size - offset) as usize);
+    if rng.random_range(0..100) < read_percent {
+        Io::Read { offset, len }
+    } else {
+        Io::Write { offset, len }
+    }
+}
+
+/// Walks the array from start to end in `io_size` steps, then wraps around
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Sequential {
+    pub io_size: usize,
+    pub read_percent: u32,
+    next: u64,
+}
+
+impl Sequential {
+    pub fn new(io_size: usize, read_percent: u32) -> Self {
+        Self {
+            io_size,
+            read_percent,
+            next: 0,
+        }
+    }
+}
+
+impl Workload for Sequential {
+    fn next_io(&mut self, size: u64, rng: &mut StdRng) -> Io {
+        if self.next >= size {
+            self.next = 0;
+        }
+        let io = io(self.next, self.io_size, size, self.read_percent, rng);
+        self.next += self.io_size as u64;
+        io
+    }
+}
+
+/// Starts every I/O at an `io_size` aligned offset picked uniformly across the array
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct UniformRandom {
+    pub io_size: usize,
+    pub read_percent: u32,
+}
+
+impl Workload for UniformRandom {
+    fn next_io(&mut self, size: u64, rng: &mut StdRng) -> Io {
+        let block = rng.random_range(0..size.div_ceil(self.io_size as u64));
+        io(
+            block * self.io_size as u64,
+            self.io_size,
+            size,
+            self.read_percent,
+            rng,
+        )
+    }
+}
+
+/// Picks `io_size` blocks with a Zipfian skew, the block of rank `k` is picked in proportion to `1 / k^theta`.
+/// Ranks run from the start of the array, so a few blocks at the front take most of the I/O.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zipfian {
+    pub io_size: usize,
+    pub read_percent: u32,
+    /// How skewed the picks are, 0 is uniform and around 1 is typical of real hot spots
+    pub theta: f64,
+    /// Cumulative probability of each block, worked out for the size of array last seen
+    cdf: Vec<f64>,
+}
+
+impl Zipfian {
+    pub fn new(io_size: usize, read_percent: u32, theta: f64) -> Self {
+        Self {
+            io_size,
+            read_percent,
+            theta,
+            cdf: vec![],
+        }
+    }
+}
+
+impl Workload for Zipfian {
+    fn next_io(&mut self, size: u64, rng: &mut StdRng) -> Io {
+        let blocks = size.div_ceil(self.io_size as u64) as usize;
+        if self.cdf.len() != blocks {
+            let weights = (1..=blocks).map(|k| 1.0 / (k as f64).powf(self.theta));
+            let total = weights.clone().sum::<f64>();
+            self.cdf = weights
+                .scan(0.0, |sum, w| {
+                    *sum += w / total;
+                    Some(*sum)
+                })
+                .collect();
+        }
+        let pick = rng.random::<f64>();
+        let block = self.cdf.partition_point(|p| *p < pick).min(blocks - 1);
+        io(
+            (block * self.io_size) as u64,
+            self.io_size,
+            size,
+            self.read_percent,
+            rng,
+        )
+    }
+}
+
+/// What a workload did to an array
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct WorkloadReport {
+    pub reads: u64,
+    pub writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl RaidSim {
+    /// Runs `ops` I/Os from `workload` against the array, writing random data seeded by `seed`.
+    /// Stops at the first I/O the array fails.
+    pub fn run_workload(
+        &mut self,
+        workload: &mut dyn Workload,
+        ops: usize,
+        seed: u64,
+    ) -> Result<WorkloadReport> {
+        let size = self.size();
+        if size == 0 {
+            bail!("Array has no room for a workload");
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut report = WorkloadReport::default();
+        for _ in 0..ops {
+            match workload.next_io(size, &mut rng) {
+                Io::Read { offset, len } => {
+                    let start = self.logical_offset(offset, len)?;
+                    if self.state() == RaidState::Failed {
+                        return Err(RaidError::Failed {
+                            access: Access::Read,
+                        }
+                        .into());
+                    }
+                    for i in start..start + len {
+                        self.read_at(i)?;
+                    }
+                    report.reads += 1;
+                    report.bytes_read += len as u64;
+                }
+                Io::Write { offset, len } => {
+                    let data = (0..len).map(|_| rng.random()).collect::<Vec<u8>>();
+                    self.write_slice(offset, &data)?;
+                    report.writes += 1;
+                    report.bytes_written += len as u64;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn workloads_follow_their_patterns() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let size = 10_000;
+
+        let mut sequential = Sequential::new(4096, 0);
+        let offsets = (0..4)
+            .map(|_| sequential.next_io(size, &mut rng))
+            .collect::<Vec<Io>>();
+        assert_eq!(
+            offsets,
+            [
+                Io::Write {
+                    offset: 0,
+                    len: 4096
+                },
+                Io::Write {
+                    offset: 4096,
+                    len: 4096
+                },
+                Io::Write {
+                    offset: 8192,
+                    len: 1808
+                },
+                Io::Write {
+                    offset: 0,
+                    len: 4096
+                },
+            ]
+        );
+
+        let mut uniform = UniformRandom {
+            io_size: 100,
+            read_percent: 100,
+        };
+        for _ in 0..100 {
+            let Io::Read { offset, len } = uniform.next_io(size, &mut rng) else {
+                panic!("Expected only reads");
+            };
+            assert_eq!((offset % 100, len), (0, 100));
+        }
+
+        // The first block of a skewed workload is by far the hottest
+        let mut zipfian = Zipfian::new(100, 50, 1.0);
+        let first = (0..1000)
+            .filter(|_| {
+                let (Io::Read { offset, .. } | Io::Write { offset, .. }) =
+                    zipfian.next_io(size, &mut rng);
+                offset == 0
+            })
+            .count();
+        assert!(first > 100, "{}", first);
+    }
+
+    #[test]
+    fn raid6_runs_workload_repeatably() {
+        let run = || {
+            let mut sim = RaidSim::builder()
+                .drives(NUM_DRIVES, DRIVE_SIZE)
+                .build()
+                .unwrap();
+            sim.init().unwrap();
+            let mut workload = UniformRandom {
+                io_size: 512,
+                read_percent: 70,
+            };
+            let report = sim.run_workload(&mut workload, 200, 9).unwrap();
+            let data = (0..sim.size())
+                .map(|i| sim.read(i).unwrap())
+                .collect::<Vec<u8>>();
+            (report, data)
+        };
+        let (report, data) = run();
+        assert_eq!(report.reads + report.writes, 200);
+        assert!(report.reads > report.writes);
+        assert_eq!(report.bytes_written, report.writes * 512);
+        assert_eq!(run().1, data);
+    }
+}
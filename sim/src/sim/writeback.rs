@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::generator::syndrome;
+
+use super::{ignore_ejected, Op, OpCategory, RaidMode, RaidSim, RaidState, P_INDEX, Q_INDEX};
+
+/// Sizes the write-back cache
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct WriteBackConfig {
+    /// Number of bytes held before the cache is flushed to make room
+    pub capacity: usize,
+}
+
+impl Default for WriteBackConfig {
+    fn default() -> Self {
+        Self { capacity: 4096 }
+    }
+}
+
+/// Counters kept by the write-back cache
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct WriteBackStats {
+    /// Writes held in the cache instead of going to the drives
+    pub absorbed: u64,
+    /// Writes to a byte that was already waiting in the cache, saving a write to the drives
+    pub overwritten: u64,
+    /// Rows flushed with every data byte in the cache, so parity was computed without reading anything
+    pub full_stripe_rows: u64,
+    /// Bytes flushed with a read-modify-write because the rest of their row wasn't in the cache
+    pub read_modify_writes: u64,
+    /// Number of times the cache was flushed
+    pub flushes: u64,
+}
+
+/// Writes held above the stripe logic until they are flushed
+#[derive(Debug, Clone)]
+pub(super) struct WriteBack {
+    config: WriteBackConfig,
+    /// Bytes waiting to be written, keyed by array offset
+    pending: BTreeMap<usize, u8>,
+    stats: WriteBackStats,
+}
+
+impl RaidSim {
+    /// Turns the write-back cache on with the given config, or off with `None`.
+    /// Anything waiting in the old cache is flushed first.
+    pub fn set_write_back(&mut self, config: Option<WriteBackConfig>) -> Result<()> {
+        let _recording = self.record(|| Op::SetWriteBack { config });
+        self.flush()?;
+        self.write_back = config.map(|config| WriteBack {
+            config,
+            pending: BTreeMap::new(),
+            stats: WriteBackStats::default(),
+        });
+        Ok(())
+    }
+
+    /// Returns the write-back cache's counters, or `None` if there is no write-back cache
+    pub fn write_back_stats(&self) -> Option<WriteBackStats> {
+        self.write_back.as_ref().map(|wb| wb.stats)
+    }
+
+    /// Returns the number of bytes written to the array that haven't reached the drives yet
+    pub fn pending_writes(&self) -> usize {
+        self.write_back.as_ref().map_or(0, |wb| wb.pending.len())
+    }
+
+    /// Returns the byte waiting in the write-back cache for array offset `offset`, if there is one
+    pub(super) fn pending_byte(&self, offset: usize) -> Option<u8> {
+        self.write_back.as_ref()?.pending.get(&offset).copied()
+    }
+
+    /// Whether the same bytes are waiting in both arrays' write-back caches, none counting the same as no cache
+    pub(super) fn same_pending(&self, other: &RaidSim) -> bool {
+        let pending = |sim: &RaidSim| sim.write_back.as_ref().map(|wb| wb.pending.clone());
+        pending(self).unwrap_or_default() == pending(other).unwrap_or_default()
+    }
+
+    /// Holds a write in the write-back cache, returning false if there is no cache and it has to go to the drives.
+    /// The cache is flushed once it fills up.
+    pub(super) fn absorb_write(&mut self, offset: usize, data: u8) -> Result<bool> {
+        let wb = match self.write_back.as_mut() {
+            Some(wb) => wb,
+            None => return Ok(false),
+        };
+        wb.stats.absorbed += 1;
+        if wb.pending.insert(offset, data).is_some() {
+            wb.stats.overwritten += 1;
+        }
+        if wb.pending.len() >= wb.config.capacity {
+            self.flush()?;
+        }
+        Ok(true)
+    }
+
+    /// Writes everything waiting in the write-back cache to the drives.
+    /// Rows where every data byte is waiting get their parity computed straight from the new data,
+    /// the rest are written with a read-modify-write as usual.
+    /// Bytes stay in the cache until they've been written, so nothing is lost if the flush fails.
+    pub fn flush(&mut self) -> Result<()> {
+        let _recording = self.record(|| Op::Flush);
+        let _counting = self.count_ops(OpCategory::Write);
+        let mut rows: BTreeMap<usize, Vec<(usize, u8)>> = BTreeMap::new();
+        match self.write_back.as_ref() {
+            Some(wb) if !wb.pending.is_empty() => {
+                for (&offset, &data) in &wb.pending {
+                    let (drive_index, drive_offset) = self.locate(offset);
+                    rows.entry(drive_offset)
+                        .or_default()
+                        .push((drive_index, data));
+                }
+            }
+            _ => return Ok(()),
+        }
+        if self.state() == RaidState::Failed {
+            bail!(
+                "Array failed, {} cached bytes can't be written",
+                self.pending_writes()
+            );
+        }
+
+        let width = self.data_drives().count();
+        for (drive_offset, writes) in rows {
+            let offsets = writes
+                .iter()
+                .map(|&(drive_index, _)| self.logical_at(drive_index, drive_offset))
+                .collect::<Vec<_>>();
+            if writes.len() == width && !self.defer_parity() {
+                let data = writes.into_iter().map(|(_, d)| d).collect::<Vec<u8>>();
+                self.write_full_row(drive_offset, &data)?;
+                self.flushed(&offsets, true);
+            } else {
+                for ((drive_index, data), offset) in writes.into_iter().zip(offsets) {
+                    self.write_byte(drive_index, drive_offset, data)?;
+                    self.flushed(&[offset], false);
+                }
+            }
+        }
+
+        self.write_back.as_mut().unwrap().stats.flushes += 1;
+        Ok(())
+    }
+
+    /// Drops bytes that have reached the drives from the write-back cache, and any blocks the read cache
+    /// fetched while they were still waiting
+    fn flushed(&mut self, offsets: &[usize], full_row: bool) {
+        for &offset in offsets {
+            self.invalidate_cache(offset, offset + 1);
+        }
+        let wb = self.write_back.as_mut().unwrap();
+        for offset in offsets {
+            wb.pending.remove(offset);
+        }
+        if full_row {
+            wb.stats.full_stripe_rows += 1;
+        } else {
+            wb.stats.read_modify_writes += offsets.len() as u64;
+        }
+    }
+
+    /// Writes a byte to every data drive at `drive_offset` along with parity computed from them
+    fn write_full_row(&mut self, drive_offset: usize, data: &[u8]) -> Result<()> {
+        self.mark_bitmap(drive_offset, drive_offset + 1);
+        for member in self.data_start()..self.drives.len() {
+            self.note_rebuild_write(member, drive_offset, drive_offset + 1);
+        }
+        for (member, byte) in (self.data_start()..self.drives.len()).zip(data) {
+            if !self.drives[member].has_failed() {
+                let result = self.write_member(member, drive_offset, &[*byte]);
+                ignore_ejected(&self.drives[member], result)?;
+            }
+        }
+        if self.current_at(P_INDEX, drive_offset) {
+            let p = syndrome(data, 0);
+            let result = self.write_member(P_INDEX, drive_offset, &[p]);
+            ignore_ejected(self.p_parity(), result)?;
+        }
+        if self.mode == RaidMode::Raid6 && self.current_at(Q_INDEX, drive_offset) {
+            let q = syndrome(data, 1);
+            let result = self.write_member(Q_INDEX, drive_offset, &[q]);
+            ignore_ejected(self.q_parity(), result)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::tests::*;
+    use crate::sim::*;
+
+    #[test]
+    fn raid6_write_back_coalesces_full_stripes() {
+        let mut sim = RaidSim::builder()
+            .drives(NUM_DRIVES, DRIVE_SIZE)
+            .build()
+            .unwrap();
+        sim.init().unwrap();
+        sim.set_write_back(Some(WriteBackConfig {
+            capacity: sim.size() as usize + 1,
+        }))
+        .unwrap();
+        let data = write_random(&mut sim);
+        assert_eq!(sim.pending_writes() as u64, sim.size());
+        assert_eq!(sim.stats().physical_bytes_written, 0);
+        assert_sim_equal(&sim, &data);
+
+        sim.flush().unwrap();
+        let stats = sim.write_back_stats().unwrap();
+        assert_eq!(stats.full_stripe_rows, DRIVE_SIZE as u64);
+        assert_eq!(stats.read_modify_writes, 0);
+        assert_eq!(
+            sim.stats().write_amplification(),
+            Some(NUM_DRIVES as f64 / (NUM_DRIVES - 2) as f64)
+        );
+        sim.fail_random_data();
+        sim.fail_random_data();
+        assert_sim_equal(&sim, &data);
+    }
+
+    #[test]
+    fn raid6_flush_drops_stale_read_cache_blocks() {
+        let (mut sim, data) = init_random(RaidMode::Raid6);
+        sim.set_read_cache(Some(ReadCacheConfig::default()));
+        sim.set_write_back(Some(WriteBackConfig::default()))
+            .unwrap();
+        sim.write(0, !data[0]).unwrap();
+        assert_eq!(sim.read(1).unwrap(), data[1]);
+        sim.flush().unwrap();
+        assert_eq!(sim.read(0).unwrap(), !data[0]);
+    }
+
+    #[test]
+    fn raid5_failed_flush_keeps_cached_writes() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_write_back(Some(WriteBackConfig::default()))
+            .unwrap();
+        sim.write(0, !data[0]).unwrap();
+        sim.fail_drive(2);
+        sim.fail_drive(3);
+        assert!(sim.flush().is_err());
+        assert_eq!(sim.pending_writes(), 1);
+    }
+
+    #[test]
+    fn raid5_power_loss_drops_unflushed_writes() {
+        let (mut sim, data) = init_random(RaidMode::Raid5);
+        sim.set_write_back(Some(WriteBackConfig::default()))
+            .unwrap();
+        sim.write(0, !data[0]).unwrap();
+        sim.write(1, !data[1]).unwrap();
+        sim.flush().unwrap();
+        assert_eq!(sim.write_back_stats().unwrap().read_modify_writes, 2);
+        sim.write(0, data[0]).unwrap();
+        assert_eq!(sim.read(0).unwrap(), data[0]);
+
+        let (sim, _) = RaidSim::assemble(sim.crash(), false).unwrap();
+        assert_eq!(sim.read(0).unwrap(), !data[0]);
+        assert_eq!(sim.read(1).unwrap(), !data[1]);
+    }
+}
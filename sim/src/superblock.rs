@@ -0,0 +1,347 @@
+use std::convert::TryInto;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    cipher::mix,
+    codec::{Decoder, Encoder},
+    drive::SECTOR_SIZE,
+    sim::RaidMode,
+};
+
+/// Marks a Linux md version 1 superblock
+pub const MD_MAGIC: u32 = 0xa92b_4efc;
+
+/// Where a version 1.2 superblock goes, 4 KiB from the start of the device
+pub const MD_SUPER_OFFSET: usize = 4096;
+
+/// Where member data starts on an md image, right after the sector the superblock is in
+pub const MD_DATA_OFFSET: usize = 2 * MD_SUPER_OFFSET;
+
+/// md's `ALGORITHM_LEFT_ASYMMETRIC`: parity starts on the last member and moves back one member a row,
+/// with data on the rest in member order
+const MD_LAYOUT_LEFT_ASYMMETRIC: u32 = 0;
+
+/// md's `ALGORITHM_RIGHT_ASYMMETRIC`: parity starts on the first member and moves on one member a row,
+/// with data on the rest in member order
+const MD_LAYOUT_RIGHT_ASYMMETRIC: u32 = 1;
+
+/// md's `ALGORITHM_LEFT_SYMMETRIC`, what `mdadm --create` uses by default: parity moves like left-asymmetric,
+/// with data starting on the member after it and wrapping around
+const MD_LAYOUT_LEFT_SYMMETRIC: u32 = 2;
+
+/// md's `ALGORITHM_RIGHT_SYMMETRIC`: parity moves like right-asymmetric, with data starting on the member after it
+/// and wrapping around
+const MD_LAYOUT_RIGHT_SYMMETRIC: u32 = 3;
+
+/// md's `ALGORITHM_PARITY_0`: P, or P and Q, on the first members with data on the rest, as the sim lays them out
+const MD_LAYOUT_PARITY_0: u32 = 4;
+
+/// Array metadata stored on every member, used to put an array back together
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Superblock {
+    /// Identifies the array the drive belongs to
+    pub array_id: u64,
+    pub mode: RaidMode,
+    /// Position of the drive in the array
+    pub slot: usize,
+    pub num_drives: usize,
+    pub drive_size: usize,
+    /// Bytes of the array on a data member before the next member takes over, see `RaidSimBuilder::chunk_size()`
+    pub chunk_size: usize,
+    /// Bumped every time the array's membership changes, a member with an older count missed some of it
+    pub events: u64,
+    /// Set when the array was stopped with parity consistent with its data, cleared while writes may be in flight
+    pub clean: bool,
+}
+
+impl Superblock {
+    /// Writes the superblock into a serialized drive image
+    pub(crate) fn encode(&self, out: &mut Encoder) {
+        out.u64(self.array_id);
+        out.u8(match self.mode {
+            RaidMode::Raid5 => 5,
+            RaidMode::Raid6 => 6,
+        });
+        for value in [self.slot, self.num_drives, self.drive_size, self.chunk_size] {
+            out.u64(value as u64);
+        }
+        out.u64(self.events);
+        out.bool(self.clean);
+    }
+
+    /// Reads back a superblock `encode()` wrote into a drive image of version `version`.
+    /// Images from before version 4 had no chunk size, every array then had a chunk per member.
+    pub(crate) fn decode(input: &mut Decoder, version: u16) -> Result<Self> {
+        let array_id = input.u64()?;
+        let mode = match input.u8()? {
+            5 => RaidMode::Raid5,
+            6 => RaidMode::Raid6,
+            other => bail!("Unknown RAID mode {} in superblock", other),
+        };
+        let (slot, num_drives, drive_size) = (input.usize()?, input.usize()?, input.usize()?);
+        let chunk_size = if version >= 4 {
+            input.usize()?
+        } else {
+            drive_size
+        };
+        Ok(Self {
+            array_id,
+            mode,
+            slot,
+            num_drives,
+            drive_size,
+            chunk_size,
+            events: input.u64()?,
+            clean: input.bool()?,
+        })
+    }
+
+    /// Returns the Linux md version 1.2 superblock for this member, to go `MD_SUPER_OFFSET` bytes into its image
+    /// with its data from `MD_DATA_OFFSET` on.
+    ///
+    /// md's chunks are powers of two of at least 4 KiB, so only arrays with chunks of such a size can be described.
+    /// Devices are numbered by slot and every one is active.
+    /// A member that wasn't stopped clean is marked as needing a resync.
+    pub fn to_md(&self) -> Result<Vec<u8>> {
+        if !self.chunk_size.is_power_of_two() || self.chunk_size < 4096 {
+            bail!(
+                "md chunks are powers of two of at least 4096 bytes, unable to describe chunks of {} bytes",
+                self.chunk_size
+            );
+        }
+        let sectors = (self.drive_size / SECTOR_SIZE) as u64;
+        let chunk = (self.chunk_size / SECTOR_SIZE) as u32;
+        let max_dev = self.num_drives as u32;
+        let level: u32 = match self.mode {
+            RaidMode::Raid5 => 5,
+            RaidMode::Raid6 => 6,
+        };
+        let mut sb = vec![0u8; 256 + 2 * self.num_drives];
+        let mut put =
+            |offset: usize, bytes: &[u8]| sb[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(0, &MD_MAGIC.to_le_bytes());
+        put(4, &1u32.to_le_bytes());
+        put(16, &uuid(self.array_id));
+        put(32, format!("raid-fun:{:016x}", self.array_id).as_bytes());
+        put(72, &level.to_le_bytes());
+        put(76, &MD_LAYOUT_PARITY_0.to_le_bytes());
+        put(80, &sectors.to_le_bytes());
+        put(88, &chunk.to_le_bytes());
+        put(92, &max_dev.to_le_bytes());
+        // Nothing is being reshaped, so the new geometry is the same as the old
+        put(100, &level.to_le_bytes());
+        put(116, &MD_LAYOUT_PARITY_0.to_le_bytes());
+        put(120, &chunk.to_le_bytes());
+        put(128, &((MD_DATA_OFFSET / SECTOR_SIZE) as u64).to_le_bytes());
+        put(136, &sectors.to_le_bytes());
+        put(144, &((MD_SUPER_OFFSET / SECTOR_SIZE) as u64).to_le_bytes());
+        put(160, &(self.slot as u32).to_le_bytes());
+        put(168, &uuid(self.array_id ^ mix(self.slot as u64 + 1)));
+        put(200, &self.events.to_le_bytes());
+        let resync = if self.clean { u64::MAX } else { 0 };
+        put(208, &resync.to_le_bytes());
+        put(220, &max_dev.to_le_bytes());
+        for slot in 0..self.num_drives {
+            put(256 + 2 * slot, &(slot as u16).to_le_bytes());
+        }
+        let csum = md_checksum(&sb);
+        sb[216..220].copy_from_slice(&csum.to_le_bytes());
+        Ok(sb)
+    }
+}
+
+/// The parts of a Linux md version 1 superblock that say where a member sits in its array
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MdSuperblock {
+    pub set_uuid: [u8; 16],
+    /// The array's name, usually `host:name`
+    pub set_name: String,
+    pub level: u32,
+    pub layout: u32,
+    /// Sectors of each member the array uses
+    pub size: u64,
+    /// Sectors in a chunk
+    pub chunk_size: u32,
+    pub raid_disks: u32,
+    /// Sector the member's data starts at
+    pub data_offset: u64,
+    /// Permanent number of the member, its role is found by looking it up in `roles`
+    pub dev_number: u32,
+    pub events: u64,
+    /// Sectors known to be in sync, `u64::MAX` when the array was stopped clean
+    pub resync_offset: u64,
+    /// Role of every device number, its slot or 0xffff for a spare and 0xfffe for a faulty member
+    pub roles: Vec<u16>,
+}
+
+impl MdSuperblock {
+    /// Reads a version 1.2 superblock from the start of `bytes`, checking its magic, version, location and checksum
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let field = |offset: usize, len: usize| -> Result<&[u8]> {
+            match bytes.get(offset..offset + len) {
+                Some(field) => Ok(field),
+                None => bail!("md superblock ends partway through"),
+            }
+        };
+        let u16_at =
+            |offset| -> Result<u16> { Ok(u16::from_le_bytes(field(offset, 2)?.try_into()?)) };
+        let u32_at =
+            |offset| -> Result<u32> { Ok(u32::from_le_bytes(field(offset, 4)?.try_into()?)) };
+        let u64_at =
+            |offset| -> Result<u64> { Ok(u64::from_le_bytes(field(offset, 8)?.try_into()?)) };
+
+        if u32_at(0)? != MD_MAGIC {
+            bail!("No md superblock, magic is {:#x}", u32_at(0)?);
+        }
+        if u32_at(4)? != 1 {
+            bail!("Unsupported md superblock version {}", u32_at(4)?);
+        }
+        let super_offset = u64_at(144)?;
+        if super_offset != (MD_SUPER_OFFSET / SECTOR_SIZE) as u64 {
+            bail!(
+                "md superblock claims to be at sector {}, not where version 1.2 puts it",
+                super_offset
+            );
+        }
+        let max_dev = u32_at(220)? as usize;
+        let sb = field(0, 256 + 2 * max_dev)?;
+        if md_checksum(sb) != u32_at(216)? {
+            bail!("md superblock doesn't match its checksum");
+        }
+        let name = field(32, 32)?;
+        Ok(Self {
+            set_uuid: field(16, 16)?.try_into()?,
+            set_name: String::from_utf8_lossy(
+                &name[..name.iter().position(|b| *b == 0).unwrap_or(32)],
+            )
+            .into_owned(),
+            level: u32_at(72)?,
+            layout: u32_at(76)?,
+            size: u64_at(80)?,
+            chunk_size: u32_at(88)?,
+            raid_disks: u32_at(92)?,
+            data_offset: u64_at(128)?,
+            dev_number: u32_at(160)?,
+            events: u64_at(200)?,
+            resync_offset: u64_at(208)?,
+            roles: (0..max_dev)
+                .map(|i| u16_at(256 + 2 * i))
+                .collect::<Result<Vec<u16>>>()?,
+        })
+    }
+
+    /// Returns the member's superblock as the sim keeps it, failing if the array can't be simulated:
+    /// RAID 5 or 6 in one of md's first five layouts, with chunks that members hold a whole number of.
+    /// The array id comes from the set UUID.
+    ///
+    /// The parity-first layout `Superblock::to_md()` writes is the sim's own. Every other one rotates parity,
+    /// left-symmetric by default, so a member holds a different part of each row of chunks, see `chunk_slots()`.
+    pub fn to_superblock(&self) -> Result<Superblock> {
+        let mode = match self.level {
+            5 => RaidMode::Raid5,
+            6 => RaidMode::Raid6,
+            other => bail!("md RAID level {} can't be simulated", other),
+        };
+        if self.layout > MD_LAYOUT_PARITY_0 {
+            bail!(
+                "md layout {} can't be simulated, only layouts {} to {}",
+                self.layout,
+                MD_LAYOUT_LEFT_ASYMMETRIC,
+                MD_LAYOUT_PARITY_0
+            );
+        }
+        if self.chunk_size == 0 || !self.size.is_multiple_of(self.chunk_size as u64) {
+            bail!(
+                "md chunks of {} sectors don't divide members of {} sectors",
+                self.chunk_size,
+                self.size
+            );
+        }
+        let slot = match self.roles.get(self.dev_number as usize) {
+            Some(&role) if (role as u32) < self.raid_disks => role as usize,
+            Some(0xffff) => bail!("Device {} is a spare", self.dev_number),
+            Some(0xfffe) => bail!("Device {} is faulty", self.dev_number),
+            _ => bail!("Device {} has no role in the array", self.dev_number),
+        };
+        Ok(Superblock {
+            array_id: u64::from_le_bytes(self.set_uuid[..8].try_into()?),
+            mode,
+            slot,
+            num_drives: self.raid_disks as usize,
+            drive_size: self.size as usize * SECTOR_SIZE,
+            chunk_size: self.chunk_size as usize * SECTOR_SIZE,
+            events: self.events,
+            clean: self.resync_offset == u64::MAX,
+        })
+    }
+
+    /// Whether parity moves from member to member, as it does in every layout but the parity-first one
+    pub fn rotates(&self) -> bool {
+        self.layout != MD_LAYOUT_PARITY_0
+    }
+
+    /// Returns the slot in the sim of the chunk each member holds in row `row` of chunks:
+    /// the first slot for P, the second for Q, and the slot of the data drive that holds the same chunk of data
+    pub fn chunk_slots(&self, row: usize) -> Vec<usize> {
+        let n = self.raid_disks as usize;
+        let parity = if self.level == 6 { 2 } else { 1 };
+        let pd = match self.layout {
+            MD_LAYOUT_LEFT_ASYMMETRIC | MD_LAYOUT_LEFT_SYMMETRIC => n - 1 - row % n,
+            MD_LAYOUT_RIGHT_ASYMMETRIC | MD_LAYOUT_RIGHT_SYMMETRIC => row % n,
+            _ => 0,
+        };
+        // Q always follows P, wrapping around to the first member
+        let parity_members = (0..parity).map(|i| (pd + i) % n).collect::<Vec<usize>>();
+        let first_data = match self.layout {
+            MD_LAYOUT_LEFT_SYMMETRIC | MD_LAYOUT_RIGHT_SYMMETRIC => pd + parity,
+            _ => 0,
+        };
+        let mut slots = vec![0; n];
+        for (i, member) in parity_members.iter().enumerate() {
+            slots[*member] = i;
+        }
+        let data = (first_data..first_data + n)
+            .map(|member| member % n)
+            .filter(|member| !parity_members.contains(member));
+        for (j, member) in data.enumerate() {
+            slots[member] = parity + j;
+        }
+        slots
+    }
+
+    /// Whether Q weighs the data chunks in data order, as the sim's does. md weighs them in the order of the members
+    /// from the one after Q, wrapping around, which is something else for RAID 6 in the asymmetric layouts.
+    pub fn q_in_data_order(&self) -> bool {
+        self.level != 6
+            || !matches!(
+                self.layout,
+                MD_LAYOUT_LEFT_ASYMMETRIC | MD_LAYOUT_RIGHT_ASYMMETRIC
+            )
+    }
+}
+
+/// Returns a 16 byte UUID made from `seed`
+fn uuid(seed: u64) -> [u8; 16] {
+    let mut uuid = [0; 16];
+    uuid[..8].copy_from_slice(&mix(seed).to_le_bytes());
+    uuid[8..].copy_from_slice(&mix(!seed).to_le_bytes());
+    uuid
+}
+
+/// Computes an md version 1 superblock's checksum the way the kernel does, summing it as little endian words
+/// with the checksum field taken as zero, then folding the carries back in
+pub fn md_checksum(sb: &[u8]) -> u32 {
+    let mut sum = sb
+        .chunks(4)
+        .enumerate()
+        .map(|(i, word)| match word {
+            // The checksum itself, at byte 216
+            _ if i == 54 => 0,
+            [a, b] => u16::from_le_bytes([*a, *b]) as u64,
+            _ => u32::from_le_bytes(word.try_into().unwrap()) as u64,
+        })
+        .sum::<u64>();
+    sum = (sum & 0xffff_ffff) + (sum >> 32);
+    sum as u32
+}